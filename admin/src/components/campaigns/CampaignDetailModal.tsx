@@ -25,7 +25,7 @@ export const CampaignDetailModal: React.FC<CampaignDetailModalProps> = ({ campai
                 <h3 className="text-sm font-medium text-gray-500">Token Info</h3>
                 <p className="mt-1 text-sm text-gray-900">{campaign.token_name} ({campaign.token_symbol})</p>
                 <p className="mt-1 text-sm text-gray-900">Supply: {campaign.token_supply}</p>
-                <p className="mt-1 text-sm text-gray-900">Price: ${Number(campaign.suggested_price).toFixed(4)}</p>
+                <p className="mt-1 text-sm text-gray-900">Price: ${(Number(campaign.suggested_price) / 1e9).toFixed(4)}</p>
             </div>
             <div>
                 <h3 className="text-sm font-medium text-gray-500">Timeline</h3>
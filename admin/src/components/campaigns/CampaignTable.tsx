@@ -54,7 +54,7 @@ export const CampaignTable: React.FC<CampaignTableProps> = ({ campaigns, onSelec
                  </div>
               </td>
               <td className="px-6 py-4 whitespace-nowrap">
-                <div className="text-sm text-gray-900">${Number(campaign.suggested_price).toFixed(2)}</div>
+                <div className="text-sm text-gray-900">${(Number(campaign.suggested_price) / 1e9).toFixed(2)}</div>
                 <div className="text-xs text-gray-500">Supply: {campaign.token_supply}</div>
               </td>
               <td className="px-6 py-4 whitespace-nowrap">
@@ -20,7 +20,7 @@ export interface Campaign {
   image_url?: string;
   start_time: string;
   end_time: string;
-  suggested_price: string;
+  suggested_price: string; // nanocoins per whole token
   status: 'pending' | 'running' | 'paused' | 'finished' | 'rejected' | 'cancelled' | 'approved';
   token_address?: string;
   tx_hash?: string;
@@ -0,0 +1,19 @@
+-- Uploaded media (campaign logos and images)
+-- Files live in the configured storage backend under a content-hash key;
+-- this table keeps the metadata and who uploaded them.
+
+CREATE TABLE IF NOT EXISTS media_files (
+    key VARCHAR(255) PRIMARY KEY, -- <sha256>.<ext>
+    content_type VARCHAR(50) NOT NULL,
+    byte_size BIGINT NOT NULL,
+    width INT NOT NULL,
+    height INT NOT NULL,
+    original_key VARCHAR(255), -- NULL for originals, set for thumbnails
+    variant VARCHAR(20) NOT NULL DEFAULT 'original',
+    uploaded_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_media_files_original ON media_files(original_key);
+
+COMMENT ON TABLE media_files IS 'Content-addressed uploads. Keys never change once published, so jetton metadata images stay stable.';
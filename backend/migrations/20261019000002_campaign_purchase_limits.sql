@@ -0,0 +1,19 @@
+-- Sale limits for campaigns
+-- All amounts are MKOIN nanocoins (1 MKOIN = 1e9), same unit as purchases.mkoin_paid.
+-- NULL means "no limit"; token_supply always caps tokens sold.
+
+ALTER TABLE campaigns
+ADD COLUMN IF NOT EXISTS soft_cap NUMERIC(78, 0),
+ADD COLUMN IF NOT EXISTS hard_cap NUMERIC(78, 0),
+ADD COLUMN IF NOT EXISTS min_ticket NUMERIC(78, 0),
+ADD COLUMN IF NOT EXISTS max_ticket NUMERIC(78, 0),
+ADD COLUMN IF NOT EXISTS max_per_investor NUMERIC(78, 0);
+
+COMMENT ON COLUMN campaigns.soft_cap IS 'Minimum MKOIN (nanocoins) to raise for the campaign to succeed';
+COMMENT ON COLUMN campaigns.hard_cap IS 'Maximum MKOIN (nanocoins) the campaign accepts';
+COMMENT ON COLUMN campaigns.min_ticket IS 'Minimum MKOIN (nanocoins) per purchase';
+COMMENT ON COLUMN campaigns.max_ticket IS 'Maximum MKOIN (nanocoins) per purchase';
+COMMENT ON COLUMN campaigns.max_per_investor IS 'Maximum total MKOIN (nanocoins) a single address may spend on the campaign';
+
+-- Speeds up the allocation sums taken while a campaign row is locked
+CREATE INDEX IF NOT EXISTS idx_purchases_campaign_user ON purchases(campaign_id, user_address);
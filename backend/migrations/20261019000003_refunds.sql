@@ -0,0 +1,46 @@
+-- Refunds for cancelled or undersubscribed campaigns
+-- A batch groups the refunds issued for one campaign; each confirmed
+-- purchase can be refunded at most once. Refunds are paid from the
+-- campaign's treasury, the platform-controlled wallet that received its
+-- sale proceeds; NULL is the platform (admin) wallet.
+
+ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS treasury_address VARCHAR(255);
+
+CREATE TABLE IF NOT EXISTS refund_batches (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    reason VARCHAR(50) NOT NULL, -- cancelled, soft_cap_missed
+    status VARCHAR(50) NOT NULL DEFAULT 'created', -- created, processing, completed, partial
+    total_amount NUMERIC(78, 0) NOT NULL DEFAULT 0,
+    treasury_address VARCHAR(255),
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE TABLE IF NOT EXISTS refunds (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    batch_id UUID NOT NULL REFERENCES refund_batches(id) ON DELETE CASCADE,
+    purchase_id UUID NOT NULL UNIQUE REFERENCES purchases(id) ON DELETE CASCADE,
+    user_address VARCHAR(255) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending', -- pending, sent, confirmed, failed
+    attempts INT NOT NULL DEFAULT 0,
+    msg_hash VARCHAR(255),
+    tx_hash VARCHAR(255),
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMP WITH TIME ZONE,
+    confirmed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_refund_batches_campaign ON refund_batches(campaign_id);
+CREATE INDEX IF NOT EXISTS idx_refunds_batch ON refunds(batch_id);
+CREATE INDEX IF NOT EXISTS idx_refunds_status ON refunds(status);
+
+COMMENT ON COLUMN campaigns.treasury_address IS 'Wallet holding the campaign''s raised MKOIN; NULL for the platform wallet';
+COMMENT ON TABLE refunds IS 'MKOIN paid back to buyers of cancelled or undersubscribed campaigns';
+COMMENT ON COLUMN refunds.amount IS 'Amount in nanocoins (1 MKOIN = 1e9 nanocoins)';
+COMMENT ON COLUMN refunds.status IS 'pending: not sent yet, sent: transfer may have been broadcast, confirmed: delivered on chain, failed: known not to have moved any MKOIN, safe to send again';
+COMMENT ON COLUMN refunds.attempts IS 'Transfers sent so far; each gets its own query_id, so a late bounce of an earlier attempt is never taken for the current one';
+COMMENT ON COLUMN purchases.status IS 'pending: awaiting blockchain confirmation, confirmed: verified on chain, failed: transaction failed, refunded: MKOIN paid back';
@@ -0,0 +1,23 @@
+-- Whitelist presale: an optional window before start_time in which only
+-- allowlisted buyers can purchase, optionally at a presale price.
+
+ALTER TABLE campaigns
+ADD COLUMN IF NOT EXISTS presale_start_time TIMESTAMP WITH TIME ZONE,
+ADD COLUMN IF NOT EXISTS presale_price NUMERIC(78, 0);
+
+CREATE TABLE IF NOT EXISTS campaign_allowlist (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    address VARCHAR(255),
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    added_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((address IS NULL) <> (user_id IS NULL)),
+    UNIQUE (campaign_id, address),
+    UNIQUE (campaign_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_campaign_allowlist_campaign ON campaign_allowlist(campaign_id);
+
+COMMENT ON COLUMN campaigns.presale_start_time IS 'Start of the allowlist-only presale; must be before start_time';
+COMMENT ON COLUMN campaigns.presale_price IS 'MKOIN per token during presale, same unit as suggested_price';
+COMMENT ON COLUMN campaign_allowlist.address IS 'Raw workchain:hex TON address';
@@ -0,0 +1,29 @@
+-- Store campaign prices in MKOIN nanocoins per whole token, and minted
+-- token amounts in nano-units.
+-- The columns are NUMERIC(78, 0), so fractional prices such as 0.1 MKOIN
+-- could not be represented; values are rescaled to the same nano-unit used
+-- for every other amount. Prices already truncated to 0 cannot be recovered.
+
+UPDATE campaigns
+SET suggested_price = suggested_price * 1000000000,
+    presale_price = presale_price * 1000000000;
+
+COMMENT ON COLUMN campaigns.suggested_price IS 'MKOIN nanocoins per whole token';
+COMMENT ON COLUMN campaigns.presale_price IS 'MKOIN nanocoins per whole token during presale';
+
+-- Token amounts recorded at campaign mint time are now stored in nano-units
+-- as well; rows written before that hold whole tokens.
+UPDATE campaigns
+SET mint_amount = mint_amount * 1000000000
+WHERE mint_amount IS NOT NULL;
+
+UPDATE campaign_token_mints
+SET amount = amount * 1000000000;
+
+UPDATE token_minters
+SET total_supply = total_supply * 1000000000
+WHERE total_supply IS NOT NULL;
+
+COMMENT ON COLUMN campaigns.mint_amount IS 'Campaign tokens minted, in nano-units';
+COMMENT ON COLUMN campaign_token_mints.amount IS 'Campaign tokens minted, in nano-units';
+COMMENT ON COLUMN token_minters.total_supply IS 'Total supply in nano-units';
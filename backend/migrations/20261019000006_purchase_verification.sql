@@ -0,0 +1,10 @@
+-- On-chain verification of purchase payments
+ALTER TABLE purchases
+ADD COLUMN IF NOT EXISTS verified_tx_hash VARCHAR(255) UNIQUE,
+ADD COLUMN IF NOT EXISTS failure_reason TEXT,
+ADD COLUMN IF NOT EXISTS last_checked_at TIMESTAMP WITH TIME ZONE;
+
+COMMENT ON COLUMN purchases.tx_hash IS 'Buyer wallet transaction (or the external message it processed) submitted with the purchase';
+COMMENT ON COLUMN purchases.verified_tx_hash IS 'Treasury transaction that received the MKOIN transfer notification; a payment confirms at most one purchase';
+COMMENT ON COLUMN purchases.failure_reason IS 'Why verification rejected the payment';
+COMMENT ON COLUMN purchases.last_checked_at IS 'Last time the pending purchase was checked on chain, to throttle manual verification requests';
@@ -0,0 +1,26 @@
+-- Signed, single-use purchase quotes: a buyer fixes price and allocation
+-- before paying, and a purchase is only accepted against a live quote.
+
+CREATE TABLE IF NOT EXISTS purchase_quotes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    user_address VARCHAR(255) NOT NULL,
+    mkoin_amount NUMERIC(78, 0) NOT NULL,
+    tokens NUMERIC(78, 0) NOT NULL,
+    price NUMERIC(78, 0) NOT NULL,
+    treasury_address VARCHAR(255),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_purchase_quotes_campaign ON purchase_quotes(campaign_id);
+
+ALTER TABLE purchases
+ADD COLUMN IF NOT EXISTS quote_id UUID UNIQUE REFERENCES purchase_quotes(id);
+
+COMMENT ON COLUMN purchase_quotes.mkoin_amount IS 'MKOIN nanocoins the buyer must transfer';
+COMMENT ON COLUMN purchase_quotes.price IS 'MKOIN nanocoins per whole token, locked for the lifetime of the quote';
+COMMENT ON COLUMN purchase_quotes.treasury_address IS 'Wallet the buyer pays; NULL for the platform wallet. Fixed by the quote, so changing a campaign''s treasury never invalidates payments in flight';
+COMMENT ON COLUMN purchase_quotes.used_at IS 'Set when a purchase claims the quote; a quote is used at most once';
+COMMENT ON COLUMN purchases.quote_id IS 'Quote the purchase was created from; its id is the query_id and comment of the payment';
@@ -0,0 +1,19 @@
+-- Manual purchase confirmations by admins, and expiry of purchases whose
+-- payment never arrived.
+
+CREATE TABLE IF NOT EXISTS purchase_audit_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    purchase_id UUID NOT NULL REFERENCES purchases(id) ON DELETE CASCADE,
+    action VARCHAR(50) NOT NULL, -- 'admin_confirm'
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    previous_status VARCHAR(50) NOT NULL,
+    new_status VARCHAR(50) NOT NULL,
+    verified_tx_hash VARCHAR(255),
+    note TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_purchase_audit_purchase ON purchase_audit_log(purchase_id);
+
+COMMENT ON TABLE purchase_audit_log IS 'Admin overrides of purchase status';
+COMMENT ON COLUMN purchases.status IS 'pending: awaiting payment verification, confirmed: payment verified on chain or by an admin, failed: payment rejected, expired: no payment within the timeout, refunded: MKOIN returned';
@@ -0,0 +1,23 @@
+-- Delivery of purchased campaign jettons from the platform wallet to buyers
+
+CREATE TABLE IF NOT EXISTS token_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    purchase_id UUID NOT NULL UNIQUE REFERENCES purchases(id) ON DELETE CASCADE,
+    token_address VARCHAR(255) NOT NULL,
+    user_address VARCHAR(255) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending', -- pending, sent, delivered, failed
+    attempts INT NOT NULL DEFAULT 0,
+    msg_hash VARCHAR(255),
+    tx_hash VARCHAR(255),
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMP WITH TIME ZONE,
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_token_deliveries_status ON token_deliveries(status);
+
+COMMENT ON TABLE token_deliveries IS 'TEP-74 transfers of purchased campaign jettons, one per confirmed purchase';
+COMMENT ON COLUMN token_deliveries.msg_hash IS 'External message of the latest attempt';
+COMMENT ON COLUMN token_deliveries.tx_hash IS 'Platform wallet transaction that received the excesses of a successful transfer';
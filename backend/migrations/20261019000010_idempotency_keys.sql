@@ -0,0 +1,22 @@
+-- Idempotency keys for endpoints that move money or trigger on-chain actions.
+-- A key is scoped to the endpoint and the caller; replays return the stored
+-- response, a different request under the same key is rejected. Failures on
+-- our side are stored too, since a request may have acted on chain before
+-- failing.
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    scope VARCHAR(100) NOT NULL,
+    actor VARCHAR(255) NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    response_status INT,
+    response_body TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP WITH TIME ZONE,
+    PRIMARY KEY (scope, actor, key)
+);
+
+COMMENT ON COLUMN idempotency_keys.actor IS 'Admin user id or buyer address that sent the request';
+COMMENT ON COLUMN idempotency_keys.fingerprint IS 'SHA-256 (hex) of the request, to detect a key reused for a different request';
+COMMENT ON COLUMN idempotency_keys.response_status IS 'NULL while the first request is still running; a key left NULL past its lease can be claimed again';
+COMMENT ON COLUMN idempotency_keys.response_body IS 'JSON response of a success, or the error message of a failure';
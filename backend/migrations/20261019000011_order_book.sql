@@ -0,0 +1,68 @@
+-- Secondary market: an off-chain order book per campaign token/MKOIN pair.
+-- Orders are backed by jettons escrowed in the platform wallet; matched
+-- trades settle by transfers out of that escrow.
+
+CREATE TABLE IF NOT EXISTS market_orders (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    user_address VARCHAR(255) NOT NULL,
+    side VARCHAR(10) NOT NULL, -- buy, sell
+    order_type VARCHAR(10) NOT NULL, -- limit, market
+    price NUMERIC(78, 0),
+    amount NUMERIC(78, 0) NOT NULL,
+    filled NUMERIC(78, 0) NOT NULL DEFAULT 0,
+    escrow_amount NUMERIC(78, 0) NOT NULL,
+    escrow_used NUMERIC(78, 0) NOT NULL DEFAULT 0,
+    status VARCHAR(50) NOT NULL DEFAULT 'awaiting_escrow', -- awaiting_escrow, open, filled, cancelled, expired
+    escrow_tx_hash VARCHAR(255) UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_market_orders_book ON market_orders(campaign_id, side, status, price);
+CREATE INDEX IF NOT EXISTS idx_market_orders_user ON market_orders(user_address);
+CREATE INDEX IF NOT EXISTS idx_market_orders_status ON market_orders(status);
+
+CREATE TABLE IF NOT EXISTS market_trades (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    buy_order_id UUID NOT NULL REFERENCES market_orders(id),
+    sell_order_id UUID NOT NULL REFERENCES market_orders(id),
+    buyer_address VARCHAR(255) NOT NULL,
+    seller_address VARCHAR(255) NOT NULL,
+    price NUMERIC(78, 0) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    mkoin_amount NUMERIC(78, 0) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_market_trades_campaign ON market_trades(campaign_id, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS market_transfers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    order_id UUID NOT NULL REFERENCES market_orders(id),
+    trade_id UUID REFERENCES market_trades(id),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    asset VARCHAR(10) NOT NULL, -- tokens, mkoin
+    recipient VARCHAR(255) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending', -- pending, sent, delivered, failed
+    attempts INT NOT NULL DEFAULT 0,
+    msg_hash VARCHAR(255),
+    tx_hash VARCHAR(255),
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMP WITH TIME ZONE,
+    delivered_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS idx_market_transfers_status ON market_transfers(status);
+CREATE INDEX IF NOT EXISTS idx_market_transfers_trade ON market_transfers(trade_id);
+
+COMMENT ON COLUMN market_orders.price IS 'Limit price in MKOIN nanocoins per whole token; NULL for market orders';
+COMMENT ON COLUMN market_orders.amount IS 'Tokens to buy or sell, in nano-units';
+COMMENT ON COLUMN market_orders.escrow_amount IS 'Escrowed in the platform wallet: tokens for sells, MKOIN for buys';
+COMMENT ON COLUMN market_orders.escrow_used IS 'Part of the escrow paid out to counterparties';
+COMMENT ON COLUMN market_orders.escrow_tx_hash IS 'Platform wallet transaction that received the escrow';
+COMMENT ON TABLE market_transfers IS 'Transfers out of escrow: trade legs to counterparties and refunds of unused escrow';
+COMMENT ON COLUMN market_transfers.trade_id IS 'Trade the transfer settles; NULL for a refund of unused escrow';
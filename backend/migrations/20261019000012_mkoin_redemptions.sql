@@ -0,0 +1,56 @@
+-- Redemption of MKOIN for EUR: the user sends MKOIN to the treasury, an
+-- admin pays out to the user's bank account and the MKOIN is burned.
+-- Burns and returns are settled from the treasury's history: a message that
+-- expired unseen is sent again, and MKOIN arriving after a request closed is
+-- sent back.
+
+CREATE TABLE IF NOT EXISTS bank_accounts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_address VARCHAR(255) NOT NULL,
+    iban VARCHAR(34) NOT NULL,
+    holder_name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    removed_at TIMESTAMP WITH TIME ZONE,
+    UNIQUE (user_address, iban)
+);
+
+CREATE TABLE IF NOT EXISTS mkoin_redemptions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_address VARCHAR(255) NOT NULL,
+    bank_account_id UUID NOT NULL REFERENCES bank_accounts(id),
+    amount NUMERIC(78, 0) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'awaiting_transfer',
+    transfer_tx_hash VARCHAR(255) UNIQUE,
+    bank_reference VARCHAR(255),
+    settle_attempts INT NOT NULL DEFAULT 0,
+    settle_msg_hash VARCHAR(255),
+    settle_tx_hash VARCHAR(255),
+    settle_sent_at TIMESTAMP WITH TIME ZONE,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_mkoin_redemptions_user ON mkoin_redemptions(user_address);
+CREATE INDEX IF NOT EXISTS idx_mkoin_redemptions_status ON mkoin_redemptions(status);
+
+CREATE TABLE IF NOT EXISTS redemption_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    redemption_id UUID NOT NULL REFERENCES mkoin_redemptions(id) ON DELETE CASCADE,
+    previous_status VARCHAR(50),
+    new_status VARCHAR(50) NOT NULL,
+    actor_id UUID,
+    note TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_redemption_events_redemption ON redemption_events(redemption_id);
+
+COMMENT ON COLUMN mkoin_redemptions.amount IS 'MKOIN nanocoins to redeem, paid out as EUR 1:1';
+COMMENT ON COLUMN mkoin_redemptions.status IS 'awaiting_transfer -> received -> approved -> paid -> burning -> burned; rejected -> returning -> returned; cancelled, expired -> rejected when the MKOIN arrives late';
+COMMENT ON COLUMN mkoin_redemptions.transfer_tx_hash IS 'Treasury transaction that received the MKOIN';
+COMMENT ON COLUMN mkoin_redemptions.bank_reference IS 'Reference of the EUR payout, set when marked paid';
+COMMENT ON COLUMN mkoin_redemptions.settle_msg_hash IS 'External message of the latest burn or return of the received MKOIN';
+COMMENT ON COLUMN mkoin_redemptions.settle_tx_hash IS 'Treasury transaction that received the excesses of the burn or return';
+COMMENT ON COLUMN mkoin_redemptions.settle_sent_at IS 'When the latest burn or return was sent';
+COMMENT ON COLUMN redemption_events.actor_id IS 'Admin who made the change; NULL for the user or the worker';
@@ -0,0 +1,64 @@
+-- Buying MKOIN by bank transfer: a user announces a deposit and pays it with
+-- a unique reference; imported bank statements are matched against those
+-- references and matched deposits are minted as MKOIN 1:1. Partial payments
+-- add up on their deposit intent.
+
+CREATE TABLE IF NOT EXISTS deposit_intents (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_address VARCHAR(255) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    reference VARCHAR(32) NOT NULL UNIQUE,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending',
+    paid_amount NUMERIC(78, 0),
+    mint_attempts INT NOT NULL DEFAULT 0,
+    mint_msg_hash VARCHAR(255),
+    mint_tx_hash VARCHAR(255),
+    error TEXT,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_deposit_intents_user ON deposit_intents(user_address);
+CREATE INDEX IF NOT EXISTS idx_deposit_intents_status ON deposit_intents(status);
+
+CREATE TABLE IF NOT EXISTS bank_statements (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    format VARCHAR(20) NOT NULL, -- 'camt053', 'csv'
+    file_name VARCHAR(255),
+    imported_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    entry_count INT NOT NULL DEFAULT 0,
+    matched_count INT NOT NULL DEFAULT 0,
+    review_count INT NOT NULL DEFAULT 0,
+    duplicate_count INT NOT NULL DEFAULT 0,
+    imported_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS bank_payments (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    statement_id UUID NOT NULL REFERENCES bank_statements(id) ON DELETE CASCADE,
+    bank_ref VARCHAR(255) NOT NULL UNIQUE,
+    booking_date DATE,
+    amount NUMERIC(78, 0) NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+    debtor_name VARCHAR(255),
+    debtor_iban VARCHAR(34),
+    remittance TEXT NOT NULL DEFAULT '',
+    deposit_intent_id UUID REFERENCES deposit_intents(id),
+    status VARCHAR(50) NOT NULL, -- 'matched', 'review', 'assigned', 'dismissed'
+    review_reason VARCHAR(50),
+    resolved_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    resolution_note TEXT,
+    resolved_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_bank_payments_status ON bank_payments(status);
+CREATE INDEX IF NOT EXISTS idx_bank_payments_intent ON bank_payments(deposit_intent_id);
+
+COMMENT ON COLUMN deposit_intents.amount IS 'EUR announced by the user, in 9-decimal units like MKOIN nanocoins';
+COMMENT ON COLUMN deposit_intents.status IS 'pending -> paid -> minting -> minted; expired, cancelled; mint_failed until an admin retries. A pending intent with partial payments is paid at its deadline';
+COMMENT ON COLUMN deposit_intents.paid_amount IS 'EUR received so far, the sum of its payments; minted as MKOIN 1:1';
+COMMENT ON COLUMN deposit_intents.mint_msg_hash IS 'External message of the latest mint';
+COMMENT ON COLUMN bank_payments.bank_ref IS 'Account IBAN and bank-assigned id of a CAMT.053 entry, or a digest of a CSV row; re-imported entries are skipped';
+COMMENT ON COLUMN bank_payments.review_reason IS 'unmatched, partial, overpaid, expired, duplicate, currency';
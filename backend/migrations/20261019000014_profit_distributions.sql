@@ -0,0 +1,46 @@
+-- Profit distributions: an MKOIN amount shared pro rata among the holders
+-- of a campaign token, paid out as one MKOIN transfer per holder by a
+-- background worker, which resumes them after a restart. A payout only
+-- fails once no MKOIN can have moved.
+
+CREATE TABLE IF NOT EXISTS distributions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    token_address VARCHAR(255) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'created', -- created, processing, completed, partial
+    total_amount NUMERIC(78, 0) NOT NULL,
+    distributed_amount NUMERIC(78, 0) NOT NULL,
+    remainder NUMERIC(78, 0) NOT NULL,
+    snapshot_supply NUMERIC(78, 0) NOT NULL,
+    holder_count INT NOT NULL,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE TABLE IF NOT EXISTS distribution_payouts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    distribution_id UUID NOT NULL REFERENCES distributions(id) ON DELETE CASCADE,
+    user_address VARCHAR(255) NOT NULL,
+    balance NUMERIC(78, 0) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending', -- pending, sent, confirmed, failed
+    attempts INT NOT NULL DEFAULT 0,
+    msg_hash VARCHAR(255),
+    tx_hash VARCHAR(255),
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMP WITH TIME ZONE,
+    confirmed_at TIMESTAMP WITH TIME ZONE,
+    UNIQUE (distribution_id, user_address)
+);
+
+CREATE INDEX IF NOT EXISTS idx_distributions_campaign ON distributions(campaign_id);
+CREATE INDEX IF NOT EXISTS idx_distribution_payouts_distribution ON distribution_payouts(distribution_id);
+
+COMMENT ON COLUMN distributions.total_amount IS 'MKOIN nanocoins to distribute';
+COMMENT ON COLUMN distributions.remainder IS 'Rounding dust of the pro-rata shares, kept by the treasury';
+COMMENT ON COLUMN distributions.snapshot_supply IS 'Sum of the token balances the shares were computed from';
+COMMENT ON COLUMN distribution_payouts.balance IS 'Token balance of the holder in the snapshot';
+COMMENT ON COLUMN distribution_payouts.status IS 'pending: not sent yet, sent: transfer broadcast or possibly broadcast, confirmed: excesses seen on chain, failed: not sent, bounced or expired without landing; sent again when the distribution is executed again';
+COMMENT ON COLUMN distribution_payouts.attempts IS 'Transfers sent so far; the latest one carries query_id = id + attempts';
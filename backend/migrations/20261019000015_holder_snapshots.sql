@@ -0,0 +1,93 @@
+-- Point-in-time holder snapshots of campaign tokens.
+--
+-- Every change of a holder's balance is appended to a ledger keyed by the
+-- logical time (lt) of the change, so the holders of a token can be
+-- materialized at any earlier lt or timestamp. The ledger is fed by the
+-- indexer: every campaign token transfer credits the recipient at the lt of
+-- its transaction and debits the sender at the lt of the message it sent.
+-- Snapshots persist such a holder set, or one read from the jetton wallets
+-- on chain.
+--
+-- Balances are stored under the raw form of the holder's address, so one
+-- wallet has one portfolio row per token.
+
+CREATE FUNCTION pg_temp.raw_ton_address(address TEXT) RETURNS TEXT AS $$
+    SELECT CASE
+        WHEN address ~ '^[A-Za-z0-9_+/-]{48}$' THEN (
+            SELECT CASE get_byte(bytes, 1) WHEN 255 THEN '-1' ELSE get_byte(bytes, 1)::TEXT END
+                   || ':' || encode(substring(bytes FROM 3 FOR 32), 'hex')
+            FROM (SELECT decode(translate(address, '-_', '+/'), 'base64') AS bytes) decoded
+        )
+        ELSE address
+    END
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Portfolios stored under a user-friendly form are converted; where both
+-- forms had a row, the most recent one wins
+DELETE FROM portfolios p
+USING portfolios q
+WHERE p.token_address = q.token_address
+  AND p.user_address <> q.user_address
+  AND pg_temp.raw_ton_address(p.user_address) = pg_temp.raw_ton_address(q.user_address)
+  AND (p.last_updated_lt, COALESCE(p.updated_at, 'epoch'), p.ctid)
+      < (q.last_updated_lt, COALESCE(q.updated_at, 'epoch'), q.ctid);
+
+UPDATE portfolios
+SET user_address = pg_temp.raw_ton_address(user_address)
+WHERE user_address <> pg_temp.raw_ton_address(user_address);
+
+COMMENT ON COLUMN portfolios.user_address IS 'Raw address of the holder';
+
+CREATE TABLE IF NOT EXISTS token_balance_ledger (
+    id BIGSERIAL PRIMARY KEY,
+    token_address VARCHAR(255) NOT NULL,
+    user_address VARCHAR(255) NOT NULL,
+    lt BIGINT NOT NULL,
+    balance NUMERIC(78, 0) NOT NULL,
+    source VARCHAR(50) NOT NULL, -- chain, overdraft, purchase, backfill
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_token_balance_ledger_holder
+    ON token_balance_ledger(token_address, user_address, lt DESC, id DESC);
+
+COMMENT ON COLUMN token_balance_ledger.user_address IS 'Raw address of the holder';
+COMMENT ON COLUMN token_balance_ledger.lt IS 'Logical time of the change on chain; rows after a change indexed late are shifted by its amount';
+COMMENT ON COLUMN token_balance_ledger.balance IS 'Balance of the holder after the change';
+COMMENT ON COLUMN token_balance_ledger.source IS 'chain: indexed transfer or balance read from chain, overdraft: indexed debit of more than the ledger held, purchase: confirmed purchase credited before transfers were indexed, backfill: balance known when the ledger was created. Holders with purchase or overdraft rows missed changes; their history is only on chain';
+COMMENT ON COLUMN token_balance_ledger.occurred_at IS 'Chain time of the transaction that changed the balance';
+
+-- Seed the ledger with the balances known so far
+INSERT INTO token_balance_ledger (token_address, user_address, lt, balance, source, occurred_at, recorded_at)
+SELECT token_address, user_address, last_updated_lt, balance, 'backfill',
+       COALESCE(updated_at, CURRENT_TIMESTAMP), COALESCE(updated_at, CURRENT_TIMESTAMP)
+FROM portfolios;
+
+CREATE TABLE IF NOT EXISTS holder_snapshots (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    token_address VARCHAR(255) NOT NULL,
+    source VARCHAR(50) NOT NULL, -- ledger, chain
+    at_lt BIGINT,
+    at_time TIMESTAMP WITH TIME ZONE,
+    total_supply NUMERIC(78, 0) NOT NULL,
+    holder_count INT NOT NULL,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS holder_snapshot_entries (
+    snapshot_id UUID NOT NULL REFERENCES holder_snapshots(id) ON DELETE CASCADE,
+    user_address VARCHAR(255) NOT NULL,
+    balance NUMERIC(78, 0) NOT NULL,
+    lt BIGINT NOT NULL,
+    PRIMARY KEY (snapshot_id, user_address)
+);
+
+CREATE INDEX IF NOT EXISTS idx_holder_snapshots_token ON holder_snapshots(token_address, created_at DESC);
+
+COMMENT ON COLUMN holder_snapshots.at_lt IS 'Ledger snapshots: latest lt included, NULL for no limit';
+COMMENT ON COLUMN holder_snapshots.at_time IS 'Ledger snapshots: latest chain time included, NULL for no limit';
+COMMENT ON COLUMN holder_snapshot_entries.lt IS 'Ledger: lt of the balance change; chain: last transaction lt of the jetton wallet';
+
+ALTER TABLE distributions ADD COLUMN IF NOT EXISTS snapshot_id UUID REFERENCES holder_snapshots(id);
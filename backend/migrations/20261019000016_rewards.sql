@@ -0,0 +1,47 @@
+-- Claimable rewards: distributions in `claim` mode credit each holder with
+-- a reward instead of transferring it. A holder claims all unclaimed
+-- rewards at once with a single MKOIN transfer; unclaimed rewards expire
+-- after the campaign's claim period. Claims are settled from the admin
+-- wallet's history: a claim only fails, freeing its rewards, once its
+-- transfer bounced or can no longer land.
+
+ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS reward_claim_days INT DEFAULT 365;
+COMMENT ON COLUMN campaigns.reward_claim_days IS 'Days holders have to claim a reward of the campaign, NULL: rewards never expire';
+
+ALTER TABLE distributions ADD COLUMN IF NOT EXISTS payout_mode VARCHAR(50) NOT NULL DEFAULT 'transfer'; -- transfer, claim
+
+CREATE TABLE IF NOT EXISTS reward_claims (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_address VARCHAR(255) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending', -- pending, sent, confirmed, failed
+    msg_hash VARCHAR(255),
+    tx_hash VARCHAR(255),
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    sent_at TIMESTAMP WITH TIME ZONE,
+    confirmed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE TABLE IF NOT EXISTS rewards (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    distribution_id UUID NOT NULL REFERENCES distributions(id) ON DELETE CASCADE,
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    user_address VARCHAR(255) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'unclaimed', -- unclaimed, claiming, claimed, expired
+    claim_id UUID REFERENCES reward_claims(id),
+    expires_at TIMESTAMP WITH TIME ZONE,
+    claimed_at TIMESTAMP WITH TIME ZONE,
+    tx_hash VARCHAR(255),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (distribution_id, user_address)
+);
+
+CREATE INDEX IF NOT EXISTS idx_rewards_user ON rewards(user_address, status);
+CREATE INDEX IF NOT EXISTS idx_rewards_claim ON rewards(claim_id);
+CREATE INDEX IF NOT EXISTS idx_reward_claims_status ON reward_claims(status);
+
+COMMENT ON COLUMN rewards.status IS 'unclaimed: can be claimed, claiming: part of a claim being paid, claimed: paid, expired: claim period ended';
+COMMENT ON COLUMN rewards.tx_hash IS 'Transaction of the claim that paid the reward';
+COMMENT ON COLUMN reward_claims.status IS 'pending: being sent, or abandoned by a request that died and settled by query_id, sent: transfer broadcast or possibly broadcast, confirmed: excesses seen on chain, failed: not sent, bounced or expired without landing';
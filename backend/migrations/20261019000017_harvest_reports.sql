@@ -0,0 +1,38 @@
+-- Harvest reports: what a campaign season actually yielded, reported by the
+-- farmer and verified by an admin. Verified reports give the actual yield
+-- shown next to the campaign's target and can seed a draft distribution.
+
+ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS target_yield_bps INT;
+COMMENT ON COLUMN campaigns.target_yield_bps IS 'Yearly yield promised to investors, in basis points of the capital raised';
+
+CREATE TABLE IF NOT EXISTS harvest_reports (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    season VARCHAR(20) NOT NULL,
+    quantity NUMERIC(20, 3) NOT NULL,
+    quantity_unit VARCHAR(20) NOT NULL,
+    revenue NUMERIC(78, 0) NOT NULL,
+    costs NUMERIC(78, 0) NOT NULL,
+    evidence_keys TEXT[] NOT NULL DEFAULT '{}',
+    notes TEXT,
+    status VARCHAR(50) NOT NULL DEFAULT 'submitted', -- submitted, verified, rejected
+    invested NUMERIC(78, 0),
+    submitted_by UUID REFERENCES users(id),
+    reviewed_by UUID REFERENCES users(id),
+    review_note TEXT,
+    reviewed_at TIMESTAMP WITH TIME ZONE,
+    distribution_id UUID REFERENCES distributions(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+-- A season has one report under review or verified; rejected ones can be redone
+CREATE UNIQUE INDEX IF NOT EXISTS idx_harvest_reports_season
+    ON harvest_reports(campaign_id, season) WHERE status <> 'rejected';
+CREATE INDEX IF NOT EXISTS idx_harvest_reports_status ON harvest_reports(status);
+
+COMMENT ON COLUMN harvest_reports.revenue IS 'Sale revenue of the harvest in EUR, 9 decimals like MKOIN';
+COMMENT ON COLUMN harvest_reports.costs IS 'Costs of the season in EUR, 9 decimals like MKOIN';
+COMMENT ON COLUMN harvest_reports.evidence_keys IS 'Uploaded media (receipts, photos) backing the report';
+COMMENT ON COLUMN harvest_reports.invested IS 'MKOIN raised by the campaign when the report was verified, the base of the actual yield';
+
+COMMENT ON COLUMN distributions.status IS 'draft: awaiting release by an admin, created, processing, completed, partial';
@@ -0,0 +1,11 @@
+-- Merkle distributions: instead of one MKOIN transfer per holder, the payout
+-- lines become the leaves of a Merkle tree. The backend only computes the
+-- root and serves each holder's proof; the claim contract the holders claim
+-- from is deployed and funded outside the backend.
+
+ALTER TABLE distributions ADD COLUMN IF NOT EXISTS merkle_root VARCHAR(64);
+
+COMMENT ON COLUMN distributions.merkle_root IS 'Hex root of the payout tree of a merkle distribution, computed when released';
+COMMENT ON COLUMN distributions.payout_mode IS 'transfer: sent per holder, claim: credited as rewards, merkle: claimed on chain with proofs';
+COMMENT ON COLUMN distributions.status IS 'draft: awaiting release by an admin, created, processing, completed, partial, root_ready: merkle root computed, proofs served; the claim contract is deployed and funded outside the backend';
+COMMENT ON COLUMN distribution_payouts.status IS 'pending: not sent yet, sent: transfer broadcast or possibly broadcast, confirmed: excesses seen on chain, failed: not sent, bounced or expired without landing; sent again when the distribution is executed again. Leaves of merkle distributions stay pending';
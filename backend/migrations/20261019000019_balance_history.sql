@@ -0,0 +1,6 @@
+-- Historical balance queries: the balances of one address at an earlier
+-- lt or time are read from the ledger per holder, so it is indexed by
+-- holder as well as by token.
+
+CREATE INDEX IF NOT EXISTS idx_token_balance_ledger_user
+    ON token_balance_ledger(user_address, token_address, lt DESC, id DESC);
@@ -0,0 +1,39 @@
+-- Transfers seen by the chain indexer: MKOIN and campaign token credits
+-- decoded from jetton wallets, and TON transfers involving known addresses.
+-- Each is stored once, from the transaction of the receiving account, and
+-- serves the per-address transaction history.
+
+CREATE TABLE IF NOT EXISTS indexed_transfers (
+    id BIGSERIAL PRIMARY KEY,
+    tx_hash VARCHAR(64) UNIQUE NOT NULL,
+    lt BIGINT NOT NULL,
+    asset VARCHAR(50) NOT NULL, -- TON, MKOIN, campaign_token
+    token_address VARCHAR(255),
+    campaign_id UUID REFERENCES campaigns(id) ON DELETE SET NULL,
+    sender VARCHAR(255) NOT NULL,
+    recipient VARCHAR(255) NOT NULL,
+    amount NUMERIC(78, 0) NOT NULL,
+    is_mint BOOLEAN NOT NULL DEFAULT FALSE,
+    status VARCHAR(50) NOT NULL, -- completed, failed
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    indexed_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_indexed_transfers_sender ON indexed_transfers(sender, occurred_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_indexed_transfers_recipient ON indexed_transfers(recipient, occurred_at DESC, id DESC);
+
+COMMENT ON COLUMN indexed_transfers.tx_hash IS 'Hex hash of the transaction of the receiving account';
+COMMENT ON COLUMN indexed_transfers.sender IS 'Raw address of the sending owner; for jettons the owner of the sending wallet';
+COMMENT ON COLUMN indexed_transfers.recipient IS 'Raw address of the receiving owner';
+COMMENT ON COLUMN indexed_transfers.is_mint IS 'Jettons credited by the master itself rather than by another wallet';
+COMMENT ON COLUMN indexed_transfers.status IS 'completed: credited, failed: bounced back to the sender';
+
+-- Progress of the indexer, so a restart resumes after the last masterchain
+-- block it processed rather than at the head of the chain
+CREATE TABLE IF NOT EXISTS indexer_state (
+    id VARCHAR(50) PRIMARY KEY,
+    seqno BIGINT NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+COMMENT ON COLUMN indexer_state.seqno IS 'Last masterchain block whose shard blocks were all processed';
@@ -0,0 +1,40 @@
+-- Token pricing: every price a campaign token had is kept as a time series
+-- with its source. Each campaign picks the source its tokens are valued
+-- at; admins may publish a net asset value (NAV) and switch sources, both
+-- of which are audited.
+
+ALTER TABLE campaigns ADD COLUMN IF NOT EXISTS price_source VARCHAR(50) NOT NULL DEFAULT 'trade';
+
+COMMENT ON COLUMN campaigns.price_source IS 'sale: primary sale price, trade: last secondary trade, nav: admin-set NAV; missing prices fall back to the last trade, then the sale price';
+
+CREATE TABLE IF NOT EXISTS token_prices (
+    id BIGSERIAL PRIMARY KEY,
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    price NUMERIC(78, 0) NOT NULL, -- MKOIN nanocoins per token
+    source VARCHAR(50) NOT NULL, -- sale, trade, nav
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_token_prices_campaign ON token_prices(campaign_id, source, recorded_at DESC, id DESC);
+
+CREATE TABLE IF NOT EXISTS price_audit_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    campaign_id UUID NOT NULL REFERENCES campaigns(id) ON DELETE CASCADE,
+    action VARCHAR(50) NOT NULL, -- set_nav, set_source
+    previous_value VARCHAR(255),
+    new_value VARCHAR(255) NOT NULL,
+    actor_id UUID REFERENCES users(id),
+    note TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_price_audit_log_campaign ON price_audit_log(campaign_id, created_at DESC);
+
+-- Seed the series with the sale prices and the trades so far
+INSERT INTO token_prices (campaign_id, price, source, recorded_at)
+SELECT id, suggested_price, 'sale', COALESCE(created_at, CURRENT_TIMESTAMP)
+FROM campaigns;
+
+INSERT INTO token_prices (campaign_id, price, source, recorded_at)
+SELECT campaign_id, price, 'trade', COALESCE(created_at, CURRENT_TIMESTAMP)
+FROM market_trades;
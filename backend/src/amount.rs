@@ -0,0 +1,311 @@
+//! Fixed-point amounts of MKOIN and campaign jettons
+//!
+//! All jettons in the system use 9 decimals, so an amount is stored as an
+//! integer number of nano-units (1 MKOIN = 1_000_000_000 nanocoins). This is
+//! the representation used on chain and in the `NUMERIC(78, 0)` columns.
+
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef, Postgres};
+use std::fmt;
+use std::str::FromStr;
+
+/// Decimals of MKOIN and campaign tokens
+pub const DECIMALS: usize = 9;
+
+/// Nano-units per whole token
+pub const NANO_PER_UNIT: u128 = 1_000_000_000;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("Amount is empty")]
+    Empty,
+    #[error("Invalid amount '{0}'")]
+    Invalid(String),
+    #[error("Amount '{0}' has more than 9 decimals")]
+    TooManyDecimals(String),
+    #[error("Amount '{0}' is negative")]
+    Negative(String),
+    #[error("Amount overflow")]
+    Overflow,
+}
+
+/// An amount in nano-units (9 decimals)
+///
+/// Serializes as the integer nano-unit string (e.g. `"1500000000"`), matching
+/// how amounts are stored; `Display` gives the decimal form (`"1.500000000"`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TokenAmount(u128);
+
+impl TokenAmount {
+    pub const ZERO: TokenAmount = TokenAmount(0);
+
+    pub const fn from_nano(nano: u128) -> Self {
+        Self(nano)
+    }
+
+    pub const fn nano(self) -> u128 {
+        self.0
+    }
+
+    pub fn from_whole(units: u128) -> Result<Self, AmountError> {
+        units
+            .checked_mul(NANO_PER_UNIT)
+            .map(Self)
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Parse a decimal amount in whole tokens, e.g. `"12.5"` or `"0.000000001"`
+    pub fn parse_decimal(s: &str) -> Result<Self, AmountError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(AmountError::Empty);
+        }
+        if s.starts_with('-') {
+            return Err(AmountError::Negative(s.to_string()));
+        }
+
+        let (int_part, frac_part) = s.split_once('.').unwrap_or((s, ""));
+        let all_digits = |p: &str| p.bytes().all(|b| b.is_ascii_digit());
+        if (int_part.is_empty() && frac_part.is_empty())
+            || !all_digits(int_part)
+            || !all_digits(frac_part)
+        {
+            return Err(AmountError::Invalid(s.to_string()));
+        }
+
+        let frac_trimmed = frac_part.trim_end_matches('0');
+        if frac_trimmed.len() > DECIMALS {
+            return Err(AmountError::TooManyDecimals(s.to_string()));
+        }
+
+        let whole: u128 = if int_part.is_empty() {
+            0
+        } else {
+            int_part.parse().map_err(|_| AmountError::Overflow)?
+        };
+        let frac: u128 = if frac_trimmed.is_empty() {
+            0
+        } else {
+            format!("{:0<width$}", frac_trimmed, width = DECIMALS)
+                .parse()
+                .map_err(|_| AmountError::Invalid(s.to_string()))?
+        };
+
+        Self::from_whole(whole)?
+            .checked_add(Self(frac))
+            .ok_or(AmountError::Overflow)
+    }
+
+    /// Parse an integer amount already in nano-units
+    pub fn parse_nano(s: &str) -> Result<Self, AmountError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(AmountError::Empty);
+        }
+        if s.starts_with('-') {
+            return Err(AmountError::Negative(s.to_string()));
+        }
+        if !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(AmountError::Invalid(s.to_string()));
+        }
+        s.parse().map(Self).map_err(|_| AmountError::Overflow)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    pub fn checked_mul(self, factor: u128) -> Option<Self> {
+        self.0.checked_mul(factor).map(Self)
+    }
+
+    /// Sum amounts, failing on overflow
+    pub fn checked_sum<I: IntoIterator<Item = Self>>(amounts: I) -> Option<Self> {
+        amounts
+            .into_iter()
+            .try_fold(Self::ZERO, |acc, a| acc.checked_add(a))
+    }
+
+    /// Cost of `self` tokens at `price` per whole token, rounded up
+    pub fn cost_at(self, price: Self) -> Option<Self> {
+        // (whole + frac / 1e9) * price, split so large amounts do not overflow
+        let whole = (self.0 / NANO_PER_UNIT).checked_mul(price.0)?;
+        let frac = (self.0 % NANO_PER_UNIT).checked_mul(price.0)?;
+        whole.checked_add(frac.div_ceil(NANO_PER_UNIT)).map(Self)
+    }
+
+    /// Tokens that `self` buys at `price` per whole token, rounded down
+    pub fn tokens_at(self, price: Self) -> Option<Self> {
+        if price.is_zero() {
+            return None;
+        }
+        self.0.checked_mul(NANO_PER_UNIT).map(|n| Self(n / price.0))
+    }
+
+    /// Whole units as a float, for display only
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / NANO_PER_UNIT as f64
+    }
+
+    pub fn to_bigdecimal(self) -> BigDecimal {
+        BigDecimal::from(self.0)
+    }
+}
+
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.0 / NANO_PER_UNIT,
+            self.0 % NANO_PER_UNIT,
+            width = DECIMALS
+        )
+    }
+}
+
+impl FromStr for TokenAmount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_decimal(s)
+    }
+}
+
+impl TryFrom<&BigDecimal> for TokenAmount {
+    type Error = AmountError;
+
+    /// Convert a nano-unit `NUMERIC` value
+    fn try_from(value: &BigDecimal) -> Result<Self, Self::Error> {
+        if !value.is_integer() {
+            return Err(AmountError::Invalid(value.to_string()));
+        }
+        let (digits, _) = value.with_scale(0).into_bigint_and_exponent();
+        Self::parse_nano(&digits.to_string())
+    }
+}
+
+impl Serialize for TokenAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenAmount {
+    /// Accepts nano-units as a string or an integer
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Str(String),
+            Int(u64),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Str(s) => Self::parse_nano(&s).map_err(serde::de::Error::custom),
+            Repr::Int(n) => Ok(Self(n as u128)),
+        }
+    }
+}
+
+impl sqlx::Type<Postgres> for TokenAmount {
+    fn type_info() -> PgTypeInfo {
+        <BigDecimal as sqlx::Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <BigDecimal as sqlx::Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl sqlx::Encode<'_, Postgres> for TokenAmount {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        <BigDecimal as sqlx::Encode<Postgres>>::encode(self.to_bigdecimal(), buf)
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Postgres> for TokenAmount {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let decimal = <BigDecimal as sqlx::Decode<Postgres>>::decode(value)?;
+        Ok(Self::try_from(&decimal)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(TokenAmount::parse_decimal("1").unwrap().nano(), 1_000_000_000);
+        assert_eq!(TokenAmount::parse_decimal("0.1").unwrap().nano(), 100_000_000);
+        assert_eq!(TokenAmount::parse_decimal(".5").unwrap().nano(), 500_000_000);
+        assert_eq!(TokenAmount::parse_decimal("2.000000001").unwrap().nano(), 2_000_000_001);
+        assert_eq!(TokenAmount::parse_decimal("3.1000000000").unwrap().nano(), 3_100_000_000);
+        assert_eq!(
+            TokenAmount::parse_decimal("0.0000000001"),
+            Err(AmountError::TooManyDecimals("0.0000000001".to_string()))
+        );
+        assert!(matches!(TokenAmount::parse_decimal("-1"), Err(AmountError::Negative(_))));
+        assert!(matches!(TokenAmount::parse_decimal("1e9"), Err(AmountError::Invalid(_))));
+        assert!(matches!(TokenAmount::parse_decimal("."), Err(AmountError::Invalid(_))));
+        assert_eq!(
+            TokenAmount::parse_decimal("999999999999999999999999999999999999999"),
+            Err(AmountError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(TokenAmount::from_nano(1_500_000_000).to_string(), "1.500000000");
+        assert_eq!(TokenAmount::from_nano(1).to_string(), "0.000000001");
+        // Beyond f64 precision
+        let big = TokenAmount::parse_decimal("123456789012345678.123456789").unwrap();
+        assert_eq!(big.to_string(), "123456789012345678.123456789");
+    }
+
+    #[test]
+    fn test_cost_at() {
+        let price = TokenAmount::parse_decimal("0.1").unwrap();
+        let tokens = TokenAmount::parse_decimal("25").unwrap();
+        assert_eq!(tokens.cost_at(price).unwrap(), TokenAmount::parse_decimal("2.5").unwrap());
+        // Fractions of a nanocoin are rounded up
+        assert_eq!(TokenAmount::from_nano(1).cost_at(price).unwrap().nano(), 1);
+    }
+
+    #[test]
+    fn test_tokens_at() {
+        let price = TokenAmount::parse_decimal("3").unwrap();
+        let paid = TokenAmount::parse_decimal("10").unwrap();
+        // Rounded down so the buyer never gets more than they paid for
+        assert_eq!(paid.tokens_at(price).unwrap().nano(), 3_333_333_333);
+        assert!(paid.tokens_at(price).unwrap().cost_at(price).unwrap() <= paid);
+        assert_eq!(paid.tokens_at(TokenAmount::ZERO), None);
+    }
+
+    #[test]
+    fn test_serde_and_bigdecimal() {
+        let amount = TokenAmount::from_nano(42);
+        assert_eq!(serde_json::to_string(&amount).unwrap(), "\"42\"");
+        assert_eq!(serde_json::from_str::<TokenAmount>("\"42\"").unwrap(), amount);
+        assert_eq!(serde_json::from_str::<TokenAmount>("42").unwrap(), amount);
+        assert!(serde_json::from_str::<TokenAmount>("\"4.2\"").is_err());
+
+        assert_eq!(TokenAmount::try_from(&amount.to_bigdecimal()).unwrap(), amount);
+        assert!(TokenAmount::try_from(&BigDecimal::from_str("1.5").unwrap()).is_err());
+    }
+}
@@ -1,13 +1,20 @@
+use super::presale::{parse_presale_price, validate_presale};
 use super::{check_admin_role, get_current_user};
+use crate::amount::TokenAmount;
 use crate::api::AppState;
+use crate::api::idempotency::idempotent;
+use crate::auth::Claims;
 use crate::db::Campaign;
+use crate::db::harvests::SeasonYield;
+use crate::db::presale::address_variants;
+use crate::ton::factory_service::jetton_metadata_url;
 use axum::{
     Json,
     extract::{Path, State},
     http::{HeaderMap, StatusCode},
 };
-use bigdecimal::BigDecimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -24,13 +31,89 @@ pub struct CreateCampaignRequest {
     pub start_time: chrono::DateTime<chrono::Utc>,
     pub end_time: chrono::DateTime<chrono::Utc>,
     pub suggested_price: String, // Decimal as string
+    #[serde(flatten)]
+    pub limits: CampaignLimitsRequest,
+    pub presale_start_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub presale_price: Option<String>, // Decimal as string
 }
 
-#[derive(Debug, Deserialize)]
+/// A campaign as seen by the caller
+#[derive(Debug, Serialize)]
+pub struct CampaignView {
+    #[serde(flatten)]
+    pub campaign: Campaign,
+    /// Whether the caller may buy during the presale; None if there is no presale
+    pub presale_eligible: Option<bool>,
+    /// Yield the campaign aims for, in basis points
+    pub target_yield_bps: Option<i32>,
+    /// Actual yield of each season with a verified harvest report, latest first
+    pub yields: Vec<SeasonYield>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateCampaignStatusRequest {
     pub status: String,
 }
 
+/// Sale limits in MKOIN nanocoins (integer strings); omitted = no limit
+#[derive(Debug, Default, Deserialize)]
+pub struct CampaignLimitsRequest {
+    pub soft_cap: Option<String>,
+    pub hard_cap: Option<String>,
+    pub min_ticket: Option<String>,
+    pub max_ticket: Option<String>,
+    pub max_per_investor: Option<String>,
+}
+
+struct CampaignLimits {
+    soft_cap: Option<TokenAmount>,
+    hard_cap: Option<TokenAmount>,
+    min_ticket: Option<TokenAmount>,
+    max_ticket: Option<TokenAmount>,
+    max_per_investor: Option<TokenAmount>,
+}
+
+fn parse_nanocoins(field: &str, value: Option<&String>) -> Result<Option<TokenAmount>, (StatusCode, String)> {
+    let Some(value) = value else { return Ok(None) };
+    let amount = TokenAmount::parse_nano(value)
+        .ok()
+        .filter(|v| !v.is_zero())
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid {}: expected a positive amount in nanocoins", field),
+        ))?;
+    Ok(Some(amount))
+}
+
+impl CampaignLimitsRequest {
+    fn parse(&self) -> Result<CampaignLimits, (StatusCode, String)> {
+        let limits = CampaignLimits {
+            soft_cap: parse_nanocoins("soft_cap", self.soft_cap.as_ref())?,
+            hard_cap: parse_nanocoins("hard_cap", self.hard_cap.as_ref())?,
+            min_ticket: parse_nanocoins("min_ticket", self.min_ticket.as_ref())?,
+            max_ticket: parse_nanocoins("max_ticket", self.max_ticket.as_ref())?,
+            max_per_investor: parse_nanocoins("max_per_investor", self.max_per_investor.as_ref())?,
+        };
+
+        let out_of_order = |lo: &Option<TokenAmount>, hi: &Option<TokenAmount>| {
+            matches!((lo, hi), (Some(lo), Some(hi)) if lo > hi)
+        };
+        if out_of_order(&limits.soft_cap, &limits.hard_cap) {
+            return Err((StatusCode::BAD_REQUEST, "soft_cap exceeds hard_cap".to_string()));
+        }
+        if out_of_order(&limits.min_ticket, &limits.max_ticket) {
+            return Err((StatusCode::BAD_REQUEST, "min_ticket exceeds max_ticket".to_string()));
+        }
+        if out_of_order(&limits.min_ticket, &limits.max_per_investor) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "min_ticket exceeds max_per_investor".to_string(),
+            ));
+        }
+        Ok(limits)
+    }
+}
+
 pub async fn request_campaign(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -40,8 +123,11 @@ pub async fn request_campaign(
     // Farmer or Admin can request
     let farmer_id = Uuid::from_str(&claims.sub).unwrap_or_default();
 
-    let price = BigDecimal::from_str(&payload.suggested_price)
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid price format".to_string()))?;
+    let price = TokenAmount::parse_decimal(&payload.suggested_price)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid price: {}", e)))?;
+    let limits = payload.limits.parse()?;
+    let presale_price = parse_presale_price(payload.presale_price.as_deref())?;
+    validate_presale(payload.presale_start_time, presale_price, payload.start_time)?;
 
     let campaign = Campaign {
         id: Uuid::new_v4(),
@@ -62,6 +148,13 @@ pub async fn request_campaign(
         minted_at: None,
         mint_amount: None,
         mint_tx_hash: None,
+        soft_cap: limits.soft_cap,
+        hard_cap: limits.hard_cap,
+        min_ticket: limits.min_ticket,
+        max_ticket: limits.max_ticket,
+        max_per_investor: limits.max_per_investor,
+        presale_start_time: payload.presale_start_time,
+        presale_price,
     };
 
     let id = state
@@ -80,7 +173,7 @@ pub async fn request_campaign(
 pub async fn list_campaigns(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-) -> Result<Json<Vec<Campaign>>, (StatusCode, String)> {
+) -> Result<Json<Vec<CampaignView>>, (StatusCode, String)> {
     let claims = get_current_user(&headers).await?;
 
     // Admin/Superadmin see all, Farmer sees only theirs
@@ -94,61 +187,234 @@ pub async fn list_campaigns(
     let cache_key = format!("campaigns:list:{:?}", farmer_id_filter);
 
     // Try to get from cache
-    if let Some(cached) = state.cache.get_cached::<Vec<Campaign>>(&cache_key).await {
-        return Ok(Json(cached));
+    let campaigns = match state.cache.get_cached::<Vec<Campaign>>(&cache_key).await {
+        Some(cached) => cached,
+        None => {
+            let campaigns = state
+                .db
+                .list_campaigns(None, farmer_id_filter)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+            // Set cache (TTL 60 seconds)
+            state.cache.set_cached(&cache_key, &campaigns, 60).await;
+            campaigns
+        }
+    };
+
+    // Eligibility depends on the caller, so it is never cached
+    let eligible = caller_allowlists(&state, &claims, &headers).await?;
+    let ids: Vec<Uuid> = campaigns.iter().map(|c| c.id).collect();
+    let mut yields = campaign_yields(&state, &ids).await?;
+    Ok(Json(
+        campaigns
+            .into_iter()
+            .map(|c| view_for(c, &eligible, &mut yields))
+            .collect(),
+    ))
+}
+
+pub async fn get_campaign(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<CampaignView>, (StatusCode, String)> {
+    let claims = get_current_user(&headers).await?;
+
+    let cache_key = format!("campaigns:id:{}", id);
+    let campaign = match state.cache.get_cached::<Campaign>(&cache_key).await {
+        Some(cached) => cached,
+        None => {
+            let campaign = state
+                .db
+                .get_campaign(id)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+
+            state.cache.set_cached(&cache_key, &campaign, 300).await; // 5 min TTL for individual campaign
+            campaign
+        }
+    };
+
+    // Check ownership: farmers can only see their own campaigns (cached ones too)
+    if !check_admin_role(&claims.role) {
+        let user_id = Uuid::from_str(&claims.sub).unwrap_or_default();
+        if campaign.farmer_id != user_id {
+            return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+        }
     }
 
-    let campaigns = state
+    let eligible = caller_allowlists(&state, &claims, &headers).await?;
+    let mut yields = campaign_yields(&state, &[campaign.id]).await?;
+    Ok(Json(view_for(campaign, &eligible, &mut yields)))
+}
+
+fn view_for(
+    campaign: Campaign,
+    eligible: &HashSet<Uuid>,
+    yields: &mut HashMap<Uuid, (Option<i32>, Vec<SeasonYield>)>,
+) -> CampaignView {
+    let presale_eligible = campaign
+        .presale_start_time
+        .map(|_| eligible.contains(&campaign.id));
+    let (target_yield_bps, yields) = yields.remove(&campaign.id).unwrap_or_default();
+    CampaignView {
+        campaign,
+        presale_eligible,
+        target_yield_bps,
+        yields,
+    }
+}
+
+/// Target and actual yields, read fresh so a verified harvest shows at once
+async fn campaign_yields(
+    state: &AppState,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, (Option<i32>, Vec<SeasonYield>)>, (StatusCode, String)> {
+    state
         .db
-        .list_campaigns(None, farmer_id_filter)
+        .campaign_yields(ids)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
 
-    // Set cache (TTL 60 seconds)
-    state.cache.set_cached(&cache_key, &campaigns, 60).await;
+/// Campaigns whose allowlist contains the caller
+///
+/// The caller is matched by user id, by their registered address and by the
+/// optional `X-User-Address` header (the wallet they are connected with).
+async fn caller_allowlists(
+    state: &AppState,
+    claims: &Claims,
+    headers: &HeaderMap,
+) -> Result<HashSet<Uuid>, (StatusCode, String)> {
+    let user_id = Uuid::from_str(&claims.sub).ok();
+
+    let mut addresses = Vec::new();
+    if let Some(address) = headers.get("X-User-Address").and_then(|v| v.to_str().ok()) {
+        addresses.extend(address_variants(address));
+    }
+    if let Some(id) = user_id
+        && let Some(user) = state
+            .db
+            .get_user_by_id(id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        addresses.extend(address_variants(&user.address));
+    }
 
-    Ok(Json(campaigns))
+    state
+        .db
+        .allowlisted_campaigns(&addresses, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
 }
 
-pub async fn get_campaign(
+/// Set or clear the sale limits of a campaign
+///
+/// PUT /campaigns/:id/limits
+pub async fn update_campaign_limits(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     Path(id): Path<Uuid>,
-) -> Result<Json<Campaign>, (StatusCode, String)> {
+    Json(payload): Json<CampaignLimitsRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let claims = get_current_user(&headers).await?;
-
-    let cache_key = format!("campaigns:id:{}", id);
-    if let Some(cached) = state.cache.get_cached::<Campaign>(&cache_key).await {
-        // Check ownership for cached campaigns too
-        if !check_admin_role(&claims.role) {
-            let user_id = Uuid::from_str(&claims.sub).unwrap_or_default();
-            if cached.farmer_id != user_id {
-                return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
-            }
-        }
-        return Ok(Json(cached));
+    if !check_admin_role(&claims.role) {
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
     }
 
-    let campaign = state
+    let limits = payload.parse()?;
+
+    state
         .db
         .get_campaign(id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
 
-    // Check ownership: farmers can only see their own campaigns
+    state
+        .db
+        .update_campaign_limits(
+            id,
+            limits.soft_cap,
+            limits.hard_cap,
+            limits.min_ticket,
+            limits.max_ticket,
+            limits.max_per_investor,
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state
+        .cache
+        .invalidate(&format!("campaigns:id:{}", id))
+        .await;
+    state.cache.invalidate_pattern("campaigns:list:*").await;
+    state
+        .cache
+        .invalidate(&format!("campaign:stats:{}", id))
+        .await;
+
+    Ok(Json(serde_json::json!({ "status": "updated" })))
+}
+
+/// Wallet holding a campaign's raised MKOIN: its treasury, else the
+/// platform wallet
+pub(crate) async fn campaign_treasury(state: &AppState, campaign_id: Uuid) -> anyhow::Result<String> {
+    Ok(state
+        .db
+        .get_campaign_treasury(campaign_id)
+        .await?
+        .unwrap_or_else(|| state.mkoin_service.get_admin_address()))
+}
+
+/// Omitting `address` moves the campaign back to the platform wallet
+#[derive(Debug, Deserialize)]
+pub struct CampaignTreasuryRequest {
+    pub address: Option<String>,
+}
+
+/// Set the wallet that receives a campaign's sale proceeds and pays its
+/// refunds; it must be a wallet the platform signs for
+///
+/// PUT /campaigns/:id/treasury
+pub async fn update_campaign_treasury(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CampaignTreasuryRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let claims = get_current_user(&headers).await?;
     if !check_admin_role(&claims.role) {
-        let user_id = Uuid::from_str(&claims.sub).unwrap_or_default();
-        if campaign.farmer_id != user_id {
-            return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
-        }
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
     }
 
-    state.cache.set_cached(&cache_key, &campaign, 300).await; // 5 min TTL for individual campaign
+    let address = payload.address.as_deref().map(str::trim).filter(|a| !a.is_empty());
+    if let Some(address) = address
+        && !state.mkoin_service.controls(address)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} is not a wallet the platform signs for", address),
+        ));
+    }
 
-    Ok(Json(campaign))
+    let updated = state
+        .db
+        .set_campaign_treasury(id, address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !updated {
+        return Err((StatusCode::NOT_FOUND, "Campaign not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({ "status": "updated", "treasury_address": address })))
 }
 
+/// Approving deploys the campaign jetton, so this honours `Idempotency-Key`
+/// to keep a repeated approval from deploying it twice
 pub async fn update_campaign_status(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -160,6 +426,22 @@ pub async fn update_campaign_status(
         return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
     }
 
+    idempotent(
+        &state,
+        &headers,
+        "campaign_status",
+        &claims.sub,
+        &(id, &payload),
+        apply_campaign_status(&state, id, &payload),
+    )
+    .await
+}
+
+async fn apply_campaign_status(
+    state: &AppState,
+    id: Uuid,
+    payload: &UpdateCampaignStatusRequest,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     state
         .db
         .update_campaign_status(id, &payload.status)
@@ -189,42 +471,26 @@ pub async fn update_campaign_status(
                 "Campaign not found after update".to_string(),
             ))?;
 
-        // Get farmer user info to get their TON address
-        let farmer = state
-            .db
-            .get_user_by_id(campaign.farmer_id)
-            .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-            .ok_or((StatusCode::NOT_FOUND, "Farmer not found".to_string()))?;
-
-        // Parse token supply
-        let supply: u128 = campaign
-            .token_supply
-            .parse()
-            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid token supply".to_string()))?;
-
-        // Convert to nanocoins (multiply by 10^9)
-        let supply_nanocoins = supply * 1_000_000_000;
-
-        // Fallback for test/seed data addresses (EVM style)
-        let farmer_address = if farmer.address.starts_with("0x") {
-            println!(
-                "WARN: Farmer address is EVM-style ({}), using Factory address as fallback owner",
-                farmer.address
-            );
-            "EQBY-OWwam2n7DO25xV7juUWS9MV9xjJ1bwL1dISkYDNcGP2" // Factory address
-        } else {
-            &farmer.address
-        };
+        // Token supply is given in whole tokens
+        let supply = TokenAmount::parse_decimal(&campaign.token_supply)
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid token supply: {}", e)))?;
+
+        // The platform wallet holds the supply and delivers purchased
+        // tokens from it (see `api::settlement`)
+        let supply_wallet = state.mkoin_service.get_admin_address();
+
+        // Jetton content points at our metadata endpoint, keyed by campaign id
+        let metadata_url = jetton_metadata_url(id);
 
         // Call Factory service to create campaign token
         let result = state
             .factory_service
             .create_campaign_token(
-                farmer_address,
+                &supply_wallet,
                 &campaign.token_name,
                 &campaign.token_symbol,
-                supply_nanocoins,
+                supply,
+                &metadata_url,
             )
             .await
             .map_err(|e| {
@@ -255,7 +521,7 @@ pub async fn update_campaign_status(
         // Record the mint transaction info in campaign table
         state
             .db
-            .update_campaign_mint_info(id, &supply.to_string(), Some(&tx_hash))
+            .update_campaign_mint_info(id, supply, Some(&tx_hash))
             .await
             .map_err(|e| {
                 (
@@ -265,10 +531,9 @@ pub async fn update_campaign_status(
             })?;
 
         // Also record this as a mint event in campaign_token_mints table
-        // This represents the initial supply being minted to the farmer
         state
             .db
-            .record_campaign_mint(id, &farmer.address, &supply.to_string(), Some(&tx_hash))
+            .record_campaign_mint(id, &supply_wallet, supply, Some(&tx_hash))
             .await
             .map_err(|e| {
                 (
@@ -283,9 +548,9 @@ pub async fn update_campaign_status(
             .upsert_token_minter(
                 &token_address,
                 Some(&campaign.token_symbol),
-                None, // Metadata URL TODO
+                Some(&metadata_url),
                 true, // is_agri_token
-                Some(bigdecimal::BigDecimal::from_str(&campaign.token_supply).unwrap_or_default()),
+                Some(supply),
                 Some(id),
             )
             .await
@@ -0,0 +1,241 @@
+use crate::api::AppState;
+use crate::api::admin::require_admin;
+use crate::api::deposits::deposit_error;
+use crate::db::deposits::{BankPayment, BankStatement, DepositIntent};
+use crate::statement::{StatementFormat, parse_statement};
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post, put},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+const MAX_STATEMENT_BYTES: usize = 10 * 1024 * 1024;
+const DEFAULT_LIST_LIMIT: i64 = 100;
+
+#[derive(Debug, Serialize)]
+pub struct StatementImportResponse {
+    pub statement: BankStatement,
+    pub payments: Vec<BankPayment>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignPaymentRequest {
+    pub deposit_id: Uuid,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DismissPaymentRequest {
+    pub note: Option<String>,
+}
+
+pub fn deposit_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/admin/bank-statements",
+            // Leave headroom for multipart framing; the file itself is checked below
+            post(import_statement).layer(DefaultBodyLimit::max(MAX_STATEMENT_BYTES + 64 * 1024)),
+        )
+        .route("/admin/bank-statements/{id}", get(get_statement))
+        .route("/admin/bank-payments", get(list_payments))
+        .route("/admin/bank-payments/{id}/assign", put(assign_payment))
+        .route("/admin/bank-payments/{id}/dismiss", put(dismiss_payment))
+        .route("/admin/deposits", get(list_deposits))
+        .route("/admin/deposits/{id}/retry-mint", put(retry_mint))
+}
+
+/// Import a bank statement and match its payments to deposits
+///
+/// POST /admin/bank-statements (multipart/form-data, field "file" and an
+/// optional "format" of "camt053" or "csv"; detected from the file if absent)
+async fn import_statement(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<StatementImportResponse>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+
+    let mut file: Option<(Option<String>, Vec<u8>)> = None;
+    let mut format: Option<String> = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid multipart body: {}", e)))?
+    {
+        match field.name() {
+            Some("file") => {
+                let file_name = field.file_name().map(str::to_string);
+                let bytes = field.bytes().await.map_err(|e| {
+                    (StatusCode::PAYLOAD_TOO_LARGE, format!("Failed to read upload: {}", e))
+                })?;
+                file = Some((file_name, bytes.to_vec()));
+            }
+            Some("format") => {
+                format = Some(field.text().await.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?);
+            }
+            _ => {}
+        }
+    }
+
+    let (file_name, bytes) = file.ok_or((StatusCode::BAD_REQUEST, "Missing 'file' field".to_string()))?;
+    if bytes.len() > MAX_STATEMENT_BYTES {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("File exceeds {} bytes", MAX_STATEMENT_BYTES),
+        ));
+    }
+    let data = String::from_utf8(bytes)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Statement is not UTF-8 text".to_string()))?;
+    let format = match format.as_deref().map(str::trim) {
+        Some("camt053") => StatementFormat::Camt053,
+        Some("csv") => StatementFormat::Csv,
+        Some(other) => {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown statement format '{}'", other)));
+        }
+        None if data.trim_start().starts_with('<') => StatementFormat::Camt053,
+        None => StatementFormat::Csv,
+    };
+
+    let entries = parse_statement(format, &data).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let statement = state
+        .db
+        .import_bank_statement(format, file_name.as_deref(), admin_id, &entries)
+        .await
+        .map_err(|e| deposit_error(e, "import statement"))?;
+    info!(
+        "Imported bank statement {}: {} matched, {} to review, {} already imported",
+        statement.id, statement.matched_count, statement.review_count, statement.duplicate_count
+    );
+
+    let payments = state
+        .db
+        .get_statement_payments(statement.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(StatementImportResponse { statement, payments }))
+}
+
+/// GET /admin/bank-statements/:id
+async fn get_statement(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<StatementImportResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    let statement = state
+        .db
+        .get_bank_statement(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Bank statement not found".to_string()))?;
+    let payments = state
+        .db
+        .get_statement_payments(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(StatementImportResponse { statement, payments }))
+}
+
+/// Bank payments by status; the default `review` is the review queue of
+/// unmatched, partial and otherwise doubtful payments
+///
+/// GET /admin/bank-payments?status=review
+async fn list_payments(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<Vec<BankPayment>>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    let status = query.status.as_deref().unwrap_or("review");
+    let payments = state
+        .db
+        .get_bank_payments_by_status(status, DEFAULT_LIST_LIMIT)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(payments))
+}
+
+/// Credit a reviewed payment to a deposit; the amount received is minted
+///
+/// PUT /admin/bank-payments/:id/assign
+/// Body: { "deposit_id": "...", "note": "..." }
+async fn assign_payment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<AssignPaymentRequest>,
+) -> Result<Json<BankPayment>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let payment = state
+        .db
+        .assign_bank_payment(id, req.deposit_id, admin_id, req.note.as_deref())
+        .await
+        .map_err(|e| deposit_error(e, "assign payment"))?;
+    info!("Bank payment {} assigned to deposit {} by {:?}", id, req.deposit_id, admin_id);
+    Ok(Json(payment))
+}
+
+/// Close a reviewed payment without minting, e.g. after returning it
+///
+/// PUT /admin/bank-payments/:id/dismiss
+async fn dismiss_payment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<DismissPaymentRequest>>,
+) -> Result<Json<BankPayment>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let Json(req) = payload.unwrap_or_default();
+    let payment = state
+        .db
+        .dismiss_bank_payment(id, admin_id, req.note.as_deref())
+        .await
+        .map_err(|e| deposit_error(e, "dismiss payment"))?;
+    info!("Bank payment {} dismissed by {:?}", id, admin_id);
+    Ok(Json(payment))
+}
+
+/// GET /admin/deposits?status=mint_failed
+async fn list_deposits(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<Vec<DepositIntent>>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    let status = query.status.as_deref().unwrap_or("mint_failed");
+    let deposits = state
+        .db
+        .get_deposit_intents_by_status(status, DEFAULT_LIST_LIMIT)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(deposits))
+}
+
+/// Queue a failed mint again once it is certain it did not land
+///
+/// PUT /admin/deposits/:id/retry-mint
+async fn retry_mint(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DepositIntent>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let deposit = state
+        .db
+        .retry_failed_deposit_mint(id)
+        .await
+        .map_err(|e| deposit_error(e, "retry mint"))?;
+    info!("Deposit {} mint requeued by {:?}", id, admin_id);
+    Ok(Json(deposit))
+}
+
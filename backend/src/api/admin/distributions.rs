@@ -0,0 +1,317 @@
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::admin::snapshots::load_snapshot;
+use crate::api::admin::require_admin;
+use crate::db::distributions::{Distribution, DistributionPayout, DistributionPlan, Holding, PayoutMode, pro_rata};
+use crate::db::rewards::Reward;
+use crate::db::snapshots::SnapshotEntry;
+use crate::ton::address_utils::to_raw_address;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistributionRequest {
+    pub target_token: String,
+    pub amount_mkoin: String, // in MKOIN, up to 9 decimals
+    /// Holder snapshot to share by; the current ledger holders by default
+    pub snapshot_id: Option<Uuid>,
+    /// "transfer" (default) sends the shares, "claim" credits them as rewards,
+    /// "merkle" only computes the root for a claim contract run outside the
+    /// backend (see `check_payout_mode`)
+    #[serde(default)]
+    pub mode: PayoutMode,
+    /// Only compute the payouts, nothing is stored or sent
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DistributionPreview {
+    pub campaign_id: Uuid,
+    pub token_address: String,
+    pub snapshot_id: Option<Uuid>,
+    pub mode: PayoutMode,
+    #[serde(flatten)]
+    pub plan: DistributionPlan,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notice: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DistributionResponse {
+    pub distribution: Distribution,
+    pub payouts: Vec<DistributionPayout>,
+    /// Claim distributions only
+    pub rewards: Vec<Reward>,
+    /// Merkle distributions only: `MERKLE_NOTICE`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notice: Option<&'static str>,
+}
+
+/// Sent along with every merkle distribution
+pub const MERKLE_NOTICE: &str = "Payouts of merkle distributions are not executed: the backend only computes \
+     the root and serves proofs. Deploying, funding and updating the claim contract is up to the operator.";
+
+/// Merkle distributions pay nobody unless the operator runs a claim
+/// contract, so they can only be created with `MERKLE_DISTRIBUTIONS=true`
+pub(crate) fn check_payout_mode(mode: PayoutMode) -> Result<(), (StatusCode, String)> {
+    let enabled = std::env::var("MERKLE_DISTRIBUTIONS").is_ok_and(|v| matches!(v.as_str(), "true" | "1"));
+    if mode == PayoutMode::Merkle && !enabled {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Merkle distributions are disabled. {}", MERKLE_NOTICE),
+        ));
+    }
+    Ok(())
+}
+
+pub fn distribution_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/distribution", post(create_distribution))
+        .route("/admin/distributions/{id}", get(get_distribution).delete(discard_distribution))
+        .route("/admin/distributions/{id}/execute", post(execute_distribution))
+}
+
+/// Share an MKOIN amount among the holders of a campaign token
+///
+/// POST /admin/distribution
+/// Body: { "target_token": "EQ...", "amount_mkoin": "1000", "snapshot_id": null, "mode": "transfer", "dry_run": true }
+///
+/// Without a `snapshot_id` the current holders are snapshotted from the
+/// balance ledger when the distribution is created. The platform wallet's
+/// own balance is left out. `dry_run` previews the payouts.
+async fn create_distribution(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<DistributionRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    check_payout_mode(req.mode)?;
+
+    let amount = TokenAmount::parse_decimal(&req.amount_mkoin)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if amount.is_zero() {
+        return Err((StatusCode::BAD_REQUEST, "Amount must be greater than 0".to_string()));
+    }
+    let campaign = state
+        .db
+        .get_campaign_by_token_address(&req.target_token)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "No campaign issued this token".to_string()))?;
+
+    let entries = snapshot_holders(&state, &req.target_token, req.snapshot_id).await?;
+    let plan = plan_shares(&state, amount, &entries)?;
+
+    if req.dry_run {
+        return Ok(Json(serde_json::to_value(DistributionPreview {
+            campaign_id: campaign.id,
+            token_address: req.target_token,
+            snapshot_id: req.snapshot_id,
+            mode: req.mode,
+            plan,
+            notice: (req.mode == PayoutMode::Merkle).then_some(MERKLE_NOTICE),
+        })
+        .unwrap_or_default()));
+    }
+
+    let snapshot_id = persist_snapshot(&state, &req.target_token, req.snapshot_id, &entries, admin_id).await?;
+    let id = state
+        .db
+        .create_distribution(campaign.id, &req.target_token, &plan, req.mode, Some(snapshot_id), admin_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    info!(
+        "Created {} distribution {} of {} MKOIN to {} holders of {} ({} nanocoins remainder)",
+        req.mode.as_str(),
+        id,
+        amount,
+        plan.payouts.len(),
+        req.target_token,
+        plan.remainder.nano()
+    );
+    if req.mode == PayoutMode::Transfer {
+        start_distribution(&state, id).await?;
+    }
+
+    let response = load_distribution(&state, id).await?;
+    Ok(Json(serde_json::to_value(response).unwrap_or_default()))
+}
+
+/// GET /admin/distributions/:id
+async fn get_distribution(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DistributionResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    Ok(Json(load_distribution(&state, id).await?))
+}
+
+/// Release a draft, or resume a distribution; its payouts, failed ones
+/// included, are then sent and confirmed by the distribution worker
+///
+/// POST /admin/distributions/:id/execute
+async fn execute_distribution(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DistributionResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    let existing = load_distribution(&state, id).await?;
+    let sends_transfers = existing.distribution.payout_mode == PayoutMode::Transfer.as_str();
+
+    if existing.distribution.status == "draft" {
+        let released = state
+            .db
+            .release_distribution(id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if !released {
+            return Err((StatusCode::CONFLICT, "Distribution was released already".to_string()));
+        }
+        info!("Released draft distribution {}", id);
+    } else if !sends_transfers {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Shares of a {} distribution are claimed by the holders", existing.distribution.payout_mode),
+        ));
+    }
+
+    if sends_transfers {
+        start_distribution(&state, id).await?;
+    }
+    Ok(Json(load_distribution(&state, id).await?))
+}
+
+/// Discard a draft distribution
+///
+/// DELETE /admin/distributions/:id
+async fn discard_distribution(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    load_distribution(&state, id).await?;
+    let deleted = state
+        .db
+        .delete_draft_distribution(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !deleted {
+        return Err((StatusCode::CONFLICT, "Only draft distributions can be discarded".to_string()));
+    }
+    Ok(Json(serde_json::json!({ "status": "deleted" })))
+}
+
+/// Holders to share by: a stored snapshot of the token, or the current
+/// holders from the balance ledger
+pub(crate) async fn snapshot_holders(
+    state: &AppState,
+    token_address: &str,
+    snapshot_id: Option<Uuid>,
+) -> Result<Vec<SnapshotEntry>, (StatusCode, String)> {
+    match snapshot_id {
+        Some(snapshot_id) => {
+            let snapshot = load_snapshot(state, snapshot_id).await?;
+            if snapshot.snapshot.token_address != token_address {
+                return Err((StatusCode::BAD_REQUEST, "Snapshot is of another token".to_string()));
+            }
+            Ok(snapshot.entries)
+        }
+        None => state
+            .db
+            .ledger_holders(token_address, None, None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// Pro-rata shares of `amount` among the holders, without the platform wallet
+pub(crate) fn plan_shares(
+    state: &AppState,
+    amount: TokenAmount,
+    entries: &[SnapshotEntry],
+) -> Result<DistributionPlan, (StatusCode, String)> {
+    let holders: Vec<Holding> = entries.iter().cloned().map(Holding::from).collect();
+    let holders = without_platform_wallet(holders, &state.mkoin_service.get_admin_address());
+    pro_rata(amount, &holders).ok_or((StatusCode::CONFLICT, "Token has no holders to distribute to".to_string()))
+}
+
+/// The snapshot a distribution is based on; current holders are stored as a
+/// new ledger snapshot
+pub(crate) async fn persist_snapshot(
+    state: &AppState,
+    token_address: &str,
+    snapshot_id: Option<Uuid>,
+    entries: &[SnapshotEntry],
+    admin_id: Option<Uuid>,
+) -> Result<Uuid, (StatusCode, String)> {
+    match snapshot_id {
+        Some(snapshot_id) => Ok(snapshot_id),
+        None => state
+            .db
+            .create_holder_snapshot(token_address, "ledger", None, None, entries, admin_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// Leave the platform wallet's escrow and unsold tokens out of the shares
+fn without_platform_wallet(holders: Vec<Holding>, platform_wallet: &str) -> Vec<Holding> {
+    let platform_raw = to_raw_address(platform_wallet).ok();
+    holders
+        .into_iter()
+        .filter(|h| {
+            h.user_address != platform_wallet
+                && (platform_raw.is_none() || to_raw_address(&h.user_address).ok() != platform_raw)
+        })
+        .collect()
+}
+
+pub(crate) async fn load_distribution(
+    state: &AppState,
+    id: Uuid,
+) -> Result<DistributionResponse, (StatusCode, String)> {
+    let distribution = state
+        .db
+        .get_distribution(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Distribution not found".to_string()))?;
+    let payouts = state
+        .db
+        .get_distribution_payouts(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let rewards = state
+        .db
+        .get_distribution_rewards(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let notice = (distribution.payout_mode == PayoutMode::Merkle.as_str()).then_some(MERKLE_NOTICE);
+    Ok(DistributionResponse { distribution, payouts, rewards, notice })
+}
+
+/// Hand the distribution to the distribution worker
+async fn start_distribution(state: &AppState, id: Uuid) -> Result<(), (StatusCode, String)> {
+    let claimed = state
+        .db
+        .claim_distribution(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !claimed {
+        return Err((StatusCode::CONFLICT, "Distribution is already processing".to_string()));
+    }
+    info!("Distribution {} queued for payout", id);
+    Ok(())
+}
@@ -0,0 +1,356 @@
+//! Harvest reports
+//!
+//! A farmer reports each season's harvest of a campaign: the quantity, the
+//! sale revenue and costs, and uploaded evidence. An admin verifies or
+//! rejects the report. Verified reports give the campaign its actual yield,
+//! shown next to the target in the catalog, and can seed a draft profit
+//! distribution that is reviewed and executed like any other
+//! (see `admin::distributions`).
+
+use super::distributions::{check_payout_mode, load_distribution, persist_snapshot, plan_shares, snapshot_holders};
+use super::{check_admin_role, get_current_user, require_admin};
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::db::distributions::PayoutMode;
+use crate::db::harvests::{HarvestError, HarvestReport, NewHarvestReport};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post, put},
+};
+use bigdecimal::BigDecimal;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct HarvestReportRequest {
+    /// e.g. "2026"
+    pub season: String,
+    pub quantity: String, // Decimal as string
+    /// e.g. "kg"
+    pub quantity_unit: String,
+    pub revenue: String, // EUR, up to 9 decimals
+    pub costs: String,   // EUR, up to 9 decimals
+    /// Keys of files uploaded through /upload
+    #[serde(default)]
+    pub evidence_keys: Vec<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewHarvestRequest {
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HarvestListQuery {
+    pub status: Option<String>,
+}
+
+/// Omitting `amount_mkoin` distributes the report's net profit
+#[derive(Debug, Default, Deserialize)]
+pub struct HarvestDistributionRequest {
+    pub amount_mkoin: Option<String>,
+    pub snapshot_id: Option<Uuid>,
+    #[serde(default)]
+    pub mode: PayoutMode,
+}
+
+/// Yield the campaign aims for, in basis points; omitted = no target
+#[derive(Debug, Deserialize)]
+pub struct TargetYieldRequest {
+    pub target_yield_bps: Option<i32>,
+}
+
+pub fn harvest_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/campaigns/{id}/harvest-reports",
+            get(list_campaign_reports).post(submit_report),
+        )
+        .route("/campaigns/{id}/target-yield", put(update_target_yield))
+        .route("/admin/harvest-reports", get(list_reports))
+        .route("/admin/harvest-reports/{id}/verify", put(verify_report))
+        .route("/admin/harvest-reports/{id}/reject", put(reject_report))
+        .route("/admin/harvest-reports/{id}/distribution", post(seed_distribution))
+}
+
+/// Admins, or the farmer who owns the campaign
+async fn require_owner(state: &AppState, headers: &HeaderMap, campaign_id: Uuid) -> Result<Option<Uuid>, (StatusCode, String)> {
+    let claims = get_current_user(headers).await?;
+    let user_id = Uuid::from_str(&claims.sub).ok();
+    if check_admin_role(&claims.role) {
+        return Ok(user_id);
+    }
+
+    let campaign = state
+        .db
+        .get_campaign(campaign_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+    if user_id != Some(campaign.farmer_id) {
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+    }
+    Ok(user_id)
+}
+
+fn harvest_error(e: anyhow::Error, action: &str) -> (StatusCode, String) {
+    match e.downcast_ref::<HarvestError>() {
+        Some(err) => {
+            let status = match err {
+                HarvestError::InvalidSeason
+                | HarvestError::InvalidQuantity
+                | HarvestError::InvalidUnit
+                | HarvestError::UnknownEvidence(_) => StatusCode::BAD_REQUEST,
+                HarvestError::NotFound => StatusCode::NOT_FOUND,
+                HarvestError::DuplicateSeason(_)
+                | HarvestError::AlreadyReviewed(_)
+                | HarvestError::NotVerified(_)
+                | HarvestError::AlreadyDistributed
+                | HarvestError::NoProfit => StatusCode::CONFLICT,
+            };
+            (status, err.to_string())
+        }
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {}: {}", action, e),
+        ),
+    }
+}
+
+/// Report a season's harvest
+///
+/// POST /campaigns/:id/harvest-reports
+/// Body: { "season": "2026", "quantity": "1250.5", "quantity_unit": "kg", "revenue": "8000", "costs": "2500", "evidence_keys": ["..."], "notes": null }
+///
+/// A season has one report at a time; a rejected report can be submitted again.
+pub async fn submit_report(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<HarvestReportRequest>,
+) -> Result<Json<HarvestReport>, (StatusCode, String)> {
+    let user_id = require_owner(&state, &headers, id).await?;
+
+    let quantity = BigDecimal::from_str(&req.quantity)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid quantity".to_string()))?;
+    let revenue = TokenAmount::parse_decimal(&req.revenue)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid revenue: {}", e)))?;
+    let costs = TokenAmount::parse_decimal(&req.costs)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid costs: {}", e)))?;
+
+    let report = NewHarvestReport {
+        season: req.season,
+        quantity,
+        quantity_unit: req.quantity_unit,
+        revenue,
+        costs,
+        evidence_keys: req.evidence_keys,
+        notes: req.notes,
+    };
+    let created = state
+        .db
+        .create_harvest_report(id, &report, user_id)
+        .await
+        .map_err(|e| harvest_error(e, "submit harvest report"))?;
+    info!("Harvest report {} submitted for campaign {} season {}", created.id, id, created.season);
+
+    Ok(Json(created))
+}
+
+/// GET /campaigns/:id/harvest-reports
+pub async fn list_campaign_reports(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<HarvestReport>>, (StatusCode, String)> {
+    require_owner(&state, &headers, id).await?;
+
+    let reports = state
+        .db
+        .get_campaign_harvest_reports(id)
+        .await
+        .map_err(|e| harvest_error(e, "load harvest reports"))?;
+    Ok(Json(reports))
+}
+
+/// GET /admin/harvest-reports?status=submitted
+pub async fn list_reports(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<HarvestListQuery>,
+) -> Result<Json<Vec<HarvestReport>>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    let status = query.status.as_deref().unwrap_or("submitted");
+    let reports = state
+        .db
+        .get_harvest_reports_by_status(status, 100)
+        .await
+        .map_err(|e| harvest_error(e, "load harvest reports"))?;
+    Ok(Json(reports))
+}
+
+/// Verify a submitted report
+///
+/// PUT /admin/harvest-reports/:id/verify
+/// Body: { "note": "Checked against the buyer's invoices" }
+///
+/// The MKOIN raised by the campaign so far is recorded as the capital the
+/// season's yield is computed on.
+pub async fn verify_report(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<ReviewHarvestRequest>,
+) -> Result<Json<HarvestReport>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+
+    let report = state
+        .db
+        .verify_harvest_report(id, admin_id, req.note.as_deref())
+        .await
+        .map_err(|e| harvest_error(e, "verify harvest report"))?;
+    info!(
+        "Harvest report {} verified, yield {:?} bps",
+        id,
+        report.actual_yield_bps()
+    );
+
+    Ok(Json(report))
+}
+
+/// PUT /admin/harvest-reports/:id/reject
+/// Body: { "note": "Invoices missing" }
+pub async fn reject_report(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<ReviewHarvestRequest>,
+) -> Result<Json<HarvestReport>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+
+    let report = state
+        .db
+        .reject_harvest_report(id, admin_id, req.note.as_deref())
+        .await
+        .map_err(|e| harvest_error(e, "reject harvest report"))?;
+    info!("Harvest report {} rejected", id);
+
+    Ok(Json(report))
+}
+
+/// Seed a draft profit distribution from a verified report
+///
+/// POST /admin/harvest-reports/:id/distribution
+/// Body: { "amount_mkoin": null, "snapshot_id": null, "mode": "claim" }
+///
+/// The draft is shared among the campaign token's holders and goes out
+/// once executed through /admin/distributions/:id/execute. Discarding the
+/// draft lets the report seed a new one.
+pub async fn seed_distribution(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<HarvestDistributionRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    check_payout_mode(req.mode)?;
+
+    let report = state
+        .db
+        .get_harvest_report(id)
+        .await
+        .map_err(|e| harvest_error(e, "load harvest report"))?
+        .ok_or_else(|| harvest_error(HarvestError::NotFound.into(), "load harvest report"))?;
+    if report.status != "verified" {
+        return Err(harvest_error(HarvestError::NotVerified(report.status).into(), "seed distribution"));
+    }
+    if report.distribution_id.is_some() {
+        return Err(harvest_error(HarvestError::AlreadyDistributed.into(), "seed distribution"));
+    }
+
+    let amount = match req.amount_mkoin.as_deref() {
+        Some(amount) => TokenAmount::parse_decimal(amount).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?,
+        None => report
+            .net_profit()
+            .ok_or_else(|| harvest_error(HarvestError::NoProfit.into(), "seed distribution"))?,
+    };
+    if amount.is_zero() {
+        return Err(harvest_error(HarvestError::NoProfit.into(), "seed distribution"));
+    }
+
+    let campaign = state
+        .db
+        .get_campaign(report.campaign_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+    let token_address = campaign
+        .token_address
+        .ok_or((StatusCode::CONFLICT, "Campaign has no token yet".to_string()))?;
+
+    let entries = snapshot_holders(&state, &token_address, req.snapshot_id).await?;
+    let plan = plan_shares(&state, amount, &entries)?;
+    let snapshot_id = persist_snapshot(&state, &token_address, req.snapshot_id, &entries, admin_id).await?;
+    let distribution_id = state
+        .db
+        .create_draft_distribution(campaign.id, &token_address, &plan, req.mode, Some(snapshot_id), admin_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let attached = state
+        .db
+        .attach_harvest_distribution(id, distribution_id)
+        .await
+        .map_err(|e| harvest_error(e, "seed distribution"))?;
+    if !attached {
+        // Another request seeded the report first
+        state
+            .db
+            .delete_draft_distribution(distribution_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return Err(harvest_error(HarvestError::AlreadyDistributed.into(), "seed distribution"));
+    }
+    info!(
+        "Harvest report {} seeded draft {} distribution {} of {} MKOIN",
+        id,
+        req.mode.as_str(),
+        distribution_id,
+        amount
+    );
+
+    let response = load_distribution(&state, distribution_id).await?;
+    Ok(Json(serde_json::to_value(response).unwrap_or_default()))
+}
+
+/// Set or clear the yield a campaign aims for
+///
+/// PUT /campaigns/:id/target-yield
+/// Body: { "target_yield_bps": 800 }
+pub async fn update_target_yield(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<TargetYieldRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    let updated = state
+        .db
+        .set_target_yield(id, req.target_yield_bps)
+        .await
+        .map_err(|e| harvest_error(e, "update target yield"))?;
+    if !updated {
+        return Err((StatusCode::NOT_FOUND, "Campaign not found".to_string()));
+    }
+    info!("Campaign {} targets a yield of {:?} bps", id, req.target_yield_bps);
+
+    Ok(Json(serde_json::json!({ "status": "updated", "target_yield_bps": req.target_yield_bps })))
+}
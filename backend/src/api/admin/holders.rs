@@ -0,0 +1,120 @@
+//! Holders of a campaign token, now or at a point in history
+//!
+//! Balances come from the balance ledger. Holders whose ledger history
+//! starts after the requested point (balances backfilled when the ledger
+//! was introduced) or misses changes (purchases credited before transfers
+//! were indexed) are replayed from their jetton wallets on chain.
+
+use super::{check_admin_role, get_current_user};
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::balances::{BalancesQuery, HistoricalBalance, parse_at, resolve_history};
+use crate::db::snapshots::BalanceAt;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::get,
+};
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct HoldersResponse {
+    pub campaign_id: Uuid,
+    pub token_address: String,
+    /// None for the current holders
+    pub at: Option<BalanceAt>,
+    pub holder_count: usize,
+    /// Sum of the known balances
+    pub total_supply: TokenAmount,
+    pub holders: Vec<HistoricalBalance>,
+}
+
+pub fn holder_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/campaigns/{id}/holders", get(get_holders))
+}
+
+/// Admins, or the farmer who owns the campaign
+async fn require_owner(state: &AppState, headers: &HeaderMap, campaign_id: Uuid) -> Result<(), (StatusCode, String)> {
+    let claims = get_current_user(headers).await?;
+    if check_admin_role(&claims.role) {
+        return Ok(());
+    }
+
+    let campaign = state
+        .db
+        .get_campaign(campaign_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+    if Uuid::from_str(&claims.sub).ok() != Some(campaign.farmer_id) {
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+    }
+    Ok(())
+}
+
+/// Holders of the campaign token
+///
+/// GET /campaigns/:id/holders?at=<lt|rfc3339>
+async fn get_holders(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(campaign_id): Path<Uuid>,
+    Query(query): Query<BalancesQuery>,
+) -> Result<Json<HoldersResponse>, (StatusCode, String)> {
+    require_owner(&state, &headers, campaign_id).await?;
+    let at = query.at.as_deref().map(parse_at).transpose()?;
+
+    let campaign = state
+        .db
+        .get_campaign(campaign_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+    let token_address = campaign
+        .token_address
+        .ok_or((StatusCode::CONFLICT, "Campaign token is not deployed".to_string()))?;
+
+    let holders = match at {
+        Some(at) => {
+            let ledger = state
+                .db
+                .ledger_balances_of_token(&token_address, at)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            resolve_history(&state, ledger, at)
+                .await
+                .into_iter()
+                .filter(|h| h.balance.is_none_or(|b| !b.is_zero()))
+                .collect()
+        }
+        None => state
+            .db
+            .ledger_holders(&token_address, None, None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .into_iter()
+            .map(|entry| HistoricalBalance {
+                token_address: token_address.clone(),
+                user_address: entry.user_address,
+                balance: Some(entry.balance),
+                lt: Some(entry.lt),
+                source: "ledger".to_string(),
+            })
+            .collect::<Vec<_>>(),
+    };
+
+    let total_supply = TokenAmount::checked_sum(holders.iter().filter_map(|h| h.balance))
+        .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Total supply overflows".to_string()))?;
+    Ok(Json(HoldersResponse {
+        campaign_id,
+        token_address,
+        at,
+        holder_count: holders.len(),
+        total_supply,
+        holders,
+    }))
+}
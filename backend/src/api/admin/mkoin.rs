@@ -1,17 +1,24 @@
+use crate::amount::TokenAmount;
 use crate::api::AppState;
+use crate::api::idempotency::idempotent;
+use crate::ton::mkoin_service::{MessageStatus, uncertain_message};
 use axum::{
     extract::{Path, State},
     http::{HeaderMap, StatusCode},
     Json, Router,
     routing::{get, post},
 };
-use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::Duration;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
+// How long a mint request waits for the mint to land
+const MINT_CONFIRM_ATTEMPTS: u32 = 10;
+const MINT_CONFIRM_INTERVAL: Duration = Duration::from_secs(3);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MintMkoinRequest {
     pub recipient: String,
@@ -61,68 +68,117 @@ pub fn mkoin_routes() -> Router<Arc<AppState>> {
 ///
 /// POST /admin/mkoin/mint
 /// Body: { "recipient": "EQ...", "amount": "100" }
+///
+/// Send an `Idempotency-Key` header to make retries safe: a repeated key
+/// returns the first mint's response instead of minting again.
 async fn mint_mkoin(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     Json(req): Json<MintMkoinRequest>,
-) -> Result<Json<MintMkoinResponse>, (StatusCode, String)> {
-    info!("Minting {} MKOIN to {}", req.amount, req.recipient);
-
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     // Get current user from JWT
     let claims = super::super::admin::get_current_user(&headers).await?;
     let admin_id = Uuid::from_str(&claims.sub).map_err(|_| {
         (StatusCode::INTERNAL_SERVER_ERROR, "Invalid user ID".to_string())
     })?;
 
-    // Parse amount (in MKOIN) to nanocoins
-    let amount_mkoin: f64 = req
-        .amount
-        .parse()
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid amount format".to_string()))?;
+    idempotent(
+        &state,
+        &headers,
+        "mkoin_mint",
+        &claims.sub,
+        &req,
+        send_mint(&state, admin_id, &req),
+    )
+    .await
+}
 
-    if amount_mkoin <= 0.0 {
+async fn send_mint(
+    state: &AppState,
+    admin_id: Uuid,
+    req: &MintMkoinRequest,
+) -> Result<Json<MintMkoinResponse>, (StatusCode, String)> {
+    info!("Minting {} MKOIN to {}", req.amount, req.recipient);
+
+    // Parse amount (in MKOIN, up to 9 decimals)
+    let amount = TokenAmount::parse_decimal(&req.amount)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    if amount.is_zero() {
         return Err((
             StatusCode::BAD_REQUEST,
             "Amount must be greater than 0".to_string(),
         ));
     }
 
-    // Convert MKOIN to nanocoins (1 MKOIN = 1_000_000_000 nanocoins)
-    let amount_nanocoins = (amount_mkoin * 1_000_000_000.0) as u128;
-    let amount_bd = BigDecimal::from_str(&amount_nanocoins.to_string())
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Amount conversion error".to_string()))?;
-
-    // Call minting service
-    match state.mkoin_service.mint_mkoin(&req.recipient, amount_nanocoins).await {
-        Ok(tx_hash) => {
-            info!("Successfully minted {} MKOIN to {}. TX: {}", amount_mkoin, req.recipient, tx_hash);
-
-            // Record mint in database
-            if let Err(e) = state.db.record_mkoin_mint(
-                &req.recipient,
-                &amount_bd,
-                &tx_hash,
-                Some(admin_id),
-                "confirmed"
-            ).await {
-                error!("Failed to record mint in database: {}", e);
-                // Continue anyway - the blockchain transaction succeeded
-            }
-
-            Ok(Json(MintMkoinResponse {
-                success: true,
-                tx_hash: Some(tx_hash),
-                message: format!("Successfully minted {} MKOIN", amount_mkoin),
-            }))
-        }
+    let sent_at = chrono::Utc::now();
+    let msg_hash = match state.mkoin_service.mint_mkoin(&req.recipient, amount).await {
+        Ok(msg_hash) => msg_hash,
         Err(e) => {
             error!("Failed to mint MKOIN: {}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Minting failed: {}", e),
-            ))
+            let Some(msg_hash) = uncertain_message(&e) else {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Minting failed: {}", e),
+                ));
+            };
+            record_mint(state, admin_id, req, amount, msg_hash, "sent").await;
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                format!(
+                    "Mint may have been broadcast as message {}; check it on chain before minting again: {}",
+                    msg_hash, e
+                ),
+            ));
+        }
+    };
+
+    for _ in 0..MINT_CONFIRM_ATTEMPTS {
+        tokio::time::sleep(MINT_CONFIRM_INTERVAL).await;
+        match state.mkoin_service.admin_message_status(&msg_hash, sent_at).await {
+            Ok(MessageStatus::Landed { tx_hash }) => {
+                info!("Successfully minted {} MKOIN to {}. TX: {}", amount, req.recipient, tx_hash);
+                record_mint(state, admin_id, req, amount, &tx_hash, "confirmed").await;
+                return Ok(Json(MintMkoinResponse {
+                    success: true,
+                    tx_hash: Some(tx_hash),
+                    message: format!("Successfully minted {} MKOIN", amount),
+                }));
+            }
+            Ok(MessageStatus::Pending) => {}
+            Ok(MessageStatus::Expired) => break,
+            Err(e) => warn!("Failed to check mint {}: {}", msg_hash, e),
         }
     }
+
+    // Broadcast but not seen yet; the message hash identifies it on chain
+    record_mint(state, admin_id, req, amount, &msg_hash, "sent").await;
+    Ok(Json(MintMkoinResponse {
+        success: true,
+        tx_hash: None,
+        message: format!(
+            "Mint of {} MKOIN sent as message {}, not confirmed yet",
+            amount, msg_hash
+        ),
+    }))
+}
+
+async fn record_mint(
+    state: &AppState,
+    admin_id: Uuid,
+    req: &MintMkoinRequest,
+    amount: TokenAmount,
+    hash: &str,
+    status: &str,
+) {
+    if let Err(e) = state
+        .db
+        .record_mkoin_mint(&req.recipient, amount, hash, Some(admin_id), status)
+        .await
+    {
+        // The mint itself went out; only the history misses it
+        error!("Failed to record mint in database: {}", e);
+    }
 }
 
 /// Get MKOIN balance for an address
@@ -135,15 +191,11 @@ async fn get_balance(
     info!("Fetching MKOIN balance for {}", address);
 
     match state.mkoin_service.get_balance(&address).await {
-        Ok(balance_nanocoins) => {
-            let balance_mkoin = balance_nanocoins as f64 / 1_000_000_000.0;
-
-            Ok(Json(BalanceResponse {
-                address,
-                balance: format!("{:.9}", balance_mkoin),
-                balance_nanocoins: balance_nanocoins.to_string(),
-            }))
-        }
+        Ok(balance) => Ok(Json(BalanceResponse {
+            address,
+            balance: balance.to_string(),
+            balance_nanocoins: balance.nano().to_string(),
+        })),
         Err(e) => {
             error!("Failed to get balance: {}", e);
             Err((
@@ -163,14 +215,10 @@ async fn get_total_supply(
     info!("Fetching MKOIN total supply");
 
     match state.mkoin_service.get_total_supply().await {
-        Ok(supply_nanocoins) => {
-            let supply_mkoin = supply_nanocoins as f64 / 1_000_000_000.0;
-
-            Ok(Json(TotalSupplyResponse {
-                total_supply: format!("{:.9}", supply_mkoin),
-                total_supply_nanocoins: supply_nanocoins.to_string(),
-            }))
-        }
+        Ok(supply) => Ok(Json(TotalSupplyResponse {
+            total_supply: supply.to_string(),
+            total_supply_nanocoins: supply.nano().to_string(),
+        })),
         Err(e) => {
             error!("Failed to get total supply: {}", e);
             Err((
@@ -197,19 +245,14 @@ async fn get_mint_history(
         Ok(mints) => {
             let history: Vec<MintHistoryItem> = mints
                 .into_iter()
-                .map(|mint| {
-                    let amount_nanocoins = mint.amount.to_string().parse::<f64>().unwrap_or(0.0);
-                    let amount_mkoin = amount_nanocoins / 1_000_000_000.0;
-
-                    MintHistoryItem {
-                        id: mint.id.to_string(),
-                        recipient_address: mint.recipient_address,
-                        amount: format!("{:.9}", amount_mkoin),
-                        tx_hash: mint.tx_hash,
-                        status: mint.status,
-                        minted_at: mint.minted_at.to_rfc3339(),
-                        confirmed_at: mint.confirmed_at.map(|dt| dt.to_rfc3339()),
-                    }
+                .map(|mint| MintHistoryItem {
+                    id: mint.id.to_string(),
+                    recipient_address: mint.recipient_address,
+                    amount: mint.amount.to_string(),
+                    tx_hash: mint.tx_hash,
+                    status: mint.status,
+                    minted_at: mint.minted_at.to_rfc3339(),
+                    confirmed_at: mint.confirmed_at.map(|dt| dt.to_rfc3339()),
                 })
                 .collect();
 
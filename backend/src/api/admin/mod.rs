@@ -5,12 +5,25 @@ use axum::{
     Router,
     http::{HeaderMap, StatusCode},
 };
+use std::str::FromStr;
 use std::sync::Arc;
+use uuid::Uuid;
 
 pub mod auth;
 pub mod users;
 pub mod campaigns;
 pub mod mkoin;
+pub mod presale;
+pub mod purchases;
+pub mod refunds;
+pub mod redemptions;
+pub mod deposits;
+pub mod distributions;
+pub mod snapshots;
+pub mod rewards;
+pub mod harvests;
+pub mod holders;
+pub mod prices;
 
 pub fn admin_routes(_db: crate::db::Database) -> Router<Arc<AppState>> {
      Router::new()
@@ -22,7 +35,20 @@ pub fn admin_routes(_db: crate::db::Database) -> Router<Arc<AppState>> {
         .route("/campaigns", get(campaigns::list_campaigns).post(campaigns::request_campaign))
         .route("/campaigns/{id}", get(campaigns::get_campaign))
         .route("/campaigns/{id}/status", put(campaigns::update_campaign_status))
+        .route("/campaigns/{id}/limits", put(campaigns::update_campaign_limits))
+        .route("/campaigns/{id}/treasury", put(campaigns::update_campaign_treasury))
         .merge(mkoin::mkoin_routes())
+        .merge(presale::presale_routes())
+        .merge(purchases::purchase_routes())
+        .merge(refunds::refund_routes())
+        .merge(redemptions::redemption_routes())
+        .merge(deposits::deposit_routes())
+        .merge(distributions::distribution_routes())
+        .merge(snapshots::snapshot_routes())
+        .merge(rewards::reward_routes())
+        .merge(harvests::harvest_routes())
+        .merge(holders::holder_routes())
+        .merge(prices::price_routes())
 }
 
 // --- Shared Helpers ---
@@ -31,6 +57,21 @@ pub fn check_admin_role(role: &str) -> bool {
     role == "admin" || role == "superadmin"
 }
 
+/// Reject requests that are not from an admin; returns the admin's user
+/// id, if the token carries one
+pub async fn require_admin(headers: &HeaderMap) -> Result<Option<Uuid>, (StatusCode, String)> {
+    let claims = get_current_user(headers).await?;
+    if !check_admin_role(&claims.role) {
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+    }
+    Ok(Uuid::from_str(&claims.sub).ok())
+}
+
+/// Farmers and admins, who manage campaigns and their media
+pub fn check_campaign_role(role: &str) -> bool {
+    role == "farmer" || check_admin_role(role)
+}
+
 pub async fn get_current_user(headers: &HeaderMap) -> Result<app_auth::Claims, (StatusCode, String)> {
     let auth_header = headers.get("Authorization")
         .and_then(|h| h.to_str().ok())
@@ -0,0 +1,182 @@
+use super::require_admin;
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::db::presale::{AllowlistRow, parse_allowlist_csv};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, put},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Presale settings; omitting `presale_start_time` disables the presale
+#[derive(Debug, Deserialize)]
+pub struct UpdatePresaleRequest {
+    pub presale_start_time: Option<chrono::DateTime<chrono::Utc>>,
+    pub presale_price: Option<String>, // Decimal as string, MKOIN per token
+}
+
+#[derive(Debug, Serialize)]
+pub struct AllowlistImportResponse {
+    pub added: u64,
+    pub duplicates: u64,
+    /// Lines that were neither a TON address nor a user id
+    pub invalid: Vec<String>,
+}
+
+pub fn presale_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/campaigns/{id}/presale", put(update_presale))
+        .route(
+            "/campaigns/{id}/allowlist",
+            get(list_allowlist).post(import_allowlist).delete(clear_allowlist),
+        )
+}
+
+async fn invalidate_campaign(state: &AppState, id: Uuid) {
+    state
+        .cache
+        .invalidate(&format!("campaigns:id:{}", id))
+        .await;
+    state.cache.invalidate_pattern("campaigns:list:*").await;
+}
+
+/// Configure or disable the presale window of a campaign
+///
+/// PUT /campaigns/:id/presale
+pub async fn update_presale(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdatePresaleRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    let campaign = state
+        .db
+        .get_campaign(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+
+    let price = parse_presale_price(payload.presale_price.as_deref())?;
+    validate_presale(payload.presale_start_time, price, campaign.start_time)?;
+
+    state
+        .db
+        .update_campaign_presale(id, payload.presale_start_time, price)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    invalidate_campaign(&state, id).await;
+
+    Ok(Json(serde_json::json!({ "status": "updated" })))
+}
+
+pub(super) fn parse_presale_price(
+    value: Option<&str>,
+) -> Result<Option<TokenAmount>, (StatusCode, String)> {
+    let Some(value) = value else { return Ok(None) };
+    let price = TokenAmount::parse_decimal(value)
+        .ok()
+        .filter(|p| !p.is_zero())
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid presale_price".to_string()))?;
+    Ok(Some(price))
+}
+
+pub(super) fn validate_presale(
+    presale_start_time: Option<chrono::DateTime<chrono::Utc>>,
+    presale_price: Option<TokenAmount>,
+    start_time: chrono::DateTime<chrono::Utc>,
+) -> Result<(), (StatusCode, String)> {
+    match presale_start_time {
+        Some(presale_start) if presale_start >= start_time => Err((
+            StatusCode::BAD_REQUEST,
+            "presale_start_time must be before start_time".to_string(),
+        )),
+        None if presale_price.is_some() => Err((
+            StatusCode::BAD_REQUEST,
+            "presale_price requires presale_start_time".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// GET /campaigns/:id/allowlist
+pub async fn list_allowlist(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<AllowlistRow>>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    let rows = state
+        .db
+        .list_allowlist(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(rows))
+}
+
+/// Import allowlist entries from CSV (one address or user id per line)
+///
+/// POST /campaigns/:id/allowlist
+/// Body: text/csv
+pub async fn import_allowlist(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    body: String,
+) -> Result<Json<AllowlistImportResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    state
+        .db
+        .get_campaign(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+
+    let import = parse_allowlist_csv(&body);
+    let added = state
+        .db
+        .add_allowlist_entries(id, &import.entries)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    info!(
+        "Imported {} allowlist entries for campaign {} ({} invalid)",
+        added,
+        id,
+        import.invalid.len()
+    );
+    invalidate_campaign(&state, id).await;
+
+    Ok(Json(AllowlistImportResponse {
+        added,
+        duplicates: import.entries.len() as u64 - added,
+        invalid: import.invalid,
+    }))
+}
+
+/// DELETE /campaigns/:id/allowlist
+pub async fn clear_allowlist(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    let removed = state
+        .db
+        .clear_allowlist(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    invalidate_campaign(&state, id).await;
+
+    Ok(Json(serde_json::json!({ "status": "cleared", "removed": removed })))
+}
@@ -0,0 +1,164 @@
+//! Campaign token prices
+//!
+//! Each campaign token is valued at its price source: the primary sale
+//! price, the last secondary market trade or a NAV set here by an admin
+//! (see `db::prices`). Every price is kept in the campaign's price history,
+//! and NAV updates and source switches are recorded in the price audit log.
+
+use super::require_admin;
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::db::prices::{PriceAuditEntry, PriceError, PriceSource, ResolvedPrice, TokenPrice};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, put},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+// Prices shown in a campaign's history
+const PRICE_HISTORY_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SetNavRequest {
+    pub price: String, // MKOIN per token, up to 9 decimals
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPriceSourceRequest {
+    pub source: PriceSource,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CampaignPricesResponse {
+    pub campaign_id: Uuid,
+    pub price_source: PriceSource,
+    /// None if the campaign has no price yet
+    pub current: Option<ResolvedPrice>,
+    pub history: Vec<TokenPrice>,
+    pub audit: Vec<PriceAuditEntry>,
+}
+
+pub fn price_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/campaigns/{id}/prices", get(get_prices))
+        .route("/admin/campaigns/{id}/price", put(set_nav))
+        .route("/admin/campaigns/{id}/price-source", put(set_source))
+}
+
+fn price_error(e: anyhow::Error, action: &str) -> (StatusCode, String) {
+    match e.downcast_ref::<PriceError>() {
+        Some(err) => {
+            let status = match err {
+                PriceError::NotFound => StatusCode::NOT_FOUND,
+                PriceError::InvalidPrice => StatusCode::BAD_REQUEST,
+                PriceError::NoNav => StatusCode::CONFLICT,
+            };
+            (status, err.to_string())
+        }
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {}: {}", action, e),
+        ),
+    }
+}
+
+/// Current price, price history and audit log of a campaign token
+///
+/// GET /admin/campaigns/:id/prices
+pub async fn get_prices(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<CampaignPricesResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    load_prices(&state, id).await.map(Json)
+}
+
+/// Value the campaign token at a NAV
+///
+/// PUT /admin/campaigns/:id/price
+/// Body: { "price": "1.25", "note": "Q3 valuation" }
+pub async fn set_nav(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<SetNavRequest>,
+) -> Result<Json<CampaignPricesResponse>, (StatusCode, String)> {
+    let actor_id = require_admin(&headers).await?;
+    let price = TokenAmount::parse_decimal(&req.price)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid price: {}", e)))?;
+
+    state
+        .db
+        .set_nav_price(id, price, actor_id, req.note.as_deref())
+        .await
+        .map_err(|e| price_error(e, "set NAV"))?;
+    info!("Campaign {} valued at a NAV of {} MKOIN by {:?}", id, price, actor_id);
+
+    load_prices(&state, id).await.map(Json)
+}
+
+/// Switch the source the campaign token is valued at
+///
+/// PUT /admin/campaigns/:id/price-source
+/// Body: { "source": "sale" | "trade" | "nav", "note": "..." }
+pub async fn set_source(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<SetPriceSourceRequest>,
+) -> Result<Json<CampaignPricesResponse>, (StatusCode, String)> {
+    let actor_id = require_admin(&headers).await?;
+
+    let previous = state
+        .db
+        .set_price_source(id, req.source, actor_id, req.note.as_deref())
+        .await
+        .map_err(|e| price_error(e, "set price source"))?;
+    info!(
+        "Campaign {} price source {} -> {} by {:?}",
+        id,
+        previous.as_str(),
+        req.source.as_str(),
+        actor_id
+    );
+
+    load_prices(&state, id).await.map(Json)
+}
+
+async fn load_prices(state: &AppState, id: Uuid) -> Result<CampaignPricesResponse, (StatusCode, String)> {
+    let internal = |e: anyhow::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let price_source = state
+        .db
+        .get_price_source(id)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+    let current = state
+        .db
+        .token_prices_at(&[id], None)
+        .await
+        .map_err(internal)?
+        .remove(&id);
+    let history = state
+        .db
+        .get_price_history(id, PRICE_HISTORY_LIMIT)
+        .await
+        .map_err(internal)?;
+    let audit = state.db.get_price_audit(id).await.map_err(internal)?;
+
+    Ok(CampaignPricesResponse {
+        campaign_id: id,
+        price_source,
+        current,
+        history,
+        audit,
+    })
+}
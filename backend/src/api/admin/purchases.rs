@@ -0,0 +1,100 @@
+use crate::api::AppState;
+use crate::api::admin::require_admin;
+use crate::api::purchases::invalidate_purchase_caches;
+use crate::db::Purchase;
+use crate::db::limits::PurchaseLimitError;
+use crate::db::purchase_audit::{CONFIRMABLE_STATUSES, PurchaseAuditEntry};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, put},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfirmPurchaseRequest {
+    /// Treasury transaction that received the payment, if known
+    pub verified_tx_hash: Option<String>,
+    pub note: Option<String>,
+}
+
+pub fn purchase_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/purchases/{id}/confirm", put(confirm_purchase))
+        .route("/admin/purchases/{id}/audit", get(get_purchase_audit))
+}
+
+/// Confirm a purchase by hand, e.g. when a payment was verified off band
+///
+/// PUT /purchases/:id/confirm
+async fn confirm_purchase(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<ConfirmPurchaseRequest>>,
+) -> Result<Json<Purchase>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let Json(req) = payload.unwrap_or_default();
+
+    let purchase = state
+        .db
+        .get_purchase(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Purchase not found".to_string()))?;
+    if !CONFIRMABLE_STATUSES.contains(&purchase.status.as_str()) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Purchase is already {}", purchase.status),
+        ));
+    }
+
+    let previous = state
+        .db
+        .admin_confirm_purchase(
+            id,
+            admin_id,
+            req.verified_tx_hash.as_deref(),
+            req.note.as_deref(),
+        )
+        .await
+        .map_err(|e| match e.downcast_ref::<PurchaseLimitError>() {
+            Some(limit) => (StatusCode::CONFLICT, limit.to_string()),
+            None => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?
+        .ok_or((
+            StatusCode::CONFLICT,
+            "Purchase changed status or the payment already confirmed another purchase".to_string(),
+        ))?;
+    info!("Purchase {} confirmed by admin {:?} (was {})", id, admin_id, previous);
+
+    invalidate_purchase_caches(&state, &purchase).await;
+
+    let purchase = state
+        .db
+        .get_purchase(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Purchase not found".to_string()))?;
+    Ok(Json(purchase))
+}
+
+/// GET /admin/purchases/:id/audit
+async fn get_purchase_audit(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<PurchaseAuditEntry>>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    let entries = state
+        .db
+        .get_purchase_audit(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(entries))
+}
@@ -0,0 +1,136 @@
+use crate::api::AppState;
+use crate::api::admin::require_admin;
+use crate::api::redemptions::{RedemptionDetails, load_redemption, redemption_error};
+use crate::db::redemptions::Redemption;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, put},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+const DEFAULT_LIST_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct RedemptionListQuery {
+    /// Defaults to `received`, the requests waiting for review
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReviewRedemptionRequest {
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarkPaidRequest {
+    /// Reference of the outgoing bank transfer
+    pub bank_reference: String,
+    pub note: Option<String>,
+}
+
+pub fn redemption_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/redemptions", get(list_redemptions))
+        .route("/admin/redemptions/{id}", get(get_redemption))
+        .route("/admin/redemptions/{id}/approve", put(approve_redemption))
+        .route("/admin/redemptions/{id}/paid", put(mark_redemption_paid))
+        .route("/admin/redemptions/{id}/reject", put(reject_redemption))
+}
+
+/// GET /admin/redemptions?status=received
+async fn list_redemptions(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<RedemptionListQuery>,
+) -> Result<Json<Vec<Redemption>>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    let status = query.status.as_deref().unwrap_or("received");
+    let redemptions = state
+        .db
+        .get_redemptions_by_status(status, DEFAULT_LIST_LIMIT)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(redemptions))
+}
+
+/// GET /admin/redemptions/:id
+async fn get_redemption(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<RedemptionDetails>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    Ok(Json(load_redemption(&state, id).await?))
+}
+
+/// Approve the payout of a redemption whose MKOIN was received
+///
+/// PUT /admin/redemptions/:id/approve
+async fn approve_redemption(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<ReviewRedemptionRequest>>,
+) -> Result<Json<Redemption>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let Json(req) = payload.unwrap_or_default();
+
+    let redemption = state
+        .db
+        .approve_redemption(id, admin_id, req.note.as_deref())
+        .await
+        .map_err(|e| redemption_error(e, "approve redemption"))?;
+    info!("Redemption {} approved by {:?}", id, admin_id);
+    Ok(Json(redemption))
+}
+
+/// Record the bank transfer of an approved redemption; its MKOIN is burned next
+///
+/// PUT /admin/redemptions/:id/paid
+/// Body: { "bank_reference": "...", "note": "..." }
+async fn mark_redemption_paid(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(req): Json<MarkPaidRequest>,
+) -> Result<Json<Redemption>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let bank_reference = req.bank_reference.trim();
+    if bank_reference.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Bank reference is required".to_string()));
+    }
+
+    let redemption = state
+        .db
+        .mark_redemption_paid(id, admin_id, bank_reference, req.note.as_deref())
+        .await
+        .map_err(|e| redemption_error(e, "mark redemption paid"))?;
+    info!("Redemption {} paid out ({}) by {:?}", id, bank_reference, admin_id);
+    Ok(Json(redemption))
+}
+
+/// Refuse a redemption; its received MKOIN is returned to the user
+///
+/// PUT /admin/redemptions/:id/reject
+async fn reject_redemption(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<ReviewRedemptionRequest>>,
+) -> Result<Json<Redemption>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let Json(req) = payload.unwrap_or_default();
+
+    let redemption = state
+        .db
+        .reject_redemption(id, admin_id, req.note.as_deref())
+        .await
+        .map_err(|e| redemption_error(e, "reject redemption"))?;
+    info!("Redemption {} rejected by {:?}", id, admin_id);
+    Ok(Json(redemption))
+}
@@ -0,0 +1,330 @@
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::admin::campaigns::campaign_treasury;
+use crate::api::admin::require_admin;
+use crate::db::refunds::{PlannedRefund, Refund, RefundBatch, refund_reason};
+use crate::ton::delivery::{DeliveryOutcome, delivery_query_id, find_delivery_outcome};
+use crate::ton::mkoin_service::{
+    MESSAGE_LOOKBACK_SECS, MessageStatus, get_mkoin_address, message_outcome, uncertain_message,
+};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+// Confirmation polling after all transfers of a batch were sent
+const CONFIRM_ATTEMPTS: u32 = 20;
+const CONFIRM_INTERVAL: Duration = Duration::from_secs(6);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateRefundBatchRequest {
+    /// Only compute the refunds, nothing is stored or sent
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefundPreview {
+    pub campaign_id: Uuid,
+    pub reason: String,
+    pub total_amount: TokenAmount,
+    pub refunds: Vec<PlannedRefund>,
+    /// Confirmed purchases whose tokens were already sent or delivered; no
+    /// batch refunds them, an admin decides on each
+    pub tokens_delivered: Vec<PlannedRefund>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefundBatchResponse {
+    pub batch: RefundBatch,
+    pub refunds: Vec<Refund>,
+}
+
+pub fn refund_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/campaigns/{id}/refunds", post(create_refund_batch))
+        .route("/admin/refunds/{batch_id}", get(get_refund_batch))
+        .route("/admin/refunds/{batch_id}/execute", post(execute_refund_batch))
+}
+
+/// Refund every confirmed purchase of a cancelled or undersubscribed campaign
+/// whose tokens have not gone out yet, cancelling their token deliveries
+///
+/// POST /admin/campaigns/:id/refunds
+/// Body: { "dry_run": true } to preview without storing or sending anything
+async fn create_refund_batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<CreateRefundBatchRequest>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+    let Json(req) = payload.unwrap_or_default();
+
+    let campaign = state
+        .db
+        .get_campaign(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+
+    let stats = state
+        .db
+        .get_campaign_stats(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let raised = TokenAmount::parse_nano(&stats.total_mkoin_raised).unwrap_or_default();
+
+    let reason = refund_reason(&campaign.status, campaign.soft_cap, raised).ok_or((
+        StatusCode::CONFLICT,
+        format!(
+            "Campaign is '{}'; only cancelled campaigns or finished ones below their soft cap are refundable",
+            campaign.status
+        ),
+    ))?;
+
+    if req.dry_run {
+        let plan = state
+            .db
+            .plan_refunds(id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let total = TokenAmount::checked_sum(plan.refunds.iter().map(|r| r.amount)).ok_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Refund total overflows".to_string(),
+        ))?;
+
+        return Ok(Json(serde_json::to_value(RefundPreview {
+            campaign_id: id,
+            reason: reason.as_str().to_string(),
+            total_amount: total,
+            refunds: plan.refunds,
+            tokens_delivered: plan.tokens_delivered,
+        })
+        .unwrap_or_default()));
+    }
+
+    // Refunds are paid from the wallet the campaign's proceeds went to
+    let treasury = campaign_treasury(&state, id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !state.mkoin_service.controls(&treasury) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Campaign treasury {} is not a wallet the platform signs for", treasury),
+        ));
+    }
+
+    let batch_id = state
+        .db
+        .create_refund_batch(id, reason, Some(&treasury), admin_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((
+            StatusCode::CONFLICT,
+            "No confirmed purchase is left to refund; those whose tokens were sent are not refunded by a batch"
+                .to_string(),
+        ))?;
+
+    info!("Created refund batch {} for campaign {} ({})", batch_id, id, reason.as_str());
+    start_batch(&state, batch_id).await?;
+
+    let response = load_batch(&state, batch_id).await?;
+    Ok(Json(serde_json::to_value(response).unwrap_or_default()))
+}
+
+/// GET /admin/refunds/:batch_id
+async fn get_refund_batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(batch_id): Path<Uuid>,
+) -> Result<Json<RefundBatchResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    Ok(Json(load_batch(&state, batch_id).await?))
+}
+
+/// Resume a batch: send pending or failed refunds and confirm sent ones
+///
+/// POST /admin/refunds/:batch_id/execute
+async fn execute_refund_batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(batch_id): Path<Uuid>,
+) -> Result<Json<RefundBatchResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    load_batch(&state, batch_id).await?;
+    start_batch(&state, batch_id).await?;
+    Ok(Json(load_batch(&state, batch_id).await?))
+}
+
+async fn load_batch(
+    state: &AppState,
+    batch_id: Uuid,
+) -> Result<RefundBatchResponse, (StatusCode, String)> {
+    let batch = state
+        .db
+        .get_refund_batch(batch_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Refund batch not found".to_string()))?;
+    let refunds = state
+        .db
+        .get_batch_refunds(batch_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(RefundBatchResponse { batch, refunds })
+}
+
+/// Claim the batch and process it in the background
+async fn start_batch(state: &Arc<AppState>, batch_id: Uuid) -> Result<(), (StatusCode, String)> {
+    let claimed = state
+        .db
+        .claim_refund_batch(batch_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !claimed {
+        return Err((StatusCode::CONFLICT, "Refund batch is already processing".to_string()));
+    }
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = process_batch(&state, batch_id).await {
+            error!("Refund batch {} failed: {}", batch_id, e);
+        }
+        match state.db.finish_refund_batch(batch_id).await {
+            Ok(status) => info!("Refund batch {} finished: {}", batch_id, status),
+            Err(e) => error!("Failed to finish refund batch {}: {}", batch_id, e),
+        }
+    });
+    Ok(())
+}
+
+async fn process_batch(state: &AppState, batch_id: Uuid) -> anyhow::Result<()> {
+    let batch = state
+        .db
+        .get_refund_batch(batch_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Refund batch not found"))?;
+    let treasury = batch
+        .treasury_address
+        .clone()
+        .unwrap_or_else(|| state.mkoin_service.get_admin_address());
+
+    // Settle what an earlier run sent before anything is sent again
+    settle_sent(state, batch_id, &treasury).await?;
+
+    for refund in state.db.get_batch_refunds(batch_id).await? {
+        if refund.status != "pending" && refund.status != "failed" {
+            continue;
+        }
+        let Some(attempt) = state.db.start_refund_attempt(refund.id).await? else {
+            continue;
+        };
+
+        // Lets the buyer's wallet correlate the transfer with the refund
+        let query_id = delivery_query_id(refund.id, attempt);
+        let comment = format!("Hazelnut refund {}", refund.purchase_id);
+
+        match state
+            .mkoin_service
+            .transfer_jetton_from(&treasury, &get_mkoin_address(), &refund.user_address, refund.amount, query_id, Some(&comment))
+            .await
+        {
+            Ok(msg_hash) => state.db.mark_refund_sent(refund.id, &msg_hash).await?,
+            Err(e) => match uncertain_message(&e) {
+                // Settled from the chain like any sent refund
+                Some(msg_hash) => {
+                    warn!("Refund {} may have been sent: {}", refund.id, e);
+                    state.db.mark_refund_sent(refund.id, msg_hash).await?;
+                }
+                None => {
+                    warn!("Refund {} could not be sent: {}", refund.id, e);
+                    state.db.mark_refund_failed(refund.id, &e.to_string()).await?;
+                }
+            },
+        }
+    }
+
+    for attempt in 0..CONFIRM_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(CONFIRM_INTERVAL).await;
+        }
+        if settle_sent(state, batch_id, &treasury).await? == 0 {
+            break;
+        }
+    }
+
+    // Refunded purchases no longer count towards the campaign
+    state
+        .cache
+        .invalidate(&format!("campaign:stats:{}", batch.campaign_id))
+        .await;
+
+    Ok(())
+}
+
+/// Resolve the batch's sent refunds from the treasury's history; returns
+/// how many are still unresolved
+///
+/// A refund is confirmed once its transfer's excesses come back. It fails,
+/// and may be sent again, only when it is known not to have moved any MKOIN:
+/// the transfer bounced, or the message expired without landing.
+async fn settle_sent(state: &AppState, batch_id: Uuid, treasury: &str) -> anyhow::Result<usize> {
+    let sent: Vec<_> = state
+        .db
+        .get_batch_refunds(batch_id)
+        .await?
+        .into_iter()
+        .filter(|r| r.status == "sent")
+        .collect();
+    let Some(oldest) = sent.iter().filter_map(|r| r.sent_at).min() else {
+        return Ok(sent.len());
+    };
+
+    let history = state
+        .mkoin_service
+        .wallet_transactions_since(treasury, oldest - chrono::Duration::seconds(MESSAGE_LOOKBACK_SECS))
+        .await?;
+    let jetton_wallet = state
+        .mkoin_service
+        .jetton_wallet_of(treasury, &get_mkoin_address())
+        .await?;
+
+    let mut unresolved = 0;
+    for refund in sent {
+        let (Some(msg_hash), Some(sent_at)) = (refund.msg_hash.as_deref(), refund.sent_at) else {
+            unresolved += 1;
+            continue;
+        };
+        match message_outcome(&history, msg_hash, sent_at, chrono::Utc::now()) {
+            MessageStatus::Landed { .. } => {
+                let query_id = delivery_query_id(refund.id, refund.attempts);
+                match find_delivery_outcome(&history.txs, &jetton_wallet, query_id) {
+                    DeliveryOutcome::Delivered { tx_hash } => {
+                        state.db.confirm_refund(refund.id, &tx_hash).await?;
+                        info!("Refund {} confirmed in {}", refund.id, tx_hash);
+                    }
+                    DeliveryOutcome::Bounced { tx_hash } => {
+                        warn!("Refund {} bounced in {}", refund.id, tx_hash);
+                        let reason = format!("Transfer bounced in {}", tx_hash);
+                        state.db.mark_refund_failed(refund.id, &reason).await?;
+                    }
+                    DeliveryOutcome::InFlight => unresolved += 1,
+                }
+            }
+            MessageStatus::Expired => {
+                warn!("Refund {} transfer {} expired without landing", refund.id, msg_hash);
+                state.db.mark_refund_failed(refund.id, "Transfer expired without landing").await?;
+            }
+            MessageStatus::Pending => unresolved += 1,
+        }
+    }
+    Ok(unresolved)
+}
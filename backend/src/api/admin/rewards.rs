@@ -0,0 +1,50 @@
+use super::require_admin;
+use crate::api::AppState;
+use crate::api::rewards::reward_error;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::put,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Reward rules of a campaign; omitting `claim_days` lets rewards never expire
+#[derive(Debug, Deserialize)]
+pub struct UpdateRewardRulesRequest {
+    pub claim_days: Option<i32>,
+}
+
+pub fn reward_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/campaigns/{id}/reward-rules", put(update_reward_rules))
+}
+
+/// Set how long holders have to claim rewards of a campaign
+///
+/// PUT /campaigns/:id/reward-rules
+/// Body: { "claim_days": 365 }
+///
+/// Applies to rewards of later distributions only.
+pub async fn update_reward_rules(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateRewardRulesRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+
+    let updated = state
+        .db
+        .set_reward_claim_days(id, payload.claim_days)
+        .await
+        .map_err(|e| reward_error(e, "update reward rules"))?;
+    if !updated {
+        return Err((StatusCode::NOT_FOUND, "Campaign not found".to_string()));
+    }
+    info!("Campaign {} rewards expire after {:?} days", id, payload.claim_days);
+
+    Ok(Json(serde_json::json!({ "status": "updated", "claim_days": payload.claim_days })))
+}
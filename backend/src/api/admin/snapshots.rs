@@ -0,0 +1,176 @@
+use crate::api::AppState;
+use crate::api::admin::require_admin;
+use crate::db::snapshots::{HolderSnapshot, SnapshotEntry, SnapshotMismatch, compare_snapshots};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+const SNAPSHOT_LIST_LIMIT: i64 = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotRequest {
+    pub token_address: String,
+    /// "ledger" (default) or "chain"
+    pub source: Option<String>,
+    /// Ledger snapshots only: latest lt to include
+    pub at_lt: Option<i64>,
+    /// Ledger snapshots only: latest chain time to include
+    pub at_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotListQuery {
+    pub token_address: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotResponse {
+    pub snapshot: HolderSnapshot,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotComparison {
+    pub left: HolderSnapshot,
+    pub right: HolderSnapshot,
+    pub mismatches: Vec<SnapshotMismatch>,
+}
+
+pub fn snapshot_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/snapshots", get(list_snapshots).post(create_snapshot))
+        .route("/admin/snapshots/{id}", get(get_snapshot))
+        .route("/admin/snapshots/{id}/compare/{other_id}", get(compare_snapshot))
+}
+
+/// Take a holder snapshot of a campaign token
+///
+/// POST /admin/snapshots
+/// Body: { "token_address": "EQ...", "source": "ledger", "at_lt": 4711 }
+///
+/// Ledger snapshots materialize the holders at the given lt and/or time,
+/// the current ones by default. Chain snapshots read the current balance
+/// of every known holder from its jetton wallet.
+async fn create_snapshot(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<SnapshotRequest>,
+) -> Result<Json<SnapshotResponse>, (StatusCode, String)> {
+    let admin_id = require_admin(&headers).await?;
+
+    state
+        .db
+        .get_campaign_by_token_address(&req.token_address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "No campaign issued this token".to_string()))?;
+
+    let source = req.source.as_deref().unwrap_or("ledger");
+    let entries = match source {
+        "ledger" => state
+            .db
+            .ledger_holders(&req.token_address, req.at_lt, req.at_time)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        "chain" => {
+            if req.at_lt.is_some() || req.at_time.is_some() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Chain snapshots can only be taken of the current state".to_string(),
+                ));
+            }
+            let owners = state
+                .db
+                .ledger_addresses(&req.token_address)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            state
+                .snapshotter
+                .holder_balances(&req.token_address, &owners)
+                .await
+                .map_err(|e| (StatusCode::BAD_GATEWAY, format!("{:#}", e)))?
+        }
+        other => return Err((StatusCode::BAD_REQUEST, format!("Unknown snapshot source: {}", other))),
+    };
+
+    let id = state
+        .db
+        .create_holder_snapshot(&req.token_address, source, req.at_lt, req.at_time, &entries, admin_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    info!("Took {} snapshot {} of {} ({} holders)", source, id, req.token_address, entries.len());
+
+    Ok(Json(load_snapshot(&state, id).await?))
+}
+
+/// GET /admin/snapshots?token_address=EQ...
+async fn list_snapshots(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<SnapshotListQuery>,
+) -> Result<Json<Vec<HolderSnapshot>>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    let snapshots = state
+        .db
+        .list_holder_snapshots(&query.token_address, SNAPSHOT_LIST_LIMIT)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(snapshots))
+}
+
+/// GET /admin/snapshots/:id
+async fn get_snapshot(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SnapshotResponse>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    Ok(Json(load_snapshot(&state, id).await?))
+}
+
+/// Holders whose balances differ between two snapshots of the same token,
+/// e.g. a ledger snapshot cross-checked against a chain snapshot
+///
+/// GET /admin/snapshots/:id/compare/:other_id
+async fn compare_snapshot(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path((id, other_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<SnapshotComparison>, (StatusCode, String)> {
+    require_admin(&headers).await?;
+    let left = load_snapshot(&state, id).await?;
+    let right = load_snapshot(&state, other_id).await?;
+    if left.snapshot.token_address != right.snapshot.token_address {
+        return Err((StatusCode::BAD_REQUEST, "Snapshots are of different tokens".to_string()));
+    }
+
+    let mismatches = compare_snapshots(&left.entries, &right.entries);
+    Ok(Json(SnapshotComparison {
+        left: left.snapshot,
+        right: right.snapshot,
+        mismatches,
+    }))
+}
+
+pub(crate) async fn load_snapshot(state: &AppState, id: Uuid) -> Result<SnapshotResponse, (StatusCode, String)> {
+    let snapshot = state
+        .db
+        .get_holder_snapshot(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Snapshot not found".to_string()))?;
+    let entries = state
+        .db
+        .get_holder_snapshot_entries(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(SnapshotResponse { snapshot, entries })
+}
@@ -1,13 +1,31 @@
+use crate::amount::TokenAmount;
 use crate::api::AppState;
+use crate::db::Campaign;
+use crate::db::presale::address_variants;
+use crate::db::prices::{PriceSource, ResolvedPrice};
+use crate::db::snapshots::{BalanceAt, LedgerBalance, holder_address};
+use crate::ton::mkoin_service::get_mkoin_address;
+use crate::api::admin::{check_admin_role, get_current_user};
+use chrono::Utc;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
     Json, Router,
-    routing::get,
+    routing::{get, post},
 };
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+// Jetton wallet balances are cached briefly to spare toncenter
+const CHAIN_BALANCE_TTL_SECS: u64 = 30;
+// Lookups accepted in one batch request
+const MAX_BATCH_LOOKUPS: usize = 1_000;
+// Batch lookups resolved at once
+const BATCH_CONCURRENCY: usize = 8;
 
 #[derive(Debug, Serialize)]
 pub struct TokenBalance {
@@ -16,6 +34,21 @@ pub struct TokenBalance {
     pub balance: String, // in tokens
     pub balance_nanocoins: String,
     pub token_address: Option<String>,
+    /// MKOIN per token, at the campaign's price source (see `db::prices`)
+    pub price_mkoin: Option<String>,
+    /// Where `price_mkoin` comes from: "sale", "trade" or "nav"
+    pub price_source: Option<String>,
+    /// Balance valued at `price_mkoin`, in MKOIN
+    pub value_mkoin: Option<String>,
+    /// Where `balance` comes from: "chain", or while the token is not minted
+    /// or its wallet could not be read, "indexed" for a holder the indexer
+    /// saw receive the token and "purchases" otherwise
+    pub balance_source: String,
+    /// Tokens bought in confirmed purchases, campaign tokens only
+    pub purchased_balance: Option<String>,
+    /// The balance differs from what was purchased: tokens were transferred,
+    /// sold or bought on the market
+    pub balance_mismatch: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -26,41 +59,127 @@ pub struct PortfolioResponse {
     pub total_value_mkoin: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BalancesQuery {
+    /// Point in history: an lt, or an RFC 3339 time
+    pub at: Option<String>,
+}
+
+/// A holder's balance at a point in history
+#[derive(Debug, Serialize)]
+pub struct HistoricalBalance {
+    pub token_address: String,
+    pub user_address: String,
+    /// None if it could not be determined
+    pub balance: Option<TokenAmount>,
+    /// Lt of the ledger entry the balance comes from
+    pub lt: Option<i64>,
+    /// "ledger", "chain" when replayed from the jetton wallet, or
+    /// "unavailable"
+    pub source: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoricalTokenBalance {
+    pub symbol: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub balance: HistoricalBalance,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoricalBalancesResponse {
+    pub user_address: String,
+    pub at: BalanceAt,
+    pub mkoin_balance: HistoricalTokenBalance,
+    pub campaign_tokens: Vec<HistoricalTokenBalance>,
+}
+
+/// Current balances, or the balances at a point in history
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BalancesResponse {
+    Current(PortfolioResponse),
+    Historical(HistoricalBalancesResponse),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchBalanceRequest {
+    pub addresses: Vec<String>,
+    /// Jetton masters to look up for every address; MKOIN if empty
+    #[serde(default)]
+    pub masters: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchBalance {
+    pub address: String,
+    pub master: String,
+    /// None if the lookup failed
+    pub balance: Option<TokenAmount>,
+    /// Served from the balance cache
+    pub cached: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchBalanceResponse {
+    pub balances: Vec<BatchBalance>,
+    pub failed: usize,
+}
+
 pub fn balances_routes() -> Router<Arc<AppState>> {
     Router::new()
+        .route("/balances/batch", post(batch_balances))
         .route("/balances/{address}", get(get_user_balances))
         .route("/balances/{address}/mkoin", get(get_mkoin_balance))
 }
 
 /// Get all balances for a user (MKOIN + campaign tokens)
 ///
-/// GET /balances/:address
+/// GET /balances/:address?at=<lt|rfc3339>
+///
+/// With `at` the balances at that point in history are returned instead,
+/// as a `HistoricalBalancesResponse`.
 async fn get_user_balances(
     State(state): State<Arc<AppState>>,
     Path(address): Path<String>,
-) -> Result<Json<PortfolioResponse>, (StatusCode, String)> {
+    Query(query): Query<BalancesQuery>,
+) -> Result<Json<BalancesResponse>, (StatusCode, String)> {
+    if let Some(at) = &query.at {
+        let at = parse_at(at)?;
+        info!("Fetching balances for user {} at {:?}", address, at);
+        let balances = historical_balances(&state, &address, at)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to load balances: {}", e)))?;
+        return Ok(Json(BalancesResponse::Historical(balances)));
+    }
     info!("Fetching balances for user {}", address);
 
     // Get MKOIN balance
-    let mkoin_balance_nanocoins = match state.mkoin_service.get_balance(&address).await {
+    let mkoin_amount = match state.mkoin_service.get_balance(&address).await {
         Ok(balance) => balance,
         Err(e) => {
             error!("Failed to get MKOIN balance: {}", e);
-            0 // Default to 0 if error
+            TokenAmount::ZERO // Default to 0 if error
         }
     };
 
-    let mkoin_balance_tokens = mkoin_balance_nanocoins as f64 / 1_000_000_000.0;
-
     let mkoin_balance = TokenBalance {
         symbol: "MKOIN".to_string(),
         name: "MKOIN Stablecoin".to_string(),
-        balance: format!("{:.9}", mkoin_balance_tokens),
-        balance_nanocoins: mkoin_balance_nanocoins.to_string(),
+        balance: mkoin_amount.to_string(),
+        balance_nanocoins: mkoin_amount.nano().to_string(),
         token_address: Some("0:00d2042b5a38fa538142608b0c87eaab75780684ca2313066dbc693c954253c9".to_string()),
+        price_mkoin: None,
+        price_source: None,
+        value_mkoin: Some(mkoin_amount.to_string()),
+        balance_source: "chain".to_string(),
+        purchased_balance: None,
+        balance_mismatch: false,
     };
 
-    // Get campaign token balances from database (purchases)
+    // Campaign token balances from chain, compared with the purchases
     let campaign_tokens = match get_campaign_token_balances(&state, &address).await {
         Ok(tokens) => tokens,
         Err(e) => {
@@ -69,19 +188,22 @@ async fn get_user_balances(
         }
     };
 
-    // Calculate total value in MKOIN (sum of all balances)
-    let total_value = mkoin_balance_tokens
-        + campaign_tokens
-            .iter()
-            .filter_map(|t| t.balance.parse::<f64>().ok())
-            .sum::<f64>();
+    // Total value in MKOIN: MKOIN plus campaign tokens at their price
+    let total_value = TokenAmount::checked_sum(
+        std::iter::once(mkoin_amount).chain(
+            campaign_tokens
+                .iter()
+                .filter_map(|t| TokenAmount::parse_decimal(t.value_mkoin.as_deref()?).ok()),
+        ),
+    );
 
-    Ok(Json(PortfolioResponse {
+    let response = PortfolioResponse {
         user_address: address,
         mkoin_balance,
         campaign_tokens,
-        total_value_mkoin: Some(format!("{:.9}", total_value)),
-    }))
+        total_value_mkoin: total_value.map(|v| v.to_string()),
+    };
+    Ok(Json(BalancesResponse::Current(response)))
 }
 
 /// Get MKOIN balance only
@@ -94,17 +216,19 @@ async fn get_mkoin_balance(
     info!("Fetching MKOIN balance for {}", address);
 
     match state.mkoin_service.get_balance(&address).await {
-        Ok(balance_nanocoins) => {
-            let balance_tokens = balance_nanocoins as f64 / 1_000_000_000.0;
-
-            Ok(Json(TokenBalance {
-                symbol: "MKOIN".to_string(),
-                name: "MKOIN Stablecoin".to_string(),
-                balance: format!("{:.9}", balance_tokens),
-                balance_nanocoins: balance_nanocoins.to_string(),
-                token_address: Some("0:00d2042b5a38fa538142608b0c87eaab75780684ca2313066dbc693c954253c9".to_string()),
-            }))
-        }
+        Ok(balance) => Ok(Json(TokenBalance {
+            symbol: "MKOIN".to_string(),
+            name: "MKOIN Stablecoin".to_string(),
+            balance: balance.to_string(),
+            balance_nanocoins: balance.nano().to_string(),
+            token_address: Some("0:00d2042b5a38fa538142608b0c87eaab75780684ca2313066dbc693c954253c9".to_string()),
+            price_mkoin: None,
+            price_source: None,
+            value_mkoin: Some(balance.to_string()),
+            balance_source: "chain".to_string(),
+            purchased_balance: None,
+            balance_mismatch: false,
+        })),
         Err(e) => {
             error!("Failed to get MKOIN balance: {}", e);
             Err((
@@ -115,41 +239,385 @@ async fn get_mkoin_balance(
     }
 }
 
-/// Helper function to get campaign token balances from database
+/// Balances of the campaign tokens the user bought or holds
+///
+/// Minted tokens are read from the user's jetton wallets. Where a wallet
+/// could not be read, the indexed balance stands in, and the tokens bought
+/// in confirmed purchases for tokens the indexer has not seen the user
+/// receive, such as tokens not minted yet.
 async fn get_campaign_token_balances(
     state: &Arc<AppState>,
     user_address: &str,
 ) -> Result<Vec<TokenBalance>, anyhow::Error> {
-    // Get user's purchases from database
-    let purchases = state.db.get_user_purchases(user_address).await?;
-
-    // Get campaigns for these purchases
-    let mut token_balances: std::collections::HashMap<String, (String, f64)> = std::collections::HashMap::new();
-
-    for purchase in purchases {
-        // Get campaign details
-        if let Ok(Some(campaign)) = state.db.get_campaign(purchase.campaign_id).await {
-            let tokens_received = purchase.tokens_received.to_string().parse::<f64>().unwrap_or(0.0);
-            let tokens_received_mkoin = tokens_received / 1_000_000_000.0;
-
-            let entry = token_balances
-                .entry(campaign.token_symbol.clone())
-                .or_insert((campaign.token_name.clone(), 0.0));
-            entry.1 += tokens_received_mkoin;
+    let mut purchased: HashMap<Uuid, TokenAmount> = HashMap::new();
+    for purchase in state.db.get_user_purchases(user_address).await? {
+        if purchase.status != "confirmed" {
+            continue;
+        }
+        let entry = purchased.entry(purchase.campaign_id).or_insert(TokenAmount::ZERO);
+        *entry = entry
+            .checked_add(purchase.tokens_received)
+            .ok_or_else(|| anyhow::anyhow!("Token balance overflow"))?;
+    }
+
+    // Tokens received by transfer or on the market show up in the indexed balances
+    let positions = state.db.get_portfolio_positions(user_address).await?;
+    let indexed: HashMap<Uuid, TokenAmount> = positions.iter().map(|p| (p.campaign_id, p.balance)).collect();
+    let mut campaign_ids: Vec<Uuid> = purchased
+        .keys()
+        .copied()
+        .chain(positions.iter().map(|p| p.campaign_id))
+        .collect();
+    campaign_ids.sort();
+    campaign_ids.dedup();
+
+    let campaigns = state.db.get_campaigns_by_ids(&campaign_ids).await?;
+    let prices = current_prices(state, &campaigns).await?;
+    let onchain = chain_balances(state, user_address, &campaigns).await;
+
+    let mut balances = Vec::new();
+    for campaign in campaigns {
+        let purchased_balance = purchased.get(&campaign.id).copied().unwrap_or(TokenAmount::ZERO);
+        let (balance, source, mismatch) = reconcile(
+            purchased_balance,
+            indexed.get(&campaign.id).copied(),
+            onchain.get(&campaign.id).copied(),
+        );
+        if balance.is_zero() && !mismatch {
+            continue;
+        }
+        let ResolvedPrice { price, source: price_source, .. } = prices[&campaign.id];
+
+        balances.push(TokenBalance {
+            symbol: campaign.token_symbol,
+            name: campaign.token_name,
+            balance: balance.to_string(),
+            balance_nanocoins: balance.nano().to_string(),
+            token_address: campaign.token_address,
+            price_mkoin: Some(price.to_string()),
+            price_source: Some(price_source.as_str().to_string()),
+            value_mkoin: balance.cost_at(price).map(|v| v.to_string()),
+            balance_source: source.to_string(),
+            purchased_balance: Some(purchased_balance.to_string()),
+            balance_mismatch: mismatch,
+        });
+    }
+
+    Ok(balances)
+}
+
+/// Balance to report, its source and whether it differs from the purchases
+fn reconcile(
+    purchased: TokenAmount,
+    indexed: Option<TokenAmount>,
+    onchain: Option<TokenAmount>,
+) -> (TokenAmount, &'static str, bool) {
+    match (onchain, indexed) {
+        (Some(balance), _) => (balance, "chain", balance != purchased),
+        (None, Some(balance)) => (balance, "indexed", balance != purchased),
+        (None, None) => (purchased, "purchases", false),
+    }
+}
+
+/// The user's jetton wallet balance of each minted campaign token
+///
+/// Balances are cached for `CHAIN_BALANCE_TTL_SECS`. Tokens whose wallet
+/// could not be read are left out.
+async fn chain_balances(state: &AppState, owner: &str, campaigns: &[Campaign]) -> HashMap<Uuid, TokenAmount> {
+    let mut balances = HashMap::new();
+    let mut uncached = Vec::new();
+    for campaign in campaigns {
+        let Some(master) = &campaign.token_address else {
+            continue;
+        };
+        match state.cache.get_cached::<TokenAmount>(&chain_balance_key(master, owner)).await {
+            Some(balance) => {
+                balances.insert(campaign.id, balance);
+            }
+            None => uncached.push((campaign.id, master.clone())),
+        }
+    }
+
+    let masters: Vec<String> = uncached.iter().map(|(_, master)| master.clone()).collect();
+    let results = state.snapshotter.owner_balances(owner, &masters).await;
+    for ((campaign_id, master), result) in uncached.into_iter().zip(results) {
+        match result {
+            Ok(balance) => {
+                state
+                    .cache
+                    .set_cached(&chain_balance_key(&master, owner), &balance, CHAIN_BALANCE_TTL_SECS)
+                    .await;
+                balances.insert(campaign_id, balance);
+            }
+            Err(e) => warn!("Using purchases for {} of {}: {:#}", master, owner, e),
+        }
+    }
+    balances
+}
+
+/// Cache key of a jetton wallet balance, the same for every form of the
+/// addresses
+fn chain_balance_key(master: &str, owner: &str) -> String {
+    format!("balances:chain:{}:{}", holder_address(master), holder_address(owner))
+}
+
+/// Balances of many addresses at once, for the admin users page
+///
+/// POST /balances/batch
+/// Body: { "addresses": ["EQ...", ...], "masters": ["EQ..."] }
+///
+/// Every address is looked up in every master (MKOIN by default), up to
+/// `BATCH_CONCURRENCY` at a time. Balances are cached like those of
+/// `/balances/{address}`; lookups of the same wallet, whatever the form of
+/// its addresses, share one read. A failed lookup is reported in its own
+/// entry.
+async fn batch_balances(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<BatchBalanceRequest>,
+) -> Result<Json<BatchBalanceResponse>, (StatusCode, String)> {
+    let claims = get_current_user(&headers).await?;
+    if !check_admin_role(&claims.role) {
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+    }
+
+    let masters = if req.masters.is_empty() {
+        vec![get_mkoin_address()]
+    } else {
+        req.masters
+    };
+    let lookups: Vec<(String, String)> = req
+        .addresses
+        .iter()
+        .flat_map(|address| masters.iter().map(move |master| (address.clone(), master.clone())))
+        .collect();
+    if lookups.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No addresses given".to_string()));
+    }
+    if lookups.len() > MAX_BATCH_LOOKUPS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} lookups per batch, got {}", MAX_BATCH_LOOKUPS, lookups.len()),
+        ));
+    }
+    info!("Resolving {} balances in a batch", lookups.len());
+
+    let mut wallets: Vec<(String, String)> = lookups
+        .iter()
+        .map(|(address, master)| (holder_address(address), holder_address(master)))
+        .collect();
+    wallets.sort();
+    wallets.dedup();
+
+    // Collected first: a stream over a borrowing closure is not Send in handlers
+    let reads: Vec<_> = wallets
+        .iter()
+        .map(|(address, master)| batch_lookup(&state, address.clone(), master.clone()))
+        .collect();
+    let results: HashMap<(String, String), BatchBalance> = wallets
+        .into_iter()
+        .zip(futures::stream::iter(reads).buffered(BATCH_CONCURRENCY).collect::<Vec<_>>().await)
+        .collect();
+
+    // One entry per requested pair, in the form it was asked for
+    let balances: Vec<BatchBalance> = lookups
+        .into_iter()
+        .map(|(address, master)| {
+            let result = &results[&(holder_address(&address), holder_address(&master))];
+            BatchBalance {
+                address,
+                master,
+                ..result.clone()
+            }
+        })
+        .collect();
+
+    let failed = balances.iter().filter(|b| b.error.is_some()).count();
+    Ok(Json(BatchBalanceResponse { balances, failed }))
+}
+
+async fn batch_lookup(state: &AppState, address: String, master: String) -> BatchBalance {
+    let key = chain_balance_key(&master, &address);
+    if let Some(balance) = state.cache.get_cached::<TokenAmount>(&key).await {
+        return BatchBalance {
+            address,
+            master,
+            balance: Some(balance),
+            cached: true,
+            error: None,
+        };
+    }
+
+    match state.snapshotter.owner_balance(&master, &address).await {
+        Ok(balance) => {
+            state.cache.set_cached(&key, &balance, CHAIN_BALANCE_TTL_SECS).await;
+            BatchBalance {
+                address,
+                master,
+                balance: Some(balance),
+                cached: false,
+                error: None,
+            }
         }
+        Err(e) => BatchBalance {
+            address,
+            master,
+            balance: None,
+            cached: false,
+            error: Some(format!("{:#}", e)),
+        },
     }
+}
+
+pub(crate) fn parse_at(value: &str) -> Result<BalanceAt, (StatusCode, String)> {
+    BalanceAt::parse(value).ok_or((
+        StatusCode::BAD_REQUEST,
+        "at must be an lt or an RFC 3339 time".to_string(),
+    ))
+}
 
-    // Convert to TokenBalance vec
-    let balances: Vec<TokenBalance> = token_balances
+/// Balances of the user at `at`: campaign tokens from the balance ledger,
+/// MKOIN (which the ledger does not track) replayed from chain
+async fn historical_balances(
+    state: &AppState,
+    address: &str,
+    at: BalanceAt,
+) -> Result<HistoricalBalancesResponse, anyhow::Error> {
+    let ledger = state
+        .db
+        .ledger_balances_of_holder(&address_variants(address), at)
+        .await?;
+
+    let mut campaign_tokens = Vec::new();
+    for balance in resolve_history(state, ledger, at).await {
+        if balance.balance.is_some_and(|b| b.is_zero()) {
+            continue;
+        }
+        let campaign = state.db.get_campaign_by_token_address(&balance.token_address).await?;
+        campaign_tokens.push(HistoricalTokenBalance {
+            symbol: campaign.as_ref().map(|c| c.token_symbol.clone()).unwrap_or_default(),
+            name: campaign.map(|c| c.token_name).unwrap_or_default(),
+            balance,
+        });
+    }
+
+    let mkoin_master = get_mkoin_address();
+    let mkoin = state.snapshotter.balance_at(&mkoin_master, address, at).await;
+    let mkoin_balance = HistoricalTokenBalance {
+        symbol: "MKOIN".to_string(),
+        name: "MKOIN Stablecoin".to_string(),
+        balance: replayed(mkoin_master, address.to_string(), mkoin),
+    };
+
+    Ok(HistoricalBalancesResponse {
+        user_address: address.to_string(),
+        at,
+        mkoin_balance,
+        campaign_tokens,
+    })
+}
+
+/// Ledger balances at `at`, with the ones the ledger does not cover
+/// replayed from chain
+pub(crate) async fn resolve_history(
+    state: &AppState,
+    ledger: Vec<LedgerBalance>,
+    at: BalanceAt,
+) -> Vec<HistoricalBalance> {
+    let (covered, uncovered): (Vec<_>, Vec<_>) = ledger.into_iter().partition(LedgerBalance::covered);
+
+    let wallets: Vec<(String, String)> = uncovered
         .into_iter()
-        .map(|(symbol, (name, balance))| TokenBalance {
-            symbol,
-            name,
-            balance: format!("{:.9}", balance),
-            balance_nanocoins: ((balance * 1_000_000_000.0) as u128).to_string(),
-            token_address: None, // TODO: Get from campaign
+        .map(|b| (b.token_address, b.user_address))
+        .collect();
+    let results = state.snapshotter.balances_at(&wallets, at).await;
+
+    let mut balances: Vec<HistoricalBalance> = covered
+        .into_iter()
+        .map(|b| HistoricalBalance {
+            token_address: b.token_address,
+            user_address: b.user_address,
+            balance: Some(b.balance.unwrap_or(TokenAmount::ZERO)),
+            lt: b.lt,
+            source: "ledger".to_string(),
         })
         .collect();
+    balances.extend(
+        wallets
+            .into_iter()
+            .zip(results)
+            .map(|((master, owner), result)| replayed(master, owner, result)),
+    );
+    balances.sort_by(|a, b| (&a.token_address, &a.user_address).cmp(&(&b.token_address, &b.user_address)));
+    balances
+}
 
-    Ok(balances)
+fn replayed(master: String, owner: String, result: anyhow::Result<TokenAmount>) -> HistoricalBalance {
+    let (balance, source) = match result {
+        Ok(balance) => (Some(balance), "chain"),
+        Err(e) => {
+            warn!("Could not replay {} of {}: {:#}", master, owner, e);
+            (None, "unavailable")
+        }
+    };
+    HistoricalBalance {
+        token_address: master,
+        user_address: owner,
+        balance,
+        lt: None,
+        source: source.to_string(),
+    }
+}
+
+/// Price each campaign token is valued at now, the sale price if none was
+/// recorded
+pub(crate) async fn current_prices(
+    state: &AppState,
+    campaigns: &[Campaign],
+) -> Result<HashMap<Uuid, ResolvedPrice>, anyhow::Error> {
+    let campaign_ids: Vec<Uuid> = campaigns.iter().map(|c| c.id).collect();
+    let mut prices = state.db.token_prices_at(&campaign_ids, None).await?;
+    for campaign in campaigns {
+        prices.entry(campaign.id).or_insert_with(|| ResolvedPrice {
+            price: campaign.suggested_price,
+            source: PriceSource::Sale,
+            recorded_at: campaign.created_at.unwrap_or_else(Utc::now),
+        });
+    }
+    Ok(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_balance_key_ignores_address_form() {
+        let raw = "0:2222222222222222222222222222222222222222222222222222222222222222";
+        let master = "0:1111111111111111111111111111111111111111111111111111111111111111";
+        let keys: Vec<String> = address_variants(raw)
+            .iter()
+            .map(|owner| chain_balance_key(master, owner))
+            .collect();
+        assert_eq!(keys.len(), 5);
+        assert!(keys.iter().all(|k| *k == chain_balance_key(master, raw)));
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let bought = TokenAmount::from_nano(100);
+        let held = TokenAmount::from_nano(40);
+        assert_eq!(reconcile(bought, Some(held), Some(bought)), (bought, "chain", false));
+        // Sold part of the tokens
+        assert_eq!(reconcile(bought, Some(bought), Some(held)), (held, "chain", true));
+        // The wallet could not be read: the indexer saw the sale
+        assert_eq!(reconcile(bought, Some(held), None), (held, "indexed", true));
+        // Received by transfer without buying any
+        assert_eq!(
+            reconcile(TokenAmount::ZERO, Some(held), None),
+            (held, "indexed", true)
+        );
+        // Not minted yet
+        assert_eq!(reconcile(bought, None, None), (bought, "purchases", false));
+    }
 }
@@ -0,0 +1,112 @@
+//! Minting of bank deposits
+//!
+//! Each pass:
+//!
+//! 1. expires deposit intents that were never paid;
+//! 2. settles sent mints from the admin wallet's history: a landed mint is
+//!    confirmed, one that expired unseen is queued again, as it can no
+//!    longer execute. A mint whose message was never recorded (the worker
+//!    stopped while sending) is failed for an admin to check after
+//!    `MINT_CONFIRM_TIMEOUT_SECS`, as sending it again could mint twice;
+//! 3. mints the received EUR of paid deposits as MKOIN, 1:1.
+
+use crate::api::AppState;
+use crate::db::deposits::DepositIntent;
+use crate::ton::mkoin_service::{MessageStatus, uncertain_message};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+pub const MAX_MINT_ATTEMPTS: i32 = 3;
+pub const MINT_CONFIRM_TIMEOUT_SECS: i64 = 600;
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+const BATCH_SIZE: i64 = 50;
+
+pub async fn run_deposit_worker(state: Arc<AppState>) {
+    info!("Starting deposit worker");
+
+    loop {
+        if let Err(e) = process_deposits(&state).await {
+            error!("Deposit step failed: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn process_deposits(state: &AppState) -> anyhow::Result<()> {
+    let expired = state.db.expire_deposit_intents().await?;
+    if expired > 0 {
+        info!("Expired {} unpaid deposit(s)", expired);
+    }
+
+    for deposit in state.db.get_deposit_intents_by_status("minting", BATCH_SIZE).await? {
+        confirm_mint(state, &deposit).await?;
+    }
+    for deposit in state.db.get_deposit_intents_by_status("paid", BATCH_SIZE).await? {
+        send_mint(state, &deposit).await?;
+    }
+    Ok(())
+}
+
+async fn confirm_mint(state: &AppState, deposit: &DepositIntent) -> anyhow::Result<()> {
+    let Some(msg_hash) = deposit.mint_msg_hash.as_deref() else {
+        let timeout = Duration::seconds(MINT_CONFIRM_TIMEOUT_SECS);
+        let stale = deposit.updated_at.is_some_and(|t| Utc::now() - t >= timeout);
+        if stale {
+            warn!("Deposit {} mint was started but never recorded as sent", deposit.id);
+            state.db.fail_deposit_mint(deposit.id, "Mint message unknown").await?;
+        }
+        return Ok(());
+    };
+
+    let sent_at = deposit.updated_at.unwrap_or_else(Utc::now);
+    match state.mkoin_service.admin_message_status(msg_hash, sent_at).await {
+        Ok(MessageStatus::Landed { tx_hash }) => {
+            if state.db.confirm_deposit_mint(deposit.id, &tx_hash).await? {
+                let amount = deposit.paid_amount.unwrap_or(deposit.amount);
+                state
+                    .db
+                    .record_mkoin_mint(&deposit.user_address, amount, &tx_hash, None, "confirmed")
+                    .await?;
+                info!("Deposit {} minted {} MKOIN in {}", deposit.id, amount, tx_hash);
+            }
+        }
+        Ok(MessageStatus::Expired) => {
+            let status = state
+                .db
+                .retry_deposit_mint(deposit.id, "Mint expired without landing", MAX_MINT_ATTEMPTS)
+                .await?;
+            warn!("Deposit {} mint {} expired, now {}", deposit.id, msg_hash, status);
+        }
+        Ok(MessageStatus::Pending) => {}
+        Err(e) => warn!("Failed to check mint of deposit {}: {}", deposit.id, e),
+    }
+    Ok(())
+}
+
+async fn send_mint(state: &AppState, deposit: &DepositIntent) -> anyhow::Result<()> {
+    let Some(attempt) = state.db.start_deposit_mint(deposit.id).await? else {
+        return Ok(());
+    };
+    let amount = deposit.paid_amount.unwrap_or(deposit.amount);
+
+    match state.mkoin_service.mint_mkoin(&deposit.user_address, amount).await {
+        Ok(msg_hash) => state.db.mark_deposit_mint_sent(deposit.id, &msg_hash).await?,
+        Err(e) => match uncertain_message(&e) {
+            Some(msg_hash) => {
+                warn!("Deposit {} mint attempt {} may have been sent: {}", deposit.id, attempt, e);
+                state.db.mark_deposit_mint_sent(deposit.id, msg_hash).await?;
+            }
+            None => {
+                let status = state
+                    .db
+                    .retry_deposit_mint(deposit.id, &e.to_string(), MAX_MINT_ATTEMPTS)
+                    .await?;
+                warn!("Deposit {} mint attempt {} failed ({}): {}", deposit.id, attempt, status, e);
+            }
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! Buying MKOIN by bank transfer
+//!
+//! A user announces a deposit and pays it to the treasury's bank account
+//! quoting the returned reference. Imported bank statements credit the
+//! deposit (see `admin::deposits`) and the MKOIN is minted to the user
+//! (see `deposit_worker`).
+
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::idempotency::idempotent;
+use crate::api::purchases::get_user_address;
+use crate::db::deposits::{DepositError, DepositIntent};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateDepositRequest {
+    pub amount: TokenAmount, // EUR in 9-decimal units, as a string
+}
+
+/// Where and how to pay a deposit
+#[derive(Debug, Serialize)]
+pub struct BankTransferInstructions {
+    pub beneficiary: String,
+    pub iban: String,
+    pub bic: Option<String>,
+    /// EUR, as a decimal
+    pub amount: String,
+    /// Must appear in the transfer's remittance information
+    pub reference: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateDepositResponse {
+    pub deposit: DepositIntent,
+    pub bank_transfer: BankTransferInstructions,
+}
+
+pub fn deposit_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/deposits", post(create_deposit))
+        .route("/deposits/my", get(get_my_deposits))
+        .route("/deposits/{id}", get(get_deposit).delete(cancel_deposit))
+}
+
+pub(crate) fn deposit_error(e: anyhow::Error, action: &str) -> (StatusCode, String) {
+    match e.downcast_ref::<DepositError>() {
+        Some(err) => {
+            let status = match err {
+                DepositError::InvalidAmount => StatusCode::BAD_REQUEST,
+                DepositError::NotFound | DepositError::PaymentNotFound => StatusCode::NOT_FOUND,
+                DepositError::NotPending(_)
+                | DepositError::PaymentResolved(_)
+                | DepositError::Currency(_)
+                | DepositError::MintNotFailed(_)
+                | DepositError::PartiallyPaid => StatusCode::CONFLICT,
+            };
+            (status, err.to_string())
+        }
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {}: {}", action, e),
+        ),
+    }
+}
+
+/// The treasury bank account from `DEPOSIT_IBAN`, `DEPOSIT_BENEFICIARY` and
+/// `DEPOSIT_BIC`
+fn treasury_account() -> Option<(String, String, Option<String>)> {
+    let iban = std::env::var("DEPOSIT_IBAN").ok().filter(|v| !v.is_empty())?;
+    let beneficiary = std::env::var("DEPOSIT_BENEFICIARY").unwrap_or_else(|_| "Hazelnut".to_string());
+    let bic = std::env::var("DEPOSIT_BIC").ok().filter(|v| !v.is_empty());
+    Some((beneficiary, iban, bic))
+}
+
+/// Announce a deposit and get the bank transfer that pays it
+///
+/// POST /deposits
+/// Body: { "amount": "100000000000" }
+///
+/// Honours `Idempotency-Key`.
+async fn create_deposit(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateDepositRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+
+    idempotent(
+        &state,
+        &headers,
+        "deposit",
+        &user_address,
+        &payload,
+        request_deposit(&state, &user_address, &payload),
+    )
+    .await
+}
+
+async fn request_deposit(
+    state: &AppState,
+    user_address: &str,
+    payload: &CreateDepositRequest,
+) -> Result<Json<CreateDepositResponse>, (StatusCode, String)> {
+    let (beneficiary, iban, bic) = treasury_account().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Bank deposits are not configured".to_string(),
+    ))?;
+
+    let deposit = state
+        .db
+        .create_deposit_intent(user_address, payload.amount)
+        .await
+        .map_err(|e| deposit_error(e, "create deposit"))?;
+
+    Ok(Json(CreateDepositResponse {
+        bank_transfer: BankTransferInstructions {
+            beneficiary,
+            iban,
+            bic,
+            amount: deposit.amount.to_string(),
+            reference: deposit.reference.clone(),
+        },
+        deposit,
+    }))
+}
+
+/// GET /deposits/my
+async fn get_my_deposits(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<DepositIntent>>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let deposits = state
+        .db
+        .get_user_deposit_intents(&user_address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(deposits))
+}
+
+/// GET /deposits/:id
+async fn get_deposit(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DepositIntent>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    state
+        .db
+        .get_deposit_intent(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|d| d.user_address == user_address)
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, DepositError::NotFound.to_string()))
+}
+
+/// Withdraw a deposit nothing was received for yet
+///
+/// DELETE /deposits/:id
+async fn cancel_deposit(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DepositIntent>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let deposit = state
+        .db
+        .cancel_deposit_intent(id, &user_address)
+        .await
+        .map_err(|e| deposit_error(e, "cancel deposit"))?;
+    Ok(Json(deposit))
+}
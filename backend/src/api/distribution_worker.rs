@@ -0,0 +1,144 @@
+//! Payout of transfer distributions
+//!
+//! Executing a distribution hands it to this worker, so a restart only
+//! delays it. Each pass, for every processing distribution:
+//!
+//! 1. settles sent payouts from the platform wallet's history: excesses
+//!    confirm them, while a bounce or a transfer that expired without
+//!    landing fails them, as no MKOIN moved;
+//! 2. sends up to `PAYOUT_BATCH_SIZE` pending payouts;
+//! 3. once nothing is pending or sent, closes the distribution as
+//!    completed, or partial if payouts failed. Executing it again sends
+//!    those once more.
+
+use crate::api::AppState;
+use crate::db::distributions::DistributionPayout;
+use crate::ton::client::History;
+use crate::ton::delivery::{DeliveryOutcome, delivery_query_id, find_delivery_outcome};
+use crate::ton::mkoin_service::{
+    MESSAGE_LOOKBACK_SECS, MessageStatus, get_mkoin_address, message_outcome, uncertain_message,
+};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+const DISTRIBUTION_BATCH_SIZE: i64 = 10;
+const PAYOUT_BATCH_SIZE: usize = 20;
+
+pub async fn run_distribution_worker(state: Arc<AppState>) {
+    info!("Starting distribution worker");
+
+    loop {
+        if let Err(e) = process_distributions(&state).await {
+            error!("Distribution step failed: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn process_distributions(state: &AppState) -> anyhow::Result<()> {
+    for id in state.db.get_distribution_ids_by_status("processing", DISTRIBUTION_BATCH_SIZE).await? {
+        if let Err(e) = process_distribution(state, id).await {
+            warn!("Distribution {} step failed: {}", id, e);
+        }
+    }
+    Ok(())
+}
+
+async fn process_distribution(state: &AppState, id: Uuid) -> anyhow::Result<()> {
+    let payouts = state.db.get_distribution_payouts(id).await?;
+    let sent: Vec<&DistributionPayout> = payouts.iter().filter(|p| p.status == "sent").collect();
+    let pending: Vec<&DistributionPayout> = payouts.iter().filter(|p| p.status == "pending").collect();
+
+    if sent.is_empty() && pending.is_empty() {
+        let status = state.db.finish_distribution(id).await?;
+        info!("Distribution {} finished: {}", id, status);
+        return Ok(());
+    }
+
+    if let Some(oldest) = sent.iter().filter_map(|p| p.sent_at).min() {
+        let history = state
+            .mkoin_service
+            .wallet_transactions_since(
+                &state.mkoin_service.get_admin_address(),
+                oldest - Duration::seconds(MESSAGE_LOOKBACK_SECS),
+            )
+            .await?;
+        let jetton_wallet = state.mkoin_service.admin_jetton_wallet(&get_mkoin_address()).await?;
+        for payout in sent {
+            settle_payout(state, &history, &jetton_wallet, payout).await?;
+        }
+    }
+
+    for payout in pending.into_iter().take(PAYOUT_BATCH_SIZE) {
+        send_payout(state, id, payout).await?;
+    }
+    Ok(())
+}
+
+/// Confirm a sent payout from its excesses, or fail it once it is known not
+/// to have moved any MKOIN
+async fn settle_payout(
+    state: &AppState,
+    history: &History,
+    jetton_wallet: &str,
+    payout: &DistributionPayout,
+) -> anyhow::Result<()> {
+    let (Some(msg_hash), Some(sent_at)) = (payout.msg_hash.as_deref(), payout.sent_at) else {
+        return Ok(());
+    };
+    match message_outcome(history, msg_hash, sent_at, Utc::now()) {
+        MessageStatus::Landed { .. } => {
+            let query_id = delivery_query_id(payout.id, payout.attempts);
+            match find_delivery_outcome(&history.txs, jetton_wallet, query_id) {
+                DeliveryOutcome::Delivered { tx_hash } => {
+                    state.db.confirm_payout(payout.id, &tx_hash).await?;
+                    info!("Payout {} confirmed in {}", payout.id, tx_hash);
+                }
+                DeliveryOutcome::Bounced { tx_hash } => {
+                    warn!("Payout {} bounced in {}", payout.id, tx_hash);
+                    let reason = format!("Transfer bounced in {}", tx_hash);
+                    state.db.mark_payout_failed(payout.id, &reason).await?;
+                }
+                DeliveryOutcome::InFlight => {}
+            }
+        }
+        MessageStatus::Expired => {
+            warn!("Payout {} transfer {} expired without landing", payout.id, msg_hash);
+            state.db.mark_payout_failed(payout.id, "Transfer expired without landing").await?;
+        }
+        MessageStatus::Pending => {}
+    }
+    Ok(())
+}
+
+async fn send_payout(state: &AppState, distribution_id: Uuid, payout: &DistributionPayout) -> anyhow::Result<()> {
+    let Some(attempt) = state.db.start_payout_attempt(payout.id).await? else {
+        return Ok(());
+    };
+    // Lets the holder's wallet correlate the transfer with the payout
+    let query_id = delivery_query_id(payout.id, attempt);
+    let comment = format!("Hazelnut distribution {}", distribution_id);
+
+    match state
+        .mkoin_service
+        .transfer_mkoin(&payout.user_address, payout.amount, query_id, Some(&comment))
+        .await
+    {
+        Ok(msg_hash) => state.db.mark_payout_sent(payout.id, &msg_hash).await?,
+        Err(e) => match uncertain_message(&e) {
+            Some(msg_hash) => {
+                warn!("Payout {} may have been sent: {}", payout.id, e);
+                state.db.mark_payout_sent(payout.id, msg_hash).await?;
+            }
+            None => {
+                warn!("Payout {} could not be sent: {}", payout.id, e);
+                state.db.mark_payout_failed(payout.id, &e.to_string()).await?;
+            }
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! `Idempotency-Key` support for endpoints that move money
+//!
+//! A client sends a unique key with a request it may retry. The first
+//! request under a key runs and its response is stored; repeats with the
+//! same request get the stored response back instead of running again.
+//! Reusing a key for a different request is rejected with 422.
+//!
+//! Every outcome is stored, failures on our side (5xx) included: a request
+//! that failed late may already have broadcast a transfer or a mint, so
+//! running it again under the same key could pay twice. A key whose request
+//! never finished (the server stopped mid-request) is held for
+//! `db::idempotency::IDEMPOTENCY_LEASE_SECS` and can be reused after that.
+
+use crate::api::AppState;
+use axum::{
+    Json,
+    http::{HeaderMap, StatusCode},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use tracing::error;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+const MAX_KEY_LEN: usize = 255;
+
+/// The request's idempotency key, if it sent one
+pub fn idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, (StatusCode, String)> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|k| !k.is_empty() && k.len() <= MAX_KEY_LEN)
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            format!("{} must be 1-{} visible ASCII characters", IDEMPOTENCY_KEY_HEADER, MAX_KEY_LEN),
+        ))?;
+    Ok(Some(key))
+}
+
+/// SHA-256 of the request as JSON
+pub fn fingerprint(request: &impl Serialize) -> String {
+    let bytes = serde_json::to_vec(request).unwrap_or_default();
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Run `handler` at most once per idempotency key of `actor` on `scope`
+///
+/// Without an `Idempotency-Key` header the handler simply runs.
+pub(crate) async fn idempotent<T, F>(
+    state: &AppState,
+    headers: &HeaderMap,
+    scope: &str,
+    actor: &str,
+    request: &impl Serialize,
+    handler: F,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)>
+where
+    T: Serialize,
+    F: Future<Output = Result<Json<T>, (StatusCode, String)>>,
+{
+    let to_value = |Json(body): Json<T>| {
+        serde_json::to_value(body)
+            .map(Json)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    };
+
+    let Some(key) = idempotency_key(headers)? else {
+        return to_value(handler.await?);
+    };
+    let fingerprint = fingerprint(request);
+
+    let existing = state
+        .db
+        .begin_idempotent_request(scope, actor, key, &fingerprint)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(record) = existing {
+        if record.fingerprint != fingerprint {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency key was already used for a different request".to_string(),
+            ));
+        }
+        return replay(record.response_status, record.response_body);
+    }
+
+    let result = handler.await.and_then(to_value);
+
+    let (status, body) = match &result {
+        Ok(Json(body)) => (StatusCode::OK, body.to_string()),
+        Err((status, message)) => (*status, message.clone()),
+    };
+    let saved = state
+        .db
+        .complete_idempotent_request(scope, actor, key, status.as_u16() as i32, &body)
+        .await;
+    if let Err(e) = saved {
+        error!("Failed to store idempotent response for {} {}: {}", scope, key, e);
+    }
+
+    result
+}
+
+fn replay(
+    status: Option<i32>,
+    body: Option<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let Some(status) = status else {
+        return Err((
+            StatusCode::CONFLICT,
+            "A request with this idempotency key is still in progress".to_string(),
+        ));
+    };
+    let status = u16::try_from(status)
+        .ok()
+        .and_then(|s| StatusCode::from_u16(s).ok())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let body = body.unwrap_or_default();
+
+    if status.is_success() {
+        serde_json::from_str(&body)
+            .map(Json)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    } else {
+        Err((status, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idempotency_key_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(idempotency_key(&headers), Ok(None));
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, " mint-42 ".parse().unwrap());
+        assert_eq!(idempotency_key(&headers), Ok(Some("mint-42")));
+
+        headers.insert(IDEMPOTENCY_KEY_HEADER, "x".repeat(256).parse().unwrap());
+        assert!(idempotency_key(&headers).is_err());
+    }
+
+    #[test]
+    fn test_replay_stored_responses() {
+        assert_eq!(
+            replay(Some(200), Some(r#"{"ok":true}"#.to_string())).unwrap().0,
+            serde_json::json!({ "ok": true })
+        );
+        assert_eq!(
+            replay(Some(409), Some("Sold out".to_string())).unwrap_err(),
+            (StatusCode::CONFLICT, "Sold out".to_string())
+        );
+        assert_eq!(replay(None, None).unwrap_err().0, StatusCode::CONFLICT);
+    }
+}
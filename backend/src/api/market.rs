@@ -0,0 +1,252 @@
+//! Secondary market for campaign tokens against MKOIN
+//!
+//! Orders are matched off-chain once their escrow reached the platform
+//! wallet; trades settle through transfers out of that escrow (see
+//! `market_worker`).
+
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::idempotency::idempotent;
+use crate::api::purchases::{TonConnectMessage, TonConnectTransaction, get_user_address};
+use crate::db::market::{
+    BOOK_DEPTH, MarketError, MarketOrder, MarketTrade, MarketTransfer, NewOrder, ORDER_ESCROW_TTL_SECS,
+    OrderSide, PriceLevel,
+};
+use crate::ton::escrow::escrow_transfer_body;
+use crate::ton::mkoin_service::{TRANSFER_ATTACHED_TON, get_mkoin_address};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tonlib_core::cell::BagOfCells;
+use uuid::Uuid;
+
+const DEFAULT_TRADES_LIMIT: i64 = 50;
+const MAX_TRADES_LIMIT: i64 = 500;
+
+#[derive(Debug, Serialize)]
+pub struct OrderBookResponse {
+    pub campaign_id: Uuid,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+    /// MKOIN nanocoins per whole token of the latest trade
+    pub last_price: Option<TokenAmount>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TradesQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlaceOrderResponse {
+    pub order: MarketOrder,
+    /// Escrow transfer the owner's wallet must send, addressed to their
+    /// jetton wallet; the order trades once it arrived
+    pub transaction: TonConnectTransaction,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderDetails {
+    pub order: MarketOrder,
+    /// Trade legs and refunds paid out of escrow to this order's owner
+    pub transfers: Vec<MarketTransfer>,
+}
+
+pub fn market_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/market/{campaign_id}/orderbook", get(get_order_book))
+        .route("/market/{campaign_id}/trades", get(get_trades))
+        .route("/market/{campaign_id}/orders", post(place_order))
+        .route("/market/orders", get(get_user_orders))
+        .route("/market/orders/{id}", get(get_order).delete(cancel_order))
+}
+
+fn market_error(e: anyhow::Error, action: &str) -> (StatusCode, String) {
+    match e.downcast_ref::<MarketError>() {
+        Some(err) => {
+            let status = match err {
+                MarketError::CampaignNotFound | MarketError::NotFound => StatusCode::NOT_FOUND,
+                MarketError::NotTradable | MarketError::NotCancellable(_) => StatusCode::CONFLICT,
+                MarketError::InvalidOrder(_) => StatusCode::BAD_REQUEST,
+            };
+            (status, err.to_string())
+        }
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {}: {}", action, e),
+        ),
+    }
+}
+
+/// GET /market/:campaign_id/orderbook
+async fn get_order_book(
+    State(state): State<Arc<AppState>>,
+    Path(campaign_id): Path<Uuid>,
+) -> Result<Json<OrderBookResponse>, (StatusCode, String)> {
+    let book = state
+        .db
+        .get_order_book(campaign_id, BOOK_DEPTH)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let last_price = state
+        .db
+        .get_last_trade_prices(&[campaign_id])
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .remove(&campaign_id);
+
+    Ok(Json(OrderBookResponse {
+        campaign_id,
+        bids: book.bids,
+        asks: book.asks,
+        last_price,
+    }))
+}
+
+/// GET /market/:campaign_id/trades?limit=50
+async fn get_trades(
+    State(state): State<Arc<AppState>>,
+    Path(campaign_id): Path<Uuid>,
+    Query(query): Query<TradesQuery>,
+) -> Result<Json<Vec<MarketTrade>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(DEFAULT_TRADES_LIMIT).clamp(1, MAX_TRADES_LIMIT);
+    let trades = state
+        .db
+        .get_trades(campaign_id, limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(trades))
+}
+
+/// Place an order and return the transaction that escrows it
+///
+/// POST /market/:campaign_id/orders
+/// Body: { "side": "sell", "order_type": "limit", "price": "...", "amount": "..." }
+///
+/// Honours `Idempotency-Key`.
+async fn place_order(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(campaign_id): Path<Uuid>,
+    Json(payload): Json<NewOrder>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+
+    idempotent(
+        &state,
+        &headers,
+        "market_order",
+        &user_address,
+        &(campaign_id, &payload),
+        create_order(&state, &user_address, campaign_id, &payload),
+    )
+    .await
+}
+
+async fn create_order(
+    state: &AppState,
+    user_address: &str,
+    campaign_id: Uuid,
+    payload: &NewOrder,
+) -> Result<Json<PlaceOrderResponse>, (StatusCode, String)> {
+    // Order checks run first so a rejected order never touches the network
+    let order = state
+        .db
+        .create_order(user_address, campaign_id, payload)
+        .await
+        .map_err(|e| market_error(e, "place order"))?;
+
+    let jetton_master = match payload.side {
+        OrderSide::Buy => get_mkoin_address(),
+        OrderSide::Sell => state
+            .db
+            .get_campaign(campaign_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .and_then(|c| c.token_address)
+            .ok_or((StatusCode::CONFLICT, MarketError::NotTradable.to_string()))?,
+    };
+    let jetton_wallet = state
+        .purchase_verifier
+        .jetton_wallet(&jetton_master, user_address)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to resolve jetton wallet: {}", e),
+            )
+        })?;
+    let platform_wallet = state.mkoin_service.get_admin_address();
+    let payload_boc = escrow_transfer_body(&order, &platform_wallet)
+        .and_then(|body| Ok(BagOfCells::from_root(body).serialize(true)?))
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to build transfer: {}", e)))?;
+
+    let created_at = order.created_at.unwrap_or_else(chrono::Utc::now);
+    Ok(Json(PlaceOrderResponse {
+        transaction: TonConnectTransaction {
+            valid_until: created_at.timestamp() + ORDER_ESCROW_TTL_SECS,
+            messages: vec![TonConnectMessage {
+                address: jetton_wallet,
+                amount: TRANSFER_ATTACHED_TON.to_string(),
+                payload: base64::engine::general_purpose::STANDARD.encode(payload_boc),
+            }],
+        },
+        order,
+    }))
+}
+
+/// GET /market/orders
+async fn get_user_orders(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<MarketOrder>>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let orders = state
+        .db
+        .get_user_orders(&user_address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(orders))
+}
+
+/// GET /market/orders/:id
+async fn get_order(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<OrderDetails>, (StatusCode, String)> {
+    let order = state
+        .db
+        .get_order(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, MarketError::NotFound.to_string()))?;
+    let transfers = state
+        .db
+        .get_order_transfers(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(OrderDetails { order, transfers }))
+}
+
+/// Cancel an order of the caller; an open order's unused escrow is refunded
+///
+/// DELETE /market/orders/:id
+async fn cancel_order(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<MarketOrder>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let order = state
+        .db
+        .cancel_order(id, &user_address)
+        .await
+        .map_err(|e| market_error(e, "cancel order"))?;
+    Ok(Json(order))
+}
@@ -0,0 +1,257 @@
+//! Escrow detection and settlement of secondary market trades
+//!
+//! Each pass reads the platform wallet's history once, back to the oldest
+//! order or transfer it has to settle, and:
+//!
+//! 1. activates orders whose escrow notification arrived, which matches
+//!    them against the book; orders still without escrow after
+//!    `ORDER_ESCROW_TTL_SECS` expire;
+//! 2. refunds escrow that arrived after its order was cancelled or expired;
+//! 3. resolves sent transfers out of escrow as token deliveries are: from
+//!    their excesses or bounce, or requeued once they expired unseen;
+//! 4. sends pending trade legs and refunds.
+
+use crate::api::AppState;
+use crate::db::market::{MarketOrder, MarketTransfer, ORDER_ESCROW_TTL_SECS};
+use crate::ton::delivery::{DeliveryOutcome, find_delivery_outcome};
+use crate::ton::escrow::{escrow_transfer_query_id, find_escrow};
+use crate::ton::mkoin_service::{
+    MESSAGE_LOOKBACK_SECS, MessageStatus, get_mkoin_address, message_outcome, uncertain_message,
+};
+use crate::ton::client::History;
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+pub const MAX_TRANSFER_ATTEMPTS: i32 = 3;
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+const BATCH_SIZE: i64 = 50;
+
+pub async fn run_market_worker(state: Arc<AppState>) {
+    info!("Starting market settlement worker");
+
+    loop {
+        if let Err(e) = settle_market(&state).await {
+            error!("Market settlement step failed: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn settle_market(state: &AppState) -> anyhow::Result<()> {
+    let awaiting = state.db.get_orders_by_status("awaiting_escrow", BATCH_SIZE).await?;
+    let closed = state.db.get_unescrowed_closed_orders(BATCH_SIZE).await?;
+    let sent = state.db.get_market_transfers_by_status("sent", BATCH_SIZE).await?;
+    let lookback = Duration::seconds(MESSAGE_LOOKBACK_SECS);
+    let since = awaiting
+        .iter()
+        .chain(&closed)
+        .filter_map(|o| o.created_at)
+        .chain(sent.iter().filter_map(|t| t.sent_at))
+        .min()
+        .map(|t| t - lookback);
+    let mut wallets = JettonWallets::default();
+
+    if let Some(since) = since {
+        let history = state
+            .mkoin_service
+            .wallet_transactions_since(&state.mkoin_service.get_admin_address(), since)
+            .await?;
+        activate_escrowed(state, &history.txs, awaiting, &mut wallets).await?;
+        refund_late_escrow(state, &history.txs, closed, &mut wallets).await?;
+        resolve_sent(state, &history, sent, &mut wallets).await?;
+    }
+
+    for transfer in state.db.get_market_transfers_by_status("pending", BATCH_SIZE).await? {
+        send_transfer(state, &transfer, &mut wallets).await?;
+    }
+    Ok(())
+}
+
+/// Jetton masters of market assets and the platform wallet's jetton wallets,
+/// resolved once per pass
+#[derive(Default)]
+struct JettonWallets {
+    masters: HashMap<(uuid::Uuid, String), String>,
+    wallets: HashMap<String, String>,
+}
+
+impl JettonWallets {
+    async fn master(&mut self, state: &AppState, campaign_id: uuid::Uuid, asset: &str) -> anyhow::Result<String> {
+        let key = (campaign_id, asset.to_string());
+        if let Some(master) = self.masters.get(&key) {
+            return Ok(master.clone());
+        }
+        let master = match asset {
+            "mkoin" => get_mkoin_address(),
+            _ => state
+                .db
+                .get_campaign(campaign_id)
+                .await?
+                .and_then(|c| c.token_address)
+                .ok_or_else(|| anyhow::anyhow!("Campaign {} has no token", campaign_id))?,
+        };
+        self.masters.insert(key, master.clone());
+        Ok(master)
+    }
+
+    async fn wallet(&mut self, state: &AppState, master: &str) -> anyhow::Result<String> {
+        if let Some(wallet) = self.wallets.get(master) {
+            return Ok(wallet.clone());
+        }
+        let wallet = state.mkoin_service.admin_jetton_wallet(master).await?;
+        self.wallets.insert(master.to_string(), wallet.clone());
+        Ok(wallet)
+    }
+}
+
+async fn escrow_wallet(state: &AppState, order: &MarketOrder, wallets: &mut JettonWallets) -> anyhow::Result<String> {
+    let asset = if order.side == "buy" { "mkoin" } else { "tokens" };
+    let master = wallets.master(state, order.campaign_id, asset).await?;
+    wallets.wallet(state, &master).await
+}
+
+async fn activate_escrowed(
+    state: &AppState,
+    txs: &[serde_json::Value],
+    awaiting: Vec<MarketOrder>,
+    wallets: &mut JettonWallets,
+) -> anyhow::Result<()> {
+    let ttl = Duration::seconds(ORDER_ESCROW_TTL_SECS);
+
+    for order in awaiting {
+        let jetton_wallet = escrow_wallet(state, &order, wallets).await?;
+
+        match find_escrow(txs, &jetton_wallet, &order) {
+            Some(tx_hash) => {
+                if let Some(trades) = state.db.activate_order(order.id, &tx_hash).await? {
+                    info!(
+                        "Order {} escrowed in {}, {} trade(s)",
+                        order.id,
+                        tx_hash,
+                        trades.len()
+                    );
+                }
+            }
+            None => {
+                let stale = order.created_at.is_some_and(|t| Utc::now() - t >= ttl);
+                if stale && state.db.expire_order(order.id).await? {
+                    info!("Order {} expired without escrow", order.id);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Refund escrow that arrived after its order was cancelled or expired
+async fn refund_late_escrow(
+    state: &AppState,
+    txs: &[serde_json::Value],
+    closed: Vec<MarketOrder>,
+    wallets: &mut JettonWallets,
+) -> anyhow::Result<()> {
+    for order in closed {
+        let jetton_wallet = escrow_wallet(state, &order, wallets).await?;
+        if let Some(tx_hash) = find_escrow(txs, &jetton_wallet, &order)
+            && state.db.refund_late_escrow(order.id, &tx_hash).await?
+        {
+            warn!("Order {} escrowed in {} after it was {}, refunding", order.id, tx_hash, order.status);
+        }
+    }
+    Ok(())
+}
+
+/// Match sent transfers with their excesses or bounce, and requeue those
+/// that expired without landing
+async fn resolve_sent(
+    state: &AppState,
+    history: &History,
+    sent: Vec<MarketTransfer>,
+    wallets: &mut JettonWallets,
+) -> anyhow::Result<()> {
+    for transfer in sent {
+        let (Some(msg_hash), Some(sent_at)) = (transfer.msg_hash.as_deref(), transfer.sent_at) else {
+            continue;
+        };
+        match message_outcome(history, msg_hash, sent_at, Utc::now()) {
+            MessageStatus::Landed { .. } => {}
+            MessageStatus::Expired => {
+                let status = state
+                    .db
+                    .retry_market_transfer(transfer.id, "Transfer expired without landing", MAX_TRANSFER_ATTEMPTS)
+                    .await?;
+                warn!("Market transfer {} expired, now {}", transfer.id, status);
+                continue;
+            }
+            MessageStatus::Pending => continue,
+        }
+
+        let master = wallets.master(state, transfer.campaign_id, &transfer.asset).await?;
+        let jetton_wallet = wallets.wallet(state, &master).await?;
+        let query_id = escrow_transfer_query_id(transfer.id, transfer.attempts);
+
+        match find_delivery_outcome(&history.txs, &jetton_wallet, query_id) {
+            DeliveryOutcome::Delivered { tx_hash } => {
+                info!("Market transfer {} completed in {}", transfer.id, tx_hash);
+                state.db.confirm_market_transfer(transfer.id, &tx_hash).await?;
+            }
+            DeliveryOutcome::Bounced { tx_hash } => {
+                let reason = format!("Transfer bounced in {}", tx_hash);
+                let status = state
+                    .db
+                    .retry_market_transfer(transfer.id, &reason, MAX_TRANSFER_ATTEMPTS)
+                    .await?;
+                warn!("Market transfer {} bounced, now {}", transfer.id, status);
+            }
+            DeliveryOutcome::InFlight => {}
+        }
+    }
+    Ok(())
+}
+
+async fn send_transfer(
+    state: &AppState,
+    transfer: &MarketTransfer,
+    wallets: &mut JettonWallets,
+) -> anyhow::Result<()> {
+    let master = wallets.master(state, transfer.campaign_id, &transfer.asset).await?;
+    let Some(attempt) = state.db.start_market_transfer_attempt(transfer.id).await? else {
+        return Ok(());
+    };
+    let comment = match transfer.trade_id {
+        Some(trade_id) => format!("Hazelnut trade {}", trade_id),
+        None => format!("Hazelnut order {} refund", transfer.order_id),
+    };
+
+    match state
+        .mkoin_service
+        .transfer_jetton(
+            &master,
+            &transfer.recipient,
+            transfer.amount,
+            escrow_transfer_query_id(transfer.id, attempt),
+            Some(&comment),
+        )
+        .await
+    {
+        Ok(msg_hash) => state.db.mark_market_transfer_sent(transfer.id, &msg_hash).await?,
+        Err(e) => match uncertain_message(&e) {
+            Some(msg_hash) => {
+                warn!("Market transfer {} may have been sent: {}", transfer.id, e);
+                state.db.mark_market_transfer_sent(transfer.id, msg_hash).await?;
+            }
+            None => {
+                let status = state
+                    .db
+                    .retry_market_transfer(transfer.id, &e.to_string(), MAX_TRANSFER_ATTEMPTS)
+                    .await?;
+                warn!("Market transfer {} could not be sent ({}): {}", transfer.id, status, e);
+            }
+        },
+    }
+    Ok(())
+}
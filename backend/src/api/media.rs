@@ -0,0 +1,174 @@
+use crate::api::AppState;
+use crate::api::admin::{check_campaign_role, get_current_user};
+use crate::media::{self, ALLOWED_CONTENT_TYPES, MAX_UPLOAD_BYTES, StoredImage};
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct UploadResponse {
+    pub url: String,
+    pub key: String,
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// Thumbnail URLs keyed by bounding box size ("512", "256", "128")
+    pub thumbnails: BTreeMap<String, String>,
+}
+
+pub fn media_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/media",
+            // Leave headroom for multipart framing; the file itself is checked below
+            post(upload_media).layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES + 64 * 1024)),
+        )
+        .route("/media/{key}", get(get_media))
+}
+
+/// Upload a campaign logo or image; farmers and admins only
+///
+/// POST /media (multipart/form-data, field "file")
+async fn upload_media(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<UploadResponse>, (StatusCode, String)> {
+    let claims = get_current_user(&headers).await?;
+    if !check_campaign_role(&claims.role) {
+        return Err((StatusCode::FORBIDDEN, "Access denied".to_string()));
+    }
+    let uploaded_by = Uuid::from_str(&claims.sub).ok();
+
+    let mut file: Option<(String, Vec<u8>)> = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid multipart body: {}", e)))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let content_type = field.content_type().unwrap_or_default().to_string();
+        let bytes = field.bytes().await.map_err(|e| {
+            (StatusCode::PAYLOAD_TOO_LARGE, format!("Failed to read upload: {}", e))
+        })?;
+        file = Some((content_type, bytes.to_vec()));
+        break;
+    }
+
+    let (content_type, bytes) =
+        file.ok_or((StatusCode::BAD_REQUEST, "Missing 'file' field".to_string()))?;
+
+    if !ALLOWED_CONTENT_TYPES.contains(&content_type.as_str()) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Unsupported content type '{}'", content_type),
+        ));
+    }
+    if bytes.len() > MAX_UPLOAD_BYTES {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("File exceeds {} bytes", MAX_UPLOAD_BYTES),
+        ));
+    }
+
+    // Decoding is CPU bound; keep it off the async workers
+    let variants = tokio::task::spawn_blocking(move || media::process_image(&bytes, &content_type))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid image: {}", e)))?;
+
+    let stored = state.media.store(variants).await.map_err(|e| {
+        error!("Failed to store media: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to store media: {}", e))
+    })?;
+
+    let (_, original) = stored
+        .iter()
+        .find(|(variant, _)| variant == "original")
+        .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Missing original variant".to_string()))?;
+
+    for (variant, image) in &stored {
+        let original_key = (variant != "original").then_some(original.key.as_str());
+        record_variant(&state, image, variant, original_key, uploaded_by).await?;
+    }
+
+    info!("Stored media {} ({} variants)", original.key, stored.len());
+
+    let thumbnails = stored
+        .iter()
+        .filter(|(variant, _)| variant != "original")
+        .map(|(variant, image)| (variant.clone(), image.url.clone()))
+        .collect();
+
+    Ok(Json(UploadResponse {
+        url: original.url.clone(),
+        key: original.key.clone(),
+        content_type: original.content_type.clone(),
+        width: original.width,
+        height: original.height,
+        thumbnails,
+    }))
+}
+
+async fn record_variant(
+    state: &AppState,
+    image: &StoredImage,
+    variant: &str,
+    original_key: Option<&str>,
+    uploaded_by: Option<Uuid>,
+) -> Result<(), (StatusCode, String)> {
+    state
+        .db
+        .record_media_file(
+            &image.key,
+            &image.content_type,
+            image.byte_size as i64,
+            image.width as i32,
+            image.height as i32,
+            variant,
+            original_key,
+            uploaded_by,
+        )
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Serve a stored media file
+///
+/// GET /media/:key
+async fn get_media(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    if !media::is_valid_key(&key) {
+        return Err((StatusCode::NOT_FOUND, "Media not found".to_string()));
+    }
+
+    let bytes = state
+        .media
+        .get(&key)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+
+    // Keys are content hashes, so the response can be cached forever
+    Ok((
+        [
+            (header::CONTENT_TYPE, media::content_type_for_key(&key)),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        bytes,
+    ))
+}
@@ -0,0 +1,71 @@
+use crate::api::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Campaign jettons are deployed with 9 decimals, same as MKOIN
+const JETTON_DECIMALS: &str = "9";
+
+/// TEP-64 off-chain jetton metadata document
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JettonMetadata {
+    pub name: String,
+    pub symbol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    pub decimals: String,
+}
+
+pub fn metadata_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/metadata/{campaign_id}", get(get_jetton_metadata))
+}
+
+/// Whether a campaign made it past review; later statuses (running,
+/// paused, finished, cancelled) keep their deployed jetton
+fn was_approved(status: &str) -> bool {
+    !matches!(status, "pending" | "rejected")
+}
+
+/// Serve TEP-64 metadata for a campaign jetton
+///
+/// GET /metadata/:campaign_id
+///
+/// Only campaigns that were approved and deployed their token have
+/// metadata; any other id is a 404.
+async fn get_jetton_metadata(
+    State(state): State<Arc<AppState>>,
+    Path(campaign_id): Path<Uuid>,
+) -> Result<Json<JettonMetadata>, (StatusCode, String)> {
+    let cache_key = format!("metadata:{}", campaign_id);
+    if let Some(cached) = state.cache.get_cached::<JettonMetadata>(&cache_key).await {
+        return Ok(Json(cached));
+    }
+
+    let campaign = state
+        .db
+        .get_campaign(campaign_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|c| was_approved(&c.status) && c.token_address.is_some())
+        .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
+
+    let metadata = JettonMetadata {
+        name: campaign.token_name,
+        symbol: campaign.token_symbol,
+        description: campaign.description,
+        image: campaign.logo_url.or(campaign.image_url),
+        decimals: JETTON_DECIMALS.to_string(),
+    };
+
+    state.cache.set_cached(&cache_key, &metadata, 300).await;
+
+    Ok(Json(metadata))
+}
@@ -1,32 +1,51 @@
 use crate::db::Database;
 use crate::cache::CacheService;
+use crate::media::MediaService;
 use crate::ton::minting::MintingService;
 use crate::ton::mkoin_service::MkoinService;
 use crate::ton::factory_service::FactoryService;
+use crate::ton::purchase_verifier::PurchaseVerifier;
+use crate::ton::snapshot::ChainSnapshotter;
 use anyhow::Result;
 use axum::{
     Json, Router,
-    extract::{Path, State},
+    extract::State,
     http::{HeaderMap, StatusCode},
-    routing::{get, post},
+    routing::post,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tower_http::cors::{CorsLayer, Any};
 
 mod admin;
+mod idempotency;
+mod market;
+mod market_worker;
 mod purchases;
+mod purchase_worker;
+mod redemptions;
+mod redemption_worker;
+mod rewards;
+mod reward_worker;
+mod settlement;
 mod balances;
+mod portfolio;
+mod transactions;
+mod deposits;
+mod deposit_worker;
+mod distribution_worker;
+mod media;
+mod metadata;
+
+pub use deposit_worker::run_deposit_worker;
+pub use distribution_worker::run_distribution_worker;
+pub use market_worker::run_market_worker;
+pub use purchase_worker::run_purchase_worker;
+pub use redemption_worker::run_redemption_worker;
+pub use reward_worker::run_reward_worker;
+pub use settlement::run_settlement_worker;
 
 // Core Data Structures
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PortfolioItem {
-    pub token_address: String,
-    pub symbol: String,
-    pub balance: String,
-    pub usd_value: Option<f64>,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisterRequest {
     pub address: String,
@@ -50,32 +69,42 @@ pub struct DeployRequest {
     pub metadata_url: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DistributionRequest {
-    pub target_token: String,
-    pub amount_mkoin: String,
-}
-
 pub struct AppState {
     pub db: Database,
     pub cache: CacheService,
     pub minting_service: MintingService,
     pub mkoin_service: MkoinService,
     pub factory_service: FactoryService,
+    pub purchase_verifier: PurchaseVerifier,
+    pub snapshotter: ChainSnapshotter,
+    pub media: MediaService,
 }
 
 pub fn router(db: Database, cache: CacheService) -> Router {
+    router_with_state(app_state(db, cache))
+}
+
+/// Shared state of the API and the background workers
+pub fn app_state(db: Database, cache: CacheService) -> Arc<AppState> {
     let minting_service = MintingService::new();
     let mkoin_service = MkoinService::new();
     let factory_service = FactoryService::new();
-    let state = Arc::new(AppState {
-        db: db.clone(),
+    let purchase_verifier = PurchaseVerifier::new();
+    let snapshotter = ChainSnapshotter::new();
+    let media = MediaService::from_env();
+    Arc::new(AppState {
+        db,
         cache,
         minting_service,
         mkoin_service,
         factory_service,
-    });
+        purchase_verifier,
+        snapshotter,
+        media,
+    })
+}
 
+pub fn router_with_state(state: Arc<AppState>) -> Router {
     // Configure CORS to allow requests from admin frontend
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -83,17 +112,23 @@ pub fn router(db: Database, cache: CacheService) -> Router {
         .allow_headers(Any);
 
     Router::new()
-        .merge(admin::admin_routes(db))
+        .merge(admin::admin_routes(state.db.clone()))
         .merge(purchases::purchases_routes())
         .merge(balances::balances_routes())
-        .route("/portfolio/{user_address}", get(get_user_portfolio))
+        .merge(portfolio::portfolio_routes())
+        .merge(transactions::transactions_routes())
+        .merge(market::market_routes())
+        .merge(redemptions::redemption_routes())
+        .merge(deposits::deposit_routes())
+        .merge(rewards::reward_routes())
+        .merge(metadata::metadata_routes())
+        .merge(media::media_routes())
         // Public/Protected User Routes
         .route("/users/register", post(register_user))
         // Admin Routes
         .route("/admin/tokens/mint", post(admin_mint_token))
         .route("/admin/tokens/burn", post(admin_burn_token))
         .route("/admin/tokens/deploy", post(admin_deploy_token))
-        .route("/admin/distribution", post(admin_distribute_rewards))
         .layer(cors)
         .with_state(state)
 }
@@ -151,38 +186,6 @@ async fn register_user(
     ))
 }
 
-async fn get_user_portfolio(
-    State(state): State<Arc<AppState>>,
-    Path(user_address): Path<String>,
-) -> Json<Vec<PortfolioItem>> {
-    let cache_key = format!("portfolio:{}", user_address);
-    if let Some(cached) = state.cache.get_cached::<Vec<PortfolioItem>>(&cache_key).await {
-        return Json(cached);
-    }
-
-    // Mock/Stub Data
-    let portfolio = vec![
-        PortfolioItem {
-            token_address: "EQ...AgriToken".to_string(),
-            symbol: "AGRI".to_string(),
-            balance: "100.50".to_string(),
-            usd_value: Some(100.50),
-        },
-        PortfolioItem {
-            token_address: "EQ...MKOIN".to_string(),
-            symbol: "MKOIN".to_string(),
-            balance: "500.00".to_string(),
-            usd_value: Some(500.00),
-        },
-    ];
-    
-    // In real app, we'd query DB or Indexer here
-    
-    state.cache.set_cached(&cache_key, &portfolio, 30).await; // 30s cache
-
-    Json(portfolio)
-}
-
 async fn admin_mint_token(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -218,16 +221,3 @@ async fn admin_deploy_token(
         "symbol": payload.symbol
     })))
 }
-
-async fn admin_distribute_rewards(
-    State(state): State<Arc<AppState>>,
-    headers: HeaderMap,
-    Json(payload): Json<DistributionRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    ensure_admin(&headers, &state.db).await?;
-    Ok(Json(serde_json::json!({
-        "status": "ok",
-        "distributed_mkoin": payload.amount_mkoin,
-        "recipient_count": 42
-    })))
-}
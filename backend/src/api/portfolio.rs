@@ -0,0 +1,189 @@
+//! Portfolio of a wallet, in the shape of the frontend `Portfolio` type
+//!
+//! Balances are the campaign token balances the indexer keeps from chain
+//! transfers (see `db::transfers`), the cost basis is the MKOIN paid in
+//! confirmed purchases and holdings are valued at the current token price of
+//! the campaign's price source (see `db::prices`). MKOIN is pegged to the
+//! euro, so MKOIN amounts are reported as EUR.
+
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::balances::current_prices;
+use crate::db::Campaign;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Portfolio {
+    pub total_invested: f64,
+    pub total_value: f64,
+    pub total_profit: f64,
+    pub profit_percentage: f64,
+    pub holdings: Vec<PortfolioHolding>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioHolding {
+    pub token: PortfolioToken,
+    /// Tokens held
+    pub amount: f64,
+    pub invested: f64,
+    pub current_value: f64,
+    pub profit: f64,
+    pub profit_percentage: f64,
+}
+
+/// The campaign token fields of the frontend `Token` type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioToken {
+    /// Campaign id
+    pub id: String,
+    pub address: String,
+    pub symbol: String,
+    pub name: String,
+    pub description: String,
+    /// EUR per token
+    pub price: f64,
+    pub total_supply: f64,
+    pub sale_start: String,
+    pub sale_end: String,
+    pub logo: String,
+    /// 'upcoming', 'active' or 'ended'
+    pub status: String,
+    /// Target yield in percent, if the campaign has one
+    pub apy: Option<f64>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortfolioEnvelope {
+    pub portfolio: Portfolio,
+}
+
+pub fn portfolio_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/portfolio/{user_address}", get(get_user_portfolio))
+}
+
+/// GET /portfolio/:user_address
+///
+/// The address may be given in raw or user-friendly form.
+async fn get_user_portfolio(
+    State(state): State<Arc<AppState>>,
+    Path(user_address): Path<String>,
+) -> Result<Json<PortfolioEnvelope>, (StatusCode, String)> {
+    let cache_key = format!("portfolio:{}", user_address);
+    if let Some(portfolio) = state.cache.get_cached::<Portfolio>(&cache_key).await {
+        return Ok(Json(PortfolioEnvelope { portfolio }));
+    }
+
+    let portfolio = build_portfolio(&state, &user_address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to load portfolio: {}", e)))?;
+    state.cache.set_cached(&cache_key, &portfolio, 30).await; // 30s cache
+
+    Ok(Json(PortfolioEnvelope { portfolio }))
+}
+
+async fn build_portfolio(state: &AppState, user_address: &str) -> anyhow::Result<Portfolio> {
+    let mut positions = state.db.get_portfolio_positions(user_address).await?;
+    positions.retain(|p| !p.balance.is_zero());
+
+    let ids: Vec<_> = positions.iter().map(|p| p.campaign_id).collect();
+    let campaigns = state.db.get_campaigns_by_ids(&ids).await?;
+    let prices = current_prices(state, &campaigns).await?;
+    let yields = state.db.campaign_yields(&ids).await?;
+
+    let mut holdings = Vec::new();
+    let (mut total_invested, mut total_value) = (TokenAmount::ZERO, TokenAmount::ZERO);
+    for position in &positions {
+        let Some(campaign) = campaigns.iter().find(|c| c.id == position.campaign_id) else {
+            continue;
+        };
+        let price = prices.get(&campaign.id).map_or(campaign.suggested_price, |p| p.price);
+        let value = position
+            .balance
+            .cost_at(price)
+            .ok_or_else(|| anyhow::anyhow!("Value of {} overflows", position.token_address))?;
+        total_invested = total_invested
+            .checked_add(position.invested)
+            .ok_or_else(|| anyhow::anyhow!("Invested total overflows"))?;
+        total_value = total_value
+            .checked_add(value)
+            .ok_or_else(|| anyhow::anyhow!("Portfolio value overflows"))?;
+
+        let target_yield_bps = yields.get(&campaign.id).and_then(|(target, _)| *target);
+        let (profit, profit_percentage) = profit(position.invested, value);
+        holdings.push(PortfolioHolding {
+            token: token_view(campaign, &position.token_address, price, target_yield_bps),
+            amount: position.balance.to_f64(),
+            invested: position.invested.to_f64(),
+            current_value: value.to_f64(),
+            profit,
+            profit_percentage,
+        });
+    }
+
+    let (total_profit, profit_percentage) = profit(total_invested, total_value);
+    Ok(Portfolio {
+        total_invested: total_invested.to_f64(),
+        total_value: total_value.to_f64(),
+        total_profit,
+        profit_percentage,
+        holdings,
+    })
+}
+
+/// Profit and profit in percent of what was invested (0 without a cost basis)
+fn profit(invested: TokenAmount, value: TokenAmount) -> (f64, f64) {
+    let profit = value.to_f64() - invested.to_f64();
+    if invested.is_zero() {
+        return (profit, 0.0);
+    }
+    (profit, profit / invested.to_f64() * 100.0)
+}
+
+fn token_view(campaign: &Campaign, token_address: &str, price: TokenAmount, target_yield_bps: Option<i32>) -> PortfolioToken {
+    let status = match campaign.status.as_str() {
+        "running" => "active",
+        "pending" | "approved" => "upcoming",
+        _ => "ended",
+    };
+    PortfolioToken {
+        id: campaign.id.to_string(),
+        address: token_address.to_string(),
+        symbol: campaign.token_symbol.clone(),
+        name: campaign.token_name.clone(),
+        description: campaign.description.clone().unwrap_or_default(),
+        price: price.to_f64(),
+        total_supply: campaign.token_supply.parse().unwrap_or_default(),
+        sale_start: campaign.start_time.to_rfc3339(),
+        sale_end: campaign.end_time.to_rfc3339(),
+        logo: campaign.logo_url.clone().unwrap_or_default(),
+        status: status.to_string(),
+        apy: target_yield_bps.map(|bps| bps as f64 / 100.0),
+        created_at: campaign.created_at.map(|t| t.to_rfc3339()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profit() {
+        let eur = |v: &str| TokenAmount::parse_decimal(v).unwrap();
+        assert_eq!(profit(eur("100"), eur("115")), (15.0, 15.0));
+        assert_eq!(profit(eur("200"), eur("150")), (-50.0, -25.0));
+        // Tokens received without a purchase have no cost basis
+        assert_eq!(profit(TokenAmount::ZERO, eur("10")), (10.0, 0.0));
+    }
+}
@@ -0,0 +1,76 @@
+//! Background resolution of pending purchases
+//!
+//! Polls pending purchases, least recently checked first, and checks each
+//! against chain data.
+//! Purchases whose payment is still not found once they are older than the
+//! expiry are marked `expired`; a purchase is never expired when the chain
+//! could not be queried.
+
+use crate::api::AppState;
+use crate::api::purchases::{invalidate_purchase_caches, verify_purchase};
+use crate::ton::purchase_verifier::Verification;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(20);
+const BATCH_SIZE: i64 = 50;
+
+/// Whether a purchase made at `purchased_at` has waited too long for payment
+pub fn is_stale(purchased_at: DateTime<Utc>, now: DateTime<Utc>, expiry: Duration) -> bool {
+    now - purchased_at >= expiry
+}
+
+pub async fn run_purchase_worker(state: Arc<AppState>, expiry: Duration) {
+    info!("Starting purchase confirmation worker (expiry {}s)", expiry.num_seconds());
+
+    loop {
+        if let Err(e) = poll_pending(&state, expiry).await {
+            error!("Purchase worker step failed: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn poll_pending(state: &AppState, expiry: Duration) -> anyhow::Result<()> {
+    for purchase in state.db.get_pending_purchases(BATCH_SIZE).await? {
+        if !state.db.start_purchase_check(purchase.id, 0).await? {
+            continue;
+        }
+        match verify_purchase(state, &purchase).await {
+            Ok(Verification::Confirmed { tx_hash }) => {
+                info!("Purchase {} confirmed by {}", purchase.id, tx_hash);
+            }
+            Ok(Verification::Rejected(reason)) => {
+                warn!("Purchase {} failed: {}", purchase.id, reason);
+            }
+            Ok(Verification::Pending) if is_stale(purchase.purchased_at, Utc::now(), expiry) => {
+                let reason = format!(
+                    "No payment found within {} minutes",
+                    expiry.num_minutes()
+                );
+                if state.db.expire_purchase(purchase.id, &reason).await? {
+                    info!("Purchase {} expired", purchase.id);
+                    invalidate_purchase_caches(state, &purchase).await;
+                }
+            }
+            Ok(Verification::Pending) => {}
+            Err(e) => warn!("Could not verify purchase {}: {}", purchase.id, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stale() {
+        let now = Utc::now();
+        let expiry = Duration::minutes(30);
+        assert!(!is_stale(now - Duration::minutes(29), now, expiry));
+        assert!(is_stale(now - Duration::minutes(30), now, expiry));
+    }
+}
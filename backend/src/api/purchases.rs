@@ -1,20 +1,83 @@
+use crate::amount::TokenAmount;
 use crate::api::AppState;
+use crate::api::admin::campaigns::campaign_treasury;
+use crate::api::idempotency::idempotent;
+use crate::auth;
 use crate::db::Purchase;
+use crate::db::deliveries::TokenDelivery;
+use crate::db::limits::PurchaseLimitError;
+use crate::db::presale::PresaleError;
+use crate::db::quotes::QuoteError;
+use crate::ton::mkoin_service::{TRANSFER_ATTACHED_TON, TRANSFER_FORWARD_TON};
+use crate::ton::purchase_verifier::{Verification, quote_transfer_body};
 use axum::{
     extract::{Path, State},
     http::{HeaderMap, StatusCode},
     routing::{get, post},
     Json, Router,
 };
+use base64::Engine;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tonlib_core::cell::BagOfCells;
 use uuid::Uuid;
 
 #[derive(Debug, Deserialize)]
+pub struct QuoteRequest {
+    pub campaign_id: Uuid,
+    pub mkoin_amount: TokenAmount, // nanocoins, as a string
+}
+
+/// TON paid on top of the MKOIN amount, in nanotons
+#[derive(Debug, Serialize)]
+pub struct QuoteFees {
+    /// Attached to the transfer for gas; the excess is returned to the buyer
+    pub attached_ton: String,
+    /// Forwarded to the treasury with the transfer notification
+    pub forward_ton: String,
+}
+
+/// A transaction request in the TON Connect `sendTransaction` format
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TonConnectTransaction {
+    pub valid_until: i64,
+    pub messages: Vec<TonConnectMessage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TonConnectMessage {
+    pub address: String,
+    /// Nanotons, as a string
+    pub amount: String,
+    /// Base64 BOC of the message body
+    pub payload: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuoteResponse {
+    pub quote_id: Uuid,
+    pub campaign_id: Uuid,
+    pub mkoin_amount: TokenAmount,
+    pub tokens: TokenAmount,
+    /// MKOIN nanocoins per whole token
+    pub price: TokenAmount,
+    pub fees: QuoteFees,
+    pub expires_at: DateTime<Utc>,
+    /// Server signature over the quoted terms, required to submit the purchase
+    pub signature: String,
+    /// Transfer the buyer's wallet must send, addressed to their MKOIN jetton wallet
+    pub transaction: TonConnectTransaction,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CreatePurchaseRequest {
     pub campaign_id: Uuid,
-    pub mkoin_paid: String,
-    pub tokens_received: String,
+    pub mkoin_paid: TokenAmount, // nanocoins, as a string
+    pub quote_id: Uuid,
+    pub signature: String,
+    /// Buyer wallet transaction that sent the MKOIN (hex or base64)
     pub tx_hash: String,
 }
 
@@ -23,10 +86,15 @@ pub struct PurchaseResponse {
     pub id: Uuid,
     pub status: String,
     pub message: String,
+    /// Tokens allocated at the quoted price
+    pub tokens_received: TokenAmount,
 }
 
+// Minimum time between two on-chain checks of a purchase requested by clients
+const VERIFY_INTERVAL_SECS: i64 = 10;
+
 // Helper to extract user address from headers
-fn get_user_address(headers: &HeaderMap) -> Result<String, (StatusCode, String)> {
+pub(crate) fn get_user_address(headers: &HeaderMap) -> Result<String, (StatusCode, String)> {
     headers
         .get("X-User-Address")
         .and_then(|v| v.to_str().ok())
@@ -37,74 +105,175 @@ fn get_user_address(headers: &HeaderMap) -> Result<String, (StatusCode, String)>
         ))
 }
 
-pub async fn create_purchase(
-    State(state): State<Arc<AppState>>,
-    headers: HeaderMap,
-    Json(payload): Json<CreatePurchaseRequest>,
-) -> Result<Json<PurchaseResponse>, (StatusCode, String)> {
-    // Get user address from header
-    let user_address = get_user_address(&headers)?;
+// Map sale check failures to the status the buyer should see
+fn sale_error(e: anyhow::Error, action: &str) -> (StatusCode, String) {
+    if let Some(presale) = e.downcast_ref::<PresaleError>() {
+        let status = match presale {
+            PresaleError::NotStarted(_) | PresaleError::Closed(_) => StatusCode::CONFLICT,
+            PresaleError::NotAllowlisted => StatusCode::FORBIDDEN,
+        };
+        return (status, presale.to_string());
+    }
+    if let Some(quote) = e.downcast_ref::<QuoteError>() {
+        let status = match quote {
+            QuoteError::NotFound => StatusCode::NOT_FOUND,
+            QuoteError::Unavailable => StatusCode::CONFLICT,
+            QuoteError::Mismatch(_) => StatusCode::BAD_REQUEST,
+        };
+        return (status, quote.to_string());
+    }
+    match e.downcast_ref::<PurchaseLimitError>() {
+        Some(limit) if limit.is_ticket_size() => (StatusCode::BAD_REQUEST, limit.to_string()),
+        Some(limit) => (StatusCode::CONFLICT, limit.to_string()),
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {}: {}", action, e),
+        ),
+    }
+}
 
-    // Verify campaign exists and is active
+async fn get_active_campaign(state: &AppState, campaign_id: Uuid) -> Result<(), (StatusCode, String)> {
     let campaign = state
         .db
-        .get_campaign(payload.campaign_id)
+        .get_campaign(campaign_id)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or((StatusCode::NOT_FOUND, "Campaign not found".to_string()))?;
 
-    // Check campaign status
     if campaign.status != "approved" && campaign.status != "running" {
         return Err((
             StatusCode::BAD_REQUEST,
             format!("Campaign is not active. Status: {}", campaign.status),
         ));
     }
+    Ok(())
+}
 
-    // Record purchase in database
-    let purchase_id = state
+/// Quote a purchase and return the transaction that pays for it
+///
+/// POST /purchases/quote
+pub async fn create_quote(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<QuoteRequest>,
+) -> Result<Json<QuoteResponse>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    get_active_campaign(&state, payload.campaign_id).await?;
+
+    let treasury = campaign_treasury(&state, payload.campaign_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // Sale checks run first so a rejected purchase never touches the network,
+    // and the wallet is resolved before the quote is stored so a chain
+    // outage leaves no quote behind that the buyer was never given
+    state
         .db
-        .create_purchase(
-            &user_address,
-            payload.campaign_id,
-            &payload.mkoin_paid,
-            &payload.tokens_received,
-            &payload.tx_hash,
-        )
+        .check_quote(&user_address, payload.campaign_id, payload.mkoin_amount)
+        .await
+        .map_err(|e| sale_error(e, "create quote"))?;
+    let jetton_wallet = state
+        .purchase_verifier
+        .mkoin_wallet(&user_address)
         .await
         .map_err(|e| {
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to create purchase: {}", e),
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to resolve MKOIN wallet: {}", e),
             )
         })?;
+    let quote = state
+        .db
+        .create_quote(&user_address, payload.campaign_id, payload.mkoin_amount, &treasury)
+        .await
+        .map_err(|e| sale_error(e, "create quote"))?;
+    let payload_boc = quote_transfer_body(&quote, &treasury)
+        .and_then(|body| Ok(BagOfCells::from_root(body).serialize(true)?))
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to build transfer: {}", e)))?;
 
-    // Update portfolio balance
-    let token_address = campaign
-        .token_address
-        .unwrap_or_else(|| "0:00d2042b5a38fa538142608b0c87eaab75780684ca2313066dbc693c954253c9".to_string());
+    Ok(Json(QuoteResponse {
+        quote_id: quote.id,
+        campaign_id: quote.campaign_id,
+        mkoin_amount: quote.mkoin_amount,
+        tokens: quote.tokens,
+        price: quote.price,
+        fees: QuoteFees {
+            attached_ton: TRANSFER_ATTACHED_TON.to_string(),
+            forward_ton: TRANSFER_FORWARD_TON.to_string(),
+        },
+        expires_at: quote.expires_at,
+        signature: auth::sign_message(&quote.signing_message()),
+        transaction: TonConnectTransaction {
+            valid_until: quote.expires_at.timestamp(),
+            messages: vec![TonConnectMessage {
+                address: jetton_wallet,
+                amount: TRANSFER_ATTACHED_TON.to_string(),
+                payload: base64::engine::general_purpose::STANDARD.encode(payload_boc),
+            }],
+        },
+    }))
+}
 
-    state
+/// Record a purchase against a signed quote
+///
+/// Honours `Idempotency-Key`, so a retried submission returns the original
+/// purchase instead of failing on the already-claimed quote.
+pub async fn create_purchase(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<CreatePurchaseRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // Get user address from header
+    let user_address = get_user_address(&headers)?;
+
+    idempotent(
+        &state,
+        &headers,
+        "purchase",
+        &user_address,
+        &payload,
+        record_purchase(&state, &user_address, &payload),
+    )
+    .await
+}
+
+async fn record_purchase(
+    state: &AppState,
+    user_address: &str,
+    payload: &CreatePurchaseRequest,
+) -> Result<Json<PurchaseResponse>, (StatusCode, String)> {
+    // The purchase must carry the terms the server quoted and signed
+    let quote = state
         .db
-        .upsert_portfolio(
-            &user_address,
-            &token_address,
-            &payload.tokens_received,
-            0, // lt will be updated by indexer
-        )
+        .get_quote(payload.quote_id)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to update portfolio: {}", e),
-            )
-        })?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, QuoteError::NotFound.to_string()))?;
+    if !auth::verify_message_signature(&quote.signing_message(), &payload.signature) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid quote signature".to_string()));
+    }
+    let mismatch = if quote.user_address != user_address {
+        Some("buyer")
+    } else if quote.campaign_id != payload.campaign_id {
+        Some("campaign")
+    } else if quote.mkoin_amount != payload.mkoin_paid {
+        Some("amount")
+    } else {
+        None
+    };
+    if let Some(field) = mismatch {
+        return Err((StatusCode::BAD_REQUEST, QuoteError::Mismatch(field).to_string()));
+    }
 
-    // Invalidate cache
-    state
-        .cache
-        .invalidate(&format!("portfolio:{}", user_address))
-        .await;
+    get_active_campaign(state, payload.campaign_id).await?;
+
+    // Claims the quote and re-checks caps and ticket limits atomically
+    let (purchase_id, tokens_received) = state
+        .db
+        .create_purchase(user_address, payload.quote_id, &payload.tx_hash)
+        .await
+        .map_err(|e| sale_error(e, "create purchase"))?;
+
+    // The tokens reach the portfolio once their delivery is indexed
     state
         .cache
         .invalidate(&format!("campaign:stats:{}", payload.campaign_id))
@@ -114,9 +283,130 @@ pub async fn create_purchase(
         id: purchase_id,
         status: "success".to_string(),
         message: "Purchase recorded. Awaiting blockchain confirmation.".to_string(),
+        tokens_received,
     }))
 }
 
+/// Invalidate what a purchase changing status affects
+pub(crate) async fn invalidate_purchase_caches(state: &AppState, purchase: &Purchase) {
+    state
+        .cache
+        .invalidate(&format!("portfolio:{}", purchase.user_address))
+        .await;
+    state
+        .cache
+        .invalidate(&format!("campaign:stats:{}", purchase.campaign_id))
+        .await;
+    state
+        .cache
+        .invalidate(&format!("campaign:purchases:{}", purchase.campaign_id))
+        .await;
+}
+
+/// Check a pending purchase against chain data and record the outcome
+///
+/// Confirmed purchases are queued for token delivery; rejected ones are
+/// marked failed with the reason. Pending ones are left untouched.
+pub(crate) async fn verify_purchase(state: &AppState, purchase: &Purchase) -> anyhow::Result<Verification> {
+    let quote = match purchase.quote_id {
+        Some(id) => state.db.get_quote(id).await?,
+        None => None,
+    };
+    // The wallet the buyer was quoted to pay, even if the campaign's
+    // treasury changed since
+    let treasury = match quote.as_ref().and_then(|q| q.treasury_address.clone()) {
+        Some(treasury) => treasury,
+        None => state.mkoin_service.get_admin_address(),
+    };
+    let verification = state
+        .purchase_verifier
+        .verify(purchase, quote.as_ref(), &treasury)
+        .await?;
+
+    match &verification {
+        Verification::Confirmed { tx_hash } => {
+            if !state.db.confirm_purchase(purchase.id, tx_hash).await? {
+                // Still pending means the payment already confirmed another purchase
+                let reason = format!("Payment {} was already used by another purchase", tx_hash);
+                state.db.fail_purchase(purchase.id, &reason).await?;
+                invalidate_purchase_caches(state, purchase).await;
+                return Ok(Verification::Rejected(reason));
+            }
+        }
+        Verification::Rejected(reason) => {
+            state.db.fail_purchase(purchase.id, reason).await?;
+        }
+        Verification::Pending => return Ok(verification),
+    }
+
+    invalidate_purchase_caches(state, purchase).await;
+    Ok(verification)
+}
+
+/// Verify the on-chain payment of a purchase
+///
+/// POST /purchases/:id/verify
+///
+/// Open to anyone holding the purchase id, so each purchase is checked on
+/// chain at most once every `VERIFY_INTERVAL_SECS`.
+pub async fn verify_purchase_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Purchase>, (StatusCode, String)> {
+    let purchase = state
+        .db
+        .get_purchase(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Purchase not found".to_string()))?;
+
+    if purchase.status != "pending" {
+        return Ok(Json(purchase));
+    }
+    let due = state
+        .db
+        .start_purchase_check(id, VERIFY_INTERVAL_SECS)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !due {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Purchase was checked moments ago, try again shortly".to_string(),
+        ));
+    }
+
+    verify_purchase(&state, &purchase).await.map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Failed to verify purchase: {}", e),
+        )
+    })?;
+
+    let purchase = state
+        .db
+        .get_purchase(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Purchase not found".to_string()))?;
+    Ok(Json(purchase))
+}
+
+/// Delivery of the purchased campaign jettons
+///
+/// GET /purchases/:id/delivery
+pub async fn get_purchase_delivery(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TokenDelivery>, (StatusCode, String)> {
+    let delivery = state
+        .db
+        .get_purchase_delivery(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "No delivery for this purchase yet".to_string()))?;
+    Ok(Json(delivery))
+}
+
 pub async fn get_user_purchases(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -185,7 +475,10 @@ pub async fn get_campaign_stats_handler(
 pub fn purchases_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/purchases", post(create_purchase))
+        .route("/purchases/quote", post(create_quote))
         .route("/purchases/my", get(get_user_purchases))
+        .route("/purchases/{id}/verify", post(verify_purchase_handler))
+        .route("/purchases/{id}/delivery", get(get_purchase_delivery))
         .route(
             "/campaigns/{campaign_id}/purchases",
             get(get_campaign_purchases_handler),
@@ -0,0 +1,210 @@
+//! Chain side of MKOIN redemptions
+//!
+//! Each pass reads the treasury's history once, back to the oldest
+//! redemption it has to settle, and:
+//!
+//! 1. marks redemptions received once the user's MKOIN arrived; requests
+//!    still without it after `REDEMPTION_TRANSFER_TTL_SECS` expire;
+//! 2. rejects cancelled or expired requests whose MKOIN arrived late, so
+//!    it is returned;
+//! 3. resolves sent burns and returns from their excesses or bounce, and
+//!    requeues those that expired without landing;
+//! 4. burns the MKOIN of paid redemptions and returns that of rejected ones.
+
+use crate::api::AppState;
+use crate::db::redemptions::{REDEMPTION_TRANSFER_TTL_SECS, Redemption};
+use crate::ton::client::History;
+use crate::ton::delivery::{DeliveryOutcome, find_burn_outcome, find_delivery_outcome};
+use crate::ton::mkoin_service::{
+    MESSAGE_LOOKBACK_SECS, MessageStatus, get_mkoin_address, message_outcome, uncertain_message,
+};
+use crate::ton::redemption::{find_redemption_transfer, settlement_query_id};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+pub const MAX_SETTLE_ATTEMPTS: i32 = 3;
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+const BATCH_SIZE: i64 = 50;
+
+pub async fn run_redemption_worker(state: Arc<AppState>) {
+    info!("Starting redemption worker");
+
+    loop {
+        if let Err(e) = process_redemptions(&state).await {
+            error!("Redemption step failed: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn process_redemptions(state: &AppState) -> anyhow::Result<()> {
+    let awaiting = state.db.get_redemptions_by_status("awaiting_transfer", BATCH_SIZE).await?;
+    let closed = state.db.get_unreceived_closed_redemptions(BATCH_SIZE).await?;
+    let mut sent = state.db.get_redemptions_by_status("burning", BATCH_SIZE).await?;
+    sent.extend(state.db.get_redemptions_by_status("returning", BATCH_SIZE).await?);
+    let lookback = Duration::seconds(MESSAGE_LOOKBACK_SECS);
+    let since = awaiting
+        .iter()
+        .chain(&closed)
+        .filter_map(|r| r.created_at)
+        .chain(sent.iter().filter_map(|r| r.settle_sent_at))
+        .min()
+        .map(|t| t - lookback);
+
+    if let Some(since) = since {
+        let history = state
+            .mkoin_service
+            .wallet_transactions_since(&state.mkoin_service.get_admin_address(), since)
+            .await?;
+        let mkoin_master = get_mkoin_address();
+        let mkoin_wallet = state.mkoin_service.admin_jetton_wallet(&mkoin_master).await?;
+
+        receive_transfers(state, &history.txs, &mkoin_wallet, awaiting).await?;
+        receive_late_transfers(state, &history.txs, &mkoin_wallet, closed).await?;
+        for redemption in &sent {
+            resolve_settlement(state, &history, &mkoin_master, &mkoin_wallet, redemption).await?;
+        }
+    }
+
+    for status in ["paid", "rejected"] {
+        for redemption in state.db.get_redemptions_by_status(status, BATCH_SIZE).await? {
+            send_settlement(state, &redemption).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn receive_transfers(
+    state: &AppState,
+    txs: &[serde_json::Value],
+    mkoin_wallet: &str,
+    awaiting: Vec<Redemption>,
+) -> anyhow::Result<()> {
+    let ttl = Duration::seconds(REDEMPTION_TRANSFER_TTL_SECS);
+
+    for redemption in awaiting {
+        match find_redemption_transfer(txs, mkoin_wallet, &redemption) {
+            Some(tx_hash) => {
+                state.db.mark_redemption_received(redemption.id, &tx_hash).await?;
+                info!("Redemption {} received in {}", redemption.id, tx_hash);
+            }
+            None => {
+                let stale = redemption.created_at.is_some_and(|t| Utc::now() - t >= ttl);
+                if stale {
+                    state.db.expire_redemption(redemption.id).await?;
+                    info!("Redemption {} expired without a transfer", redemption.id);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Queue the return of MKOIN that arrived after its request was closed
+async fn receive_late_transfers(
+    state: &AppState,
+    txs: &[serde_json::Value],
+    mkoin_wallet: &str,
+    closed: Vec<Redemption>,
+) -> anyhow::Result<()> {
+    for redemption in closed {
+        if let Some(tx_hash) = find_redemption_transfer(txs, mkoin_wallet, &redemption) {
+            state.db.reject_late_redemption(redemption.id, &tx_hash).await?;
+            warn!(
+                "Redemption {} received in {} after it was {}, returning",
+                redemption.id, tx_hash, redemption.status
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Match a sent burn or return with its excesses or bounce, or requeue it
+/// once it expired without landing
+async fn resolve_settlement(
+    state: &AppState,
+    history: &History,
+    mkoin_master: &str,
+    mkoin_wallet: &str,
+    redemption: &Redemption,
+) -> anyhow::Result<()> {
+    let (Some(msg_hash), Some(sent_at)) = (redemption.settle_msg_hash.as_deref(), redemption.settle_sent_at) else {
+        return Ok(());
+    };
+    match message_outcome(history, msg_hash, sent_at, Utc::now()) {
+        MessageStatus::Landed { .. } => {}
+        MessageStatus::Expired => {
+            state
+                .db
+                .retry_redemption_settlement(redemption, "Settlement expired without landing")
+                .await?;
+            warn!("Redemption {} settlement expired without landing", redemption.id);
+            return Ok(());
+        }
+        MessageStatus::Pending => return Ok(()),
+    }
+
+    let txs = &history.txs;
+    let query_id = settlement_query_id(redemption.id, redemption.settle_attempts);
+    let outcome = if redemption.status == "burning" {
+        find_burn_outcome(txs, mkoin_master, mkoin_wallet, query_id)
+    } else {
+        find_delivery_outcome(txs, mkoin_wallet, query_id)
+    };
+
+    match outcome {
+        DeliveryOutcome::Delivered { tx_hash } => {
+            let redemption = state.db.complete_redemption_settlement(redemption, &tx_hash).await?;
+            info!("Redemption {} {} in {}", redemption.id, redemption.status, tx_hash);
+        }
+        DeliveryOutcome::Bounced { tx_hash } => {
+            let reason = format!("Settlement bounced in {}", tx_hash);
+            state.db.retry_redemption_settlement(redemption, &reason).await?;
+            warn!("Redemption {} settlement bounced in {}", redemption.id, tx_hash);
+        }
+        DeliveryOutcome::InFlight => {}
+    }
+    Ok(())
+}
+
+/// Burn a paid redemption's MKOIN, or send a rejected one's back
+async fn send_settlement(state: &AppState, redemption: &Redemption) -> anyhow::Result<()> {
+    let Some(attempt) = state
+        .db
+        .start_redemption_settlement(redemption.id, MAX_SETTLE_ATTEMPTS)
+        .await?
+    else {
+        return Ok(());
+    };
+    let query_id = settlement_query_id(redemption.id, attempt);
+
+    let sent = if redemption.status == "paid" {
+        state.mkoin_service.burn_mkoin(redemption.amount, query_id).await
+    } else {
+        let comment = format!("Hazelnut redemption {} returned", redemption.id);
+        state
+            .mkoin_service
+            .transfer_mkoin(&redemption.user_address, redemption.amount, query_id, Some(&comment))
+            .await
+    };
+
+    match sent {
+        Ok(msg_hash) => {
+            state.db.mark_redemption_settlement_sent(redemption, &msg_hash).await?;
+        }
+        Err(e) => match uncertain_message(&e) {
+            Some(msg_hash) => {
+                warn!("Redemption {} settlement attempt {} may have been sent: {}", redemption.id, attempt, e);
+                state.db.mark_redemption_settlement_sent(redemption, msg_hash).await?;
+            }
+            None => {
+                warn!("Redemption {} settlement attempt {} failed: {}", redemption.id, attempt, e);
+                state.db.record_redemption_error(redemption.id, &e.to_string()).await?;
+            }
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,259 @@
+//! MKOIN redemption to EUR
+//!
+//! A user registers a bank account and requests to redeem MKOIN. The request
+//! waits for the user's MKOIN transfer to the treasury, then an admin
+//! approves and pays it out (see `admin::redemptions`); the received MKOIN
+//! is burned afterwards (see `redemption_worker`).
+
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::idempotency::idempotent;
+use crate::api::purchases::{TonConnectMessage, TonConnectTransaction, get_user_address};
+use crate::db::redemptions::{BankAccount, REDEMPTION_TRANSFER_TTL_SECS, Redemption, RedemptionError, RedemptionEvent};
+use crate::ton::mkoin_service::{TRANSFER_ATTACHED_TON, get_mkoin_address};
+use crate::ton::redemption::redemption_transfer_body;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{delete, get, post},
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tonlib_core::cell::BagOfCells;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct AddBankAccountRequest {
+    pub iban: String,
+    pub holder_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRedemptionRequest {
+    pub bank_account_id: Uuid,
+    pub amount: TokenAmount, // MKOIN nanocoins, as a string
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateRedemptionResponse {
+    pub redemption: Redemption,
+    /// MKOIN transfer the user's wallet must send to the treasury,
+    /// addressed to their MKOIN jetton wallet
+    pub transaction: TonConnectTransaction,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedemptionDetails {
+    pub redemption: Redemption,
+    pub bank_account: Option<BankAccount>,
+    pub events: Vec<RedemptionEvent>,
+}
+
+pub fn redemption_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/bank-accounts", get(get_bank_accounts).post(add_bank_account))
+        .route("/bank-accounts/{id}", delete(remove_bank_account))
+        .route("/redemptions", post(create_redemption))
+        .route("/redemptions/my", get(get_my_redemptions))
+        .route("/redemptions/{id}", get(get_redemption).delete(cancel_redemption))
+}
+
+pub(crate) fn redemption_error(e: anyhow::Error, action: &str) -> (StatusCode, String) {
+    match e.downcast_ref::<RedemptionError>() {
+        Some(err) => {
+            let status = match err {
+                RedemptionError::InvalidIban(_) | RedemptionError::MissingHolder | RedemptionError::InvalidAmount => {
+                    StatusCode::BAD_REQUEST
+                }
+                RedemptionError::BankAccountNotFound | RedemptionError::NotFound => StatusCode::NOT_FOUND,
+                RedemptionError::InvalidTransition { .. } => StatusCode::CONFLICT,
+            };
+            (status, err.to_string())
+        }
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {}: {}", action, e),
+        ),
+    }
+}
+
+/// GET /bank-accounts
+async fn get_bank_accounts(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<BankAccount>>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let accounts = state
+        .db
+        .get_bank_accounts(&user_address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(accounts))
+}
+
+/// POST /bank-accounts
+/// Body: { "iban": "DE89 3704 0044 0532 0130 00", "holder_name": "..." }
+async fn add_bank_account(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<AddBankAccountRequest>,
+) -> Result<Json<BankAccount>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let account = state
+        .db
+        .add_bank_account(&user_address, &payload.iban, &payload.holder_name)
+        .await
+        .map_err(|e| redemption_error(e, "add bank account"))?;
+    Ok(Json(account))
+}
+
+/// DELETE /bank-accounts/:id
+async fn remove_bank_account(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let removed = state
+        .db
+        .remove_bank_account(id, &user_address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !removed {
+        return Err((StatusCode::NOT_FOUND, RedemptionError::BankAccountNotFound.to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Request a redemption and return the transfer that funds it
+///
+/// POST /redemptions
+/// Body: { "bank_account_id": "...", "amount": "..." }
+///
+/// Honours `Idempotency-Key`.
+async fn create_redemption(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateRedemptionRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+
+    idempotent(
+        &state,
+        &headers,
+        "redemption",
+        &user_address,
+        &payload,
+        request_redemption(&state, &user_address, &payload),
+    )
+    .await
+}
+
+async fn request_redemption(
+    state: &AppState,
+    user_address: &str,
+    payload: &CreateRedemptionRequest,
+) -> Result<Json<CreateRedemptionResponse>, (StatusCode, String)> {
+    // Request checks run first so a rejected request never touches the network
+    let redemption = state
+        .db
+        .create_redemption(user_address, payload.bank_account_id, payload.amount)
+        .await
+        .map_err(|e| redemption_error(e, "request redemption"))?;
+
+    let jetton_wallet = state
+        .purchase_verifier
+        .jetton_wallet(&get_mkoin_address(), user_address)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to resolve jetton wallet: {}", e),
+            )
+        })?;
+    let treasury = state.mkoin_service.get_admin_address();
+    let payload_boc = redemption_transfer_body(&redemption, &treasury)
+        .and_then(|body| Ok(BagOfCells::from_root(body).serialize(true)?))
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to build transfer: {}", e)))?;
+
+    let created_at = redemption.created_at.unwrap_or_else(chrono::Utc::now);
+    Ok(Json(CreateRedemptionResponse {
+        transaction: TonConnectTransaction {
+            valid_until: created_at.timestamp() + REDEMPTION_TRANSFER_TTL_SECS,
+            messages: vec![TonConnectMessage {
+                address: jetton_wallet,
+                amount: TRANSFER_ATTACHED_TON.to_string(),
+                payload: base64::engine::general_purpose::STANDARD.encode(payload_boc),
+            }],
+        },
+        redemption,
+    }))
+}
+
+/// GET /redemptions/my
+async fn get_my_redemptions(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Redemption>>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let redemptions = state
+        .db
+        .get_user_redemptions(&user_address)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(redemptions))
+}
+
+/// GET /redemptions/:id
+async fn get_redemption(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<RedemptionDetails>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let details = load_redemption(&state, id).await?;
+    if details.redemption.user_address != user_address {
+        return Err((StatusCode::NOT_FOUND, RedemptionError::NotFound.to_string()));
+    }
+    Ok(Json(details))
+}
+
+/// Withdraw a request whose MKOIN was not sent yet
+///
+/// DELETE /redemptions/:id
+async fn cancel_redemption(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Redemption>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let redemption = state
+        .db
+        .cancel_redemption(id, &user_address)
+        .await
+        .map_err(|e| redemption_error(e, "cancel redemption"))?;
+    Ok(Json(redemption))
+}
+
+/// A redemption with its bank account and state history
+pub(crate) async fn load_redemption(state: &AppState, id: Uuid) -> Result<RedemptionDetails, (StatusCode, String)> {
+    let redemption = state
+        .db
+        .get_redemption(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, RedemptionError::NotFound.to_string()))?;
+    let bank_account = state
+        .db
+        .get_bank_account(redemption.bank_account_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let events = state
+        .db
+        .get_redemption_events(id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(RedemptionDetails { redemption, bank_account, events })
+}
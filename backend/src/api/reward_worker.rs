@@ -0,0 +1,133 @@
+//! Reward claims and expiry
+//!
+//! Each pass:
+//!
+//! 1. expires unclaimed rewards whose campaign claim period ended;
+//! 2. settles claims from the admin wallet's history. A sent claim is
+//!    confirmed by its excesses and failed, freeing its rewards, only once
+//!    it bounced or its message expired without landing. A claim left
+//!    pending by a request that died has no message to follow, so the
+//!    history since it was made is searched for its `query_id` instead.
+
+use crate::api::AppState;
+use crate::db::rewards::RewardClaim;
+use crate::ton::client::History;
+use crate::ton::delivery::{DeliveryOutcome, delivery_query_id, find_delivery_outcome};
+use crate::ton::mkoin_service::{MESSAGE_LOOKBACK_SECS, MessageStatus, get_mkoin_address, message_outcome};
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+const BATCH_SIZE: i64 = 50;
+
+pub async fn run_reward_worker(state: Arc<AppState>) {
+    info!("Starting reward worker");
+
+    loop {
+        if let Err(e) = process_rewards(&state).await {
+            error!("Reward step failed: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn process_rewards(state: &AppState) -> anyhow::Result<()> {
+    let expired = state.db.expire_rewards().await?;
+    if expired > 0 {
+        info!("Expired {} unclaimed reward(s)", expired);
+    }
+
+    let sent = state.db.get_reward_claims_by_status("sent", BATCH_SIZE).await?;
+    let abandoned = state.db.get_abandoned_reward_claims(BATCH_SIZE).await?;
+    let since = sent
+        .iter()
+        .filter_map(|c| c.sent_at)
+        .chain(abandoned.iter().filter_map(|c| c.created_at))
+        .min();
+    let Some(since) = since else {
+        return Ok(());
+    };
+
+    let history = state
+        .mkoin_service
+        .wallet_transactions_since(
+            &state.mkoin_service.get_admin_address(),
+            since - Duration::seconds(MESSAGE_LOOKBACK_SECS),
+        )
+        .await?;
+    let jetton_wallet = state.mkoin_service.admin_jetton_wallet(&get_mkoin_address()).await?;
+
+    for claim in &sent {
+        settle_sent_claim(state, &history, &jetton_wallet, claim).await?;
+    }
+    for claim in &abandoned {
+        settle_abandoned_claim(state, &history, &jetton_wallet, claim).await?;
+    }
+    Ok(())
+}
+
+async fn settle_sent_claim(
+    state: &AppState,
+    history: &History,
+    jetton_wallet: &str,
+    claim: &RewardClaim,
+) -> anyhow::Result<()> {
+    let (Some(msg_hash), Some(sent_at)) = (claim.msg_hash.as_deref(), claim.sent_at) else {
+        return Ok(());
+    };
+    match message_outcome(history, msg_hash, sent_at, Utc::now()) {
+        MessageStatus::Landed { .. } => {
+            settle_delivery(state, history, jetton_wallet, claim).await?;
+        }
+        MessageStatus::Expired => {
+            warn!("Reward claim {} transfer {} expired without landing", claim.id, msg_hash);
+            state.db.fail_reward_claim(claim.id, "Transfer expired without landing").await?;
+        }
+        MessageStatus::Pending => {}
+    }
+    Ok(())
+}
+
+/// Settle a claim whose request died before recording its transfer
+///
+/// Its message expired long ago, so no outcome in a complete history means
+/// it was never sent.
+async fn settle_abandoned_claim(
+    state: &AppState,
+    history: &History,
+    jetton_wallet: &str,
+    claim: &RewardClaim,
+) -> anyhow::Result<()> {
+    if settle_delivery(state, history, jetton_wallet, claim).await? || !history.complete {
+        return Ok(());
+    }
+    warn!("Reward claim {} was abandoned before its transfer was sent", claim.id);
+    state.db.fail_reward_claim(claim.id, "Transfer was never sent").await?;
+    Ok(())
+}
+
+/// Confirm or fail a claim from its transfer's outcome; false while none
+/// is seen
+async fn settle_delivery(
+    state: &AppState,
+    history: &History,
+    jetton_wallet: &str,
+    claim: &RewardClaim,
+) -> anyhow::Result<bool> {
+    match find_delivery_outcome(&history.txs, jetton_wallet, delivery_query_id(claim.id, 0)) {
+        DeliveryOutcome::Delivered { tx_hash } => {
+            if state.db.confirm_reward_claim(claim.id, &tx_hash).await? {
+                info!("Reward claim {} confirmed in {}", claim.id, tx_hash);
+            }
+        }
+        DeliveryOutcome::Bounced { tx_hash } => {
+            warn!("Reward claim {} bounced in {}", claim.id, tx_hash);
+            let reason = format!("Transfer bounced in {}", tx_hash);
+            state.db.fail_reward_claim(claim.id, &reason).await?;
+        }
+        DeliveryOutcome::InFlight => return Ok(false),
+    }
+    Ok(true)
+}
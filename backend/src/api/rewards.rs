@@ -0,0 +1,248 @@
+//! Claimable rewards
+//!
+//! Claim distributions (see `admin::distributions`) credit each holder with
+//! a reward. A holder claims all unclaimed rewards at once and receives
+//! them as one MKOIN transfer, which `reward_worker` confirms.
+//!
+//! Merkle distributions are claimed from a claim contract instead; the
+//! holder fetches the proof of their payout line here. The backend only
+//! computes the root: deploying and funding the contract happens outside
+//! it.
+
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::api::idempotency::idempotent;
+use crate::api::purchases::get_user_address;
+use crate::db::distributions::{PayoutMode, payout_tree};
+use crate::db::rewards::{Reward, RewardClaim, RewardError};
+use crate::ton::address_utils::to_raw_address;
+use crate::ton::delivery::delivery_query_id;
+use crate::ton::merkle::leaf_hash;
+use crate::ton::mkoin_service::uncertain_message;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct MyRewardsResponse {
+    /// Sum of the rewards that can be claimed now
+    pub claimable: TokenAmount,
+    pub rewards: Vec<Reward>,
+}
+
+/// What a holder submits to the claim contract of a merkle distribution
+#[derive(Debug, Serialize)]
+pub struct MerkleProofResponse {
+    pub distribution_id: Uuid,
+    pub merkle_root: String,
+    /// Address of the payout line, as snapshotted
+    pub address: String,
+    pub amount: TokenAmount,
+    pub leaf: String,
+    /// Sibling hashes from the leaf up to the root, hex
+    pub proof: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RewardClaimResponse {
+    pub claim: RewardClaim,
+    pub rewards: Vec<Reward>,
+}
+
+pub fn reward_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/rewards/my", get(get_my_rewards))
+        .route("/rewards/claim", post(claim_rewards))
+        .route("/rewards/claims/{id}", get(get_reward_claim))
+        .route("/rewards/{distribution}/proof/{address}", get(get_merkle_proof))
+}
+
+pub(crate) fn reward_error(e: anyhow::Error, action: &str) -> (StatusCode, String) {
+    match e.downcast_ref::<RewardError>() {
+        Some(err) => {
+            let status = match err {
+                RewardError::NothingToClaim => StatusCode::CONFLICT,
+                RewardError::ClaimNotFound => StatusCode::NOT_FOUND,
+                RewardError::InvalidClaimPeriod => StatusCode::BAD_REQUEST,
+            };
+            (status, err.to_string())
+        }
+        None => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {}: {}", action, e),
+        ),
+    }
+}
+
+/// GET /rewards/my
+async fn get_my_rewards(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<MyRewardsResponse>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    let rewards = state
+        .db
+        .get_user_rewards(&user_address)
+        .await
+        .map_err(|e| reward_error(e, "load rewards"))?;
+
+    let now = chrono::Utc::now();
+    let claimable = TokenAmount::checked_sum(
+        rewards
+            .iter()
+            .filter(|r| r.status == "unclaimed" && r.expires_at.is_none_or(|t| t > now))
+            .map(|r| r.amount),
+    )
+    .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "Reward total overflows".to_string()))?;
+
+    Ok(Json(MyRewardsResponse { claimable, rewards }))
+}
+
+/// Claim all unclaimed rewards with a single MKOIN transfer
+///
+/// POST /rewards/claim
+///
+/// Honours `Idempotency-Key`.
+async fn claim_rewards(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+
+    idempotent(
+        &state,
+        &headers,
+        "reward_claim",
+        &user_address,
+        &(),
+        send_claim(&state, &user_address),
+    )
+    .await
+}
+
+async fn send_claim(state: &AppState, user_address: &str) -> Result<Json<RewardClaimResponse>, (StatusCode, String)> {
+    let claim = state
+        .db
+        .start_reward_claim(user_address)
+        .await
+        .map_err(|e| reward_error(e, "claim rewards"))?;
+
+    // Lets the holder's wallet correlate the transfer with the claim
+    let query_id = delivery_query_id(claim.id, 0);
+    let comment = format!("Hazelnut rewards {}", claim.id);
+    let msg_hash = match state
+        .mkoin_service
+        .transfer_mkoin(user_address, claim.amount, query_id, Some(&comment))
+        .await
+    {
+        Ok(msg_hash) => msg_hash,
+        Err(e) => match uncertain_message(&e) {
+            Some(msg_hash) => {
+                warn!("Reward claim {} may have been sent: {}", claim.id, e);
+                msg_hash.to_string()
+            }
+            None => {
+                warn!("Reward claim {} could not be sent: {}", claim.id, e);
+                state
+                    .db
+                    .fail_reward_claim(claim.id, &e.to_string())
+                    .await
+                    .map_err(|e| reward_error(e, "record claim"))?;
+                return Err((StatusCode::BAD_GATEWAY, format!("Failed to send rewards: {}", e)));
+            }
+        },
+    };
+    state
+        .db
+        .mark_reward_claim_sent(claim.id, &msg_hash)
+        .await
+        .map_err(|e| reward_error(e, "record claim"))?;
+    info!("Reward claim {} of {} MKOIN sent to {}", claim.id, claim.amount, user_address);
+
+    Ok(Json(load_claim(state, claim.id, user_address).await?))
+}
+
+/// GET /rewards/claims/:id
+async fn get_reward_claim(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<RewardClaimResponse>, (StatusCode, String)> {
+    let user_address = get_user_address(&headers)?;
+    Ok(Json(load_claim(&state, id, &user_address).await?))
+}
+
+/// A claim of `user_address`; other users' claims are not found
+async fn load_claim(state: &AppState, id: Uuid, user_address: &str) -> Result<RewardClaimResponse, (StatusCode, String)> {
+    let claim = state
+        .db
+        .get_reward_claim(id)
+        .await
+        .map_err(|e| reward_error(e, "load claim"))?
+        .filter(|c| c.user_address == user_address)
+        .ok_or_else(|| reward_error(RewardError::ClaimNotFound.into(), "load claim"))?;
+    let rewards = state
+        .db
+        .get_claim_rewards(id)
+        .await
+        .map_err(|e| reward_error(e, "load claim"))?;
+    Ok(RewardClaimResponse { claim, rewards })
+}
+
+/// Proof of an address's share in a merkle distribution
+///
+/// GET /rewards/:distribution/proof/:address
+///
+/// The address may be given in raw or user-friendly form.
+async fn get_merkle_proof(
+    State(state): State<Arc<AppState>>,
+    Path((distribution_id, address)): Path<(Uuid, String)>,
+) -> Result<Json<MerkleProofResponse>, (StatusCode, String)> {
+    let distribution = state
+        .db
+        .get_distribution(distribution_id)
+        .await
+        .map_err(|e| reward_error(e, "load distribution"))?
+        .filter(|d| d.payout_mode == PayoutMode::Merkle.as_str())
+        .ok_or((StatusCode::NOT_FOUND, "Merkle distribution not found".to_string()))?;
+    let merkle_root = distribution
+        .merkle_root
+        .ok_or((StatusCode::CONFLICT, "Distribution has no merkle root yet".to_string()))?;
+
+    let payouts = state
+        .db
+        .get_distribution_payouts(distribution_id)
+        .await
+        .map_err(|e| reward_error(e, "load payouts"))?;
+    let raw = to_raw_address(&address).ok();
+    let line = payouts
+        .iter()
+        .find(|p| p.user_address == address || (raw.is_some() && to_raw_address(&p.user_address).ok() == raw))
+        .ok_or((StatusCode::NOT_FOUND, "Address has no share in this distribution".to_string()))?;
+
+    let internal = |e: anyhow::Error| reward_error(e, "build proof");
+    let tree = payout_tree(payouts.iter().map(|p| (p.user_address.as_str(), p.amount))).map_err(internal)?;
+    if tree.root().to_hex() != merkle_root {
+        return Err(internal(anyhow::anyhow!("Payout lines no longer match the merkle root")));
+    }
+    let leaf = leaf_hash(&line.user_address, line.amount).map_err(internal)?;
+    let proof = tree
+        .proof(&leaf)
+        .ok_or_else(|| internal(anyhow::anyhow!("Leaf missing from the payout tree")))?;
+
+    Ok(Json(MerkleProofResponse {
+        distribution_id,
+        merkle_root,
+        address: line.user_address.clone(),
+        amount: line.amount,
+        leaf: leaf.to_hex(),
+        proof: proof.iter().map(|h| h.to_hex()).collect(),
+    }))
+}
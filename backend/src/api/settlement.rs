@@ -0,0 +1,149 @@
+//! Delivery of purchased campaign jettons
+//!
+//! A campaign's whole supply is minted to the platform wallet when the
+//! campaign is approved. Once a purchase is confirmed, a delivery is queued
+//! and the purchased amount is sent from the platform wallet to the buyer
+//! with a TEP-74 `transfer`. Sent deliveries are settled from the platform
+//! wallet's history: `excesses` mark them delivered, while a bounce or a
+//! transfer that expired without landing puts them back in the queue until
+//! `MAX_DELIVERY_ATTEMPTS` is hit.
+
+use crate::api::AppState;
+use crate::db::deliveries::TokenDelivery;
+use crate::ton::delivery::{DeliveryOutcome, delivery_query_id, find_delivery_outcome};
+use crate::ton::mkoin_service::{MESSAGE_LOOKBACK_SECS, MessageStatus, message_outcome, uncertain_message};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 3;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const BATCH_SIZE: i64 = 20;
+
+pub async fn run_settlement_worker(state: Arc<AppState>) {
+    info!("Starting token delivery worker");
+
+    loop {
+        if let Err(e) = settle(&state).await {
+            error!("Token delivery step failed: {}", e);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn settle(state: &AppState) -> anyhow::Result<()> {
+    let queued = state.db.queue_token_deliveries().await?;
+    if queued > 0 {
+        info!("Queued {} token deliveries", queued);
+    }
+
+    resolve_sent(state).await?;
+
+    for delivery in state.db.get_deliveries_by_status("pending", BATCH_SIZE).await? {
+        send_delivery(state, &delivery).await?;
+    }
+    Ok(())
+}
+
+/// Match sent deliveries with their excesses or bounce, and requeue those
+/// whose transfer expired without landing
+async fn resolve_sent(state: &AppState) -> anyhow::Result<()> {
+    let sent = state.db.get_deliveries_by_status("sent", BATCH_SIZE).await?;
+    let Some(oldest) = sent.iter().filter_map(|d| d.sent_at).min() else {
+        return Ok(());
+    };
+
+    let history = state
+        .mkoin_service
+        .wallet_transactions_since(
+            &state.mkoin_service.get_admin_address(),
+            oldest - chrono::Duration::seconds(MESSAGE_LOOKBACK_SECS),
+        )
+        .await?;
+    let mut jetton_wallets = HashMap::new();
+
+    for delivery in sent {
+        let (Some(msg_hash), Some(sent_at)) = (delivery.msg_hash.as_deref(), delivery.sent_at) else {
+            continue;
+        };
+        match message_outcome(&history, msg_hash, sent_at, Utc::now()) {
+            MessageStatus::Landed { .. } => {}
+            MessageStatus::Expired => {
+                let status = state
+                    .db
+                    .retry_delivery(delivery.id, "Transfer expired without landing", MAX_DELIVERY_ATTEMPTS)
+                    .await?;
+                warn!("Delivery {} transfer {} expired, now {}", delivery.id, msg_hash, status);
+                continue;
+            }
+            MessageStatus::Pending => continue,
+        }
+
+        if !jetton_wallets.contains_key(&delivery.token_address) {
+            let wallet = state
+                .mkoin_service
+                .admin_jetton_wallet(&delivery.token_address)
+                .await?;
+            jetton_wallets.insert(delivery.token_address.clone(), wallet);
+        }
+        let jetton_wallet = &jetton_wallets[&delivery.token_address];
+        let query_id = delivery_query_id(delivery.id, delivery.attempts);
+
+        match find_delivery_outcome(&history.txs, jetton_wallet, query_id) {
+            DeliveryOutcome::Delivered { tx_hash } => {
+                info!("Delivery {} completed in {}", delivery.id, tx_hash);
+                state.db.confirm_delivery(delivery.id, &tx_hash).await?;
+            }
+            DeliveryOutcome::Bounced { tx_hash } => {
+                let reason = format!("Transfer bounced in {}", tx_hash);
+                let status = state
+                    .db
+                    .retry_delivery(delivery.id, &reason, MAX_DELIVERY_ATTEMPTS)
+                    .await?;
+                warn!("Delivery {} bounced, now {}", delivery.id, status);
+            }
+            DeliveryOutcome::InFlight => {}
+        }
+    }
+    Ok(())
+}
+
+async fn send_delivery(state: &AppState, delivery: &TokenDelivery) -> anyhow::Result<()> {
+    let Some(attempt) = state.db.start_delivery_attempt(delivery.id).await? else {
+        return Ok(());
+    };
+    let comment = format!("Hazelnut purchase {}", delivery.purchase_id);
+
+    match state
+        .mkoin_service
+        .transfer_jetton(
+            &delivery.token_address,
+            &delivery.user_address,
+            delivery.amount,
+            delivery_query_id(delivery.id, attempt),
+            Some(&comment),
+        )
+        .await
+    {
+        Ok(msg_hash) => state.db.mark_delivery_sent(delivery.id, &msg_hash).await?,
+        Err(e) => match uncertain_message(&e) {
+            // Settled from the chain like any sent delivery
+            Some(msg_hash) => {
+                warn!("Delivery {} may have been sent: {}", delivery.id, e);
+                state.db.mark_delivery_sent(delivery.id, msg_hash).await?;
+            }
+            None => {
+                let status = state
+                    .db
+                    .retry_delivery(delivery.id, &e.to_string(), MAX_DELIVERY_ATTEMPTS)
+                    .await?;
+                warn!("Delivery {} could not be sent ({}): {}", delivery.id, status, e);
+            }
+        },
+    }
+    Ok(())
+}
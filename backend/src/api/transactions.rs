@@ -0,0 +1,235 @@
+//! Transaction history of an address, in the shape of the frontend
+//! `Transaction` type
+//!
+//! Entries are the transfers stored by the chain indexer (`ton::indexer`),
+//! newest first. Pages continue with `cursor`, the id of the last entry of
+//! the previous page. EUR values are taken at the time of the transfer:
+//! MKOIN is pegged to the euro and campaign tokens are valued at the price
+//! of their campaign's price source then (see `db::prices`).
+
+use crate::amount::TokenAmount;
+use crate::api::AppState;
+use crate::db::presale::address_variants;
+use crate::db::transfers::IndexedTransfer;
+use crate::ton::address_utils::to_raw_address;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionsQuery {
+    pub cursor: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionEntry {
+    /// Hash of the transaction that credited the transfer
+    pub id: String,
+    /// "sent" or "received"
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub from: String,
+    pub to: String,
+    pub counterparty: String,
+    /// "mint", "treasury", "user" or "external"
+    pub counterparty_label: String,
+    pub amount: f64,
+    /// "TON", "MKOIN" or the campaign token symbol
+    pub currency: String,
+    pub token_address: Option<String>,
+    /// None for TON, which has no EUR price here
+    pub eur_value: Option<f64>,
+    /// "completed" or "failed"
+    pub status: String,
+    pub timestamp: String,
+    pub lt: i64,
+    pub explorer_url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsPage {
+    pub transactions: Vec<TransactionEntry>,
+    /// Pass as `cursor` for the next page; None on the last page
+    pub next_cursor: Option<i64>,
+}
+
+pub fn transactions_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/transactions/{address}", get(get_transactions))
+}
+
+/// GET /transactions/:address?cursor=&limit=
+async fn get_transactions(
+    State(state): State<Arc<AppState>>,
+    Path(address): Path<String>,
+    Query(query): Query<TransactionsQuery>,
+) -> Result<Json<TransactionsPage>, (StatusCode, String)> {
+    let owner = to_raw_address(&address).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid address".to_string()))?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let page = build_page(&state, &owner, query.cursor, limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to load transactions: {}", e)))?;
+    Ok(Json(page))
+}
+
+async fn build_page(state: &AppState, owner: &str, cursor: Option<i64>, limit: i64) -> anyhow::Result<TransactionsPage> {
+    let transfers = state
+        .db
+        .get_address_transfers(&[owner.to_string()], cursor, limit)
+        .await?;
+
+    let mut campaign_ids: Vec<_> = transfers.iter().filter_map(|t| t.campaign_id).collect();
+    campaign_ids.sort();
+    campaign_ids.dedup();
+    let campaigns: HashMap<_, _> = state
+        .db
+        .get_campaigns_by_ids(&campaign_ids)
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect();
+
+    // Only the counterparties on this page, in any form users were stored in
+    let candidates: Vec<String> = transfers
+        .iter()
+        .flat_map(|t| address_variants(direction(t, owner).1))
+        .collect();
+    let users: HashSet<String> = state
+        .db
+        .get_registered_addresses(&candidates)
+        .await?
+        .iter()
+        .filter_map(|a| to_raw_address(a).ok())
+        .collect();
+    let treasury = to_raw_address(&state.mkoin_service.get_admin_address()).ok();
+    let explorer = std::env::var("EXPLORER_URL").unwrap_or_else(|_| "https://testnet.tonviewer.com".to_string());
+
+    let mut entries = Vec::new();
+    for transfer in &transfers {
+        let (kind, counterparty) = direction(transfer, owner);
+        let label = counterparty_label(transfer, counterparty, treasury.as_deref(), &users);
+
+        let campaign = transfer.campaign_id.and_then(|id| campaigns.get(&id));
+        let (currency, eur_value) = match (transfer.asset.as_str(), campaign) {
+            ("MKOIN", _) => ("MKOIN".to_string(), Some(transfer.amount.to_f64())),
+            ("campaign_token", Some(campaign)) => {
+                let price = state
+                    .db
+                    .token_prices_at(&[campaign.id], Some(transfer.occurred_at))
+                    .await?
+                    .remove(&campaign.id)
+                    .map_or(campaign.suggested_price, |p| p.price);
+                let value = transfer.amount.cost_at(price).map(TokenAmount::to_f64);
+                (campaign.token_symbol.clone(), value)
+            }
+            (asset, _) => (asset.to_string(), None),
+        };
+
+        entries.push(TransactionEntry {
+            id: transfer.tx_hash.clone(),
+            kind: kind.to_string(),
+            from: transfer.sender.clone(),
+            to: transfer.recipient.clone(),
+            counterparty: counterparty.to_string(),
+            counterparty_label: label.to_string(),
+            amount: transfer.amount.to_f64(),
+            currency,
+            token_address: transfer.token_address.clone(),
+            eur_value,
+            status: transfer.status.clone(),
+            timestamp: transfer.occurred_at.to_rfc3339(),
+            lt: transfer.lt,
+            explorer_url: format!("{}/transaction/{}", explorer.trim_end_matches('/'), transfer.tx_hash),
+        });
+    }
+
+    let next_cursor = match transfers.last() {
+        Some(last) if transfers.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+    Ok(TransactionsPage {
+        transactions: entries,
+        next_cursor,
+    })
+}
+
+/// "sent" or "received" as seen by `owner`, and the other side
+fn direction<'a>(transfer: &'a IndexedTransfer, owner: &str) -> (&'static str, &'a str) {
+    if transfer.sender == owner {
+        ("sent", &transfer.recipient)
+    } else {
+        ("received", &transfer.sender)
+    }
+}
+
+fn counterparty_label(
+    transfer: &IndexedTransfer,
+    counterparty: &str,
+    treasury: Option<&str>,
+    users: &HashSet<String>,
+) -> &'static str {
+    if transfer.is_mint {
+        "mint"
+    } else if treasury == Some(counterparty) {
+        "treasury"
+    } else if users.contains(counterparty) {
+        "user"
+    } else {
+        "external"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(sender: &str, recipient: &str, is_mint: bool) -> IndexedTransfer {
+        IndexedTransfer {
+            id: 1,
+            tx_hash: "ab".to_string(),
+            lt: 1,
+            asset: "MKOIN".to_string(),
+            token_address: None,
+            campaign_id: None,
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount: TokenAmount::from_nano(1),
+            is_mint,
+            status: "completed".to_string(),
+            occurred_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_direction_and_label() {
+        let users: HashSet<String> = ["bob".to_string()].into();
+        let treasury = Some("treasury");
+
+        let sent = transfer("alice", "bob", false);
+        assert_eq!(direction(&sent, "alice"), ("sent", "bob"));
+        assert_eq!(counterparty_label(&sent, "bob", treasury, &users), "user");
+
+        let paid = transfer("alice", "treasury", false);
+        assert_eq!(counterparty_label(&paid, direction(&paid, "alice").1, treasury, &users), "treasury");
+
+        // Minted by the admin, which is also the treasury
+        let minted = transfer("treasury", "alice", true);
+        assert_eq!(direction(&minted, "alice"), ("received", "treasury"));
+        assert_eq!(counterparty_label(&minted, "treasury", treasury, &users), "mint");
+
+        let unknown = transfer("carol", "alice", false);
+        assert_eq!(counterparty_label(&unknown, "carol", treasury, &users), "external");
+    }
+}
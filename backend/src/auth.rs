@@ -3,13 +3,16 @@ use argon2::{
     Argon2,
 };
 use anyhow::Result;
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{encode, decode, Header, Algorithm, Validation, EncodingKey, DecodingKey};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 use std::sync::OnceLock;
 
 static JWT_SECRET: OnceLock<String> = OnceLock::new();
+static MESSAGE_SIGNING_KEY: OnceLock<String> = OnceLock::new();
 
 fn get_jwt_secret() -> &'static str {
     JWT_SECRET.get_or_init(|| {
@@ -21,6 +24,17 @@ fn get_jwt_secret() -> &'static str {
     })
 }
 
+// Kept apart from the JWT secret so a leaked quote key cannot mint sessions
+fn get_message_signing_key() -> &'static str {
+    MESSAGE_SIGNING_KEY.get_or_init(|| {
+        std::env::var("MESSAGE_SIGNING_KEY")
+            .unwrap_or_else(|_| {
+                eprintln!("WARNING: MESSAGE_SIGNING_KEY not set, using default (INSECURE for production!)");
+                "default_signing_key_change_in_production".to_string()
+            })
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user id
@@ -67,3 +81,25 @@ pub fn verify_jwt(token: &str) -> Result<Claims> {
     )?;
     Ok(token_data.claims)
 }
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Sign a server-issued message (e.g. a purchase quote) with the message
+/// signing key
+pub fn sign_message(message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(get_message_signing_key().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Check a signature produced by [`sign_message`] in constant time
+pub fn verify_message_signature(message: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(get_message_signing_key().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
@@ -9,6 +9,8 @@ pub struct Config {
     pub ton_config_url: String, // URL to TON global config (e.g. from ton-center)
     pub api_host: String,
     pub api_port: u16,
+    /// Seconds a purchase may stay pending before its payment is considered lost
+    pub purchase_expiry_secs: i64,
 }
 
 impl Config {
@@ -25,6 +27,10 @@ impl Config {
                 .unwrap_or_else(|_| "8080".to_string())
                 .parse()
                 .expect("API_PORT must be a valid number"),
+            purchase_expiry_secs: env::var("PURCHASE_EXPIRY_SECS")
+                .unwrap_or_else(|_| "1800".to_string())
+                .parse()
+                .expect("PURCHASE_EXPIRY_SECS must be a valid number"),
         })
     }
 }
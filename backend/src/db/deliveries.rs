@@ -0,0 +1,151 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TokenDelivery {
+    pub id: Uuid,
+    pub purchase_id: Uuid,
+    /// Campaign jetton master
+    pub token_address: String,
+    pub user_address: String,
+    pub amount: TokenAmount,
+    pub status: String, // 'pending', 'sent', 'delivered', 'failed', 'cancelled'
+    pub attempts: i32,
+    pub msg_hash: Option<String>,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl Database {
+    /// Queue a delivery for every confirmed purchase whose campaign jetton
+    /// exists and that has none yet
+    pub async fn queue_token_deliveries(&self) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO token_deliveries (purchase_id, token_address, user_address, amount)
+            SELECT p.id, c.token_address, p.user_address, p.tokens_received
+            FROM purchases p
+            JOIN campaigns c ON c.id = p.campaign_id
+            WHERE p.status = 'confirmed'
+              AND c.token_address IS NOT NULL
+              AND c.token_address NOT LIKE 'COMPUTE_FAILED%'
+            ON CONFLICT (purchase_id) DO NOTHING
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn get_deliveries_by_status(&self, status: &str, limit: i64) -> Result<Vec<TokenDelivery>> {
+        let deliveries = sqlx::query_as::<_, TokenDelivery>(
+            r#"
+            SELECT id, purchase_id, token_address, user_address, amount, status, attempts,
+                   msg_hash, tx_hash, error, created_at, sent_at, delivered_at
+            FROM token_deliveries
+            WHERE status = $1
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(deliveries)
+    }
+
+    pub async fn get_purchase_delivery(&self, purchase_id: Uuid) -> Result<Option<TokenDelivery>> {
+        let delivery = sqlx::query_as::<_, TokenDelivery>(
+            r#"
+            SELECT id, purchase_id, token_address, user_address, amount, status, attempts,
+                   msg_hash, tx_hash, error, created_at, sent_at, delivered_at
+            FROM token_deliveries
+            WHERE purchase_id = $1
+            "#,
+        )
+        .bind(purchase_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(delivery)
+    }
+
+    /// Count a new attempt for a pending delivery; returns the attempt
+    /// number, or None if the delivery is not pending or its purchase is no
+    /// longer confirmed (e.g. refunded)
+    pub async fn start_delivery_attempt(&self, id: Uuid) -> Result<Option<i32>> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE token_deliveries
+            SET attempts = attempts + 1
+            WHERE id = $1 AND status = 'pending'
+              AND EXISTS (
+                  SELECT 1 FROM purchases p
+                  WHERE p.id = token_deliveries.purchase_id AND p.status = 'confirmed'
+              )
+            RETURNING attempts
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(rec.map(|r| r.attempts))
+    }
+
+    pub async fn mark_delivery_sent(&self, id: Uuid, msg_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE token_deliveries
+            SET status = 'sent', msg_hash = $2, error = NULL, sent_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            msg_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Send the delivery again on the next pass, or give up once
+    /// `max_attempts` were made; returns the new status
+    pub async fn retry_delivery(&self, id: Uuid, error: &str, max_attempts: i32) -> Result<String> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE token_deliveries
+            SET status = CASE WHEN attempts >= $3 THEN 'failed' ELSE 'pending' END,
+                error = $2
+            WHERE id = $1
+            RETURNING status
+            "#,
+            id,
+            error,
+            max_attempts
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(rec.status)
+    }
+
+    pub async fn confirm_delivery(&self, id: Uuid, tx_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE token_deliveries
+            SET status = 'delivered', tx_hash = $2, delivered_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            tx_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
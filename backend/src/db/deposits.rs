@@ -0,0 +1,747 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use crate::statement::{StatementEntry, StatementFormat};
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How long a deposit intent waits for its bank transfer
+pub const DEPOSIT_INTENT_TTL_DAYS: i64 = 14;
+
+/// Prefix of deposit references
+pub const REFERENCE_PREFIX: &str = "HZN";
+
+/// Crockford base32: no I, L, O or U to misread
+const REFERENCE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const REFERENCE_BODY_LEN: usize = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DepositIntent {
+    pub id: Uuid,
+    pub user_address: String,
+    /// EUR announced by the user, in 9-decimal units
+    pub amount: TokenAmount,
+    /// To be quoted in the bank transfer's remittance information
+    pub reference: String,
+    pub status: String,
+    /// EUR received so far, minted as MKOIN 1:1 once the intent is paid
+    pub paid_amount: Option<TokenAmount>,
+    pub mint_attempts: i32,
+    pub mint_msg_hash: Option<String>,
+    pub mint_tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BankStatement {
+    pub id: Uuid,
+    pub format: String,
+    pub file_name: Option<String>,
+    pub imported_by: Option<Uuid>,
+    pub entry_count: i32,
+    pub matched_count: i32,
+    pub review_count: i32,
+    pub duplicate_count: i32,
+    pub imported_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BankPayment {
+    pub id: Uuid,
+    pub statement_id: Uuid,
+    pub bank_ref: String,
+    pub booking_date: Option<NaiveDate>,
+    pub amount: TokenAmount,
+    pub currency: String,
+    pub debtor_name: Option<String>,
+    pub debtor_iban: Option<String>,
+    pub remittance: String,
+    pub deposit_intent_id: Option<Uuid>,
+    pub status: String,
+    pub review_reason: Option<String>,
+    pub resolved_by: Option<Uuid>,
+    pub resolution_note: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum DepositError {
+    #[error("Amount must be greater than 0")]
+    InvalidAmount,
+    #[error("Deposit not found")]
+    NotFound,
+    #[error("Deposit is {0}")]
+    NotPending(String),
+    #[error("Bank payment not found")]
+    PaymentNotFound,
+    #[error("Bank payment is already {0}")]
+    PaymentResolved(String),
+    #[error("Only EUR payments can be credited, not {0}")]
+    Currency(String),
+    #[error("Deposit mint is {0}, not failed")]
+    MintNotFailed(String),
+    #[error("Deposit was already partially paid")]
+    PartiallyPaid,
+}
+
+/// A fresh deposit reference: the prefix, 8 random characters and a check
+/// character
+pub fn new_deposit_reference() -> String {
+    let mut rng = rand::thread_rng();
+    let body: Vec<u8> = (0..REFERENCE_BODY_LEN)
+        .map(|_| REFERENCE_ALPHABET[rng.gen_range(0..REFERENCE_ALPHABET.len())])
+        .collect();
+    let check = reference_check(&body);
+    format!("{}{}{}", REFERENCE_PREFIX, String::from_utf8_lossy(&body), check as char)
+}
+
+/// Weighted sum with odd weights, so any single mistyped character changes it
+fn reference_check(body: &[u8]) -> u8 {
+    let sum: usize = body
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let value = REFERENCE_ALPHABET.iter().position(|a| a == c).unwrap_or(0);
+            value * (2 * i + 1)
+        })
+        .sum();
+    REFERENCE_ALPHABET[sum % REFERENCE_ALPHABET.len()]
+}
+
+/// The first valid deposit reference in a remittance text
+///
+/// Case, spaces, dashes and Crockford look-alikes (`O`, `I`, `L`) are
+/// tolerated, as banks and users reformat references freely.
+pub fn find_deposit_reference(remittance: &str) -> Option<String> {
+    let compact: Vec<u8> = remittance
+        .to_uppercase()
+        .bytes()
+        .filter(u8::is_ascii_alphanumeric)
+        .collect();
+    let prefix = REFERENCE_PREFIX.as_bytes();
+    let len = prefix.len() + REFERENCE_BODY_LEN + 1;
+
+    (0..compact.len().saturating_sub(len - 1))
+        .filter(|&start| compact[start..].starts_with(prefix))
+        .find_map(|start| {
+            let code: Vec<u8> = compact[start + prefix.len()..start + len]
+                .iter()
+                .map(|c| match c {
+                    b'O' => b'0',
+                    b'I' | b'L' => b'1',
+                    c => *c,
+                })
+                .collect();
+            let (body, check) = code.split_at(REFERENCE_BODY_LEN);
+            let valid = code.iter().all(|c| REFERENCE_ALPHABET.contains(c)) && reference_check(body) == check[0];
+            valid.then(|| format!("{}{}", REFERENCE_PREFIX, String::from_utf8_lossy(&code)))
+        })
+}
+
+/// EUR still owed on a pending intent
+pub fn remaining_amount(intent: &DepositIntent) -> TokenAmount {
+    intent.amount.saturating_sub(intent.paid_amount.unwrap_or_default())
+}
+
+/// Why a payment needs review, or None if it pays what is left of `intent`
+/// exactly
+pub fn review_reason(currency: &str, amount: TokenAmount, intent: Option<&DepositIntent>) -> Option<&'static str> {
+    if currency != "EUR" {
+        return Some("currency");
+    }
+    let Some(intent) = intent else {
+        return Some("unmatched");
+    };
+    match intent.status.as_str() {
+        "pending" => {}
+        "expired" | "cancelled" => return Some("expired"),
+        _ => return Some("duplicate"),
+    }
+    match amount.cmp(&remaining_amount(intent)) {
+        std::cmp::Ordering::Equal => None,
+        std::cmp::Ordering::Less => Some("partial"),
+        std::cmp::Ordering::Greater => Some("overpaid"),
+    }
+}
+
+impl Database {
+    /// Announce a bank transfer of `amount` EUR to be minted as MKOIN
+    pub async fn create_deposit_intent(&self, user_address: &str, amount: TokenAmount) -> Result<DepositIntent> {
+        if amount.is_zero() {
+            return Err(DepositError::InvalidAmount.into());
+        }
+        let expires_at = Utc::now() + chrono::Duration::days(DEPOSIT_INTENT_TTL_DAYS);
+
+        // References are random; a collision just draws another one
+        loop {
+            let intent = sqlx::query_as::<_, DepositIntent>(
+                r#"
+                INSERT INTO deposit_intents (user_address, amount, reference, expires_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (reference) DO NOTHING
+                RETURNING id, user_address, amount, reference, status, paid_amount, mint_attempts,
+                          mint_msg_hash, mint_tx_hash, error, expires_at, created_at, updated_at
+                "#,
+            )
+            .bind(user_address)
+            .bind(amount)
+            .bind(new_deposit_reference())
+            .bind(expires_at)
+            .fetch_optional(&self.pool)
+            .await?;
+            if let Some(intent) = intent {
+                return Ok(intent);
+            }
+        }
+    }
+
+    pub async fn get_deposit_intent(&self, id: Uuid) -> Result<Option<DepositIntent>> {
+        let intent = sqlx::query_as::<_, DepositIntent>(
+            r#"
+            SELECT id, user_address, amount, reference, status, paid_amount, mint_attempts,
+                   mint_msg_hash, mint_tx_hash, error, expires_at, created_at, updated_at
+            FROM deposit_intents
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(intent)
+    }
+
+    pub async fn get_user_deposit_intents(&self, user_address: &str) -> Result<Vec<DepositIntent>> {
+        let intents = sqlx::query_as::<_, DepositIntent>(
+            r#"
+            SELECT id, user_address, amount, reference, status, paid_amount, mint_attempts,
+                   mint_msg_hash, mint_tx_hash, error, expires_at, created_at, updated_at
+            FROM deposit_intents
+            WHERE user_address = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_address)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(intents)
+    }
+
+    pub async fn get_deposit_intents_by_status(&self, status: &str, limit: i64) -> Result<Vec<DepositIntent>> {
+        let intents = sqlx::query_as::<_, DepositIntent>(
+            r#"
+            SELECT id, user_address, amount, reference, status, paid_amount, mint_attempts,
+                   mint_msg_hash, mint_tx_hash, error, expires_at, created_at, updated_at
+            FROM deposit_intents
+            WHERE status = $1
+            ORDER BY updated_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(intents)
+    }
+
+    /// Withdraw a deposit intent nothing was received for yet
+    pub async fn cancel_deposit_intent(&self, id: Uuid, user_address: &str) -> Result<DepositIntent> {
+        let intent = sqlx::query_as::<_, DepositIntent>(
+            r#"
+            UPDATE deposit_intents
+            SET status = 'cancelled', updated_at = NOW()
+            WHERE id = $1 AND user_address = $2 AND status = 'pending' AND paid_amount IS NULL
+            RETURNING id, user_address, amount, reference, status, paid_amount, mint_attempts,
+                      mint_msg_hash, mint_tx_hash, error, expires_at, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(user_address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match intent {
+            Some(intent) => Ok(intent),
+            None => match self.get_deposit_intent(id).await? {
+                Some(intent) if intent.user_address == user_address => match intent.status.as_str() {
+                    "pending" => Err(DepositError::PartiallyPaid.into()),
+                    _ => Err(DepositError::NotPending(intent.status).into()),
+                },
+                _ => Err(DepositError::NotFound.into()),
+            },
+        }
+    }
+
+    /// Close pending intents past their deadline; returns how many
+    ///
+    /// An intent that received part of its amount is paid with what arrived,
+    /// the others expire.
+    pub async fn expire_deposit_intents(&self) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE deposit_intents
+            SET status = CASE WHEN paid_amount IS NULL THEN 'expired' ELSE 'paid' END,
+                updated_at = NOW()
+            WHERE status = 'pending' AND expires_at < NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Store a statement's payments and match them against deposit intents
+    ///
+    /// Entries already imported with an earlier statement are skipped. A
+    /// payment quoting the reference of a pending intent with exactly what
+    /// is left of its amount marks the intent paid; every other payment
+    /// goes to review.
+    pub async fn import_bank_statement(
+        &self,
+        format: StatementFormat,
+        file_name: Option<&str>,
+        imported_by: Option<Uuid>,
+        entries: &[StatementEntry],
+    ) -> Result<BankStatement> {
+        let mut tx = self.pool.begin().await?;
+
+        let statement_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO bank_statements (format, file_name, imported_by, entry_count)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            format.as_str(),
+            file_name,
+            imported_by,
+            entries.len() as i32
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let (mut matched, mut review, mut duplicates) = (0, 0, 0);
+        for entry in entries {
+            let reference = find_deposit_reference(&entry.remittance);
+            let intent = match &reference {
+                Some(reference) => sqlx::query_as::<_, DepositIntent>(
+                    r#"
+                    SELECT id, user_address, amount, reference, status, paid_amount, mint_attempts,
+                           mint_msg_hash, mint_tx_hash, error, expires_at, created_at, updated_at
+                    FROM deposit_intents
+                    WHERE reference = $1
+                    FOR UPDATE
+                    "#,
+                )
+                .bind(reference)
+                .fetch_optional(&mut *tx)
+                .await?,
+                None => None,
+            };
+            let reason = review_reason(&entry.currency, entry.amount, intent.as_ref());
+
+            let inserted = sqlx::query_scalar::<_, Uuid>(
+                r#"
+                INSERT INTO bank_payments (
+                    statement_id, bank_ref, booking_date, amount, currency, debtor_name,
+                    debtor_iban, remittance, deposit_intent_id, status, review_reason
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                ON CONFLICT (bank_ref) DO NOTHING
+                RETURNING id
+                "#,
+            )
+            .bind(statement_id)
+            .bind(&entry.bank_ref)
+            .bind(entry.booking_date)
+            .bind(entry.amount)
+            .bind(&entry.currency)
+            .bind(&entry.debtor_name)
+            .bind(&entry.debtor_iban)
+            .bind(&entry.remittance)
+            .bind(intent.as_ref().map(|i| i.id))
+            .bind(if reason.is_some() { "review" } else { "matched" })
+            .bind(reason)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if inserted.is_none() {
+                duplicates += 1;
+                continue;
+            }
+
+            match (reason, intent) {
+                (None, Some(intent)) => {
+                    Self::credit_deposit(&mut tx, intent.id, entry.amount).await?;
+                    matched += 1;
+                }
+                _ => review += 1,
+            }
+        }
+
+        let statement = sqlx::query_as::<_, BankStatement>(
+            r#"
+            UPDATE bank_statements
+            SET matched_count = $2, review_count = $3, duplicate_count = $4
+            WHERE id = $1
+            RETURNING id, format, file_name, imported_by, entry_count, matched_count,
+                      review_count, duplicate_count, imported_at
+            "#,
+        )
+        .bind(statement_id)
+        .bind(matched)
+        .bind(review)
+        .bind(duplicates)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(statement)
+    }
+
+    pub async fn get_bank_statement(&self, id: Uuid) -> Result<Option<BankStatement>> {
+        let statement = sqlx::query_as::<_, BankStatement>(
+            r#"
+            SELECT id, format, file_name, imported_by, entry_count, matched_count,
+                   review_count, duplicate_count, imported_at
+            FROM bank_statements
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(statement)
+    }
+
+    pub async fn get_statement_payments(&self, statement_id: Uuid) -> Result<Vec<BankPayment>> {
+        let payments = sqlx::query_as::<_, BankPayment>(
+            r#"
+            SELECT id, statement_id, bank_ref, booking_date, amount, currency, debtor_name,
+                   debtor_iban, remittance, deposit_intent_id, status, review_reason,
+                   resolved_by, resolution_note, resolved_at, created_at
+            FROM bank_payments
+            WHERE statement_id = $1
+            ORDER BY created_at ASC, bank_ref ASC
+            "#,
+        )
+        .bind(statement_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(payments)
+    }
+
+    /// Bank payments in `status`, oldest first; `review` is the review queue
+    pub async fn get_bank_payments_by_status(&self, status: &str, limit: i64) -> Result<Vec<BankPayment>> {
+        let payments = sqlx::query_as::<_, BankPayment>(
+            r#"
+            SELECT id, statement_id, bank_ref, booking_date, amount, currency, debtor_name,
+                   debtor_iban, remittance, deposit_intent_id, status, review_reason,
+                   resolved_by, resolution_note, resolved_at, created_at
+            FROM bank_payments
+            WHERE status = $1
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(payments)
+    }
+
+    /// Credit a reviewed payment to a deposit intent
+    ///
+    /// Payments add up: a pending intent is paid once they cover its amount,
+    /// or at its deadline with what arrived. An expired intent is paid with
+    /// the payment right away.
+    pub async fn assign_bank_payment(
+        &self,
+        payment_id: Uuid,
+        intent_id: Uuid,
+        resolved_by: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<BankPayment> {
+        let mut tx = self.pool.begin().await?;
+
+        let payment = Self::lock_review_payment(&mut tx, payment_id).await?;
+        if payment.currency != "EUR" {
+            return Err(DepositError::Currency(payment.currency).into());
+        }
+        let status = sqlx::query_scalar!(
+            "SELECT status FROM deposit_intents WHERE id = $1 FOR UPDATE",
+            intent_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(DepositError::NotFound)?;
+        // An expired intent still names the user the money came from
+        if status != "pending" && status != "expired" {
+            return Err(DepositError::NotPending(status).into());
+        }
+
+        Self::credit_deposit(&mut tx, intent_id, payment.amount).await?;
+        let payment = sqlx::query_as::<_, BankPayment>(
+            r#"
+            UPDATE bank_payments
+            SET status = 'assigned', deposit_intent_id = $2, resolved_by = $3,
+                resolution_note = $4, resolved_at = NOW()
+            WHERE id = $1
+            RETURNING id, statement_id, bank_ref, booking_date, amount, currency, debtor_name,
+                      debtor_iban, remittance, deposit_intent_id, status, review_reason,
+                      resolved_by, resolution_note, resolved_at, created_at
+            "#,
+        )
+        .bind(payment_id)
+        .bind(intent_id)
+        .bind(resolved_by)
+        .bind(note)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(payment)
+    }
+
+    /// Close a reviewed payment without crediting it, e.g. once it was sent
+    /// back to the payer
+    pub async fn dismiss_bank_payment(
+        &self,
+        payment_id: Uuid,
+        resolved_by: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<BankPayment> {
+        let mut tx = self.pool.begin().await?;
+        Self::lock_review_payment(&mut tx, payment_id).await?;
+
+        let payment = sqlx::query_as::<_, BankPayment>(
+            r#"
+            UPDATE bank_payments
+            SET status = 'dismissed', resolved_by = $2, resolution_note = $3, resolved_at = NOW()
+            WHERE id = $1
+            RETURNING id, statement_id, bank_ref, booking_date, amount, currency, debtor_name,
+                      debtor_iban, remittance, deposit_intent_id, status, review_reason,
+                      resolved_by, resolution_note, resolved_at, created_at
+            "#,
+        )
+        .bind(payment_id)
+        .bind(resolved_by)
+        .bind(note)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(payment)
+    }
+
+    /// Claim a paid deposit for a mint attempt; returns the attempt number
+    pub async fn start_deposit_mint(&self, id: Uuid) -> Result<Option<i32>> {
+        let attempt = sqlx::query_scalar!(
+            r#"
+            UPDATE deposit_intents
+            SET status = 'minting', mint_attempts = mint_attempts + 1, mint_msg_hash = NULL,
+                updated_at = NOW()
+            WHERE id = $1 AND status = 'paid'
+            RETURNING mint_attempts
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(attempt)
+    }
+
+    pub async fn mark_deposit_mint_sent(&self, id: Uuid, msg_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE deposit_intents
+            SET mint_msg_hash = $2, error = NULL, updated_at = NOW()
+            WHERE id = $1 AND status = 'minting'
+            "#,
+            id,
+            msg_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// A mint that was not sent or expired unseen goes back to `paid`, or to
+    /// `mint_failed` after `max_attempts`; returns the new status
+    pub async fn retry_deposit_mint(&self, id: Uuid, error: &str, max_attempts: i32) -> Result<String> {
+        let status = sqlx::query_scalar!(
+            r#"
+            UPDATE deposit_intents
+            SET status = CASE WHEN mint_attempts >= $3 THEN 'mint_failed' ELSE 'paid' END,
+                error = $2, updated_at = NOW()
+            WHERE id = $1 AND status = 'minting'
+            RETURNING status
+            "#,
+            id,
+            error,
+            max_attempts
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(status)
+    }
+
+    /// A mint whose outcome cannot be told from the chain; it is not sent
+    /// again without an admin, as it may have landed
+    pub async fn fail_deposit_mint(&self, id: Uuid, error: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE deposit_intents
+            SET status = 'mint_failed', error = $2, updated_at = NOW()
+            WHERE id = $1 AND status = 'minting'
+            "#,
+            id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn confirm_deposit_mint(&self, id: Uuid, tx_hash: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE deposit_intents
+            SET status = 'minted', mint_tx_hash = $2, error = NULL, updated_at = NOW()
+            WHERE id = $1 AND status = 'minting'
+            "#,
+            id,
+            tx_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Queue a failed mint again after an admin checked it did not land
+    pub async fn retry_failed_deposit_mint(&self, id: Uuid) -> Result<DepositIntent> {
+        let intent = sqlx::query_as::<_, DepositIntent>(
+            r#"
+            UPDATE deposit_intents
+            SET status = 'paid', mint_attempts = 0, mint_msg_hash = NULL, updated_at = NOW()
+            WHERE id = $1 AND status = 'mint_failed'
+            RETURNING id, user_address, amount, reference, status, paid_amount, mint_attempts,
+                      mint_msg_hash, mint_tx_hash, error, expires_at, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match intent {
+            Some(intent) => Ok(intent),
+            None => match self.get_deposit_intent(id).await? {
+                Some(intent) => Err(DepositError::MintNotFailed(intent.status).into()),
+                None => Err(DepositError::NotFound.into()),
+            },
+        }
+    }
+
+    /// Add `amount` to what an intent received
+    async fn credit_deposit(conn: &mut sqlx::PgConnection, id: Uuid, amount: TokenAmount) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE deposit_intents
+            SET paid_amount = COALESCE(paid_amount, 0) + $2,
+                status = CASE
+                    WHEN status = 'expired' OR COALESCE(paid_amount, 0) + $2 >= amount THEN 'paid'
+                    ELSE status
+                END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(amount)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    async fn lock_review_payment(conn: &mut sqlx::PgConnection, id: Uuid) -> Result<BankPayment> {
+        let payment = sqlx::query_as::<_, BankPayment>(
+            r#"
+            SELECT id, statement_id, bank_ref, booking_date, amount, currency, debtor_name,
+                   debtor_iban, remittance, deposit_intent_id, status, review_reason,
+                   resolved_by, resolution_note, resolved_at, created_at
+            FROM bank_payments
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(conn)
+        .await?
+        .ok_or(DepositError::PaymentNotFound)?;
+        if payment.status != "review" {
+            return Err(DepositError::PaymentResolved(payment.status).into());
+        }
+        Ok(payment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(status: &str, amount: &str) -> DepositIntent {
+        DepositIntent {
+            id: Uuid::new_v4(),
+            user_address: "EQ_USER".to_string(),
+            amount: TokenAmount::parse_decimal(amount).unwrap(),
+            reference: new_deposit_reference(),
+            status: status.to_string(),
+            paid_amount: None,
+            mint_attempts: 0,
+            mint_msg_hash: None,
+            mint_tx_hash: None,
+            error: None,
+            expires_at: Utc::now(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_reference_survives_reformatting() {
+        let reference = new_deposit_reference();
+        assert_eq!(reference.len(), 12);
+        assert_eq!(find_deposit_reference(&reference), Some(reference.clone()));
+
+        let spaced = format!("Invoice 42, ref {}-{} {} thanks", &reference[..3], &reference[3..7], &reference[7..]);
+        assert_eq!(find_deposit_reference(&spaced.to_lowercase()), Some(reference));
+    }
+
+    #[test]
+    fn test_reference_rejects_typos() {
+        let reference = new_deposit_reference();
+        let mut typo = reference.clone().into_bytes();
+        typo[5] = if typo[5] == b'7' { b'8' } else { b'7' };
+        assert_eq!(find_deposit_reference(&String::from_utf8(typo).unwrap()), None);
+        assert_eq!(find_deposit_reference("HZN"), None);
+        assert_eq!(find_deposit_reference("no reference"), None);
+    }
+
+    #[test]
+    fn test_review_reason() {
+        let pending = intent("pending", "100");
+        let amount = |a: &str| TokenAmount::parse_decimal(a).unwrap();
+        assert_eq!(review_reason("EUR", amount("100"), Some(&pending)), None);
+        assert_eq!(review_reason("EUR", amount("60"), Some(&pending)), Some("partial"));
+        assert_eq!(review_reason("EUR", amount("120"), Some(&pending)), Some("overpaid"));
+        assert_eq!(review_reason("USD", amount("100"), Some(&pending)), Some("currency"));
+        assert_eq!(review_reason("EUR", amount("100"), None), Some("unmatched"));
+        assert_eq!(review_reason("EUR", amount("100"), Some(&intent("minted", "100"))), Some("duplicate"));
+        assert_eq!(review_reason("EUR", amount("100"), Some(&intent("expired", "100"))), Some("expired"));
+    }
+}
@@ -0,0 +1,498 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use crate::ton::merkle::{MerkleTree, leaf_hash};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How the shares of a distribution reach the holders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayoutMode {
+    /// Sent to each holder as an MKOIN transfer
+    #[default]
+    Transfer,
+    /// Credited as rewards the holders claim themselves
+    Claim,
+    /// Reduced to a Merkle root for a claim contract the holders claim from;
+    /// the backend does not pay these out
+    Merkle,
+}
+
+impl PayoutMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayoutMode::Transfer => "transfer",
+            PayoutMode::Claim => "claim",
+            PayoutMode::Merkle => "merkle",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Distribution {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub token_address: String,
+    pub status: String, // 'draft', 'created', 'processing', 'completed', 'partial', 'root_ready'
+    pub payout_mode: String, // 'transfer', 'claim', 'merkle'
+    pub total_amount: TokenAmount,
+    pub distributed_amount: TokenAmount,
+    pub remainder: TokenAmount,
+    pub snapshot_supply: TokenAmount,
+    pub holder_count: i32,
+    /// Holder snapshot the shares were computed from
+    pub snapshot_id: Option<Uuid>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Hex root of the payout tree, merkle distributions only
+    pub merkle_root: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DistributionPayout {
+    pub id: Uuid,
+    pub distribution_id: Uuid,
+    pub user_address: String,
+    pub balance: TokenAmount,
+    pub amount: TokenAmount,
+    pub status: String, // 'pending', 'sent', 'confirmed', 'failed'
+    pub attempts: i32,
+    pub msg_hash: Option<String>,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// A holder's token balance in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Holding {
+    pub user_address: String,
+    pub balance: TokenAmount,
+}
+
+/// A payout line that would be created for one holder
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedPayout {
+    pub user_address: String,
+    pub balance: TokenAmount,
+    pub amount: TokenAmount,
+}
+
+/// Pro-rata shares of a distribution
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DistributionPlan {
+    pub total_amount: TokenAmount,
+    pub distributed_amount: TokenAmount,
+    /// Rounding dust, kept by the treasury
+    pub remainder: TokenAmount,
+    pub snapshot_supply: TokenAmount,
+    /// Holders whose share rounds down to nothing are left out
+    pub payouts: Vec<PlannedPayout>,
+}
+
+/// Merkle tree whose leaves pay each line's amount to its address
+pub fn payout_tree<'a>(lines: impl IntoIterator<Item = (&'a str, TokenAmount)>) -> Result<MerkleTree> {
+    let leaves = lines
+        .into_iter()
+        .map(|(address, amount)| leaf_hash(address, amount))
+        .collect::<Result<Vec<_>>>()?;
+    MerkleTree::build(leaves)
+}
+
+/// Split `total` among `holdings` in proportion to their balances
+///
+/// Each share is `total * balance / supply` rounded down, computed without
+/// overflow, so the shares never add up to more than `total`; what is left
+/// is the remainder. Returns None if there is nothing to split by.
+pub fn pro_rata(total: TokenAmount, holdings: &[Holding]) -> Option<DistributionPlan> {
+    let supply = TokenAmount::checked_sum(holdings.iter().map(|h| h.balance))?;
+    if supply.is_zero() {
+        return None;
+    }
+
+    let total_big = BigUint::from(total.nano());
+    let supply_big = BigUint::from(supply.nano());
+    let payouts: Vec<PlannedPayout> = holdings
+        .iter()
+        .filter_map(|h| {
+            let share = &total_big * BigUint::from(h.balance.nano()) / &supply_big;
+            // A share never exceeds `total`, so it fits
+            let amount = TokenAmount::from_nano(u128::try_from(share).ok()?);
+            (!amount.is_zero()).then(|| PlannedPayout {
+                user_address: h.user_address.clone(),
+                balance: h.balance,
+                amount,
+            })
+        })
+        .collect();
+
+    let distributed = TokenAmount::checked_sum(payouts.iter().map(|p| p.amount))?;
+    Some(DistributionPlan {
+        total_amount: total,
+        distributed_amount: distributed,
+        remainder: total.checked_sub(distributed)?,
+        snapshot_supply: supply,
+        payouts,
+    })
+}
+
+impl Database {
+    /// Persist a distribution with one line per planned payout
+    ///
+    /// Transfer distributions get pending payout lines to send. Claim
+    /// distributions credit rewards instead and are complete right away.
+    pub async fn create_distribution(
+        &self,
+        campaign_id: Uuid,
+        token_address: &str,
+        plan: &DistributionPlan,
+        mode: PayoutMode,
+        snapshot_id: Option<Uuid>,
+        created_by: Option<Uuid>,
+    ) -> Result<Uuid> {
+        let mut tx = self.pool.begin().await?;
+        let id = Self::insert_distribution(&mut tx, campaign_id, token_address, plan, mode, snapshot_id, created_by)
+            .await?;
+        Self::release(&mut tx, id, mode).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// Persist a distribution as a draft, released later by an admin
+    pub async fn create_draft_distribution(
+        &self,
+        campaign_id: Uuid,
+        token_address: &str,
+        plan: &DistributionPlan,
+        mode: PayoutMode,
+        snapshot_id: Option<Uuid>,
+        created_by: Option<Uuid>,
+    ) -> Result<Uuid> {
+        let mut tx = self.pool.begin().await?;
+        let id = Self::insert_distribution(&mut tx, campaign_id, token_address, plan, mode, snapshot_id, created_by)
+            .await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// Release a draft distribution; returns false if it is not a draft
+    pub async fn release_distribution(&self, id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let mode = sqlx::query_scalar!(
+            "SELECT payout_mode FROM distributions WHERE id = $1 AND status = 'draft' FOR UPDATE",
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let mode = match mode.as_deref() {
+            Some("claim") => PayoutMode::Claim,
+            Some("merkle") => PayoutMode::Merkle,
+            Some(_) => PayoutMode::Transfer,
+            None => return Ok(false),
+        };
+
+        Self::release(&mut tx, id, mode).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Delete a draft distribution; returns false if it is not a draft
+    pub async fn delete_draft_distribution(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM distributions WHERE id = $1 AND status = 'draft'", id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Insert a draft distribution with a pending line per planned payout
+    async fn insert_distribution(
+        conn: &mut sqlx::PgConnection,
+        campaign_id: Uuid,
+        token_address: &str,
+        plan: &DistributionPlan,
+        mode: PayoutMode,
+        snapshot_id: Option<Uuid>,
+        created_by: Option<Uuid>,
+    ) -> Result<Uuid> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO distributions (
+                campaign_id, token_address, total_amount, distributed_amount, remainder,
+                snapshot_supply, holder_count, snapshot_id, created_by, payout_mode, status
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, 'draft')
+            RETURNING id
+            "#,
+            campaign_id,
+            token_address,
+            plan.total_amount as _,
+            plan.distributed_amount as _,
+            plan.remainder as _,
+            plan.snapshot_supply as _,
+            plan.payouts.len() as i32,
+            snapshot_id,
+            created_by,
+            mode.as_str()
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        for payout in &plan.payouts {
+            sqlx::query!(
+                r#"
+                INSERT INTO distribution_payouts (distribution_id, user_address, balance, amount)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                id,
+                payout.user_address,
+                payout.balance as _,
+                payout.amount as _
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(id)
+    }
+
+    /// Make a draft ready to send, or credit its lines as rewards
+    async fn release(conn: &mut sqlx::PgConnection, id: Uuid, mode: PayoutMode) -> Result<()> {
+        match mode {
+            PayoutMode::Claim => {
+                Self::credit_rewards(&mut *conn, id).await?;
+                sqlx::query!(
+                    "UPDATE distributions SET status = 'completed', completed_at = CURRENT_TIMESTAMP WHERE id = $1",
+                    id
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            PayoutMode::Merkle => {
+                let lines = sqlx::query!(
+                    r#"SELECT user_address, amount as "amount: TokenAmount" FROM distribution_payouts WHERE distribution_id = $1"#,
+                    id
+                )
+                .fetch_all(&mut *conn)
+                .await?;
+                let tree = payout_tree(lines.iter().map(|l| (l.user_address.as_str(), l.amount)))?;
+                sqlx::query!(
+                    "UPDATE distributions SET status = 'root_ready', merkle_root = $2, completed_at = CURRENT_TIMESTAMP WHERE id = $1",
+                    id,
+                    tree.root().to_hex()
+                )
+                .execute(&mut *conn)
+                .await?;
+            }
+            PayoutMode::Transfer => {
+                sqlx::query!("UPDATE distributions SET status = 'created' WHERE id = $1", id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get_distribution(&self, id: Uuid) -> Result<Option<Distribution>> {
+        let distribution = sqlx::query_as::<_, Distribution>(
+            r#"
+            SELECT id, campaign_id, token_address, status, payout_mode, total_amount, distributed_amount,
+                   remainder, snapshot_supply, holder_count, snapshot_id, created_by, created_at,
+                   completed_at, merkle_root
+            FROM distributions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(distribution)
+    }
+
+    pub async fn get_distribution_payouts(&self, distribution_id: Uuid) -> Result<Vec<DistributionPayout>> {
+        let payouts = sqlx::query_as::<_, DistributionPayout>(
+            r#"
+            SELECT id, distribution_id, user_address, balance, amount, status, attempts, msg_hash,
+                   tx_hash, error, created_at, sent_at, confirmed_at
+            FROM distribution_payouts
+            WHERE distribution_id = $1
+            ORDER BY user_address
+            "#,
+        )
+        .bind(distribution_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(payouts)
+    }
+
+    /// Hand a distribution to the payout worker; returns false if it is
+    /// already processing or is not a released transfer distribution
+    ///
+    /// Failed payouts of an earlier run are queued again.
+    pub async fn claim_distribution(&self, id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            "UPDATE distributions SET status = 'processing' WHERE id = $1 AND status NOT IN ('processing', 'draft', 'root_ready')",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() != 1 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "UPDATE distribution_payouts SET status = 'pending' WHERE distribution_id = $1 AND status = 'failed'",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn get_distribution_ids_by_status(&self, status: &str, limit: i64) -> Result<Vec<Uuid>> {
+        let ids = sqlx::query_scalar!(
+            "SELECT id FROM distributions WHERE status = $1 ORDER BY created_at ASC LIMIT $2",
+            status,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids)
+    }
+
+    /// Close a processing distribution as `completed` or `partial` depending
+    /// on its payouts
+    pub async fn finish_distribution(&self, id: Uuid) -> Result<String> {
+        let status = sqlx::query_scalar!(
+            r#"
+            UPDATE distributions
+            SET status = CASE
+                    WHEN EXISTS (
+                        SELECT 1 FROM distribution_payouts
+                        WHERE distribution_id = $1 AND status <> 'confirmed'
+                    )
+                    THEN 'partial' ELSE 'completed' END,
+                completed_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            RETURNING status
+            "#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(status)
+    }
+
+    /// Count a new attempt at sending a pending payout; returns the attempt
+    /// number, or None if it is not pending
+    pub async fn start_payout_attempt(&self, id: Uuid) -> Result<Option<i32>> {
+        let attempt = sqlx::query_scalar!(
+            r#"
+            UPDATE distribution_payouts
+            SET attempts = attempts + 1
+            WHERE id = $1 AND status = 'pending'
+            RETURNING attempts
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(attempt)
+    }
+
+    pub async fn mark_payout_sent(&self, id: Uuid, msg_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE distribution_payouts
+            SET status = 'sent', msg_hash = $2, error = NULL, sent_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            "#,
+            id,
+            msg_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn mark_payout_failed(&self, id: Uuid, error: &str) -> Result<()> {
+        sqlx::query!(
+            "UPDATE distribution_payouts SET status = 'failed', error = $2 WHERE id = $1",
+            id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn confirm_payout(&self, id: Uuid, tx_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE distribution_payouts
+            SET status = 'confirmed', tx_hash = $2, confirmed_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            "#,
+            id,
+            tx_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holding(user: &str, nano: u128) -> Holding {
+        Holding {
+            user_address: user.to_string(),
+            balance: TokenAmount::from_nano(nano),
+        }
+    }
+
+    #[test]
+    fn test_pro_rata_leaves_dust_as_remainder() {
+        let holdings = [holding("a", 1), holding("b", 1), holding("c", 1)];
+        let plan = pro_rata(TokenAmount::from_nano(100), &holdings).unwrap();
+        let amounts: Vec<u128> = plan.payouts.iter().map(|p| p.amount.nano()).collect();
+        assert_eq!(amounts, vec![33, 33, 33]);
+        assert_eq!(plan.distributed_amount, TokenAmount::from_nano(99));
+        assert_eq!(plan.remainder, TokenAmount::from_nano(1));
+        assert_eq!(plan.snapshot_supply, TokenAmount::from_nano(3));
+    }
+
+    #[test]
+    fn test_pro_rata_is_exact_for_large_amounts() {
+        // total * balance exceeds u128 here
+        let big = u128::MAX / 4;
+        let holdings = [holding("a", big), holding("b", big * 3)];
+        let plan = pro_rata(TokenAmount::from_nano(big), &holdings).unwrap();
+        assert_eq!(plan.payouts[0].amount.nano(), big / 4);
+        assert_eq!(
+            plan.distributed_amount.nano() + plan.remainder.nano(),
+            big
+        );
+        assert!(plan.remainder.nano() < 2);
+    }
+
+    #[test]
+    fn test_pro_rata_skips_zero_shares() {
+        let holdings = [holding("whale", 1_000_000), holding("dust", 1)];
+        let plan = pro_rata(TokenAmount::from_nano(10), &holdings).unwrap();
+        assert_eq!(plan.payouts.len(), 1);
+        assert_eq!(plan.payouts[0].amount.nano(), 9);
+        assert_eq!(plan.remainder.nano(), 1);
+        assert!(pro_rata(TokenAmount::from_nano(10), &[]).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct HarvestReport {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub season: String,
+    pub quantity: BigDecimal,
+    pub quantity_unit: String,
+    pub revenue: TokenAmount, // EUR, 9 decimals
+    pub costs: TokenAmount,   // EUR, 9 decimals
+    pub evidence_keys: Vec<String>,
+    pub notes: Option<String>,
+    pub status: String, // 'submitted', 'verified', 'rejected'
+    pub invested: Option<TokenAmount>,
+    pub submitted_by: Option<Uuid>,
+    pub reviewed_by: Option<Uuid>,
+    pub review_note: Option<String>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub distribution_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl HarvestReport {
+    /// Revenue left after costs; None for a loss
+    pub fn net_profit(&self) -> Option<TokenAmount> {
+        self.revenue.checked_sub(self.costs)
+    }
+
+    /// Yield on the capital raised, known once the report is verified
+    pub fn actual_yield_bps(&self) -> Option<i64> {
+        yield_bps(self.revenue, self.costs, self.invested?)
+    }
+}
+
+/// A harvest as the farmer reports it
+#[derive(Debug)]
+pub struct NewHarvestReport {
+    pub season: String,
+    pub quantity: BigDecimal,
+    pub quantity_unit: String,
+    pub revenue: TokenAmount,
+    pub costs: TokenAmount,
+    pub evidence_keys: Vec<String>,
+    pub notes: Option<String>,
+}
+
+/// Target and actual yield of a campaign season
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeasonYield {
+    pub season: String,
+    pub report_id: Uuid,
+    pub target_yield_bps: Option<i32>,
+    pub actual_yield_bps: Option<i64>,
+}
+
+#[derive(Debug, Error)]
+pub enum HarvestError {
+    #[error("Season must be 1 to 20 characters")]
+    InvalidSeason,
+    #[error("Quantity must be greater than 0")]
+    InvalidQuantity,
+    #[error("Quantity unit must be 1 to 20 characters")]
+    InvalidUnit,
+    #[error("Evidence {0} was not uploaded by the farmer")]
+    UnknownEvidence(String),
+    #[error("Season {0} already has a report")]
+    DuplicateSeason(String),
+    #[error("Harvest report not found")]
+    NotFound,
+    #[error("Harvest report is {0}")]
+    AlreadyReviewed(String),
+    #[error("Harvest report is {0}, not verified")]
+    NotVerified(String),
+    #[error("Harvest report already seeded a distribution")]
+    AlreadyDistributed,
+    #[error("Harvest made no profit to distribute")]
+    NoProfit,
+}
+
+/// Net profit over the capital raised, in basis points rounded toward zero;
+/// negative for a loss
+pub fn yield_bps(revenue: TokenAmount, costs: TokenAmount, invested: TokenAmount) -> Option<i64> {
+    if invested.is_zero() {
+        return None;
+    }
+    let revenue = i128::try_from(revenue.nano()).ok()?;
+    let costs = i128::try_from(costs.nano()).ok()?;
+    let invested = i128::try_from(invested.nano()).ok()?;
+    let bps = (revenue - costs).checked_mul(10_000)? / invested;
+    i64::try_from(bps).ok()
+}
+
+const REPORT_COLUMNS: &str = "id, campaign_id, season, quantity, quantity_unit, revenue, costs, evidence_keys, \
+                              notes, status, invested, submitted_by, reviewed_by, review_note, reviewed_at, \
+                              distribution_id, created_at";
+
+impl Database {
+    pub async fn create_harvest_report(
+        &self,
+        campaign_id: Uuid,
+        report: &NewHarvestReport,
+        submitted_by: Option<Uuid>,
+    ) -> Result<HarvestReport> {
+        let season = report.season.trim();
+        if season.is_empty() || season.len() > 20 {
+            return Err(HarvestError::InvalidSeason.into());
+        }
+        let unit = report.quantity_unit.trim();
+        if unit.is_empty() || unit.len() > 20 {
+            return Err(HarvestError::InvalidUnit.into());
+        }
+        if report.quantity <= BigDecimal::from(0) {
+            return Err(HarvestError::InvalidQuantity.into());
+        }
+
+        // Evidence is the farmer's own upload, not any file someone shared
+        let known = sqlx::query_scalar!(
+            r#"
+            SELECT key FROM media_files
+            WHERE key = ANY($1) AND uploaded_by = (SELECT farmer_id FROM campaigns WHERE id = $2)
+            "#,
+            &report.evidence_keys,
+            campaign_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        if let Some(missing) = report.evidence_keys.iter().find(|k| !known.contains(k)) {
+            return Err(HarvestError::UnknownEvidence(missing.clone()).into());
+        }
+
+        // The season index decides between concurrent reports
+        let created = sqlx::query_as::<_, HarvestReport>(&format!(
+            r#"
+            INSERT INTO harvest_reports (
+                campaign_id, season, quantity, quantity_unit, revenue, costs, evidence_keys, notes, submitted_by
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (campaign_id, season) WHERE status <> 'rejected' DO NOTHING
+            RETURNING {}
+            "#,
+            REPORT_COLUMNS
+        ))
+        .bind(campaign_id)
+        .bind(season)
+        .bind(&report.quantity)
+        .bind(unit)
+        .bind(report.revenue)
+        .bind(report.costs)
+        .bind(&report.evidence_keys)
+        .bind(&report.notes)
+        .bind(submitted_by)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        created.ok_or_else(|| HarvestError::DuplicateSeason(season.to_string()).into())
+    }
+
+    pub async fn get_harvest_report(&self, id: Uuid) -> Result<Option<HarvestReport>> {
+        let report = sqlx::query_as::<_, HarvestReport>(&format!(
+            "SELECT {} FROM harvest_reports WHERE id = $1",
+            REPORT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(report)
+    }
+
+    pub async fn get_campaign_harvest_reports(&self, campaign_id: Uuid) -> Result<Vec<HarvestReport>> {
+        let reports = sqlx::query_as::<_, HarvestReport>(&format!(
+            "SELECT {} FROM harvest_reports WHERE campaign_id = $1 ORDER BY season DESC, created_at DESC",
+            REPORT_COLUMNS
+        ))
+        .bind(campaign_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(reports)
+    }
+
+    pub async fn get_harvest_reports_by_status(&self, status: &str, limit: i64) -> Result<Vec<HarvestReport>> {
+        let reports = sqlx::query_as::<_, HarvestReport>(&format!(
+            "SELECT {} FROM harvest_reports WHERE status = $1 ORDER BY created_at LIMIT $2",
+            REPORT_COLUMNS
+        ))
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(reports)
+    }
+
+    /// Verify a submitted report, fixing the MKOIN raised by confirmed
+    /// purchases as the capital its yield is computed on
+    pub async fn verify_harvest_report(
+        &self,
+        id: Uuid,
+        reviewed_by: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<HarvestReport> {
+        self.review_harvest_report(id, "verified", reviewed_by, note).await
+    }
+
+    pub async fn reject_harvest_report(
+        &self,
+        id: Uuid,
+        reviewed_by: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<HarvestReport> {
+        self.review_harvest_report(id, "rejected", reviewed_by, note).await
+    }
+
+    async fn review_harvest_report(
+        &self,
+        id: Uuid,
+        status: &str,
+        reviewed_by: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<HarvestReport> {
+        let reviewed = sqlx::query_as::<_, HarvestReport>(&format!(
+            r#"
+            UPDATE harvest_reports
+            SET status = $2, reviewed_by = $3, review_note = $4, reviewed_at = NOW(),
+                invested = CASE WHEN $2 = 'verified' THEN (
+                    SELECT COALESCE(SUM(mkoin_paid), 0) FROM purchases
+                    WHERE campaign_id = harvest_reports.campaign_id AND status = 'confirmed'
+                ) END
+            WHERE id = $1 AND status = 'submitted'
+            RETURNING {}
+            "#,
+            REPORT_COLUMNS
+        ))
+        .bind(id)
+        .bind(status)
+        .bind(reviewed_by)
+        .bind(note)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match reviewed {
+            Some(report) => Ok(report),
+            None => match self.get_harvest_report(id).await? {
+                Some(report) => Err(HarvestError::AlreadyReviewed(report.status).into()),
+                None => Err(HarvestError::NotFound.into()),
+            },
+        }
+    }
+
+    /// Link the distribution a verified report seeded; returns false if it
+    /// already has one
+    pub async fn attach_harvest_distribution(&self, id: Uuid, distribution_id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE harvest_reports
+            SET distribution_id = $2
+            WHERE id = $1 AND status = 'verified' AND distribution_id IS NULL
+            "#,
+            id,
+            distribution_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn set_target_yield(&self, campaign_id: Uuid, target_yield_bps: Option<i32>) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE campaigns SET target_yield_bps = $2, updated_at = NOW() WHERE id = $1",
+            campaign_id,
+            target_yield_bps
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Target yield and verified season yields of campaigns
+    pub async fn campaign_yields(
+        &self,
+        campaign_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, (Option<i32>, Vec<SeasonYield>)>> {
+        let targets = sqlx::query!(
+            "SELECT id, target_yield_bps FROM campaigns WHERE id = ANY($1)",
+            campaign_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let reports = sqlx::query_as::<_, HarvestReport>(&format!(
+            r#"
+            SELECT {} FROM harvest_reports
+            WHERE campaign_id = ANY($1) AND status = 'verified'
+            ORDER BY season DESC
+            "#,
+            REPORT_COLUMNS
+        ))
+        .bind(campaign_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut yields: HashMap<Uuid, (Option<i32>, Vec<SeasonYield>)> = targets
+            .into_iter()
+            .map(|t| (t.id, (t.target_yield_bps, Vec::new())))
+            .collect();
+        for report in reports {
+            if let Some((target, seasons)) = yields.get_mut(&report.campaign_id) {
+                seasons.push(SeasonYield {
+                    season: report.season.clone(),
+                    report_id: report.id,
+                    target_yield_bps: *target,
+                    actual_yield_bps: report.actual_yield_bps(),
+                });
+            }
+        }
+        Ok(yields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eur(value: &str) -> TokenAmount {
+        TokenAmount::parse_decimal(value).unwrap()
+    }
+
+    #[test]
+    fn test_yield_bps() {
+        // 1 150 net on 10 000 raised
+        assert_eq!(yield_bps(eur("2000"), eur("850"), eur("10000")), Some(1150));
+        // Rounded toward zero, losses are negative
+        assert_eq!(yield_bps(eur("1"), eur("0"), eur("3")), Some(3333));
+        assert_eq!(yield_bps(eur("500"), eur("1000"), eur("10000")), Some(-500));
+        assert_eq!(yield_bps(eur("500"), eur("0"), TokenAmount::ZERO), None);
+    }
+}
@@ -0,0 +1,91 @@
+use super::Database;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// How long a request may hold its key before the key can be claimed again
+pub const IDEMPOTENCY_LEASE_SECS: i64 = 900;
+
+/// A request already seen under an idempotency key
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct IdempotencyRecord {
+    pub fingerprint: String,
+    /// None while the first request is still running
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+}
+
+impl Database {
+    /// Claim an idempotency key for a new request
+    ///
+    /// Returns None if the key was free, or held by a request that never
+    /// finished within `IDEMPOTENCY_LEASE_SECS`, and is now held by the
+    /// caller; otherwise the record of the request that already used it.
+    pub async fn begin_idempotent_request(
+        &self,
+        scope: &str,
+        actor: &str,
+        key: &str,
+        fingerprint: &str,
+    ) -> Result<Option<IdempotencyRecord>> {
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO idempotency_keys (scope, actor, key, fingerprint)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (scope, actor, key) DO UPDATE
+            SET fingerprint = EXCLUDED.fingerprint, created_at = NOW()
+            WHERE idempotency_keys.response_status IS NULL
+              AND idempotency_keys.created_at < NOW() - make_interval(secs => $5)
+            "#,
+            scope,
+            actor,
+            key,
+            fingerprint,
+            IDEMPOTENCY_LEASE_SECS as f64
+        )
+        .execute(&self.pool)
+        .await?;
+        if inserted.rows_affected() == 1 {
+            return Ok(None);
+        }
+
+        let existing = sqlx::query_as::<_, IdempotencyRecord>(
+            r#"
+            SELECT fingerprint, response_status, response_body
+            FROM idempotency_keys
+            WHERE scope = $1 AND actor = $2 AND key = $3
+            "#,
+        )
+        .bind(scope)
+        .bind(actor)
+        .bind(key)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(Some(existing))
+    }
+
+    /// Store the response replayed for later requests with the key
+    pub async fn complete_idempotent_request(
+        &self,
+        scope: &str,
+        actor: &str,
+        key: &str,
+        status: i32,
+        body: &str,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE idempotency_keys
+            SET response_status = $4, response_body = $5, completed_at = NOW()
+            WHERE scope = $1 AND actor = $2 AND key = $3
+            "#,
+            scope,
+            actor,
+            key,
+            status,
+            body
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
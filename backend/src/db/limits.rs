@@ -0,0 +1,194 @@
+use crate::amount::TokenAmount;
+use crate::ton::address_utils::to_raw_address;
+use serde::{Deserialize, Serialize};
+
+/// Sale limits of a campaign in MKOIN nanocoins; `token_supply` in token nano-units
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PurchaseLimits {
+    /// Total tokens for sale, in token nano-units
+    pub token_supply: TokenAmount,
+    pub hard_cap: Option<TokenAmount>,
+    pub min_ticket: Option<TokenAmount>,
+    pub max_ticket: Option<TokenAmount>,
+    pub max_per_investor: Option<TokenAmount>,
+}
+
+/// Amounts already allocated (pending + confirmed purchases) in a campaign
+#[derive(Debug, Clone, Default)]
+pub struct Allocation {
+    pub mkoin_raised: TokenAmount,
+    pub tokens_sold: TokenAmount,
+    /// MKOIN already spent by the buyer being checked
+    pub investor_mkoin: TokenAmount,
+}
+
+impl Allocation {
+    /// Sum the MKOIN paid and tokens bought per buyer address; the investor's
+    /// share counts every form (raw, bounceable or not) of their wallet
+    pub fn from_buyers(buyers: &[(String, TokenAmount, TokenAmount)], investor: Option<&str>) -> Option<Self> {
+        let investor = investor.map(wallet_key);
+        let mut allocation = Allocation::default();
+        for (address, mkoin, tokens) in buyers {
+            allocation.mkoin_raised = allocation.mkoin_raised.checked_add(*mkoin)?;
+            allocation.tokens_sold = allocation.tokens_sold.checked_add(*tokens)?;
+            if investor.as_deref() == Some(wallet_key(address).as_str()) {
+                allocation.investor_mkoin = allocation.investor_mkoin.checked_add(*mkoin)?;
+            }
+        }
+        Some(allocation)
+    }
+}
+
+/// Raw form of a wallet address, or the address itself if it does not parse
+fn wallet_key(address: &str) -> String {
+    to_raw_address(address).unwrap_or_else(|_| address.to_string())
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum PurchaseLimitError {
+    #[error("Purchase below minimum ticket of {0} MKOIN")]
+    BelowMinTicket(TokenAmount),
+    #[error("Purchase above maximum ticket of {0} MKOIN")]
+    AboveMaxTicket(TokenAmount),
+    #[error("Purchase exceeds per-investor maximum, {remaining} MKOIN remaining")]
+    InvestorLimit { remaining: TokenAmount },
+    #[error("Purchase exceeds hard cap, {remaining} MKOIN remaining")]
+    HardCap { remaining: TokenAmount },
+    #[error("Not enough tokens left, {remaining} tokens remaining")]
+    SoldOut { remaining: TokenAmount },
+    #[error("Payment buys no tokens at {price} MKOIN per token")]
+    NoTokens { price: TokenAmount },
+}
+
+impl PurchaseLimitError {
+    /// Ticket size errors are the caller's fault; the rest depend on other buyers
+    pub fn is_ticket_size(&self) -> bool {
+        matches!(
+            self,
+            Self::BelowMinTicket(_) | Self::AboveMaxTicket(_) | Self::NoTokens { .. }
+        )
+    }
+}
+
+/// Validate a purchase of `tokens` for `mkoin_paid` against the campaign limits
+pub fn check_purchase_limits(
+    limits: &PurchaseLimits,
+    allocation: &Allocation,
+    mkoin_paid: TokenAmount,
+    tokens: TokenAmount,
+) -> Result<(), PurchaseLimitError> {
+    // Overflowing sums can never fit under a limit
+    let exceeds = |used: TokenAmount, add: TokenAmount, limit: TokenAmount| {
+        used.checked_add(add).is_none_or(|total| total > limit)
+    };
+
+    if let Some(min) = limits.min_ticket
+        && mkoin_paid < min
+    {
+        return Err(PurchaseLimitError::BelowMinTicket(min));
+    }
+    if let Some(max) = limits.max_ticket
+        && mkoin_paid > max
+    {
+        return Err(PurchaseLimitError::AboveMaxTicket(max));
+    }
+    if let Some(max) = limits.max_per_investor
+        && exceeds(allocation.investor_mkoin, mkoin_paid, max)
+    {
+        return Err(PurchaseLimitError::InvestorLimit {
+            remaining: max.saturating_sub(allocation.investor_mkoin),
+        });
+    }
+    if let Some(cap) = limits.hard_cap
+        && exceeds(allocation.mkoin_raised, mkoin_paid, cap)
+    {
+        return Err(PurchaseLimitError::HardCap {
+            remaining: cap.saturating_sub(allocation.mkoin_raised),
+        });
+    }
+    if exceeds(allocation.tokens_sold, tokens, limits.token_supply) {
+        return Err(PurchaseLimitError::SoldOut {
+            remaining: limits.token_supply.saturating_sub(allocation.tokens_sold),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bd(v: u128) -> TokenAmount {
+        TokenAmount::from_nano(v)
+    }
+
+    fn limits() -> PurchaseLimits {
+        PurchaseLimits {
+            token_supply: bd(1000),
+            hard_cap: Some(bd(500)),
+            min_ticket: Some(bd(10)),
+            max_ticket: Some(bd(200)),
+            max_per_investor: Some(bd(300)),
+        }
+    }
+
+    #[test]
+    fn test_ticket_size() {
+        let alloc = Allocation::default();
+        assert_eq!(
+            check_purchase_limits(&limits(), &alloc, bd(5), bd(5)),
+            Err(PurchaseLimitError::BelowMinTicket(bd(10)))
+        );
+        assert_eq!(
+            check_purchase_limits(&limits(), &alloc, bd(201), bd(201)),
+            Err(PurchaseLimitError::AboveMaxTicket(bd(200)))
+        );
+        assert!(check_purchase_limits(&limits(), &alloc, bd(200), bd(200)).is_ok());
+    }
+
+    #[test]
+    fn test_investor_and_hard_cap() {
+        let alloc = Allocation {
+            mkoin_raised: bd(450),
+            tokens_sold: bd(450),
+            investor_mkoin: bd(250),
+        };
+        assert_eq!(
+            check_purchase_limits(&limits(), &alloc, bd(100), bd(100)),
+            Err(PurchaseLimitError::InvestorLimit { remaining: bd(50) })
+        );
+
+        let alloc = Allocation { investor_mkoin: bd(0), ..alloc };
+        assert_eq!(
+            check_purchase_limits(&limits(), &alloc, bd(100), bd(100)),
+            Err(PurchaseLimitError::HardCap { remaining: bd(50) })
+        );
+        assert!(check_purchase_limits(&limits(), &alloc, bd(50), bd(50)).is_ok());
+    }
+
+    #[test]
+    fn test_token_supply() {
+        let limits = PurchaseLimits { token_supply: bd(100), ..Default::default() };
+        let alloc = Allocation { tokens_sold: bd(90), ..Default::default() };
+        assert_eq!(
+            check_purchase_limits(&limits, &alloc, bd(1), bd(20)),
+            Err(PurchaseLimitError::SoldOut { remaining: bd(10) })
+        );
+    }
+
+    #[test]
+    fn test_investor_share_counts_every_address_form() {
+        let friendly = "EQATDLvt8bY8BGb-DGBZxwe6EZla3Rcij41fqv_OFlLXvgpV".to_string();
+        let raw = to_raw_address(&friendly).unwrap();
+        let buyers = vec![
+            (raw.clone(), bd(100), bd(10)),
+            (friendly.clone(), bd(50), bd(5)),
+            ("other".to_string(), bd(7), bd(1)),
+        ];
+        let allocation = Allocation::from_buyers(&buyers, Some(&friendly)).unwrap();
+        assert_eq!(allocation.mkoin_raised, bd(157));
+        assert_eq!(allocation.tokens_sold, bd(16));
+        assert_eq!(allocation.investor_mkoin, bd(150));
+        assert_eq!(Allocation::from_buyers(&buyers, None).unwrap().investor_mkoin, TokenAmount::ZERO);
+    }
+}
@@ -0,0 +1,935 @@
+use super::Database;
+use super::prices::PriceSource;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Depth of each side of a published order book, in price levels
+pub const BOOK_DEPTH: i64 = 50;
+
+/// How long an order waits for its escrow before it expires
+pub const ORDER_ESCROW_TTL_SECS: i64 = 1800;
+
+/// How long past its escrow TTL an order cancelled or expired before its
+/// escrow arrived is still watched, so a late escrow is refunded
+pub const LATE_ESCROW_GRACE_SECS: i64 = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl OrderSide {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderType {
+    Limit,
+    Market,
+}
+
+impl OrderType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderType::Limit => "limit",
+            OrderType::Market => "market",
+        }
+    }
+}
+
+/// An order for a campaign token against MKOIN
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MarketOrder {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub user_address: String,
+    pub side: String,       // 'buy', 'sell'
+    pub order_type: String, // 'limit', 'market'
+    /// MKOIN nanocoins per whole token; None for market orders
+    pub price: Option<TokenAmount>,
+    pub amount: TokenAmount,
+    pub filled: TokenAmount,
+    /// Tokens for sells, MKOIN for buys
+    pub escrow_amount: TokenAmount,
+    pub escrow_used: TokenAmount,
+    pub status: String, // 'awaiting_escrow', 'open', 'filled', 'cancelled', 'expired'
+    pub escrow_tx_hash: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl MarketOrder {
+    /// Jettons of this order the platform wallet still holds in escrow
+    pub fn unused_escrow(&self) -> TokenAmount {
+        self.escrow_amount.saturating_sub(self.escrow_used)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MarketTrade {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub buy_order_id: Uuid,
+    pub sell_order_id: Uuid,
+    pub buyer_address: String,
+    pub seller_address: String,
+    /// MKOIN nanocoins per whole token
+    pub price: TokenAmount,
+    pub amount: TokenAmount,
+    pub mkoin_amount: TokenAmount,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Transfer out of escrow: a trade leg, or a refund when `trade_id` is None
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct MarketTransfer {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub trade_id: Option<Uuid>,
+    pub campaign_id: Uuid,
+    pub asset: String, // 'tokens', 'mkoin'
+    pub recipient: String,
+    pub amount: TokenAmount,
+    pub status: String, // 'pending', 'sent', 'delivered', 'failed'
+    pub attempts: i32,
+    pub msg_hash: Option<String>,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// An order as placed by a user
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NewOrder {
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    /// MKOIN nanocoins per whole token; limit orders only
+    pub price: Option<TokenAmount>,
+    /// Tokens, in nano-units
+    pub amount: TokenAmount,
+    /// MKOIN a market buy may spend
+    pub max_mkoin: Option<TokenAmount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PriceLevel {
+    pub price: TokenAmount,
+    pub amount: TokenAmount,
+    pub orders: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderBook {
+    /// Best (highest) bid first
+    pub bids: Vec<PriceLevel>,
+    /// Best (lowest) ask first
+    pub asks: Vec<PriceLevel>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum MarketError {
+    #[error("Campaign not found")]
+    CampaignNotFound,
+    #[error("Campaign token is not tradable")]
+    NotTradable,
+    #[error("Invalid order: {0}")]
+    InvalidOrder(&'static str),
+    #[error("Order not found")]
+    NotFound,
+    #[error("Order cannot be cancelled in status {0}")]
+    NotCancellable(String),
+}
+
+/// What is left of an incoming order
+#[derive(Debug, Clone)]
+pub struct Taker {
+    pub side: OrderSide,
+    /// Worst acceptable price; None for market orders
+    pub limit: Option<TokenAmount>,
+    pub remaining: TokenAmount,
+    /// Unspent MKOIN escrow, for buy orders
+    pub budget: Option<TokenAmount>,
+}
+
+/// A resting order on the other side of the book
+#[derive(Debug, Clone)]
+pub struct RestingOrder {
+    pub id: Uuid,
+    pub price: TokenAmount,
+    pub remaining: TokenAmount,
+    /// Unspent MKOIN escrow, for buy orders
+    pub budget: Option<TokenAmount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    pub maker_id: Uuid,
+    pub price: TokenAmount,
+    pub amount: TokenAmount,
+    pub mkoin: TokenAmount,
+}
+
+/// Match `taker` against `book`, sorted best price first then oldest first
+///
+/// Trades execute at the resting order's price. A buyer never pays more
+/// than their unspent escrow: a taker buy stops once it cannot afford the
+/// next level, a resting buy that cannot afford any tokens is skipped.
+pub fn match_order(taker: &Taker, book: &[RestingOrder]) -> Vec<Fill> {
+    let mut remaining = taker.remaining;
+    let mut budget = taker.budget;
+    let mut fills = Vec::new();
+
+    for maker in book {
+        if remaining.is_zero() {
+            break;
+        }
+        if let Some(limit) = taker.limit {
+            let crosses = match taker.side {
+                OrderSide::Buy => maker.price <= limit,
+                OrderSide::Sell => maker.price >= limit,
+            };
+            if !crosses {
+                break;
+            }
+        }
+
+        let mut amount = remaining.min(maker.remaining);
+        let buyer_budget = match taker.side {
+            OrderSide::Buy => budget,
+            OrderSide::Sell => maker.budget,
+        };
+        if let Some(buyer_budget) = buyer_budget {
+            amount = amount.min(buyer_budget.tokens_at(maker.price).unwrap_or_default());
+        }
+        if amount.is_zero() {
+            match taker.side {
+                OrderSide::Buy => break,
+                OrderSide::Sell => continue,
+            }
+        }
+        let Some(mkoin) = amount.cost_at(maker.price) else {
+            break;
+        };
+
+        remaining = remaining.saturating_sub(amount);
+        if taker.side == OrderSide::Buy {
+            budget = budget.map(|b| b.saturating_sub(mkoin));
+        }
+        fills.push(Fill {
+            maker_id: maker.id,
+            price: maker.price,
+            amount,
+            mkoin,
+        });
+    }
+
+    fills
+}
+
+impl Database {
+    /// Place an order; it waits for its escrow before it can trade
+    ///
+    /// A sell escrows its tokens, a limit buy the cost of its full amount at
+    /// its price and a market buy its `max_mkoin`.
+    pub async fn create_order(
+        &self,
+        user_address: &str,
+        campaign_id: Uuid,
+        new_order: &NewOrder,
+    ) -> Result<MarketOrder> {
+        let NewOrder { side, order_type, price, amount, max_mkoin } = *new_order;
+        let campaign = self
+            .get_campaign(campaign_id)
+            .await?
+            .ok_or(MarketError::CampaignNotFound)?;
+        let tradable = campaign
+            .token_address
+            .as_deref()
+            .is_some_and(|a| !a.starts_with("COMPUTE_FAILED"));
+        if !tradable {
+            return Err(MarketError::NotTradable.into());
+        }
+
+        if amount.is_zero() {
+            return Err(MarketError::InvalidOrder("amount must be greater than 0").into());
+        }
+        let price = match (order_type, price) {
+            (OrderType::Limit, Some(price)) if !price.is_zero() => Some(price),
+            (OrderType::Limit, _) => {
+                return Err(MarketError::InvalidOrder("limit orders need a price above 0").into());
+            }
+            (OrderType::Market, None) => None,
+            (OrderType::Market, Some(_)) => {
+                return Err(MarketError::InvalidOrder("market orders take no price").into());
+            }
+        };
+        let escrow_amount = match (side, price, max_mkoin) {
+            (OrderSide::Sell, _, _) => amount,
+            (OrderSide::Buy, Some(price), _) => amount
+                .cost_at(price)
+                .ok_or(MarketError::InvalidOrder("order value is too large"))?,
+            (OrderSide::Buy, None, Some(max_mkoin)) if !max_mkoin.is_zero() => max_mkoin,
+            (OrderSide::Buy, None, _) => {
+                return Err(MarketError::InvalidOrder("market buys need max_mkoin above 0").into());
+            }
+        };
+
+        let order = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            INSERT INTO market_orders
+                (campaign_id, user_address, side, order_type, price, amount, escrow_amount)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, campaign_id, user_address, side, order_type, price, amount, filled,
+                      escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            "#,
+        )
+        .bind(campaign_id)
+        .bind(user_address)
+        .bind(side.as_str())
+        .bind(order_type.as_str())
+        .bind(price)
+        .bind(amount)
+        .bind(escrow_amount)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(order)
+    }
+
+    pub async fn get_order(&self, id: Uuid) -> Result<Option<MarketOrder>> {
+        let order = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            SELECT id, campaign_id, user_address, side, order_type, price, amount, filled,
+                   escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            FROM market_orders
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(order)
+    }
+
+    pub async fn get_user_orders(&self, user_address: &str) -> Result<Vec<MarketOrder>> {
+        let orders = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            SELECT id, campaign_id, user_address, side, order_type, price, amount, filled,
+                   escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            FROM market_orders
+            WHERE user_address = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_address)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(orders)
+    }
+
+    pub async fn get_orders_by_status(&self, status: &str, limit: i64) -> Result<Vec<MarketOrder>> {
+        let orders = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            SELECT id, campaign_id, user_address, side, order_type, price, amount, filled,
+                   escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            FROM market_orders
+            WHERE status = $1
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// Record an order's escrow and match it against the book
+    ///
+    /// Matching for a campaign is serialized on the campaign row. Limit
+    /// orders rest with whatever is left; the unfilled part of a market
+    /// order is cancelled and refunded. Returns the trades made, or None if
+    /// the order was not awaiting escrow.
+    pub async fn activate_order(&self, id: Uuid, escrow_tx_hash: &str) -> Result<Option<Vec<MarketTrade>>> {
+        let mut tx = self.pool.begin().await?;
+
+        let Some(order) = self.get_order(id).await? else {
+            return Ok(None);
+        };
+        sqlx::query!("SELECT id FROM campaigns WHERE id = $1 FOR UPDATE", order.campaign_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let order = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            SELECT id, campaign_id, user_address, side, order_type, price, amount, filled,
+                   escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            FROM market_orders
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if order.status != "awaiting_escrow" {
+            return Ok(None);
+        }
+
+        let side = match order.side.as_str() {
+            "buy" => OrderSide::Buy,
+            _ => OrderSide::Sell,
+        };
+        // Best price first, then oldest; never trade with oneself
+        let book = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            SELECT id, campaign_id, user_address, side, order_type, price, amount, filled,
+                   escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            FROM market_orders
+            WHERE campaign_id = $1 AND side = $2 AND status = 'open' AND user_address <> $3
+            ORDER BY CASE WHEN $2 = 'sell' THEN price END ASC,
+                     CASE WHEN $2 = 'buy' THEN price END DESC,
+                     created_at ASC
+            FOR UPDATE
+            "#,
+        )
+        .bind(order.campaign_id)
+        .bind(side.opposite().as_str())
+        .bind(&order.user_address)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let resting: Vec<RestingOrder> = book
+            .iter()
+            .filter_map(|o| {
+                Some(RestingOrder {
+                    id: o.id,
+                    price: o.price?,
+                    remaining: o.amount.saturating_sub(o.filled),
+                    budget: (o.side == "buy").then(|| o.unused_escrow()),
+                })
+            })
+            .collect();
+        let taker = Taker {
+            side,
+            limit: order.price,
+            remaining: order.amount,
+            budget: (side == OrderSide::Buy).then_some(order.escrow_amount),
+        };
+
+        let mut trades = Vec::new();
+        let (mut filled, mut escrow_used) = (TokenAmount::ZERO, TokenAmount::ZERO);
+        for fill in match_order(&taker, &resting) {
+            let Some(maker) = book.iter().find(|o| o.id == fill.maker_id) else {
+                continue;
+            };
+            let (buy, sell) = match side {
+                OrderSide::Buy => (&order, maker),
+                OrderSide::Sell => (maker, &order),
+            };
+
+            let trade = sqlx::query_as::<_, MarketTrade>(
+                r#"
+                INSERT INTO market_trades
+                    (campaign_id, buy_order_id, sell_order_id, buyer_address, seller_address,
+                     price, amount, mkoin_amount)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                RETURNING id, campaign_id, buy_order_id, sell_order_id, buyer_address,
+                          seller_address, price, amount, mkoin_amount, created_at
+                "#,
+            )
+            .bind(order.campaign_id)
+            .bind(buy.id)
+            .bind(sell.id)
+            .bind(&buy.user_address)
+            .bind(&sell.user_address)
+            .bind(fill.price)
+            .bind(fill.amount)
+            .bind(fill.mkoin)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            Self::record_token_price(&mut tx, order.campaign_id, fill.price, PriceSource::Trade).await?;
+
+            // Each side is paid from the other side's escrow
+            Self::queue_market_transfer(&mut tx, buy.id, Some(trade.id), order.campaign_id, "tokens", &buy.user_address, fill.amount).await?;
+            Self::queue_market_transfer(&mut tx, sell.id, Some(trade.id), order.campaign_id, "mkoin", &sell.user_address, fill.mkoin).await?;
+
+            let maker_used = match side {
+                OrderSide::Buy => fill.amount,
+                OrderSide::Sell => fill.mkoin,
+            };
+            let maker = sqlx::query_as::<_, MarketOrder>(
+                r#"
+                UPDATE market_orders
+                SET filled = filled + $2,
+                    escrow_used = escrow_used + $3,
+                    status = CASE WHEN filled + $2 >= amount THEN 'filled' ELSE status END,
+                    updated_at = NOW()
+                WHERE id = $1
+                RETURNING id, campaign_id, user_address, side, order_type, price, amount, filled,
+                          escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+                "#,
+            )
+            .bind(maker.id)
+            .bind(fill.amount)
+            .bind(maker_used)
+            .fetch_one(&mut *tx)
+            .await?;
+            // A filled buy keeps no escrow; what it saved on price goes back
+            if maker.status == "filled" {
+                Self::refund_unused_escrow(&mut tx, &maker).await?;
+            }
+
+            filled = filled.checked_add(fill.amount).ok_or_else(|| anyhow::anyhow!("Fill overflow"))?;
+            let used = match side {
+                OrderSide::Buy => fill.mkoin,
+                OrderSide::Sell => fill.amount,
+            };
+            escrow_used = escrow_used.checked_add(used).ok_or_else(|| anyhow::anyhow!("Fill overflow"))?;
+            trades.push(trade);
+        }
+
+        let status = if filled >= order.amount {
+            "filled"
+        } else if order.order_type == "market" {
+            "cancelled"
+        } else {
+            "open"
+        };
+        let order = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            UPDATE market_orders
+            SET filled = $2, escrow_used = $3, status = $4, escrow_tx_hash = $5, updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, campaign_id, user_address, side, order_type, price, amount, filled,
+                      escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(filled)
+        .bind(escrow_used)
+        .bind(status)
+        .bind(escrow_tx_hash)
+        .fetch_one(&mut *tx)
+        .await?;
+        if order.status != "open" {
+            Self::refund_unused_escrow(&mut tx, &order).await?;
+        }
+
+        tx.commit().await?;
+        Ok(Some(trades))
+    }
+
+    /// Cancel a user's order that has not been filled; an open order's
+    /// remaining escrow is refunded
+    pub async fn cancel_order(&self, id: Uuid, user_address: &str) -> Result<MarketOrder> {
+        let mut tx = self.pool.begin().await?;
+
+        let order = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            SELECT id, campaign_id, user_address, side, order_type, price, amount, filled,
+                   escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            FROM market_orders
+            WHERE id = $1 AND user_address = $2
+            FOR UPDATE
+            "#,
+        )
+        .bind(id)
+        .bind(user_address)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(MarketError::NotFound)?;
+        if order.status != "open" && order.status != "awaiting_escrow" {
+            return Err(MarketError::NotCancellable(order.status).into());
+        }
+
+        let cancelled = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            UPDATE market_orders
+            SET status = 'cancelled', updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, campaign_id, user_address, side, order_type, price, amount, filled,
+                      escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        // Nothing was escrowed yet for an order still waiting on it; an
+        // escrow arriving later is refunded by the market worker
+        if order.status == "open" {
+            Self::refund_unused_escrow(&mut tx, &cancelled).await?;
+        }
+
+        tx.commit().await?;
+        Ok(cancelled)
+    }
+
+    /// Drop an order whose escrow never arrived
+    pub async fn expire_order(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE market_orders
+            SET status = 'expired', updated_at = NOW()
+            WHERE id = $1 AND status = 'awaiting_escrow'
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Orders cancelled or expired before their escrow arrived whose escrow
+    /// transfer could still land
+    pub async fn get_unescrowed_closed_orders(&self, limit: i64) -> Result<Vec<MarketOrder>> {
+        let orders = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            SELECT id, campaign_id, user_address, side, order_type, price, amount, filled,
+                   escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            FROM market_orders
+            WHERE status IN ('cancelled', 'expired') AND escrow_tx_hash IS NULL
+              AND created_at > NOW() - make_interval(secs => $1)
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind((ORDER_ESCROW_TTL_SECS + LATE_ESCROW_GRACE_SECS) as f64)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(orders)
+    }
+
+    /// Record the escrow of an order that was closed before it arrived and
+    /// refund all of it; false if the order is not such an order
+    pub async fn refund_late_escrow(&self, id: Uuid, escrow_tx_hash: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let order = sqlx::query_as::<_, MarketOrder>(
+            r#"
+            UPDATE market_orders
+            SET escrow_tx_hash = $2, updated_at = NOW()
+            WHERE id = $1 AND status IN ('cancelled', 'expired') AND escrow_tx_hash IS NULL
+            RETURNING id, campaign_id, user_address, side, order_type, price, amount, filled,
+                      escrow_amount, escrow_used, status, escrow_tx_hash, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(escrow_tx_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(order) = order else {
+            return Ok(false);
+        };
+        Self::refund_unused_escrow(&mut tx, &order).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn get_order_book(&self, campaign_id: Uuid, depth: i64) -> Result<OrderBook> {
+        Ok(OrderBook {
+            bids: self.get_book_side(campaign_id, OrderSide::Buy, depth).await?,
+            asks: self.get_book_side(campaign_id, OrderSide::Sell, depth).await?,
+        })
+    }
+
+    /// Open orders of one side aggregated by price, best price first
+    async fn get_book_side(&self, campaign_id: Uuid, side: OrderSide, depth: i64) -> Result<Vec<PriceLevel>> {
+        let levels = sqlx::query_as::<_, PriceLevel>(
+            r#"
+            SELECT price, SUM(amount - filled) as amount, COUNT(*) as orders
+            FROM market_orders
+            WHERE campaign_id = $1 AND side = $2 AND status = 'open'
+            GROUP BY price
+            ORDER BY CASE WHEN $2 = 'sell' THEN price END ASC,
+                     CASE WHEN $2 = 'buy' THEN price END DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(campaign_id)
+        .bind(side.as_str())
+        .bind(depth)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(levels)
+    }
+
+    /// Most recent trades first
+    pub async fn get_trades(&self, campaign_id: Uuid, limit: i64) -> Result<Vec<MarketTrade>> {
+        let trades = sqlx::query_as::<_, MarketTrade>(
+            r#"
+            SELECT id, campaign_id, buy_order_id, sell_order_id, buyer_address,
+                   seller_address, price, amount, mkoin_amount, created_at
+            FROM market_trades
+            WHERE campaign_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(campaign_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(trades)
+    }
+
+    /// Price of the latest trade of each campaign that has traded
+    pub async fn get_last_trade_prices(&self, campaign_ids: &[Uuid]) -> Result<HashMap<Uuid, TokenAmount>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT DISTINCT ON (campaign_id) campaign_id, price as "price: TokenAmount"
+            FROM market_trades
+            WHERE campaign_id = ANY($1)
+            ORDER BY campaign_id, created_at DESC
+            "#,
+            campaign_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|r| (r.campaign_id, r.price)).collect())
+    }
+
+    pub async fn get_market_transfers_by_status(&self, status: &str, limit: i64) -> Result<Vec<MarketTransfer>> {
+        let transfers = sqlx::query_as::<_, MarketTransfer>(
+            r#"
+            SELECT id, order_id, trade_id, campaign_id, asset, recipient, amount, status, attempts,
+                   msg_hash, tx_hash, error, created_at, sent_at, delivered_at
+            FROM market_transfers
+            WHERE status = $1
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(transfers)
+    }
+
+    pub async fn get_order_transfers(&self, order_id: Uuid) -> Result<Vec<MarketTransfer>> {
+        let transfers = sqlx::query_as::<_, MarketTransfer>(
+            r#"
+            SELECT id, order_id, trade_id, campaign_id, asset, recipient, amount, status, attempts,
+                   msg_hash, tx_hash, error, created_at, sent_at, delivered_at
+            FROM market_transfers
+            WHERE order_id = $1
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(transfers)
+    }
+
+    /// Count a new attempt for a pending transfer; returns the attempt
+    /// number, or None if the transfer is not pending
+    pub async fn start_market_transfer_attempt(&self, id: Uuid) -> Result<Option<i32>> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE market_transfers
+            SET attempts = attempts + 1
+            WHERE id = $1 AND status = 'pending'
+            RETURNING attempts
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(rec.map(|r| r.attempts))
+    }
+
+    pub async fn mark_market_transfer_sent(&self, id: Uuid, msg_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE market_transfers
+            SET status = 'sent', msg_hash = $2, error = NULL, sent_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            msg_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Send the transfer again on the next pass, or give up once
+    /// `max_attempts` were made; returns the new status
+    pub async fn retry_market_transfer(&self, id: Uuid, error: &str, max_attempts: i32) -> Result<String> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE market_transfers
+            SET status = CASE WHEN attempts >= $3 THEN 'failed' ELSE 'pending' END,
+                error = $2
+            WHERE id = $1
+            RETURNING status
+            "#,
+            id,
+            error,
+            max_attempts
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(rec.status)
+    }
+
+    pub async fn confirm_market_transfer(&self, id: Uuid, tx_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE market_transfers
+            SET status = 'delivered', tx_hash = $2, delivered_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            tx_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Return an order's unused escrow to its owner
+    async fn refund_unused_escrow(conn: &mut sqlx::PgConnection, order: &MarketOrder) -> Result<()> {
+        let asset = if order.side == "buy" { "mkoin" } else { "tokens" };
+        Self::queue_market_transfer(
+            conn,
+            order.id,
+            None,
+            order.campaign_id,
+            asset,
+            &order.user_address,
+            order.unused_escrow(),
+        )
+        .await
+    }
+
+    async fn queue_market_transfer(
+        conn: &mut sqlx::PgConnection,
+        order_id: Uuid,
+        trade_id: Option<Uuid>,
+        campaign_id: Uuid,
+        asset: &str,
+        recipient: &str,
+        amount: TokenAmount,
+    ) -> Result<()> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO market_transfers (order_id, trade_id, campaign_id, asset, recipient, amount)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(order_id)
+        .bind(trade_id)
+        .bind(campaign_id)
+        .bind(asset)
+        .bind(recipient)
+        .bind(amount)
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(whole: u128) -> TokenAmount {
+        TokenAmount::from_whole(whole).unwrap()
+    }
+
+    fn ask(price: u128, amount: u128) -> RestingOrder {
+        RestingOrder {
+            id: Uuid::new_v4(),
+            price: tokens(price),
+            remaining: tokens(amount),
+            budget: None,
+        }
+    }
+
+    #[test]
+    fn test_limit_buy_stops_at_its_price() {
+        let book = [ask(2, 5), ask(3, 5), ask(4, 5)];
+        let taker = Taker {
+            side: OrderSide::Buy,
+            limit: Some(tokens(3)),
+            remaining: tokens(12),
+            budget: Some(tokens(36)),
+        };
+
+        let fills = match_order(&taker, &book);
+        assert_eq!(fills.len(), 2);
+        assert_eq!((fills[0].price, fills[0].amount, fills[0].mkoin), (tokens(2), tokens(5), tokens(10)));
+        assert_eq!((fills[1].price, fills[1].amount, fills[1].mkoin), (tokens(3), tokens(5), tokens(15)));
+    }
+
+    #[test]
+    fn test_market_buy_is_capped_by_budget() {
+        let book = [ask(2, 5), ask(4, 5)];
+        let taker = Taker {
+            side: OrderSide::Buy,
+            limit: None,
+            remaining: tokens(10),
+            budget: Some(tokens(18)),
+        };
+
+        let fills = match_order(&taker, &book);
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[1].amount, tokens(2));
+        let spent = TokenAmount::checked_sum(fills.iter().map(|f| f.mkoin)).unwrap();
+        assert_eq!(spent, tokens(18));
+    }
+
+    #[test]
+    fn test_sell_skips_bids_without_budget() {
+        let broke = RestingOrder {
+            id: Uuid::new_v4(),
+            price: tokens(5),
+            remaining: tokens(5),
+            budget: Some(TokenAmount::ZERO),
+        };
+        let bid = RestingOrder {
+            id: Uuid::new_v4(),
+            price: tokens(4),
+            remaining: tokens(5),
+            budget: Some(tokens(20)),
+        };
+        let taker = Taker {
+            side: OrderSide::Sell,
+            limit: Some(tokens(4)),
+            remaining: tokens(3),
+            budget: None,
+        };
+
+        let fills = match_order(&taker, &[broke, bid.clone()]);
+        assert_eq!(fills, vec![Fill { maker_id: bid.id, price: tokens(4), amount: tokens(3), mkoin: tokens(12) }]);
+    }
+}
@@ -1,10 +1,31 @@
+use crate::amount::TokenAmount;
 use anyhow::Result;
-use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use uuid::Uuid;
 
+pub mod deliveries;
+pub mod deposits;
+pub mod distributions;
+pub mod harvests;
+pub mod idempotency;
+pub mod limits;
+pub mod market;
+pub mod presale;
+pub mod prices;
+pub mod purchase_audit;
+pub mod quotes;
+pub mod redemptions;
+pub mod refunds;
+pub mod rewards;
+pub mod snapshots;
+pub mod transfers;
+
+use limits::{Allocation, PurchaseLimitError, PurchaseLimits};
+use presale::{PresaleError, SalePhase};
+use prices::PriceSource;
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: Uuid,
@@ -31,14 +52,24 @@ pub struct Campaign {
     pub image_url: Option<String>,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
-    pub suggested_price: BigDecimal,
+    /// MKOIN nanocoins per whole token
+    pub suggested_price: TokenAmount,
     pub status: String, // 'pending', 'running', 'paused', 'finished', 'rejected', 'cancelled', 'approved'
     pub token_address: Option<String>, // TON blockchain address of minted token
     pub created_at: Option<DateTime<Utc>>,
     pub minted_at: Option<DateTime<Utc>>,
-    pub mint_amount: Option<BigDecimal>,
+    pub mint_amount: Option<TokenAmount>,
     #[serde(rename = "tx_hash")]
     pub mint_tx_hash: Option<String>,
+    // Sale limits in MKOIN nanocoins, None = unlimited
+    pub soft_cap: Option<TokenAmount>,
+    pub hard_cap: Option<TokenAmount>,
+    pub min_ticket: Option<TokenAmount>,
+    pub max_ticket: Option<TokenAmount>,
+    pub max_per_investor: Option<TokenAmount>,
+    // Allowlist-only window before start_time, None = no presale
+    pub presale_start_time: Option<DateTime<Utc>>,
+    pub presale_price: Option<TokenAmount>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -46,12 +77,25 @@ pub struct Purchase {
     pub id: Uuid,
     pub user_address: String,
     pub campaign_id: Uuid,
-    pub mkoin_paid: BigDecimal,
-    pub tokens_received: BigDecimal,
+    pub mkoin_paid: TokenAmount,
+    pub tokens_received: TokenAmount,
     pub tx_hash: Option<String>,
     pub status: String,
     pub purchased_at: DateTime<Utc>,
     pub confirmed_at: Option<DateTime<Utc>>,
+    pub verified_tx_hash: Option<String>,
+    pub failure_reason: Option<String>,
+    pub quote_id: Option<Uuid>,
+}
+
+/// A campaign token held by a user
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PortfolioPosition {
+    pub campaign_id: Uuid,
+    pub token_address: String,
+    pub balance: TokenAmount,
+    /// MKOIN paid for the campaign's tokens in confirmed purchases
+    pub invested: TokenAmount,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,13 +104,18 @@ pub struct CampaignStats {
     pub total_mkoin_raised: String,
     pub total_tokens_sold: String,
     pub unique_buyers: i32,
+    /// Tokens still available, after pending and confirmed purchases
+    pub remaining_tokens: String,
+    /// MKOIN still accepted before the hard cap, if one is set
+    pub remaining_mkoin: Option<String>,
+    pub soft_cap_reached: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct MkoinMint {
     pub id: Uuid,
     pub recipient_address: String,
-    pub amount: BigDecimal,
+    pub amount: TokenAmount,
     pub tx_hash: Option<String>,
     pub minted_by: Option<Uuid>,
     pub status: String,
@@ -89,18 +138,24 @@ impl Database {
         Ok(Self { pool })
     }
 
+    /// Set a holder's balance as read from chain at `lt`; older reads are
+    /// ignored
     pub async fn upsert_portfolio(
         &self,
         user_address: &str,
         token_address: &str,
-        balance: &str, // passing as string to preserve precision for NUMERIC
+        balance: TokenAmount,
         lt: i64,
     ) -> Result<()> {
+        let user_address = snapshots::holder_address(user_address);
+        let user_address = user_address.as_str();
+        let mut tx = self.pool.begin().await?;
+
         // Using unchecked query to allow compilation without pre-existing DB schema
-        sqlx::query(
+        let result = sqlx::query(
             r#"
             INSERT INTO portfolios (user_address, token_address, balance, last_updated_lt, updated_at)
-            VALUES ($1, $2, $3::numeric, $4, NOW())
+            VALUES ($1, $2, $3, $4, NOW())
             ON CONFLICT (user_address, token_address) 
             DO UPDATE SET 
                 balance = EXCLUDED.balance,
@@ -113,9 +168,15 @@ impl Database {
         .bind(token_address)
         .bind(balance)
         .bind(lt)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
+        // Stale updates change nothing and are not recorded either
+        if result.rows_affected() == 1 {
+            Self::record_balance(&mut tx, user_address, token_address, balance, lt, "chain").await?;
+        }
+
+        tx.commit().await?;
         Ok(())
     }
     // --- User Management ---
@@ -287,14 +348,16 @@ impl Database {
         // Let's adapt to pass fields or use the struct.
         // Status defaults to pending in DB, but we can enforce it.
 
+        let mut tx = self.pool.begin().await?;
         let rec = sqlx::query!(
             r#"
             INSERT INTO campaigns (
                 farmer_id, name, description, token_name, token_symbol, 
                 token_supply, logo_url, image_url, start_time, end_time, 
-                suggested_price, status
+                suggested_price, status, soft_cap, hard_cap, min_ticket,
+                max_ticket, max_per_investor, presale_start_time, presale_price
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::campaign_status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12::campaign_status, $13, $14, $15, $16, $17, $18, $19)
             RETURNING id
             "#,
             campaign.farmer_id,
@@ -307,12 +370,21 @@ impl Database {
             campaign.image_url,
             campaign.start_time,
             campaign.end_time,
-            campaign.suggested_price,
-            campaign.status as _
+            campaign.suggested_price as _,
+            campaign.status as _,
+            campaign.soft_cap as _,
+            campaign.hard_cap as _,
+            campaign.min_ticket as _,
+            campaign.max_ticket as _,
+            campaign.max_per_investor as _,
+            campaign.presale_start_time,
+            campaign.presale_price as _
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
+        Self::record_token_price(&mut tx, rec.id, campaign.suggested_price, PriceSource::Sale).await?;
 
+        tx.commit().await?;
         Ok(rec.id)
     }
 
@@ -333,8 +405,13 @@ impl Database {
             SELECT
                 id, farmer_id, name, description, token_name, token_symbol,
                 token_supply, logo_url, image_url, start_time, end_time,
-                suggested_price, status::text as "status!", token_address, created_at,
-                minted_at, mint_amount, mint_tx_hash
+                suggested_price as "suggested_price: TokenAmount",
+                status::text as "status!", token_address, created_at, minted_at,
+                mint_amount as "mint_amount: TokenAmount", mint_tx_hash,
+                soft_cap as "soft_cap: TokenAmount", hard_cap as "hard_cap: TokenAmount",
+                min_ticket as "min_ticket: TokenAmount", max_ticket as "max_ticket: TokenAmount",
+                max_per_investor as "max_per_investor: TokenAmount", presale_start_time,
+                presale_price as "presale_price: TokenAmount"
             FROM campaigns
             WHERE (status::text = $1 OR $1 IS NULL)
               AND (farmer_id = $2 OR $2 IS NULL)
@@ -356,8 +433,13 @@ impl Database {
             SELECT
                 id, farmer_id, name, description, token_name, token_symbol,
                 token_supply, logo_url, image_url, start_time, end_time,
-                suggested_price, status::text as "status!", token_address, created_at,
-                minted_at, mint_amount, mint_tx_hash
+                suggested_price as "suggested_price: TokenAmount",
+                status::text as "status!", token_address, created_at, minted_at,
+                mint_amount as "mint_amount: TokenAmount", mint_tx_hash,
+                soft_cap as "soft_cap: TokenAmount", hard_cap as "hard_cap: TokenAmount",
+                min_ticket as "min_ticket: TokenAmount", max_ticket as "max_ticket: TokenAmount",
+                max_per_investor as "max_per_investor: TokenAmount", presale_start_time,
+                presale_price as "presale_price: TokenAmount"
             FROM campaigns
             WHERE id = $1
             "#,
@@ -368,6 +450,55 @@ impl Database {
         Ok(campaign)
     }
 
+    pub async fn get_campaigns_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Campaign>> {
+        let campaigns = sqlx::query_as!(
+            Campaign,
+            r#"
+            SELECT
+                id, farmer_id, name, description, token_name, token_symbol,
+                token_supply, logo_url, image_url, start_time, end_time,
+                suggested_price as "suggested_price: TokenAmount",
+                status::text as "status!", token_address, created_at, minted_at,
+                mint_amount as "mint_amount: TokenAmount", mint_tx_hash,
+                soft_cap as "soft_cap: TokenAmount", hard_cap as "hard_cap: TokenAmount",
+                min_ticket as "min_ticket: TokenAmount", max_ticket as "max_ticket: TokenAmount",
+                max_per_investor as "max_per_investor: TokenAmount", presale_start_time,
+                presale_price as "presale_price: TokenAmount"
+            FROM campaigns
+            WHERE id = ANY($1)
+            ORDER BY created_at
+            "#,
+            ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(campaigns)
+    }
+
+    pub async fn get_campaign_by_token_address(&self, token_address: &str) -> Result<Option<Campaign>> {
+        let campaign = sqlx::query_as!(
+            Campaign,
+            r#"
+            SELECT
+                id, farmer_id, name, description, token_name, token_symbol,
+                token_supply, logo_url, image_url, start_time, end_time,
+                suggested_price as "suggested_price: TokenAmount",
+                status::text as "status!", token_address, created_at, minted_at,
+                mint_amount as "mint_amount: TokenAmount", mint_tx_hash,
+                soft_cap as "soft_cap: TokenAmount", hard_cap as "hard_cap: TokenAmount",
+                min_ticket as "min_ticket: TokenAmount", max_ticket as "max_ticket: TokenAmount",
+                max_per_investor as "max_per_investor: TokenAmount", presale_start_time,
+                presale_price as "presale_price: TokenAmount"
+            FROM campaigns
+            WHERE token_address = $1
+            "#,
+            token_address
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(campaign)
+    }
+
     pub async fn update_campaign_status(&self, id: Uuid, status: &str) -> Result<()> {
         sqlx::query!(
             "UPDATE campaigns SET status = $1::campaign_status WHERE id = $2",
@@ -390,17 +521,64 @@ impl Database {
         Ok(())
     }
 
+    pub async fn update_campaign_limits(
+        &self,
+        id: Uuid,
+        soft_cap: Option<TokenAmount>,
+        hard_cap: Option<TokenAmount>,
+        min_ticket: Option<TokenAmount>,
+        max_ticket: Option<TokenAmount>,
+        max_per_investor: Option<TokenAmount>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE campaigns
+            SET soft_cap = $2, hard_cap = $3, min_ticket = $4, max_ticket = $5,
+                max_per_investor = $6, updated_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            soft_cap as _,
+            hard_cap as _,
+            min_ticket as _,
+            max_ticket as _,
+            max_per_investor as _
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Wallet holding a campaign's raised MKOIN; None for the platform wallet
+    pub async fn get_campaign_treasury(&self, id: Uuid) -> Result<Option<String>> {
+        let treasury = sqlx::query_scalar!("SELECT treasury_address FROM campaigns WHERE id = $1", id)
+            .fetch_optional(&self.pool)
+            .await?
+            .flatten();
+        Ok(treasury)
+    }
+
+    /// Returns false if the campaign does not exist
+    pub async fn set_campaign_treasury(&self, id: Uuid, treasury_address: Option<&str>) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE campaigns SET treasury_address = $2, updated_at = NOW() WHERE id = $1",
+            id,
+            treasury_address
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
     // --- Campaign Mint Tracking ---
 
     pub async fn record_campaign_mint(
         &self,
         campaign_id: Uuid,
         recipient: &str,
-        amount: &str,
+        amount: TokenAmount,
         tx_hash: Option<&str>,
     ) -> Result<Uuid> {
-        use std::str::FromStr;
-        let amount_bd = BigDecimal::from_str(amount)?;
         let rec = sqlx::query!(
             r#"
             INSERT INTO campaign_token_mints (campaign_id, recipient_address, amount, tx_hash)
@@ -409,7 +587,7 @@ impl Database {
             "#,
             campaign_id,
             recipient,
-            amount_bd,
+            amount as _,
             tx_hash
         )
         .fetch_one(&self.pool)
@@ -420,11 +598,9 @@ impl Database {
     pub async fn update_campaign_mint_info(
         &self,
         campaign_id: Uuid,
-        amount: &str,
+        amount: TokenAmount,
         tx_hash: Option<&str>,
     ) -> Result<()> {
-        use std::str::FromStr;
-        let amount_bd = BigDecimal::from_str(amount)?;
         sqlx::query!(
             r#"
             UPDATE campaigns
@@ -434,7 +610,7 @@ impl Database {
             WHERE id = $1
             "#,
             campaign_id,
-            amount_bd,
+            amount as _,
             tx_hash
         )
         .execute(&self.pool)
@@ -444,32 +620,147 @@ impl Database {
 
     // --- Purchase Tracking ---
 
+    /// Record a purchase against a live quote issued to `user_address`
+    ///
+    /// The quote is claimed in the same transaction, so it can back at most
+    /// one purchase. The campaign row is locked while the sale is re-checked
+    /// at the quoted price, so concurrent purchases are serialized and cannot
+    /// oversell. Limit violations are returned as a `limits::PurchaseLimitError`
+    /// inside the `anyhow::Error`, presale violations as a
+    /// `presale::PresaleError` and unusable quotes as a `quotes::QuoteError`.
     pub async fn create_purchase(
         &self,
         user_address: &str,
-        campaign_id: Uuid,
-        mkoin_paid: &str,
-        tokens_received: &str,
+        quote_id: Uuid,
         tx_hash: &str,
-    ) -> Result<Uuid> {
-        use std::str::FromStr;
-        let mkoin_paid_bd = BigDecimal::from_str(mkoin_paid)?;
-        let tokens_received_bd = BigDecimal::from_str(tokens_received)?;
+    ) -> Result<(Uuid, TokenAmount)> {
+        let mut tx = self.pool.begin().await?;
+
+        let quote = Self::claim_quote(&mut tx, quote_id, user_address).await?;
+        let (_, tokens_received) = Self::check_sale(
+            &mut tx,
+            quote.campaign_id,
+            user_address,
+            quote.mkoin_amount,
+            Some(quote.price),
+        )
+        .await?;
+
         let rec = sqlx::query!(
             r#"
-            INSERT INTO purchases (user_address, campaign_id, mkoin_paid, tokens_received, tx_hash)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO purchases (user_address, campaign_id, mkoin_paid, tokens_received, tx_hash, quote_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
             RETURNING id
             "#,
             user_address,
-            campaign_id,
-            mkoin_paid_bd,
-            tokens_received_bd,
-            tx_hash
+            quote.campaign_id,
+            quote.mkoin_amount as _,
+            tokens_received as _,
+            tx_hash,
+            quote.id
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
-        Ok(rec.id)
+
+        tx.commit().await?;
+        Ok((rec.id, tokens_received))
+    }
+
+    /// Check that `user_address` may buy for `mkoin_paid` in the campaign's
+    /// current sale phase and return the price and tokens allocated
+    ///
+    /// Locks the campaign row until `conn`'s transaction ends. `quoted_price`
+    /// replaces the phase price for purchases made against a quote.
+    async fn check_sale(
+        conn: &mut sqlx::PgConnection,
+        campaign_id: Uuid,
+        user_address: &str,
+        mkoin_paid: TokenAmount,
+        quoted_price: Option<TokenAmount>,
+    ) -> Result<(TokenAmount, TokenAmount)> {
+        let campaign = sqlx::query!(
+            r#"
+            SELECT token_supply,
+                   suggested_price as "suggested_price: TokenAmount",
+                   hard_cap as "hard_cap: TokenAmount",
+                   min_ticket as "min_ticket: TokenAmount",
+                   max_ticket as "max_ticket: TokenAmount",
+                   max_per_investor as "max_per_investor: TokenAmount",
+                   start_time, end_time, presale_start_time,
+                   presale_price as "presale_price: TokenAmount"
+            FROM campaigns
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            campaign_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Campaign not found"))?;
+
+        let phase = presale::sale_phase(
+            Utc::now(),
+            campaign.presale_start_time,
+            campaign.start_time,
+            campaign.end_time,
+        );
+        let phase_price = match phase {
+            SalePhase::NotStarted(opens) => return Err(PresaleError::NotStarted(opens).into()),
+            SalePhase::Closed(ended) => return Err(PresaleError::Closed(ended).into()),
+            SalePhase::Presale => {
+                let addresses = presale::address_variants(user_address);
+                if !Self::is_allowlisted(&mut *conn, campaign_id, &addresses, None).await? {
+                    return Err(PresaleError::NotAllowlisted.into());
+                }
+                campaign.presale_price.unwrap_or(campaign.suggested_price)
+            }
+            SalePhase::Public => campaign.suggested_price,
+        };
+        let price = quoted_price.unwrap_or(phase_price);
+
+        // Allocation is derived from the sale price, never taken from the client
+        let tokens = mkoin_paid
+            .tokens_at(price)
+            .filter(|t| !t.is_zero())
+            .ok_or(PurchaseLimitError::NoTokens { price })?;
+
+        let limits = PurchaseLimits {
+            token_supply: supply_in_nanotokens(&campaign.token_supply)?,
+            hard_cap: campaign.hard_cap,
+            min_ticket: campaign.min_ticket,
+            max_ticket: campaign.max_ticket,
+            max_per_investor: campaign.max_per_investor,
+        };
+        let allocation = Self::campaign_allocation(&mut *conn, campaign_id, Some(user_address)).await?;
+
+        limits::check_purchase_limits(&limits, &allocation, mkoin_paid, tokens)?;
+        Ok((price, tokens))
+    }
+
+    /// Sum of pending and confirmed purchases in a campaign
+    async fn campaign_allocation(
+        conn: &mut sqlx::PgConnection,
+        campaign_id: Uuid,
+        user_address: Option<&str>,
+    ) -> Result<Allocation> {
+        let buyers = sqlx::query!(
+            r#"
+            SELECT user_address,
+                   COALESCE(SUM(mkoin_paid), 0) as "mkoin_paid!: TokenAmount",
+                   COALESCE(SUM(tokens_received), 0) as "tokens_received!: TokenAmount"
+            FROM purchases
+            WHERE campaign_id = $1 AND status IN ('pending', 'confirmed')
+            GROUP BY user_address
+            "#,
+            campaign_id
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|r| (r.user_address, r.mkoin_paid, r.tokens_received))
+        .collect::<Vec<_>>();
+
+        Allocation::from_buyers(&buyers, user_address).ok_or_else(|| anyhow::anyhow!("Campaign allocation overflows"))
     }
 
     pub async fn get_user_purchases(&self, user_address: &str) -> Result<Vec<Purchase>> {
@@ -484,7 +775,10 @@ impl Database {
                 tx_hash,
                 status,
                 purchased_at,
-                confirmed_at
+                confirmed_at,
+                verified_tx_hash,
+                failure_reason,
+                quote_id
             FROM purchases
             WHERE user_address = $1
             ORDER BY purchased_at DESC
@@ -508,7 +802,10 @@ impl Database {
                 tx_hash,
                 status,
                 purchased_at,
-                confirmed_at
+                confirmed_at,
+                verified_tx_hash,
+                failure_reason,
+                quote_id
             FROM purchases
             WHERE campaign_id = $1
             ORDER BY purchased_at DESC
@@ -520,13 +817,142 @@ impl Database {
         Ok(purchases)
     }
 
+    pub async fn get_purchase(&self, id: Uuid) -> Result<Option<Purchase>> {
+        let purchase = sqlx::query_as::<_, Purchase>(
+            r#"
+            SELECT
+                id,
+                user_address,
+                campaign_id,
+                mkoin_paid,
+                tokens_received,
+                tx_hash,
+                status,
+                purchased_at,
+                confirmed_at,
+                verified_tx_hash,
+                failure_reason,
+                quote_id
+            FROM purchases
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(purchase)
+    }
+
+    /// Confirm a pending purchase paid by `verified_tx_hash`
+    ///
+    /// The buyer's portfolio is not touched: the ledger credits the tokens
+    /// when their delivery is indexed.
+    ///
+    /// Returns false if the purchase is no longer pending or the payment
+    /// already confirmed another purchase.
+    pub async fn confirm_purchase(&self, id: Uuid, verified_tx_hash: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE purchases
+            SET status = 'confirmed', verified_tx_hash = $2, confirmed_at = NOW()
+            WHERE id = $1 AND status = 'pending'
+              AND NOT EXISTS (SELECT 1 FROM purchases WHERE verified_tx_hash = $2)
+            "#,
+            id,
+            verified_tx_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Mark a pending purchase as failed; returns false if it was not pending
+    pub async fn fail_purchase(&self, id: Uuid, reason: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE purchases
+            SET status = 'failed', failure_reason = $2
+            WHERE id = $1 AND status = 'pending'
+            "#,
+            id,
+            reason
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Mark a pending purchase whose payment never arrived as expired
+    pub async fn expire_purchase(&self, id: Uuid, reason: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE purchases
+            SET status = 'expired', failure_reason = $2
+            WHERE id = $1 AND status = 'pending'
+            "#,
+            id,
+            reason
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Note that a pending purchase is being checked on chain; false if it
+    /// is no longer pending or was checked less than `interval_secs` ago
+    pub async fn start_purchase_check(&self, id: Uuid, interval_secs: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE purchases
+            SET last_checked_at = NOW()
+            WHERE id = $1 AND status = 'pending'
+              AND (last_checked_at IS NULL OR last_checked_at <= NOW() - make_interval(secs => $2))
+            "#,
+            id,
+            interval_secs as f64
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Pending purchases checked least recently first, so purchases that
+    /// stay unresolved do not hold back newer ones
+    pub async fn get_pending_purchases(&self, limit: i64) -> Result<Vec<Purchase>> {
+        let purchases = sqlx::query_as::<_, Purchase>(
+            r#"
+            SELECT
+                id,
+                user_address,
+                campaign_id,
+                mkoin_paid,
+                tokens_received,
+                tx_hash,
+                status,
+                purchased_at,
+                confirmed_at,
+                verified_tx_hash,
+                failure_reason,
+                quote_id
+            FROM purchases
+            WHERE status = 'pending'
+            ORDER BY last_checked_at ASC NULLS FIRST, purchased_at ASC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(purchases)
+    }
+
     pub async fn get_campaign_stats(&self, campaign_id: Uuid) -> Result<CampaignStats> {
         let stats = sqlx::query!(
             r#"
             SELECT
                 COUNT(*)::int as "total_purchases!",
-                COALESCE(SUM(mkoin_paid), 0) as "total_mkoin_raised!",
-                COALESCE(SUM(tokens_received), 0) as "total_tokens_sold!",
+                COALESCE(SUM(mkoin_paid), 0) as "total_mkoin_raised!: TokenAmount",
+                COALESCE(SUM(tokens_received), 0) as "total_tokens_sold!: TokenAmount",
                 COUNT(DISTINCT user_address)::int as "unique_buyers!"
             FROM purchases
             WHERE campaign_id = $1 AND status = 'confirmed'
@@ -536,43 +962,62 @@ impl Database {
         .fetch_one(&self.pool)
         .await?;
 
+        let campaign = sqlx::query!(
+            r#"
+            SELECT token_supply, soft_cap as "soft_cap: TokenAmount", hard_cap as "hard_cap: TokenAmount"
+            FROM campaigns WHERE id = $1
+            "#,
+            campaign_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        let mut conn = self.pool.acquire().await?;
+        let allocation = Self::campaign_allocation(&mut conn, campaign_id, None).await?;
+
+        let (remaining_tokens, remaining_mkoin, soft_cap_reached) = match campaign {
+            Some(c) => (
+                supply_in_nanotokens(&c.token_supply)?.saturating_sub(allocation.tokens_sold),
+                c.hard_cap.map(|cap| cap.saturating_sub(allocation.mkoin_raised)),
+                c.soft_cap.map(|cap| stats.total_mkoin_raised >= cap),
+            ),
+            None => (TokenAmount::ZERO, None, None),
+        };
+
         Ok(CampaignStats {
             total_purchases: stats.total_purchases,
-            total_mkoin_raised: stats.total_mkoin_raised.to_string(),
-            total_tokens_sold: stats.total_tokens_sold.to_string(),
+            total_mkoin_raised: stats.total_mkoin_raised.nano().to_string(),
+            total_tokens_sold: stats.total_tokens_sold.nano().to_string(),
             unique_buyers: stats.unique_buyers,
+            remaining_tokens: remaining_tokens.nano().to_string(),
+            remaining_mkoin: remaining_mkoin.map(|v| v.nano().to_string()),
+            soft_cap_reached,
         })
     }
 
-    pub async fn get_user_portfolio(
-        &self,
-        user_address: &str,
-    ) -> Result<Vec<crate::api::PortfolioItem>> {
-        let items = sqlx::query!(
+    /// Campaign tokens `address` holds or held, from the indexed balances,
+    /// with the MKOIN paid in confirmed purchases
+    ///
+    /// Balances are stored under the raw address; purchases under the form
+    /// the buyer gave, so either form matches them.
+    pub async fn get_portfolio_positions(&self, address: &str) -> Result<Vec<PortfolioPosition>> {
+        let positions = sqlx::query_as::<_, PortfolioPosition>(
             r#"
-            SELECT
-                p.token_address as "token_address!",
-                COALESCE(tm.symbol, 'UNKNOWN') as "symbol!",
-                p.balance as "balance!"
+            SELECT c.id as campaign_id, p.token_address, p.balance,
+                   COALESCE((
+                       SELECT SUM(pu.mkoin_paid) FROM purchases pu
+                       WHERE pu.campaign_id = c.id AND pu.user_address = ANY($2) AND pu.status = 'confirmed'
+                   ), 0) as invested
             FROM portfolios p
-            LEFT JOIN token_minters tm ON p.token_address = tm.address
-            WHERE p.user_address = $1 AND p.balance > 0
-            ORDER BY p.updated_at DESC
+            JOIN campaigns c ON c.token_address = p.token_address
+            WHERE p.user_address = $1
+            ORDER BY p.token_address
             "#,
-            user_address
         )
+        .bind(snapshots::holder_address(address))
+        .bind(presale::address_variants(address))
         .fetch_all(&self.pool)
         .await?;
-
-        Ok(items
-            .into_iter()
-            .map(|r| crate::api::PortfolioItem {
-                token_address: r.token_address,
-                symbol: r.symbol,
-                balance: r.balance.to_string(),
-                usd_value: None,
-            })
-            .collect())
+        Ok(positions)
     }
 
     // MKOIN Mint Operations
@@ -581,7 +1026,7 @@ impl Database {
     pub async fn record_mkoin_mint(
         &self,
         recipient_address: &str,
-        amount: &BigDecimal,
+        amount: TokenAmount,
         tx_hash: &str,
         minted_by: Option<Uuid>,
         status: &str,
@@ -593,7 +1038,7 @@ impl Database {
             RETURNING id
             "#,
             recipient_address,
-            amount,
+            amount as _,
             tx_hash,
             minted_by,
             status
@@ -623,6 +1068,41 @@ impl Database {
         Ok(mints)
     }
 
+    // --- Media ---
+
+    /// Record an uploaded media file; re-uploads of identical content are no-ops
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record_media_file(
+        &self,
+        key: &str,
+        content_type: &str,
+        byte_size: i64,
+        width: i32,
+        height: i32,
+        variant: &str,
+        original_key: Option<&str>,
+        uploaded_by: Option<Uuid>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO media_files (key, content_type, byte_size, width, height, variant, original_key, uploaded_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (key) DO NOTHING
+            "#,
+            key,
+            content_type,
+            byte_size,
+            width,
+            height,
+            variant,
+            original_key,
+            uploaded_by
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
     // --- Token Management ---
 
     pub async fn upsert_token_minter(
@@ -631,7 +1111,7 @@ impl Database {
         symbol: Option<&str>,
         metadata_url: Option<&str>,
         is_agri_token: bool,
-        total_supply: Option<BigDecimal>,
+        total_supply: Option<TokenAmount>,
         campaign_id: Option<Uuid>,
     ) -> Result<()> {
         sqlx::query!(
@@ -651,7 +1131,7 @@ impl Database {
             symbol,
             metadata_url,
             is_agri_token,
-            total_supply,
+            total_supply as _,
             campaign_id
         )
         .execute(&self.pool)
@@ -664,7 +1144,8 @@ impl Database {
         let token = sqlx::query_as!(
             Token,
             r#"
-            SELECT address, symbol, metadata_url, is_agri_token, total_supply, campaign_id, created_at, updated_at
+            SELECT address, symbol, metadata_url, is_agri_token,
+                   total_supply as "total_supply: TokenAmount", campaign_id, created_at, updated_at
             FROM token_minters
             WHERE address = $1
             "#,
@@ -677,13 +1158,18 @@ impl Database {
     }
 }
 
+/// `campaigns.token_supply` is stored in whole tokens; purchases count nano-units
+fn supply_in_nanotokens(token_supply: &str) -> Result<TokenAmount> {
+    Ok(TokenAmount::parse_decimal(token_supply)?)
+}
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Token {
     pub address: String,
     pub symbol: Option<String>,
     pub metadata_url: Option<String>,
     pub is_agri_token: Option<bool>,
-    pub total_supply: Option<BigDecimal>,
+    pub total_supply: Option<TokenAmount>,
     pub campaign_id: Option<Uuid>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
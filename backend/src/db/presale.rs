@@ -0,0 +1,347 @@
+use super::Database;
+use super::prices::PriceSource;
+use crate::amount::TokenAmount;
+use crate::ton::address_utils::to_raw_address;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tonlib_core::TonAddress;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SalePhase {
+    /// Nothing is on sale yet; holds when the first phase opens
+    NotStarted(DateTime<Utc>),
+    /// Allowlisted buyers only
+    Presale,
+    /// Open to everyone
+    Public,
+    /// The campaign has ended; holds its end time
+    Closed(DateTime<Utc>),
+}
+
+/// Which phase a campaign's sale is in at `now`
+///
+/// Without a presale the sale opens at `start_time`; either way it closes at
+/// `end_time`.
+pub fn sale_phase(
+    now: DateTime<Utc>,
+    presale_start: Option<DateTime<Utc>>,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> SalePhase {
+    if now >= end_time {
+        return SalePhase::Closed(end_time);
+    }
+    match presale_start {
+        _ if now >= start_time => SalePhase::Public,
+        Some(opens) if now >= opens => SalePhase::Presale,
+        Some(opens) => SalePhase::NotStarted(opens),
+        None => SalePhase::NotStarted(start_time),
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum PresaleError {
+    #[error("Sale opens at {0}")]
+    NotStarted(DateTime<Utc>),
+    #[error("Sale closed at {0}")]
+    Closed(DateTime<Utc>),
+    #[error("Buyer is not on the presale allowlist")]
+    NotAllowlisted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowlistEntry {
+    /// Raw `workchain:hex` TON address
+    Address(String),
+    User(Uuid),
+}
+
+#[derive(Debug, Default)]
+pub struct AllowlistImport {
+    pub entries: Vec<AllowlistEntry>,
+    /// Lines that are neither a TON address nor a user id
+    pub invalid: Vec<String>,
+}
+
+/// Parse an allowlist CSV: one address or user id per line, first column only
+///
+/// Blank lines, `#` comments and a header row (`address`, `user_id`, ...) are
+/// ignored. Addresses are normalized to raw form and duplicates dropped.
+pub fn parse_allowlist_csv(csv: &str) -> AllowlistImport {
+    let mut import = AllowlistImport::default();
+    let mut seen = HashSet::new();
+
+    for (i, line) in csv.lines().enumerate() {
+        let value = line
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches('"');
+        if value.is_empty() || value.starts_with('#') {
+            continue;
+        }
+
+        let entry = if let Ok(id) = Uuid::parse_str(value) {
+            AllowlistEntry::User(id)
+        } else if let Ok(raw) = to_raw_address(value) {
+            AllowlistEntry::Address(raw)
+        } else {
+            // Header row
+            if i == 0 && value.chars().all(|c| c.is_ascii_alphabetic() || c == '_') {
+                continue;
+            }
+            import.invalid.push(value.to_string());
+            continue;
+        };
+
+        if seen.insert(entry.clone()) {
+            import.entries.push(entry);
+        }
+    }
+
+    import
+}
+
+/// Forms under which a buyer address may be stored: as given, raw and
+/// user-friendly (bounceable or not, mainnet or testnet)
+pub fn address_variants(address: &str) -> Vec<String> {
+    let mut variants = vec![address.to_string()];
+    if let Ok(raw) = to_raw_address(address)
+        && let Ok(parsed) = TonAddress::from_hex_str(&raw)
+    {
+        variants.push(raw);
+        for (non_bounceable, testnet) in [(false, false), (true, false), (false, true), (true, true)] {
+            variants.push(parsed.to_base64_url_flags(non_bounceable, testnet));
+        }
+        variants.sort();
+        variants.dedup();
+    }
+    variants
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AllowlistRow {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub address: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub added_at: Option<DateTime<Utc>>,
+}
+
+impl Database {
+    /// Set a campaign's presale window and price
+    ///
+    /// A new presale price is added to the sale price series; removing it
+    /// puts the public sale price back.
+    pub async fn update_campaign_presale(
+        &self,
+        id: Uuid,
+        presale_start_time: Option<DateTime<Utc>>,
+        presale_price: Option<TokenAmount>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let previous = sqlx::query!(
+            r#"
+            SELECT presale_price as "presale_price: TokenAmount",
+                   suggested_price as "suggested_price: TokenAmount"
+            FROM campaigns
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Campaign not found"))?;
+
+        sqlx::query!(
+            r#"
+            UPDATE campaigns
+            SET presale_start_time = $2, presale_price = $3, updated_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            presale_start_time,
+            presale_price as _
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if presale_price != previous.presale_price {
+            let sale_price = presale_price.unwrap_or(previous.suggested_price);
+            Self::record_token_price(&mut tx, id, sale_price, PriceSource::Sale).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Add entries to a campaign allowlist; returns how many were new
+    pub async fn add_allowlist_entries(
+        &self,
+        campaign_id: Uuid,
+        entries: &[AllowlistEntry],
+    ) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut added = 0;
+        for entry in entries {
+            let (address, user_id) = match entry {
+                AllowlistEntry::Address(a) => (Some(a.as_str()), None),
+                AllowlistEntry::User(id) => (None, Some(*id)),
+            };
+            added += sqlx::query!(
+                r#"
+                INSERT INTO campaign_allowlist (campaign_id, address, user_id)
+                VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING
+                "#,
+                campaign_id,
+                address,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    pub async fn list_allowlist(&self, campaign_id: Uuid) -> Result<Vec<AllowlistRow>> {
+        let rows = sqlx::query_as::<_, AllowlistRow>(
+            r#"
+            SELECT id, campaign_id, address, user_id, added_at
+            FROM campaign_allowlist
+            WHERE campaign_id = $1
+            ORDER BY added_at, id
+            "#,
+        )
+        .bind(campaign_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    pub async fn clear_allowlist(&self, campaign_id: Uuid) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM campaign_allowlist WHERE campaign_id = $1", campaign_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Whether a buyer is allowlisted, by address or by the user owning it
+    pub(crate) async fn is_allowlisted(
+        conn: &mut sqlx::PgConnection,
+        campaign_id: Uuid,
+        addresses: &[String],
+        user_id: Option<Uuid>,
+    ) -> Result<bool> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM campaign_allowlist a
+                WHERE a.campaign_id = $1
+                  AND (a.address = ANY($2)
+                       OR a.user_id = $3
+                       OR a.user_id IN (SELECT id FROM users WHERE address = ANY($2)))
+            ) as "allowed!"
+            "#,
+            campaign_id,
+            addresses,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+        Ok(rec.allowed)
+    }
+
+    /// Campaigns whose allowlist contains the caller
+    pub async fn allowlisted_campaigns(
+        &self,
+        addresses: &[String],
+        user_id: Option<Uuid>,
+    ) -> Result<HashSet<Uuid>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT DISTINCT a.campaign_id
+            FROM campaign_allowlist a
+            WHERE a.address = ANY($1)
+               OR a.user_id = $2
+               OR a.user_id IN (SELECT id FROM users WHERE address = ANY($1))
+            "#,
+            addresses,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|r| r.campaign_id).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_sale_phase() {
+        let start = Utc::now();
+        let end = start + Duration::days(30);
+        let presale = start - Duration::days(2);
+
+        assert_eq!(
+            sale_phase(start - Duration::days(3), None, start, end),
+            SalePhase::NotStarted(start)
+        );
+        assert_eq!(sale_phase(start, None, start, end), SalePhase::Public);
+        assert_eq!(
+            sale_phase(start - Duration::days(3), Some(presale), start, end),
+            SalePhase::NotStarted(presale)
+        );
+        assert_eq!(sale_phase(start - Duration::days(1), Some(presale), start, end), SalePhase::Presale);
+        assert_eq!(sale_phase(start, Some(presale), start, end), SalePhase::Public);
+        assert_eq!(sale_phase(end, None, start, end), SalePhase::Closed(end));
+        assert_eq!(sale_phase(end, Some(presale), start, end), SalePhase::Closed(end));
+    }
+
+    #[test]
+    fn test_address_variants() {
+        let raw = "0:0d204ad68e3e94e0536049f107c11eb1f9b9242425759f9c353886fe3dfe9e59";
+        let friendly = "EQANIErWjj6U4FNgSfEHwR6x-bkkJCV1n5w1OIb-Pf6eWQwD";
+        assert_eq!(address_variants(raw), address_variants(friendly));
+        assert!(address_variants(raw).contains(&friendly.to_string()));
+        assert_eq!(address_variants(raw).len(), 5);
+        assert_eq!(address_variants("not-an-address"), vec!["not-an-address".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_allowlist_csv() {
+        let user = Uuid::new_v4();
+        let csv = format!(
+            "address,note\n\
+             EQANIErWjj6U4FNgSfEHwR6x-bkkJCV1n5w1OIb-Pf6eWQwD,early\n\
+             0:0d204ad68e3e94e0536049f107c11eb1f9b9242425759f9c353886fe3dfe9e59\n\
+             \n\
+             # comment\n\
+             \"{}\"\n\
+             not-an-address\n",
+            user
+        );
+        let import = parse_allowlist_csv(&csv);
+        assert_eq!(
+            import.entries,
+            vec![
+                AllowlistEntry::Address(
+                    "0:0d204ad68e3e94e0536049f107c11eb1f9b9242425759f9c353886fe3dfe9e59".to_string()
+                ),
+                AllowlistEntry::User(user),
+            ]
+        );
+        assert_eq!(import.invalid, vec!["not-an-address".to_string()]);
+    }
+}
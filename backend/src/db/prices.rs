@@ -0,0 +1,378 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Where a campaign token price comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceSource {
+    /// Primary sale price of the campaign
+    Sale,
+    /// Last secondary market trade
+    Trade,
+    /// Net asset value set by an admin
+    Nav,
+}
+
+impl PriceSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceSource::Sale => "sale",
+            PriceSource::Trade => "trade",
+            PriceSource::Nav => "nav",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "sale" => Some(PriceSource::Sale),
+            "trade" => Some(PriceSource::Trade),
+            "nav" => Some(PriceSource::Nav),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TokenPrice {
+    pub id: i64,
+    pub campaign_id: Uuid,
+    pub price: TokenAmount, // MKOIN per token
+    pub source: String,     // 'sale', 'trade', 'nav'
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// The price a campaign token is valued at
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedPrice {
+    pub price: TokenAmount,
+    /// Source the price was taken from, which differs from the campaign's
+    /// source when that has no price yet
+    pub source: PriceSource,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PriceAuditEntry {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub action: String, // 'set_nav', 'set_source'
+    pub previous_value: Option<String>,
+    pub new_value: String,
+    pub actor_id: Option<Uuid>,
+    pub note: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Error)]
+pub enum PriceError {
+    #[error("Campaign not found")]
+    NotFound,
+    #[error("Price must be greater than 0")]
+    InvalidPrice,
+    #[error("Campaign has no NAV to value its tokens at")]
+    NoNav,
+}
+
+/// Latest price of each source and when it was recorded
+pub type LatestPrices = HashMap<PriceSource, (TokenAmount, DateTime<Utc>)>;
+
+/// Price of the preferred source, falling back to the last trade, then the
+/// sale price
+pub fn resolve_price(preferred: PriceSource, latest: &LatestPrices) -> Option<ResolvedPrice> {
+    [preferred, PriceSource::Trade, PriceSource::Sale]
+        .into_iter()
+        .find_map(|source| {
+            latest.get(&source).map(|&(price, recorded_at)| ResolvedPrice {
+                price,
+                source,
+                recorded_at,
+            })
+        })
+}
+
+impl Database {
+    /// Add a price to a campaign's series
+    pub(crate) async fn record_token_price(
+        conn: &mut sqlx::PgConnection,
+        campaign_id: Uuid,
+        price: TokenAmount,
+        source: PriceSource,
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO token_prices (campaign_id, price, source) VALUES ($1, $2, $3)",
+            campaign_id,
+            price as _,
+            source.as_str()
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Price of each campaign token, now or at `at`
+    ///
+    /// At `at` the campaign is valued at the source it had then, according
+    /// to the audited source switches. Campaigns without any price in the
+    /// series are left out.
+    pub async fn token_prices_at(
+        &self,
+        campaign_ids: &[Uuid],
+        at: Option<DateTime<Utc>>,
+    ) -> Result<HashMap<Uuid, ResolvedPrice>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT DISTINCT ON (p.campaign_id, p.source)
+                   p.campaign_id, p.source, p.price as "price: TokenAmount", p.recorded_at,
+                   COALESCE(
+                       (SELECT a.new_value FROM price_audit_log a
+                        WHERE a.campaign_id = c.id AND a.action = 'set_source' AND a.created_at <= $2
+                        ORDER BY a.created_at DESC
+                        LIMIT 1),
+                       (SELECT a.previous_value FROM price_audit_log a
+                        WHERE a.campaign_id = c.id AND a.action = 'set_source' AND a.created_at > $2
+                        ORDER BY a.created_at
+                        LIMIT 1),
+                       c.price_source
+                   ) as "price_source!"
+            FROM token_prices p
+            JOIN campaigns c ON c.id = p.campaign_id
+            WHERE p.campaign_id = ANY($1) AND ($2::timestamptz IS NULL OR p.recorded_at <= $2)
+            ORDER BY p.campaign_id, p.source, p.recorded_at DESC, p.id DESC
+            "#,
+            campaign_ids,
+            at
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut latest: HashMap<Uuid, (PriceSource, LatestPrices)> = HashMap::new();
+        for row in rows {
+            let preferred = PriceSource::parse(&row.price_source).unwrap_or(PriceSource::Trade);
+            let Some(source) = PriceSource::parse(&row.source) else {
+                continue;
+            };
+            latest
+                .entry(row.campaign_id)
+                .or_insert_with(|| (preferred, HashMap::new()))
+                .1
+                .insert(source, (row.price, row.recorded_at));
+        }
+        Ok(latest
+            .into_iter()
+            .filter_map(|(id, (preferred, prices))| Some((id, resolve_price(preferred, &prices)?)))
+            .collect())
+    }
+
+    /// Source a campaign's tokens are valued at
+    pub async fn get_price_source(&self, campaign_id: Uuid) -> Result<Option<PriceSource>> {
+        let source = sqlx::query_scalar!("SELECT price_source FROM campaigns WHERE id = $1", campaign_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(source.map(|s| PriceSource::parse(&s).unwrap_or(PriceSource::Trade)))
+    }
+
+    /// Publish a NAV and value the campaign's tokens at it
+    pub async fn set_nav_price(
+        &self,
+        campaign_id: Uuid,
+        price: TokenAmount,
+        actor_id: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<()> {
+        if price.is_zero() {
+            return Err(PriceError::InvalidPrice.into());
+        }
+        let mut tx = self.pool.begin().await?;
+
+        let previous_source = sqlx::query_scalar!(
+            "SELECT price_source FROM campaigns WHERE id = $1 FOR UPDATE",
+            campaign_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(PriceError::NotFound)?;
+        let previous_nav = sqlx::query_scalar!(
+            r#"
+            SELECT price as "price: TokenAmount" FROM token_prices
+            WHERE campaign_id = $1 AND source = 'nav'
+            ORDER BY recorded_at DESC, id DESC
+            LIMIT 1
+            "#,
+            campaign_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        Self::record_token_price(&mut tx, campaign_id, price, PriceSource::Nav).await?;
+        Self::audit_price_change(
+            &mut tx,
+            campaign_id,
+            "set_nav",
+            previous_nav.map(|p| p.to_string()),
+            &price.to_string(),
+            actor_id,
+            note,
+        )
+        .await?;
+
+        if previous_source != PriceSource::Nav.as_str() {
+            Self::update_price_source(&mut tx, campaign_id, Some(previous_source), PriceSource::Nav, actor_id, note).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Switch the source a campaign's tokens are valued at; returns the
+    /// previous source
+    pub async fn set_price_source(
+        &self,
+        campaign_id: Uuid,
+        source: PriceSource,
+        actor_id: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<PriceSource> {
+        let mut tx = self.pool.begin().await?;
+
+        let previous = sqlx::query_scalar!(
+            "SELECT price_source FROM campaigns WHERE id = $1 FOR UPDATE",
+            campaign_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(PriceError::NotFound)?;
+        if source == PriceSource::Nav {
+            let has_nav = sqlx::query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM token_prices WHERE campaign_id = $1 AND source = 'nav') as "has_nav!""#,
+                campaign_id
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            if !has_nav {
+                return Err(PriceError::NoNav.into());
+            }
+        }
+
+        let previous_source = PriceSource::parse(&previous).unwrap_or(PriceSource::Trade);
+        if previous_source != source {
+            Self::update_price_source(&mut tx, campaign_id, Some(previous), source, actor_id, note).await?;
+        }
+
+        tx.commit().await?;
+        Ok(previous_source)
+    }
+
+    async fn update_price_source(
+        conn: &mut sqlx::PgConnection,
+        campaign_id: Uuid,
+        previous: Option<String>,
+        source: PriceSource,
+        actor_id: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE campaigns SET price_source = $2, updated_at = NOW() WHERE id = $1",
+            campaign_id,
+            source.as_str()
+        )
+        .execute(&mut *conn)
+        .await?;
+        Self::audit_price_change(conn, campaign_id, "set_source", previous, source.as_str(), actor_id, note).await
+    }
+
+    async fn audit_price_change(
+        conn: &mut sqlx::PgConnection,
+        campaign_id: Uuid,
+        action: &str,
+        previous_value: Option<String>,
+        new_value: &str,
+        actor_id: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO price_audit_log (campaign_id, action, previous_value, new_value, actor_id, note)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            campaign_id,
+            action,
+            previous_value,
+            new_value,
+            actor_id,
+            note
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Latest prices of a campaign first
+    pub async fn get_price_history(&self, campaign_id: Uuid, limit: i64) -> Result<Vec<TokenPrice>> {
+        let prices = sqlx::query_as::<_, TokenPrice>(
+            r#"
+            SELECT id, campaign_id, price, source, recorded_at
+            FROM token_prices
+            WHERE campaign_id = $1
+            ORDER BY recorded_at DESC, id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(campaign_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(prices)
+    }
+
+    pub async fn get_price_audit(&self, campaign_id: Uuid) -> Result<Vec<PriceAuditEntry>> {
+        let entries = sqlx::query_as::<_, PriceAuditEntry>(
+            r#"
+            SELECT id, campaign_id, action, previous_value, new_value, actor_id, note, created_at
+            FROM price_audit_log
+            WHERE campaign_id = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(campaign_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_price_falls_back() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let sale = (TokenAmount::from_nano(100), at("2026-01-01T00:00:00Z"));
+        let trade = (TokenAmount::from_nano(120), at("2026-03-01T00:00:00Z"));
+        let nav = (TokenAmount::from_nano(150), at("2026-02-01T00:00:00Z"));
+
+        let mut latest = HashMap::from([(PriceSource::Sale, sale)]);
+        // Nothing traded yet: the sale price
+        let resolved = resolve_price(PriceSource::Trade, &latest).unwrap();
+        assert_eq!((resolved.price, resolved.source), (sale.0, PriceSource::Sale));
+
+        latest.insert(PriceSource::Trade, trade);
+        let resolved = resolve_price(PriceSource::Nav, &latest).unwrap();
+        assert_eq!((resolved.price, resolved.source), (trade.0, PriceSource::Trade));
+        let resolved = resolve_price(PriceSource::Sale, &latest).unwrap();
+        assert_eq!((resolved.price, resolved.source), (sale.0, PriceSource::Sale));
+
+        // A NAV wins over a later trade
+        latest.insert(PriceSource::Nav, nav);
+        let resolved = resolve_price(PriceSource::Nav, &latest).unwrap();
+        assert_eq!((resolved.price, resolved.recorded_at), nav);
+
+        assert!(resolve_price(PriceSource::Trade, &HashMap::new()).is_none());
+    }
+}
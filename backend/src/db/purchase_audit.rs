@@ -0,0 +1,158 @@
+use super::limits::{self, PurchaseLimits};
+use super::{Database, supply_in_nanotokens};
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Statuses an admin may override to `confirmed`
+pub const CONFIRMABLE_STATUSES: [&str; 3] = ["pending", "failed", "expired"];
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PurchaseAuditEntry {
+    pub id: Uuid,
+    pub purchase_id: Uuid,
+    pub action: String, // 'admin_confirm'
+    pub actor_id: Option<Uuid>,
+    pub previous_status: String,
+    pub new_status: String,
+    pub verified_tx_hash: Option<String>,
+    pub note: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl Database {
+    /// Confirm a purchase by hand and record who did it
+    ///
+    /// Returns the purchase's previous status, or None if it is not in a
+    /// confirmable state or `verified_tx_hash` already confirmed another
+    /// purchase. A failed or expired purchase no longer counts towards the
+    /// campaign's allocation, so confirming it re-checks the caps under the
+    /// campaign lock and fails with a `PurchaseLimitError` if it no longer
+    /// fits.
+    pub async fn admin_confirm_purchase(
+        &self,
+        id: Uuid,
+        actor_id: Option<Uuid>,
+        verified_tx_hash: Option<&str>,
+        note: Option<&str>,
+    ) -> Result<Option<String>> {
+        let mut tx = self.pool.begin().await?;
+
+        let Some(campaign_id) = sqlx::query_scalar!("SELECT campaign_id FROM purchases WHERE id = $1", id)
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(None);
+        };
+        // Same lock order as a purchase: the campaign, then the purchase
+        let campaign = sqlx::query!(
+            r#"
+            SELECT token_supply,
+                   hard_cap as "hard_cap: TokenAmount",
+                   min_ticket as "min_ticket: TokenAmount",
+                   max_ticket as "max_ticket: TokenAmount",
+                   max_per_investor as "max_per_investor: TokenAmount"
+            FROM campaigns
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            campaign_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let purchase = sqlx::query!(
+            r#"
+            SELECT status as "status!", user_address,
+                   mkoin_paid as "mkoin_paid: TokenAmount",
+                   tokens_received as "tokens_received: TokenAmount"
+            FROM purchases
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if purchase.status == "failed" || purchase.status == "expired" {
+            let limits = PurchaseLimits {
+                token_supply: supply_in_nanotokens(&campaign.token_supply)?,
+                hard_cap: campaign.hard_cap,
+                min_ticket: campaign.min_ticket,
+                max_ticket: campaign.max_ticket,
+                max_per_investor: campaign.max_per_investor,
+            };
+            let allocation = Self::campaign_allocation(&mut tx, campaign_id, Some(&purchase.user_address)).await?;
+            // The ticket was accepted when the purchase was made
+            if let Err(e) =
+                limits::check_purchase_limits(&limits, &allocation, purchase.mkoin_paid, purchase.tokens_received)
+                && !e.is_ticket_size()
+            {
+                return Err(e.into());
+            }
+        }
+
+        let confirmed = sqlx::query_scalar!(
+            r#"
+            WITH prev AS (
+                SELECT id, status FROM purchases WHERE id = $1
+            )
+            UPDATE purchases p
+            SET status = 'confirmed',
+                verified_tx_hash = COALESCE($2, p.verified_tx_hash),
+                failure_reason = NULL,
+                confirmed_at = NOW()
+            FROM prev
+            WHERE p.id = prev.id AND prev.status = ANY($3)
+              AND ($2::varchar IS NULL
+                   OR NOT EXISTS (SELECT 1 FROM purchases WHERE verified_tx_hash = $2 AND id <> $1))
+            RETURNING prev.status as "previous_status!"
+            "#,
+            id,
+            verified_tx_hash,
+            &CONFIRMABLE_STATUSES.map(String::from)
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(previous_status) = confirmed else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO purchase_audit_log
+                (purchase_id, action, actor_id, previous_status, new_status, verified_tx_hash, note)
+            VALUES ($1, 'admin_confirm', $2, $3, 'confirmed', $4, $5)
+            "#,
+            id,
+            actor_id,
+            previous_status,
+            verified_tx_hash,
+            note
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(previous_status))
+    }
+
+    pub async fn get_purchase_audit(&self, purchase_id: Uuid) -> Result<Vec<PurchaseAuditEntry>> {
+        let entries = sqlx::query_as::<_, PurchaseAuditEntry>(
+            r#"
+            SELECT id, purchase_id, action, actor_id, previous_status, new_status,
+                   verified_tx_hash, note, created_at
+            FROM purchase_audit_log
+            WHERE purchase_id = $1
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(purchase_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(entries)
+    }
+}
@@ -0,0 +1,174 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How long a buyer has to pay and submit a quoted purchase
+pub const QUOTE_TTL_SECS: i64 = 300;
+
+/// Price and allocation fixed for one buyer before they pay
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PurchaseQuote {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub user_address: String,
+    /// MKOIN nanocoins to transfer
+    pub mkoin_amount: TokenAmount,
+    pub tokens: TokenAmount,
+    /// MKOIN nanocoins per whole token
+    pub price: TokenAmount,
+    /// Wallet the buyer pays; None for the platform wallet
+    pub treasury_address: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl PurchaseQuote {
+    /// Canonical form of the quoted terms covered by the server signature
+    pub fn signing_message(&self) -> String {
+        format!(
+            "quote:{}:{}:{}:{}:{}:{}:{}",
+            self.id,
+            self.campaign_id,
+            self.user_address,
+            self.mkoin_amount.nano(),
+            self.tokens.nano(),
+            self.price.nano(),
+            self.expires_at.timestamp()
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum QuoteError {
+    #[error("Quote not found")]
+    NotFound,
+    #[error("Quote has expired or was already used")]
+    Unavailable,
+    #[error("Purchase does not match the quote: {0}")]
+    Mismatch(&'static str),
+}
+
+impl Database {
+    /// Run the checks of `create_quote` without storing a quote
+    pub async fn check_quote(
+        &self,
+        user_address: &str,
+        campaign_id: Uuid,
+        mkoin_amount: TokenAmount,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::check_sale(&mut tx, campaign_id, user_address, mkoin_amount, None).await?;
+        tx.rollback().await?;
+        Ok(())
+    }
+
+    /// Quote a purchase of `mkoin_amount` at the campaign's current price
+    ///
+    /// Runs the same phase, allowlist and limit checks as a purchase, so a
+    /// buyer learns about a rejected purchase before paying. The quote
+    /// records `treasury`, the wallet the buyer must pay.
+    pub async fn create_quote(
+        &self,
+        user_address: &str,
+        campaign_id: Uuid,
+        mkoin_amount: TokenAmount,
+        treasury: &str,
+    ) -> Result<PurchaseQuote> {
+        let mut tx = self.pool.begin().await?;
+
+        let (price, tokens) =
+            Self::check_sale(&mut tx, campaign_id, user_address, mkoin_amount, None).await?;
+
+        let quote = sqlx::query_as::<_, PurchaseQuote>(
+            r#"
+            INSERT INTO purchase_quotes (campaign_id, user_address, mkoin_amount, tokens, price, treasury_address, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW() + make_interval(secs => $7))
+            RETURNING id, campaign_id, user_address, mkoin_amount, tokens, price, treasury_address,
+                      expires_at, used_at, created_at
+            "#,
+        )
+        .bind(campaign_id)
+        .bind(user_address)
+        .bind(mkoin_amount)
+        .bind(tokens)
+        .bind(price)
+        .bind(treasury)
+        .bind(QUOTE_TTL_SECS as f64)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(quote)
+    }
+
+    pub async fn get_quote(&self, id: Uuid) -> Result<Option<PurchaseQuote>> {
+        let quote = sqlx::query_as::<_, PurchaseQuote>(
+            r#"
+            SELECT id, campaign_id, user_address, mkoin_amount, tokens, price, treasury_address,
+                   expires_at, used_at, created_at
+            FROM purchase_quotes
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(quote)
+    }
+
+    /// Mark a live quote as used; fails with `QuoteError::Unavailable` if it
+    /// expired or was claimed before
+    pub(super) async fn claim_quote(
+        conn: &mut sqlx::PgConnection,
+        id: Uuid,
+        user_address: &str,
+    ) -> Result<PurchaseQuote> {
+        let quote = sqlx::query_as::<_, PurchaseQuote>(
+            r#"
+            UPDATE purchase_quotes
+            SET used_at = NOW()
+            WHERE id = $1 AND user_address = $2 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING id, campaign_id, user_address, mkoin_amount, tokens, price, treasury_address,
+                      expires_at, used_at, created_at
+            "#,
+        )
+        .bind(id)
+        .bind(user_address)
+        .fetch_optional(conn)
+        .await?;
+        quote.ok_or_else(|| QuoteError::Unavailable.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_message_covers_terms() {
+        let quote = PurchaseQuote {
+            id: Uuid::new_v4(),
+            campaign_id: Uuid::new_v4(),
+            user_address: "0:abc".to_string(),
+            mkoin_amount: TokenAmount::from_nano(2_000_000_000),
+            tokens: TokenAmount::from_nano(1_000_000_000),
+            price: TokenAmount::from_nano(2_000_000_000),
+            treasury_address: None,
+            expires_at: Utc::now(),
+            used_at: None,
+            created_at: None,
+        };
+        let mut tampered = quote.clone();
+        tampered.tokens = TokenAmount::from_nano(2_000_000_000);
+
+        assert_ne!(quote.signing_message(), tampered.signing_message());
+        // Bookkeeping fields are not part of the signed terms
+        tampered = quote.clone();
+        tampered.used_at = Some(Utc::now());
+        assert_eq!(quote.signing_message(), tampered.signing_message());
+    }
+}
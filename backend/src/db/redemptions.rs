@@ -0,0 +1,526 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How long a redemption waits for the user's MKOIN before it expires
+pub const REDEMPTION_TRANSFER_TTL_SECS: i64 = 1800;
+/// How long after expiry a closed redemption is still watched for its MKOIN
+pub const LATE_TRANSFER_GRACE_SECS: i64 = 600;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BankAccount {
+    pub id: Uuid,
+    pub user_address: String,
+    pub iban: String,
+    pub holder_name: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Redemption {
+    pub id: Uuid,
+    pub user_address: String,
+    pub bank_account_id: Uuid,
+    /// MKOIN nanocoins
+    pub amount: TokenAmount,
+    pub status: String,
+    pub transfer_tx_hash: Option<String>,
+    pub bank_reference: Option<String>,
+    pub settle_attempts: i32,
+    pub settle_msg_hash: Option<String>,
+    pub settle_sent_at: Option<DateTime<Utc>>,
+    pub settle_tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RedemptionEvent {
+    pub id: Uuid,
+    pub redemption_id: Uuid,
+    pub previous_status: Option<String>,
+    pub new_status: String,
+    pub actor_id: Option<Uuid>,
+    pub note: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum RedemptionError {
+    #[error("Invalid IBAN: {0}")]
+    InvalidIban(&'static str),
+    #[error("Account holder name is required")]
+    MissingHolder,
+    #[error("Bank account not found")]
+    BankAccountNotFound,
+    #[error("Amount must be greater than 0")]
+    InvalidAmount,
+    #[error("Redemption not found")]
+    NotFound,
+    #[error("Redemption cannot be {action} while {status}")]
+    InvalidTransition { action: &'static str, status: String },
+}
+
+/// Fields set alongside a status change
+#[derive(Debug, Default)]
+struct RedemptionUpdate<'a> {
+    transfer_tx_hash: Option<&'a str>,
+    bank_reference: Option<&'a str>,
+    settle_msg_hash: Option<&'a str>,
+    settle_tx_hash: Option<&'a str>,
+    error: Option<&'a str>,
+}
+
+/// Uppercase an IBAN without spaces and check its length and checksum
+pub fn normalize_iban(iban: &str) -> Result<String, RedemptionError> {
+    let iban: String = iban.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+    if !(15..=34).contains(&iban.len()) {
+        return Err(RedemptionError::InvalidIban("wrong length"));
+    }
+    if !iban.chars().all(|c| c.is_ascii_alphanumeric())
+        || !iban[..2].chars().all(|c| c.is_ascii_alphabetic())
+        || !iban[2..4].chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(RedemptionError::InvalidIban("malformed"));
+    }
+
+    // ISO 13616: move the first four characters to the end, letters count
+    // as 10..35, and the number must be 1 mod 97
+    let remainder = iban[4..].chars().chain(iban[..4].chars()).fold(0u32, |acc, c| {
+        let value = c.to_digit(36).unwrap_or(0);
+        if value >= 10 {
+            (acc * 100 + value) % 97
+        } else {
+            (acc * 10 + value) % 97
+        }
+    });
+    if remainder != 1 {
+        return Err(RedemptionError::InvalidIban("checksum mismatch"));
+    }
+    Ok(iban)
+}
+
+impl Database {
+    /// Register a bank account of a user, or bring back a removed one
+    pub async fn add_bank_account(&self, user_address: &str, iban: &str, holder_name: &str) -> Result<BankAccount> {
+        let iban = normalize_iban(iban)?;
+        let holder_name = holder_name.trim();
+        if holder_name.is_empty() {
+            return Err(RedemptionError::MissingHolder.into());
+        }
+
+        let account = sqlx::query_as::<_, BankAccount>(
+            r#"
+            INSERT INTO bank_accounts (user_address, iban, holder_name)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_address, iban)
+            DO UPDATE SET holder_name = EXCLUDED.holder_name, removed_at = NULL
+            RETURNING id, user_address, iban, holder_name, created_at
+            "#,
+        )
+        .bind(user_address)
+        .bind(&iban)
+        .bind(holder_name)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(account)
+    }
+
+    pub async fn get_bank_accounts(&self, user_address: &str) -> Result<Vec<BankAccount>> {
+        let accounts = sqlx::query_as::<_, BankAccount>(
+            r#"
+            SELECT id, user_address, iban, holder_name, created_at
+            FROM bank_accounts
+            WHERE user_address = $1 AND removed_at IS NULL
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(user_address)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(accounts)
+    }
+
+    /// Bank account by id, including removed ones that past redemptions use
+    pub async fn get_bank_account(&self, id: Uuid) -> Result<Option<BankAccount>> {
+        let account = sqlx::query_as::<_, BankAccount>(
+            r#"
+            SELECT id, user_address, iban, holder_name, created_at
+            FROM bank_accounts
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(account)
+    }
+
+    /// Hide a bank account from the user; redemptions keep referencing it
+    pub async fn remove_bank_account(&self, id: Uuid, user_address: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE bank_accounts
+            SET removed_at = NOW()
+            WHERE id = $1 AND user_address = $2 AND removed_at IS NULL
+            "#,
+            id,
+            user_address
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Request to redeem `amount` MKOIN to one of the user's bank accounts
+    pub async fn create_redemption(
+        &self,
+        user_address: &str,
+        bank_account_id: Uuid,
+        amount: TokenAmount,
+    ) -> Result<Redemption> {
+        if amount.is_zero() {
+            return Err(RedemptionError::InvalidAmount.into());
+        }
+        let owned = self
+            .get_bank_accounts(user_address)
+            .await?
+            .iter()
+            .any(|a| a.id == bank_account_id);
+        if !owned {
+            return Err(RedemptionError::BankAccountNotFound.into());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let redemption = sqlx::query_as::<_, Redemption>(
+            r#"
+            INSERT INTO mkoin_redemptions (user_address, bank_account_id, amount)
+            VALUES ($1, $2, $3)
+            RETURNING id, user_address, bank_account_id, amount, status, transfer_tx_hash,
+                      bank_reference, settle_attempts, settle_msg_hash, settle_sent_at, settle_tx_hash,
+                      error, created_at, updated_at
+            "#,
+        )
+        .bind(user_address)
+        .bind(bank_account_id)
+        .bind(amount)
+        .fetch_one(&mut *tx)
+        .await?;
+        Self::record_redemption_event(&mut tx, redemption.id, None, &redemption.status, None, None).await?;
+        tx.commit().await?;
+        Ok(redemption)
+    }
+
+    pub async fn get_redemption(&self, id: Uuid) -> Result<Option<Redemption>> {
+        let redemption = sqlx::query_as::<_, Redemption>(
+            r#"
+            SELECT id, user_address, bank_account_id, amount, status, transfer_tx_hash,
+                   bank_reference, settle_attempts, settle_msg_hash, settle_sent_at, settle_tx_hash,
+                   error, created_at, updated_at
+            FROM mkoin_redemptions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(redemption)
+    }
+
+    pub async fn get_user_redemptions(&self, user_address: &str) -> Result<Vec<Redemption>> {
+        let redemptions = sqlx::query_as::<_, Redemption>(
+            r#"
+            SELECT id, user_address, bank_account_id, amount, status, transfer_tx_hash,
+                   bank_reference, settle_attempts, settle_msg_hash, settle_sent_at, settle_tx_hash,
+                   error, created_at, updated_at
+            FROM mkoin_redemptions
+            WHERE user_address = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_address)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(redemptions)
+    }
+
+    pub async fn get_redemptions_by_status(&self, status: &str, limit: i64) -> Result<Vec<Redemption>> {
+        let redemptions = sqlx::query_as::<_, Redemption>(
+            r#"
+            SELECT id, user_address, bank_account_id, amount, status, transfer_tx_hash,
+                   bank_reference, settle_attempts, settle_msg_hash, settle_sent_at, settle_tx_hash,
+                   error, created_at, updated_at
+            FROM mkoin_redemptions
+            WHERE status = $1
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(redemptions)
+    }
+
+    /// Cancelled or expired redemptions whose MKOIN may still arrive
+    pub async fn get_unreceived_closed_redemptions(&self, limit: i64) -> Result<Vec<Redemption>> {
+        let redemptions = sqlx::query_as::<_, Redemption>(
+            r#"
+            SELECT id, user_address, bank_account_id, amount, status, transfer_tx_hash,
+                   bank_reference, settle_attempts, settle_msg_hash, settle_sent_at, settle_tx_hash,
+                   error, created_at, updated_at
+            FROM mkoin_redemptions
+            WHERE status IN ('cancelled', 'expired')
+              AND transfer_tx_hash IS NULL
+              AND created_at > NOW() - make_interval(secs => $1)
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind((REDEMPTION_TRANSFER_TTL_SECS + LATE_TRANSFER_GRACE_SECS) as f64)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(redemptions)
+    }
+
+    pub async fn get_redemption_events(&self, redemption_id: Uuid) -> Result<Vec<RedemptionEvent>> {
+        let events = sqlx::query_as::<_, RedemptionEvent>(
+            r#"
+            SELECT id, redemption_id, previous_status, new_status, actor_id, note, created_at
+            FROM redemption_events
+            WHERE redemption_id = $1
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(redemption_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(events)
+    }
+
+    /// The user's MKOIN reached the treasury in `tx_hash`
+    pub async fn mark_redemption_received(&self, id: Uuid, tx_hash: &str) -> Result<Redemption> {
+        let update = RedemptionUpdate { transfer_tx_hash: Some(tx_hash), ..Default::default() };
+        self.transition_redemption(id, "received", &["awaiting_transfer"], "received", None, None, update)
+            .await
+    }
+
+    pub async fn expire_redemption(&self, id: Uuid) -> Result<Redemption> {
+        let note = "No MKOIN transfer arrived in time";
+        self.transition_redemption(id, "expired", &["awaiting_transfer"], "expired", None, Some(note), Default::default())
+            .await
+    }
+
+    /// The MKOIN of a cancelled or expired request arrived in `tx_hash`; it
+    /// is returned to the user like that of a rejected one
+    pub async fn reject_late_redemption(&self, id: Uuid, tx_hash: &str) -> Result<Redemption> {
+        let note = "MKOIN arrived after the request was closed";
+        let update = RedemptionUpdate { transfer_tx_hash: Some(tx_hash), ..Default::default() };
+        self.transition_redemption(id, "received", &["cancelled", "expired"], "rejected", None, Some(note), update)
+            .await
+    }
+
+    /// Withdraw a request whose MKOIN was not sent yet
+    pub async fn cancel_redemption(&self, id: Uuid, user_address: &str) -> Result<Redemption> {
+        let owned = self.get_redemption(id).await?.is_some_and(|r| r.user_address == user_address);
+        if !owned {
+            return Err(RedemptionError::NotFound.into());
+        }
+        self.transition_redemption(id, "cancelled", &["awaiting_transfer"], "cancelled", None, None, Default::default())
+            .await
+    }
+
+    pub async fn approve_redemption(&self, id: Uuid, actor_id: Option<Uuid>, note: Option<&str>) -> Result<Redemption> {
+        self.transition_redemption(id, "approved", &["received"], "approved", actor_id, note, Default::default())
+            .await
+    }
+
+    /// The EUR payout was sent; the received MKOIN is burned next
+    pub async fn mark_redemption_paid(
+        &self,
+        id: Uuid,
+        actor_id: Option<Uuid>,
+        bank_reference: &str,
+        note: Option<&str>,
+    ) -> Result<Redemption> {
+        let update = RedemptionUpdate { bank_reference: Some(bank_reference), ..Default::default() };
+        self.transition_redemption(id, "marked paid", &["approved"], "paid", actor_id, note, update)
+            .await
+    }
+
+    /// Refuse a payout; the received MKOIN is returned to the user next
+    pub async fn reject_redemption(&self, id: Uuid, actor_id: Option<Uuid>, note: Option<&str>) -> Result<Redemption> {
+        self.transition_redemption(id, "rejected", &["received", "approved"], "rejected", actor_id, note, Default::default())
+            .await
+    }
+
+    /// Count a new attempt at burning (paid) or returning (rejected) the
+    /// received MKOIN; returns the attempt number, or None if there is
+    /// nothing to send or `max_attempts` were used up
+    pub async fn start_redemption_settlement(&self, id: Uuid, max_attempts: i32) -> Result<Option<i32>> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE mkoin_redemptions
+            SET settle_attempts = settle_attempts + 1, updated_at = NOW()
+            WHERE id = $1 AND status IN ('paid', 'rejected') AND settle_attempts < $2
+            RETURNING settle_attempts
+            "#,
+            id,
+            max_attempts
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(rec.map(|r| r.settle_attempts))
+    }
+
+    /// The burn or return was broadcast as `msg_hash`
+    pub async fn mark_redemption_settlement_sent(&self, redemption: &Redemption, msg_hash: &str) -> Result<Redemption> {
+        let to = if redemption.status == "paid" { "burning" } else { "returning" };
+        let update = RedemptionUpdate { settle_msg_hash: Some(msg_hash), ..Default::default() };
+        self.transition_redemption(redemption.id, to, &[&redemption.status], to, None, None, update)
+            .await
+    }
+
+    /// The burn or return completed in `tx_hash`
+    pub async fn complete_redemption_settlement(&self, redemption: &Redemption, tx_hash: &str) -> Result<Redemption> {
+        let to = if redemption.status == "burning" { "burned" } else { "returned" };
+        let update = RedemptionUpdate { settle_tx_hash: Some(tx_hash), ..Default::default() };
+        self.transition_redemption(redemption.id, to, &[&redemption.status], to, None, None, update)
+            .await
+    }
+
+    /// Put a failed burn or return back in the queue
+    pub async fn retry_redemption_settlement(&self, redemption: &Redemption, error: &str) -> Result<Redemption> {
+        let to = match redemption.status.as_str() {
+            "burning" => "paid",
+            "returning" => "rejected",
+            status => status,
+        };
+        let update = RedemptionUpdate { error: Some(error), ..Default::default() };
+        self.transition_redemption(redemption.id, "retried", &[&redemption.status], to, None, Some(error), update)
+            .await
+    }
+
+    /// Record a failed attempt without changing the status
+    pub async fn record_redemption_error(&self, id: Uuid, error: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE mkoin_redemptions
+            SET error = $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Move a redemption from one of `from` to `to` and record the change
+    #[allow(clippy::too_many_arguments)]
+    async fn transition_redemption(
+        &self,
+        id: Uuid,
+        action: &'static str,
+        from: &[&str],
+        to: &str,
+        actor_id: Option<Uuid>,
+        note: Option<&str>,
+        update: RedemptionUpdate<'_>,
+    ) -> Result<Redemption> {
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query!(
+            "SELECT status FROM mkoin_redemptions WHERE id = $1 FOR UPDATE",
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(RedemptionError::NotFound)?;
+        if !from.contains(&current.status.as_str()) {
+            return Err(RedemptionError::InvalidTransition { action, status: current.status }.into());
+        }
+
+        let redemption = sqlx::query_as::<_, Redemption>(
+            r#"
+            UPDATE mkoin_redemptions
+            SET status = $2,
+                transfer_tx_hash = COALESCE($3, transfer_tx_hash),
+                bank_reference = COALESCE($4, bank_reference),
+                settle_msg_hash = COALESCE($5, settle_msg_hash),
+                settle_sent_at = CASE WHEN $5 IS NULL THEN settle_sent_at ELSE NOW() END,
+                settle_tx_hash = COALESCE($6, settle_tx_hash),
+                error = $7,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, user_address, bank_account_id, amount, status, transfer_tx_hash,
+                      bank_reference, settle_attempts, settle_msg_hash, settle_sent_at, settle_tx_hash,
+                      error, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(to)
+        .bind(update.transfer_tx_hash)
+        .bind(update.bank_reference)
+        .bind(update.settle_msg_hash)
+        .bind(update.settle_tx_hash)
+        .bind(update.error)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        Self::record_redemption_event(&mut tx, id, Some(&current.status), to, actor_id, note).await?;
+        tx.commit().await?;
+        Ok(redemption)
+    }
+
+    async fn record_redemption_event(
+        conn: &mut sqlx::PgConnection,
+        redemption_id: Uuid,
+        previous_status: Option<&str>,
+        new_status: &str,
+        actor_id: Option<Uuid>,
+        note: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO redemption_events (redemption_id, previous_status, new_status, actor_id, note)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            redemption_id,
+            previous_status,
+            new_status,
+            actor_id,
+            note
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_iban() {
+        assert_eq!(normalize_iban("de89 3704 0044 0532 0130 00").unwrap(), "DE89370400440532013000");
+        assert_eq!(normalize_iban("GB82WEST12345698765432").unwrap(), "GB82WEST12345698765432");
+        assert_eq!(
+            normalize_iban("DE88370400440532013000"),
+            Err(RedemptionError::InvalidIban("checksum mismatch"))
+        );
+        assert_eq!(normalize_iban("DE89"), Err(RedemptionError::InvalidIban("wrong length")));
+        assert_eq!(
+            normalize_iban("1289370400440532013000"),
+            Err(RedemptionError::InvalidIban("malformed"))
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefundReason {
+    Cancelled,
+    SoftCapMissed,
+}
+
+impl RefundReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cancelled => "cancelled",
+            Self::SoftCapMissed => "soft_cap_missed",
+        }
+    }
+}
+
+/// Why a campaign's buyers are owed a refund, if they are
+///
+/// `raised` is the MKOIN (nanocoins) of confirmed purchases.
+pub fn refund_reason(
+    status: &str,
+    soft_cap: Option<TokenAmount>,
+    raised: TokenAmount,
+) -> Option<RefundReason> {
+    match (status, soft_cap) {
+        ("cancelled", _) => Some(RefundReason::Cancelled),
+        ("finished", Some(cap)) if raised < cap => Some(RefundReason::SoftCapMissed),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RefundBatch {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub reason: String,
+    pub status: String, // 'created', 'processing', 'completed', 'partial'
+    pub total_amount: TokenAmount,
+    /// Wallet the refunds are paid from; None for the platform wallet
+    pub treasury_address: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Refund {
+    pub id: Uuid,
+    pub batch_id: Uuid,
+    pub purchase_id: Uuid,
+    pub user_address: String,
+    pub amount: TokenAmount,
+    pub status: String, // 'pending', 'sent', 'confirmed', 'failed'
+    pub attempts: i32,
+    pub msg_hash: Option<String>,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// A refund that would be issued for one confirmed purchase
+#[derive(Debug, Serialize)]
+pub struct PlannedRefund {
+    pub purchase_id: Uuid,
+    pub user_address: String,
+    pub amount: TokenAmount,
+}
+
+/// Confirmed purchases of a campaign that have no refund yet
+#[derive(Debug, Default, Serialize)]
+pub struct RefundPlan {
+    /// Refunded by the next batch
+    pub refunds: Vec<PlannedRefund>,
+    /// Their tokens were already sent or delivered, so no batch refunds them;
+    /// an admin has to settle these by hand
+    pub tokens_delivered: Vec<PlannedRefund>,
+}
+
+impl Database {
+    /// Confirmed purchases of a campaign that have no refund yet, split by
+    /// whether their tokens already went out
+    pub async fn plan_refunds(&self, campaign_id: Uuid) -> Result<RefundPlan> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT p.id, p.user_address, p.mkoin_paid as "mkoin_paid: TokenAmount",
+                   EXISTS (
+                       SELECT 1 FROM token_deliveries d
+                       WHERE d.purchase_id = p.id AND d.status IN ('sent', 'delivered')
+                   ) as "tokens_delivered!"
+            FROM purchases p
+            LEFT JOIN refunds r ON r.purchase_id = p.id
+            WHERE p.campaign_id = $1 AND p.status = 'confirmed' AND r.id IS NULL
+            ORDER BY p.purchased_at
+            "#,
+            campaign_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut plan = RefundPlan::default();
+        for r in rows {
+            let refund = PlannedRefund {
+                purchase_id: r.id,
+                user_address: r.user_address,
+                amount: r.mkoin_paid,
+            };
+            if r.tokens_delivered {
+                plan.tokens_delivered.push(refund);
+            } else {
+                plan.refunds.push(refund);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Create a batch with one pending refund per unrefunded confirmed purchase,
+    /// paid from `treasury_address`
+    ///
+    /// Purchases already covered by another batch are skipped, so creating a
+    /// batch twice never refunds anything twice. So are purchases whose tokens
+    /// were already sent or delivered; the token deliveries still pending for
+    /// the refunded ones are cancelled. Returns `None` (and creates nothing)
+    /// when no purchase is left to refund.
+    pub async fn create_refund_batch(
+        &self,
+        campaign_id: Uuid,
+        reason: RefundReason,
+        treasury_address: Option<&str>,
+        created_by: Option<Uuid>,
+    ) -> Result<Option<Uuid>> {
+        let mut tx = self.pool.begin().await?;
+
+        // Serialize with concurrent batch creation for the same campaign
+        sqlx::query!("SELECT id FROM campaigns WHERE id = $1 FOR UPDATE", campaign_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Campaign not found"))?;
+
+        let batch = sqlx::query!(
+            r#"
+            INSERT INTO refund_batches (campaign_id, reason, treasury_address, created_by)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            campaign_id,
+            reason.as_str(),
+            treasury_address,
+            created_by
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO refunds (batch_id, purchase_id, user_address, amount)
+            SELECT $1, p.id, p.user_address, p.mkoin_paid
+            FROM purchases p
+            WHERE p.campaign_id = $2 AND p.status = 'confirmed'
+              AND NOT EXISTS (
+                  SELECT 1 FROM token_deliveries d
+                  WHERE d.purchase_id = p.id AND d.status IN ('sent', 'delivered')
+              )
+            ON CONFLICT (purchase_id) DO NOTHING
+            "#,
+            batch.id,
+            campaign_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if inserted.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        // Tokens that were never sent are not sent anymore
+        sqlx::query!(
+            r#"
+            UPDATE token_deliveries
+            SET status = 'cancelled'
+            WHERE status IN ('pending', 'failed')
+              AND purchase_id IN (SELECT purchase_id FROM refunds WHERE batch_id = $1)
+            "#,
+            batch.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE refund_batches
+            SET total_amount = (SELECT COALESCE(SUM(amount), 0) FROM refunds WHERE batch_id = $1)
+            WHERE id = $1
+            "#,
+            batch.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(batch.id))
+    }
+
+    pub async fn get_refund_batch(&self, id: Uuid) -> Result<Option<RefundBatch>> {
+        let batch = sqlx::query_as::<_, RefundBatch>(
+            r#"
+            SELECT id, campaign_id, reason, status, total_amount, treasury_address, created_by, created_at, completed_at
+            FROM refund_batches
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(batch)
+    }
+
+    pub async fn get_batch_refunds(&self, batch_id: Uuid) -> Result<Vec<Refund>> {
+        let refunds = sqlx::query_as::<_, Refund>(
+            r#"
+            SELECT id, batch_id, purchase_id, user_address, amount, status, attempts, msg_hash, tx_hash,
+                   error, created_at, sent_at, confirmed_at
+            FROM refunds
+            WHERE batch_id = $1
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(batch_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(refunds)
+    }
+
+    /// Mark a batch as processing; returns false if it already is
+    pub async fn claim_refund_batch(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE refund_batches SET status = 'processing' WHERE id = $1 AND status <> 'processing'",
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Close a processing batch as `completed` or `partial` depending on its refunds
+    pub async fn finish_refund_batch(&self, id: Uuid) -> Result<String> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE refund_batches
+            SET status = CASE
+                    WHEN EXISTS (SELECT 1 FROM refunds WHERE batch_id = $1 AND status <> 'confirmed')
+                    THEN 'partial' ELSE 'completed' END,
+                completed_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            RETURNING status
+            "#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(rec.status)
+    }
+
+    /// Count a new send attempt of a pending or failed refund; returns the
+    /// attempt number, or None if the refund is neither
+    pub async fn start_refund_attempt(&self, id: Uuid) -> Result<Option<i32>> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE refunds
+            SET attempts = attempts + 1
+            WHERE id = $1 AND status IN ('pending', 'failed')
+            RETURNING attempts
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(rec.map(|r| r.attempts))
+    }
+
+    pub async fn mark_refund_sent(&self, id: Uuid, msg_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE refunds
+            SET status = 'sent', msg_hash = $2, error = NULL, sent_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            "#,
+            id,
+            msg_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn mark_refund_failed(&self, id: Uuid, error: &str) -> Result<()> {
+        sqlx::query!(
+            "UPDATE refunds SET status = 'failed', error = $2 WHERE id = $1",
+            id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record on-chain confirmation and mark the purchase as refunded
+    pub async fn confirm_refund(&self, id: Uuid, tx_hash: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let rec = sqlx::query!(
+            r#"
+            UPDATE refunds
+            SET status = 'confirmed', tx_hash = $2, confirmed_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            RETURNING purchase_id
+            "#,
+            id,
+            tx_hash
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE purchases SET status = 'refunded' WHERE id = $1",
+            rec.purchase_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refund_reason() {
+        let cap = Some(TokenAmount::from_nano(100));
+        let raised = TokenAmount::from_nano;
+        assert_eq!(refund_reason("cancelled", None, raised(0)), Some(RefundReason::Cancelled));
+        assert_eq!(refund_reason("finished", cap, raised(99)), Some(RefundReason::SoftCapMissed));
+        assert_eq!(refund_reason("finished", cap, raised(100)), None);
+        assert_eq!(refund_reason("finished", None, raised(0)), None);
+        assert_eq!(refund_reason("running", cap, raised(0)), None);
+    }
+}
@@ -0,0 +1,318 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use crate::ton::wallet::MESSAGE_TTL_SECS;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// A claim still pending this long was abandoned by the request sending it;
+/// outlasts sending it and the validity of its wallet message
+pub const CLAIM_ABANDON_SECS: i64 = 900 + MESSAGE_TTL_SECS as i64;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Reward {
+    pub id: Uuid,
+    pub distribution_id: Uuid,
+    pub campaign_id: Uuid,
+    pub user_address: String,
+    pub amount: TokenAmount,
+    pub status: String, // 'unclaimed', 'claiming', 'claimed', 'expired'
+    pub claim_id: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub tx_hash: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// One MKOIN transfer paying all rewards a user had unclaimed
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RewardClaim {
+    pub id: Uuid,
+    pub user_address: String,
+    pub amount: TokenAmount,
+    pub status: String, // 'pending', 'sent', 'confirmed', 'failed'
+    pub msg_hash: Option<String>,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Error)]
+pub enum RewardError {
+    #[error("No rewards to claim")]
+    NothingToClaim,
+    #[error("Reward claim not found")]
+    ClaimNotFound,
+    #[error("Reward claim period must be positive")]
+    InvalidClaimPeriod,
+}
+
+const REWARD_COLUMNS: &str = "id, distribution_id, campaign_id, user_address, amount, status, claim_id, \
+                              expires_at, claimed_at, tx_hash, created_at";
+const CLAIM_COLUMNS: &str = "id, user_address, amount, status, msg_hash, tx_hash, error, created_at, \
+                             sent_at, confirmed_at";
+
+impl Database {
+    /// Turn the payout lines of a claim distribution into rewards; rewards
+    /// expire after the campaign's claim period
+    pub(crate) async fn credit_rewards(conn: &mut sqlx::PgConnection, distribution_id: Uuid) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO rewards (distribution_id, campaign_id, user_address, amount, expires_at)
+            SELECT p.distribution_id, d.campaign_id, p.user_address, p.amount,
+                   NOW() + make_interval(days => c.reward_claim_days)
+            FROM distribution_payouts p
+            JOIN distributions d ON d.id = p.distribution_id
+            JOIN campaigns c ON c.id = d.campaign_id
+            WHERE p.distribution_id = $1
+            "#,
+            distribution_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!("DELETE FROM distribution_payouts WHERE distribution_id = $1", distribution_id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Set how many days holders have to claim rewards of a campaign;
+    /// None lets them never expire. Only applies to future rewards.
+    pub async fn set_reward_claim_days(&self, campaign_id: Uuid, days: Option<i32>) -> Result<bool> {
+        if days.is_some_and(|d| d <= 0) {
+            return Err(RewardError::InvalidClaimPeriod.into());
+        }
+        let result = sqlx::query!(
+            "UPDATE campaigns SET reward_claim_days = $2, updated_at = NOW() WHERE id = $1",
+            campaign_id,
+            days
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn get_user_rewards(&self, user_address: &str) -> Result<Vec<Reward>> {
+        let rewards = sqlx::query_as::<_, Reward>(&format!(
+            "SELECT {} FROM rewards WHERE user_address = $1 ORDER BY created_at DESC",
+            REWARD_COLUMNS
+        ))
+        .bind(user_address)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rewards)
+    }
+
+    pub async fn get_distribution_rewards(&self, distribution_id: Uuid) -> Result<Vec<Reward>> {
+        let rewards = sqlx::query_as::<_, Reward>(&format!(
+            "SELECT {} FROM rewards WHERE distribution_id = $1 ORDER BY user_address",
+            REWARD_COLUMNS
+        ))
+        .bind(distribution_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rewards)
+    }
+
+    pub async fn get_claim_rewards(&self, claim_id: Uuid) -> Result<Vec<Reward>> {
+        let rewards = sqlx::query_as::<_, Reward>(&format!(
+            "SELECT {} FROM rewards WHERE claim_id = $1 ORDER BY created_at",
+            REWARD_COLUMNS
+        ))
+        .bind(claim_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rewards)
+    }
+
+    /// Expire unclaimed rewards whose claim period ended
+    pub async fn expire_rewards(&self) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE rewards
+            SET status = 'expired'
+            WHERE status = 'unclaimed' AND expires_at <= NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Bundle all unclaimed, unexpired rewards of a user into a pending claim
+    pub async fn start_reward_claim(&self, user_address: &str) -> Result<RewardClaim> {
+        let mut tx = self.pool.begin().await?;
+
+        let amounts = sqlx::query_scalar!(
+            r#"
+            SELECT amount as "amount: TokenAmount"
+            FROM rewards
+            WHERE user_address = $1 AND status = 'unclaimed'
+              AND (expires_at IS NULL OR expires_at > NOW())
+            FOR UPDATE
+            "#,
+            user_address
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        if amounts.is_empty() {
+            return Err(RewardError::NothingToClaim.into());
+        }
+        let total = TokenAmount::checked_sum(amounts)
+            .ok_or_else(|| anyhow::anyhow!("Reward total overflows"))?;
+
+        let claim = sqlx::query_as::<_, RewardClaim>(&format!(
+            "INSERT INTO reward_claims (user_address, amount) VALUES ($1, $2) RETURNING {}",
+            CLAIM_COLUMNS
+        ))
+        .bind(user_address)
+        .bind(total)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE rewards
+            SET status = 'claiming', claim_id = $2
+            WHERE user_address = $1 AND status = 'unclaimed'
+              AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+            user_address,
+            claim.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(claim)
+    }
+
+    pub async fn get_reward_claim(&self, id: Uuid) -> Result<Option<RewardClaim>> {
+        let claim = sqlx::query_as::<_, RewardClaim>(&format!(
+            "SELECT {} FROM reward_claims WHERE id = $1",
+            CLAIM_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(claim)
+    }
+
+    pub async fn get_reward_claims_by_status(&self, status: &str, limit: i64) -> Result<Vec<RewardClaim>> {
+        let claims = sqlx::query_as::<_, RewardClaim>(&format!(
+            "SELECT {} FROM reward_claims WHERE status = $1 ORDER BY created_at LIMIT $2",
+            CLAIM_COLUMNS
+        ))
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(claims)
+    }
+
+    /// Claims still pending `CLAIM_ABANDON_SECS` after they were made: the
+    /// request sending them died before recording the transfer
+    pub async fn get_abandoned_reward_claims(&self, limit: i64) -> Result<Vec<RewardClaim>> {
+        let claims = sqlx::query_as::<_, RewardClaim>(&format!(
+            r#"
+            SELECT {} FROM reward_claims
+            WHERE status = 'pending' AND created_at < NOW() - make_interval(secs => $1)
+            ORDER BY created_at
+            LIMIT $2
+            "#,
+            CLAIM_COLUMNS
+        ))
+        .bind(CLAIM_ABANDON_SECS as f64)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(claims)
+    }
+
+    pub async fn mark_reward_claim_sent(&self, id: Uuid, msg_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE reward_claims
+            SET status = 'sent', msg_hash = $2, sent_at = NOW()
+            WHERE id = $1 AND status = 'pending'
+            "#,
+            id,
+            msg_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Fail a claim whose transfer was not sent, bounced or expired without
+    /// landing; its rewards can be claimed again
+    pub async fn fail_reward_claim(&self, id: Uuid, error: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE reward_claims
+            SET status = 'failed', error = $2
+            WHERE id = $1 AND status IN ('pending', 'sent')
+            "#,
+            id,
+            error
+        )
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            "UPDATE rewards SET status = 'unclaimed', claim_id = NULL WHERE claim_id = $1 AND status = 'claiming'",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Confirm a claim whose transfer went through and mark its rewards
+    /// claimed; an abandoned pending claim may have been sent as well
+    pub async fn confirm_reward_claim(&self, id: Uuid, tx_hash: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE reward_claims
+            SET status = 'confirmed', tx_hash = $2, confirmed_at = NOW()
+            WHERE id = $1 AND status IN ('pending', 'sent')
+            "#,
+            id,
+            tx_hash
+        )
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE rewards
+            SET status = 'claimed', claimed_at = NOW(), tx_hash = $2
+            WHERE claim_id = $1 AND status = 'claiming'
+            "#,
+            id,
+            tx_hash
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+}
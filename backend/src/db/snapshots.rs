@@ -0,0 +1,517 @@
+use super::Database;
+use super::distributions::Holding;
+use crate::amount::TokenAmount;
+use crate::ton::address_utils::to_raw_address;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct HolderSnapshot {
+    pub id: Uuid,
+    pub token_address: String,
+    pub source: String, // 'ledger', 'chain'
+    pub at_lt: Option<i64>,
+    pub at_time: Option<DateTime<Utc>>,
+    pub total_supply: TokenAmount,
+    pub holder_count: i32,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A holder's balance in a snapshot and the lt it was last changed at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SnapshotEntry {
+    pub user_address: String,
+    pub balance: TokenAmount,
+    pub lt: i64,
+}
+
+impl From<SnapshotEntry> for Holding {
+    fn from(entry: SnapshotEntry) -> Self {
+        Holding {
+            user_address: entry.user_address,
+            balance: entry.balance,
+        }
+    }
+}
+
+/// A holder whose balance differs between two snapshots
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SnapshotMismatch {
+    pub user_address: String,
+    pub left: TokenAmount,
+    pub right: TokenAmount,
+}
+
+/// The point in history a balance is asked for: a logical time, or a chain
+/// time compared with when changes happened on chain
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum BalanceAt {
+    Lt(i64),
+    Time(DateTime<Utc>),
+}
+
+impl BalanceAt {
+    /// An lt given as a plain integer, else an RFC 3339 timestamp
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            return value.parse().ok().map(BalanceAt::Lt);
+        }
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|t| BalanceAt::Time(t.with_timezone(&Utc)))
+    }
+
+    pub fn lt(&self) -> Option<i64> {
+        match self {
+            BalanceAt::Lt(lt) => Some(*lt),
+            BalanceAt::Time(_) => None,
+        }
+    }
+
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            BalanceAt::Lt(_) => None,
+            BalanceAt::Time(time) => Some(*time),
+        }
+    }
+
+    /// Whether a change at `lt`, made at `time`, had happened by this point
+    pub fn includes(&self, lt: i64, time: DateTime<Utc>) -> bool {
+        match self {
+            BalanceAt::Lt(at) => lt <= *at,
+            BalanceAt::Time(at) => time <= *at,
+        }
+    }
+}
+
+/// A holder's balance at a point in history according to the ledger
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct LedgerBalance {
+    pub token_address: String,
+    pub user_address: String,
+    /// None if no change was recorded by then
+    pub balance: Option<TokenAmount>,
+    pub lt: Option<i64>,
+    /// The holder's history starts with a backfilled balance
+    pub backfilled: bool,
+    /// The ledger missed changes of the holder: purchases credited before
+    /// transfers were indexed, or a debit of more than it held
+    pub incomplete: bool,
+}
+
+impl LedgerBalance {
+    /// Whether the ledger knows the balance: not for incomplete histories,
+    /// nor before a backfilled first entry; before any other first entry
+    /// it was zero
+    pub fn covered(&self) -> bool {
+        !self.incomplete && (self.balance.is_some() || !self.backfilled)
+    }
+}
+
+/// Holders whose balances differ between two snapshots; a holder missing
+/// from one side counts as a zero balance there
+pub fn compare_snapshots(left: &[SnapshotEntry], right: &[SnapshotEntry]) -> Vec<SnapshotMismatch> {
+    let mut balances: BTreeMap<&str, (TokenAmount, TokenAmount)> = BTreeMap::new();
+    for entry in left {
+        balances.entry(&entry.user_address).or_insert((TokenAmount::ZERO, TokenAmount::ZERO)).0 = entry.balance;
+    }
+    for entry in right {
+        balances.entry(&entry.user_address).or_insert((TokenAmount::ZERO, TokenAmount::ZERO)).1 = entry.balance;
+    }
+
+    balances
+        .into_iter()
+        .filter(|(_, (l, r))| l != r)
+        .map(|(user_address, (left, right))| SnapshotMismatch {
+            user_address: user_address.to_string(),
+            left,
+            right,
+        })
+        .collect()
+}
+
+/// Form under which balances are stored: raw, or as given if the address
+/// does not parse
+pub fn holder_address(address: &str) -> String {
+    to_raw_address(address).unwrap_or_else(|_| address.to_string())
+}
+
+impl Database {
+    /// Append a balance change to the ledger
+    pub(crate) async fn record_balance(
+        conn: &mut sqlx::PgConnection,
+        user_address: &str,
+        token_address: &str,
+        balance: TokenAmount,
+        lt: i64,
+        source: &str,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO token_balance_ledger (token_address, user_address, lt, balance, source)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            token_address,
+            user_address,
+            lt,
+            balance as _,
+            source
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Credit or debit a holder's ledger by `amount` at `lt`, a change
+    /// that happened on chain at `occurred_at`
+    ///
+    /// Chain changes do not arrive in lt order: a debit is indexed with the
+    /// credit it paid for, possibly after later changes of the sender. The
+    /// new entry builds on the last one at or before `lt`, entries after it
+    /// shift by the same amount, and the portfolio follows the newest
+    /// entry. Balances never go below zero; a debit of more than the ledger
+    /// held is recorded as an overdraft, as earlier changes were missed.
+    pub(crate) async fn apply_balance_change(
+        conn: &mut sqlx::PgConnection,
+        user_address: &str,
+        token_address: &str,
+        lt: i64,
+        occurred_at: DateTime<Utc>,
+        amount: TokenAmount,
+        credit: bool,
+    ) -> Result<()> {
+        let user_address = holder_address(user_address);
+        let user_address = user_address.as_str();
+        sqlx::query!(
+            r#"
+            WITH prev AS (
+                SELECT COALESCE((
+                    SELECT balance FROM token_balance_ledger
+                    WHERE token_address = $1 AND user_address = $2 AND lt <= $3
+                    ORDER BY lt DESC, id DESC
+                    LIMIT 1
+                ), 0) AS balance
+            )
+            INSERT INTO token_balance_ledger (token_address, user_address, lt, balance, source, occurred_at)
+            SELECT $1::VARCHAR, $2::VARCHAR, $3,
+                   GREATEST(prev.balance + CASE WHEN $5 THEN $4::NUMERIC ELSE -$4::NUMERIC END, 0),
+                   CASE WHEN NOT $5 AND prev.balance < $4::NUMERIC THEN 'overdraft' ELSE 'chain' END,
+                   $6
+            FROM prev
+            "#,
+            token_address,
+            user_address,
+            lt,
+            amount as _,
+            credit,
+            occurred_at
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE token_balance_ledger
+            SET balance = GREATEST(balance + CASE WHEN $5 THEN $4::NUMERIC ELSE -$4::NUMERIC END, 0)
+            WHERE token_address = $1 AND user_address = $2 AND lt > $3
+            "#,
+            token_address,
+            user_address,
+            lt,
+            amount as _,
+            credit
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO portfolios (user_address, token_address, balance, last_updated_lt, updated_at)
+            SELECT user_address, token_address, balance, lt, NOW()
+            FROM token_balance_ledger
+            WHERE token_address = $1 AND user_address = $2
+            ORDER BY lt DESC, id DESC
+            LIMIT 1
+            ON CONFLICT (user_address, token_address)
+            DO UPDATE SET
+                balance = EXCLUDED.balance,
+                last_updated_lt = EXCLUDED.last_updated_lt,
+                updated_at = NOW()
+            "#,
+            token_address,
+            user_address
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Holders of a token with a positive balance according to the ledger
+    ///
+    /// Only changes up to `at_lt` and made on chain up to `at_time` are
+    /// taken into account; with neither this is the current holder set.
+    pub async fn ledger_holders(
+        &self,
+        token_address: &str,
+        at_lt: Option<i64>,
+        at_time: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SnapshotEntry>> {
+        let entries = sqlx::query_as::<_, SnapshotEntry>(
+            r#"
+            SELECT user_address, balance, lt
+            FROM (
+                SELECT DISTINCT ON (user_address) user_address, balance, lt
+                FROM token_balance_ledger
+                WHERE token_address = $1
+                  AND ($2::BIGINT IS NULL OR lt <= $2)
+                  AND ($3::TIMESTAMPTZ IS NULL OR occurred_at <= $3)
+                ORDER BY user_address, lt DESC, id DESC
+            ) latest
+            WHERE balance > 0
+            ORDER BY user_address
+            "#,
+        )
+        .bind(token_address)
+        .bind(at_lt)
+        .bind(at_time)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(entries)
+    }
+
+    /// Balances in every token `addresses` ever held, at a point in history
+    pub async fn ledger_balances_of_holder(&self, addresses: &[String], at: BalanceAt) -> Result<Vec<LedgerBalance>> {
+        self.ledger_balances_at(None, Some(addresses), at).await
+    }
+
+    /// Balances of every address that ever held the token, at a point in history
+    pub async fn ledger_balances_of_token(&self, token_address: &str, at: BalanceAt) -> Result<Vec<LedgerBalance>> {
+        self.ledger_balances_at(Some(token_address), None, at).await
+    }
+
+    async fn ledger_balances_at(
+        &self,
+        token_address: Option<&str>,
+        addresses: Option<&[String]>,
+        at: BalanceAt,
+    ) -> Result<Vec<LedgerBalance>> {
+        let balances = sqlx::query_as::<_, LedgerBalance>(
+            r#"
+            SELECT held.token_address, held.user_address, latest.balance, latest.lt,
+                   first.source = 'backfill' as backfilled,
+                   EXISTS (
+                       SELECT 1 FROM token_balance_ledger l
+                       WHERE l.token_address = held.token_address AND l.user_address = held.user_address
+                         AND l.source IN ('purchase', 'overdraft')
+                   ) as incomplete
+            FROM (
+                SELECT DISTINCT token_address, user_address
+                FROM token_balance_ledger
+                WHERE ($1::TEXT IS NULL OR token_address = $1)
+                  AND ($2::TEXT[] IS NULL OR user_address = ANY($2))
+            ) held
+            LEFT JOIN LATERAL (
+                SELECT balance, lt FROM token_balance_ledger l
+                WHERE l.token_address = held.token_address AND l.user_address = held.user_address
+                  AND ($3::BIGINT IS NULL OR l.lt <= $3)
+                  AND ($4::TIMESTAMPTZ IS NULL OR l.occurred_at <= $4)
+                ORDER BY l.lt DESC, l.id DESC
+                LIMIT 1
+            ) latest ON TRUE
+            JOIN LATERAL (
+                SELECT source FROM token_balance_ledger l
+                WHERE l.token_address = held.token_address AND l.user_address = held.user_address
+                ORDER BY l.lt, l.id
+                LIMIT 1
+            ) first ON TRUE
+            ORDER BY held.token_address, held.user_address
+            "#,
+        )
+        .bind(token_address)
+        .bind(addresses)
+        .bind(at.lt())
+        .bind(at.time())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(balances)
+    }
+
+    /// Every address that ever held the token, the candidates of a chain snapshot
+    pub async fn ledger_addresses(&self, token_address: &str) -> Result<Vec<String>> {
+        let addresses = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT user_address
+            FROM token_balance_ledger
+            WHERE token_address = $1
+            ORDER BY user_address
+            "#,
+            token_address
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(addresses)
+    }
+
+    /// Persist a snapshot; holders without a balance are left out
+    pub async fn create_holder_snapshot(
+        &self,
+        token_address: &str,
+        source: &str,
+        at_lt: Option<i64>,
+        at_time: Option<DateTime<Utc>>,
+        entries: &[SnapshotEntry],
+        created_by: Option<Uuid>,
+    ) -> Result<Uuid> {
+        let entries: Vec<&SnapshotEntry> = entries.iter().filter(|e| !e.balance.is_zero()).collect();
+        let total_supply = TokenAmount::checked_sum(entries.iter().map(|e| e.balance))
+            .ok_or_else(|| anyhow::anyhow!("Snapshot supply overflows"))?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO holder_snapshots (token_address, source, at_lt, at_time, total_supply, holder_count, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+            token_address,
+            source,
+            at_lt,
+            at_time,
+            total_supply as _,
+            entries.len() as i32,
+            created_by
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for entry in entries {
+            sqlx::query!(
+                r#"
+                INSERT INTO holder_snapshot_entries (snapshot_id, user_address, balance, lt)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                id,
+                entry.user_address,
+                entry.balance as _,
+                entry.lt
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    pub async fn get_holder_snapshot(&self, id: Uuid) -> Result<Option<HolderSnapshot>> {
+        let snapshot = sqlx::query_as::<_, HolderSnapshot>(
+            r#"
+            SELECT id, token_address, source, at_lt, at_time, total_supply, holder_count, created_by, created_at
+            FROM holder_snapshots
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(snapshot)
+    }
+
+    pub async fn get_holder_snapshot_entries(&self, snapshot_id: Uuid) -> Result<Vec<SnapshotEntry>> {
+        let entries = sqlx::query_as::<_, SnapshotEntry>(
+            r#"
+            SELECT user_address, balance, lt
+            FROM holder_snapshot_entries
+            WHERE snapshot_id = $1
+            ORDER BY user_address
+            "#,
+        )
+        .bind(snapshot_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(entries)
+    }
+
+    /// Snapshots of a token, newest first
+    pub async fn list_holder_snapshots(&self, token_address: &str, limit: i64) -> Result<Vec<HolderSnapshot>> {
+        let snapshots = sqlx::query_as::<_, HolderSnapshot>(
+            r#"
+            SELECT id, token_address, source, at_lt, at_time, total_supply, holder_count, created_by, created_at
+            FROM holder_snapshots
+            WHERE token_address = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(token_address)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user: &str, nano: u128) -> SnapshotEntry {
+        SnapshotEntry {
+            user_address: user.to_string(),
+            balance: TokenAmount::from_nano(nano),
+            lt: 1,
+        }
+    }
+
+    #[test]
+    fn test_compare_snapshots_reports_differences_only() {
+        let ledger = [entry("a", 10), entry("b", 5), entry("c", 7)];
+        let chain = [entry("a", 10), entry("b", 4), entry("d", 1)];
+
+        let mismatches = compare_snapshots(&ledger, &chain);
+        let found: Vec<(&str, u128, u128)> = mismatches
+            .iter()
+            .map(|m| (m.user_address.as_str(), m.left.nano(), m.right.nano()))
+            .collect();
+        assert_eq!(found, vec![("b", 5, 4), ("c", 7, 0), ("d", 0, 1)]);
+        assert!(compare_snapshots(&ledger, &ledger).is_empty());
+    }
+
+    #[test]
+    fn test_balance_at_parse() {
+        assert_eq!(BalanceAt::parse("47110000001"), Some(BalanceAt::Lt(47_110_000_001)));
+        let at = BalanceAt::parse("2026-03-01T12:00:00+02:00").unwrap();
+        assert_eq!(at.time().unwrap().to_rfc3339(), "2026-03-01T10:00:00+00:00");
+        assert_eq!(BalanceAt::parse(""), None);
+        assert_eq!(BalanceAt::parse("-5"), None);
+        assert_eq!(BalanceAt::parse("yesterday"), None);
+        assert_eq!(BalanceAt::parse("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_ledger_balance_coverage() {
+        let balance = |balance: Option<u128>, backfilled| LedgerBalance {
+            token_address: "t".to_string(),
+            user_address: "u".to_string(),
+            balance: balance.map(TokenAmount::from_nano),
+            lt: balance.map(|_| 1),
+            backfilled,
+            incomplete: false,
+        };
+        assert!(balance(Some(5), true).covered());
+        // Recorded from the first change on: nothing held before
+        assert!(balance(None, false).covered());
+        // Held before the ledger existed
+        assert!(!balance(None, true).covered());
+        // Changes were missed
+        let incomplete = LedgerBalance { incomplete: true, ..balance(Some(5), false) };
+        assert!(!incomplete.covered());
+    }
+}
@@ -0,0 +1,203 @@
+use super::Database;
+use crate::amount::TokenAmount;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct IndexedTransfer {
+    pub id: i64,
+    pub tx_hash: String,
+    pub lt: i64,
+    pub asset: String, // 'TON', 'MKOIN', 'campaign_token'
+    pub token_address: Option<String>,
+    pub campaign_id: Option<Uuid>,
+    pub sender: String,
+    pub recipient: String,
+    pub amount: TokenAmount,
+    pub is_mint: bool,
+    pub status: String, // 'completed', 'failed'
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// A transfer decoded by the indexer
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewTransfer {
+    pub tx_hash: String,
+    pub lt: i64,
+    pub asset: String,
+    pub token_address: Option<String>,
+    pub campaign_id: Option<Uuid>,
+    pub sender: String,
+    pub recipient: String,
+    pub amount: TokenAmount,
+    pub is_mint: bool,
+    pub status: String,
+    pub occurred_at: DateTime<Utc>,
+    /// lt of the message that carried the jettons, at which the sender's
+    /// wallet was debited; None for TON
+    pub sent_lt: Option<i64>,
+}
+
+const TRANSFER_COLUMNS: &str = "id, tx_hash, lt, asset, token_address, campaign_id, sender, recipient, amount, \
+                                is_mint, status, occurred_at";
+
+impl Database {
+    /// Store a transfer; returns false if its transaction was indexed before
+    ///
+    /// A new completed campaign token transfer also moves the balance
+    /// ledger: the recipient is credited at the transfer's lt and, unless
+    /// it is a mint, the sender debited at `sent_lt`.
+    pub async fn record_transfer(&self, transfer: &NewTransfer) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO indexed_transfers (
+                tx_hash, lt, asset, token_address, campaign_id, sender, recipient, amount, is_mint, status,
+                occurred_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (tx_hash) DO NOTHING
+            "#,
+        )
+        .bind(&transfer.tx_hash)
+        .bind(transfer.lt)
+        .bind(&transfer.asset)
+        .bind(&transfer.token_address)
+        .bind(transfer.campaign_id)
+        .bind(&transfer.sender)
+        .bind(&transfer.recipient)
+        .bind(transfer.amount)
+        .bind(transfer.is_mint)
+        .bind(&transfer.status)
+        .bind(transfer.occurred_at)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        if let Some(token_address) = &transfer.token_address
+            && transfer.asset == "campaign_token"
+            && transfer.status == "completed"
+        {
+            Self::apply_balance_change(
+                &mut tx,
+                &transfer.recipient,
+                token_address,
+                transfer.lt,
+                transfer.occurred_at,
+                transfer.amount,
+                true,
+            )
+            .await?;
+            if let Some(sent_lt) = transfer.sent_lt.filter(|_| !transfer.is_mint) {
+                // The sender's transaction precedes the credit by seconds at most
+                Self::apply_balance_change(
+                    &mut tx,
+                    &transfer.sender,
+                    token_address,
+                    sent_lt,
+                    transfer.occurred_at,
+                    transfer.amount,
+                    false,
+                )
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Transfers sent or received by `addresses`, newest first
+    ///
+    /// `before` is the id of the last transfer of the previous page.
+    pub async fn get_address_transfers(
+        &self,
+        addresses: &[String],
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<IndexedTransfer>> {
+        let transfers = sqlx::query_as::<_, IndexedTransfer>(&format!(
+            r#"
+            SELECT {} FROM indexed_transfers
+            WHERE (sender = ANY($1) OR recipient = ANY($1))
+              AND ($2::BIGINT IS NULL
+                   OR (occurred_at, id) < (SELECT occurred_at, id FROM indexed_transfers WHERE id = $2))
+            ORDER BY occurred_at DESC, id DESC
+            LIMIT $3
+            "#,
+            TRANSFER_COLUMNS
+        ))
+        .bind(addresses)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(transfers)
+    }
+
+    /// Addresses whose TON transfers are indexed: users, buyers and holders,
+    /// in the form they were stored in
+    ///
+    /// With `since` only the ones added or updated since then.
+    pub async fn tracked_addresses(&self, since: Option<DateTime<Utc>>) -> Result<Vec<String>> {
+        let addresses = sqlx::query_scalar!(
+            r#"
+            SELECT address as "address!" FROM users
+            WHERE $1::TIMESTAMPTZ IS NULL OR GREATEST(created_at, updated_at) >= $1
+            UNION SELECT user_address FROM purchases
+            WHERE $1::TIMESTAMPTZ IS NULL OR purchased_at >= $1
+            UNION SELECT user_address FROM portfolios
+            WHERE $1::TIMESTAMPTZ IS NULL OR updated_at >= $1
+            "#,
+            since
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(addresses)
+    }
+
+    /// Which of `addresses` belong to registered users, in the form they
+    /// were stored in
+    pub async fn get_registered_addresses(&self, addresses: &[String]) -> Result<Vec<String>> {
+        let registered = sqlx::query_scalar!("SELECT address FROM users WHERE address = ANY($1)", addresses)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(registered)
+    }
+
+    /// Campaigns with a deployed token and its master address
+    pub async fn get_token_masters(&self) -> Result<Vec<(Uuid, String)>> {
+        let rows = sqlx::query!(
+            r#"SELECT id, token_address as "token_address!" FROM campaigns WHERE token_address IS NOT NULL"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|r| (r.id, r.token_address)).collect())
+    }
+
+    /// Last masterchain block the indexer processed, if it ran before
+    pub async fn get_indexer_seqno(&self) -> Result<Option<i64>> {
+        let seqno = sqlx::query_scalar!("SELECT seqno FROM indexer_state WHERE id = 'masterchain'")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(seqno)
+    }
+
+    pub async fn set_indexer_seqno(&self, seqno: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO indexer_state (id, seqno) VALUES ('masterchain', $1)
+            ON CONFLICT (id) DO UPDATE SET seqno = EXCLUDED.seqno, updated_at = NOW()
+            "#,
+            seqno
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 pub mod api;
+pub mod amount;
 pub mod auth;
 pub mod cache;
 pub mod config;
 pub mod db;
+pub mod media;
+pub mod statement;
 pub mod ton;
@@ -26,8 +26,33 @@ async fn main() -> Result<()> {
         }
     });
 
+    // Resolve pending purchases against chain data in background
+    let state = api::app_state(db, cache);
+    let worker_handle = tokio::spawn(api::run_purchase_worker(
+        state.clone(),
+        chrono::Duration::seconds(config.purchase_expiry_secs),
+    ));
+
+    // Deliver purchased campaign jettons to buyers
+    let settlement_handle = tokio::spawn(api::run_settlement_worker(state.clone()));
+
+    // Activate escrowed market orders and settle their trades
+    let market_handle = tokio::spawn(api::run_market_worker(state.clone()));
+
+    // Receive redeemed MKOIN and burn it once paid out
+    let redemption_handle = tokio::spawn(api::run_redemption_worker(state.clone()));
+
+    // Mint MKOIN for bank deposits matched from imported statements
+    let deposit_handle = tokio::spawn(api::run_deposit_worker(state.clone()));
+
+    // Send and confirm the payouts of executed distributions
+    let distribution_handle = tokio::spawn(api::run_distribution_worker(state.clone()));
+
+    // Confirm reward claims and expire unclaimed rewards
+    let reward_handle = tokio::spawn(api::run_reward_worker(state.clone()));
+
     // Start API Server
-    let app = api::router(db, cache);
+    let app = api::router_with_state(state);
     let addr = format!("{}:{}", config.api_host, config.api_port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
 
@@ -36,7 +61,14 @@ async fn main() -> Result<()> {
     // Run both
     tokio::select! {
         _ = axum::serve(listener, app) => {},
-        _ = indexer_handle => {}
+        _ = indexer_handle => {},
+        _ = worker_handle => {},
+        _ = settlement_handle => {},
+        _ = market_handle => {},
+        _ = redemption_handle => {},
+        _ = deposit_handle => {},
+        _ = distribution_handle => {},
+        _ = reward_handle => {}
     }
 
     Ok(())
@@ -0,0 +1,253 @@
+use anyhow::Result;
+use image::{DynamicImage, ImageFormat, imageops::FilterType};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use std::sync::Arc;
+use tracing::info;
+
+pub mod s3;
+pub mod storage;
+
+use storage::{LocalStorage, Storage};
+
+/// Largest accepted upload (before re-encoding)
+pub const MAX_UPLOAD_BYTES: usize = 5 * 1024 * 1024;
+
+/// Content types accepted by the upload endpoint
+pub const ALLOWED_CONTENT_TYPES: [&str; 3] = ["image/png", "image/jpeg", "image/webp"];
+
+/// Square bounding boxes for generated thumbnails
+pub const THUMBNAIL_SIZES: [u32; 3] = [512, 256, 128];
+
+// Originals are downscaled to fit this box
+const MAX_DIMENSION: u32 = 2048;
+const JPEG_QUALITY: u8 = 85;
+
+/// A re-encoded image ready to be stored
+#[derive(Debug)]
+pub struct ProcessedImage {
+    /// "original" or the thumbnail bounding box size, e.g. "256"
+    pub variant: String,
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ProcessedImage {
+    /// Content-addressed storage key: `<sha256 hex>.<ext>`
+    pub fn key(&self) -> String {
+        let ext = if self.content_type == "image/jpeg" { "jpg" } else { "png" };
+        format!("{}.{}", hex::encode(Sha256::digest(&self.bytes)), ext)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredImage {
+    pub key: String,
+    pub url: String,
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub byte_size: usize,
+}
+
+/// Check that a key is one we generated (hash + known extension)
+///
+/// Used before touching storage so request paths can never escape the media root.
+pub fn is_valid_key(key: &str) -> bool {
+    match key.split_once('.') {
+        Some((hash, ext)) => {
+            hash.len() == 64
+                && hash.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+                && (ext == "png" || ext == "jpg")
+        }
+        None => false,
+    }
+}
+
+pub fn content_type_for_key(key: &str) -> &'static str {
+    if key.ends_with(".jpg") { "image/jpeg" } else { "image/png" }
+}
+
+/// Decode an uploaded image and re-encode it as an original plus thumbnails
+///
+/// Re-encoding strips EXIF and any other embedded metadata. JPEG input stays
+/// JPEG; PNG and WebP are re-encoded as PNG so transparency is kept.
+pub fn process_image(bytes: &[u8], declared_content_type: &str) -> Result<Vec<ProcessedImage>> {
+    let format = image::guess_format(bytes)?;
+    let expected = match declared_content_type {
+        "image/png" => ImageFormat::Png,
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/webp" => ImageFormat::WebP,
+        other => anyhow::bail!("Unsupported content type: {}", other),
+    };
+    if format != expected {
+        anyhow::bail!(
+            "File content ({:?}) does not match declared type {}",
+            format,
+            declared_content_type
+        );
+    }
+
+    let img = image::load_from_memory_with_format(bytes, format)?;
+    let out_format = if format == ImageFormat::Jpeg { ImageFormat::Jpeg } else { ImageFormat::Png };
+
+    let original = if img.width() > MAX_DIMENSION || img.height() > MAX_DIMENSION {
+        img.resize(MAX_DIMENSION, MAX_DIMENSION, FilterType::Lanczos3)
+    } else {
+        img
+    };
+
+    let mut variants = vec![encode("original".to_string(), &original, out_format)?];
+    for size in THUMBNAIL_SIZES {
+        let thumb = if original.width() > size || original.height() > size {
+            original.resize(size, size, FilterType::Lanczos3)
+        } else {
+            original.clone()
+        };
+        variants.push(encode(size.to_string(), &thumb, out_format)?);
+    }
+
+    Ok(variants)
+}
+
+fn encode(variant: String, img: &DynamicImage, format: ImageFormat) -> Result<ProcessedImage> {
+    let mut buf = Cursor::new(Vec::new());
+    let content_type = match format {
+        ImageFormat::Jpeg => {
+            let encoder =
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, JPEG_QUALITY);
+            img.to_rgb8().write_with_encoder(encoder)?;
+            "image/jpeg"
+        }
+        _ => {
+            img.write_to(&mut buf, ImageFormat::Png)?;
+            "image/png"
+        }
+    };
+
+    Ok(ProcessedImage {
+        variant,
+        bytes: buf.into_inner(),
+        content_type,
+        width: img.width(),
+        height: img.height(),
+    })
+}
+
+pub struct MediaService {
+    storage: Arc<dyn Storage>,
+    public_base_url: String,
+}
+
+impl MediaService {
+    pub fn new(storage: Arc<dyn Storage>, public_base_url: &str) -> Self {
+        Self {
+            storage,
+            public_base_url: public_base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Build the service from `MEDIA_STORAGE` (`local` or `s3`) and related env vars
+    pub fn from_env() -> Self {
+        let public_base_url = std::env::var("MEDIA_BASE_URL")
+            .unwrap_or_else(|_| "https://hazelnut.ag/api/media".to_string());
+
+        let storage: Arc<dyn Storage> = match std::env::var("MEDIA_STORAGE").as_deref() {
+            Ok("s3") => {
+                let var = |name: &str| std::env::var(name).unwrap_or_default();
+                info!("Using S3 media storage at {}", var("S3_ENDPOINT"));
+                Arc::new(s3::S3Storage::new(
+                    &var("S3_ENDPOINT"),
+                    &var("S3_BUCKET"),
+                    &std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                    &var("S3_ACCESS_KEY"),
+                    &var("S3_SECRET_KEY"),
+                ))
+            }
+            _ => {
+                let dir = std::env::var("MEDIA_DIR").unwrap_or_else(|_| "./media".to_string());
+                info!("Using local media storage in {}", dir);
+                Arc::new(LocalStorage::new(dir))
+            }
+        };
+
+        Self::new(storage, &public_base_url)
+    }
+
+    pub fn url_for(&self, key: &str) -> String {
+        format!("{}/{}", self.public_base_url, key)
+    }
+
+    /// Store processed variants; objects that already exist are not rewritten
+    pub async fn store(&self, images: Vec<ProcessedImage>) -> Result<Vec<(String, StoredImage)>> {
+        let mut stored = Vec::with_capacity(images.len());
+        for image in images {
+            let key = image.key();
+            let byte_size = image.bytes.len();
+            if !self.storage.exists(&key).await? {
+                self.storage.put(&key, image.bytes, image.content_type).await?;
+            }
+            stored.push((
+                image.variant,
+                StoredImage {
+                    url: self.url_for(&key),
+                    key,
+                    content_type: image.content_type.to_string(),
+                    width: image.width,
+                    height: image.height,
+                    byte_size,
+                },
+            ));
+        }
+        Ok(stored)
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.storage.get(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_png(width: u32, height: u32) -> Vec<u8> {
+        let img = DynamicImage::new_rgba8(width, height);
+        let mut buf = Cursor::new(Vec::new());
+        img.write_to(&mut buf, ImageFormat::Png).unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn test_process_image_variants() {
+        let variants = process_image(&sample_png(1000, 500), "image/png").unwrap();
+        let dims: Vec<_> = variants
+            .iter()
+            .map(|v| (v.variant.as_str(), v.width, v.height))
+            .collect();
+        assert_eq!(
+            dims,
+            vec![("original", 1000, 500), ("512", 512, 256), ("256", 256, 128), ("128", 128, 64)]
+        );
+        assert!(variants.iter().all(|v| v.content_type == "image/png"));
+    }
+
+    #[test]
+    fn test_process_image_rejects_mismatched_type() {
+        assert!(process_image(&sample_png(10, 10), "image/jpeg").is_err());
+        assert!(process_image(b"not an image", "image/png").is_err());
+    }
+
+    #[test]
+    fn test_keys_are_content_addressed() {
+        let a = process_image(&sample_png(64, 64), "image/png").unwrap();
+        let b = process_image(&sample_png(64, 64), "image/png").unwrap();
+        assert_eq!(a[0].key(), b[0].key());
+        assert!(is_valid_key(&a[0].key()));
+        assert!(!is_valid_key("../etc/passwd"));
+        assert!(!is_valid_key("abc.png"));
+    }
+}
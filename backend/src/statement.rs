@@ -0,0 +1,435 @@
+//! Bank statement parsing for the fiat on-ramp
+//!
+//! Statements of the treasury's bank account are imported as ISO 20022
+//! CAMT.053 XML or as a CSV export. Only booked incoming credits are kept;
+//! each becomes a `StatementEntry` with an id that is stable across imports
+//! and unique across accounts, so that overlapping statements can be
+//! imported without counting a payment twice.
+
+use crate::amount::TokenAmount;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    Camt053,
+    Csv,
+}
+
+impl StatementFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StatementFormat::Camt053 => "camt053",
+            StatementFormat::Csv => "csv",
+        }
+    }
+}
+
+/// An incoming payment on the treasury account
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementEntry {
+    /// Bank-assigned id of the entry scoped by the statement's account, or a
+    /// digest of its contents
+    pub bank_ref: String,
+    pub booking_date: Option<NaiveDate>,
+    /// In 9-decimal units, like MKOIN nanocoins
+    pub amount: TokenAmount,
+    pub currency: String,
+    pub debtor_name: Option<String>,
+    pub debtor_iban: Option<String>,
+    /// Unstructured and structured remittance information, joined
+    pub remittance: String,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum StatementError {
+    #[error("Invalid CAMT.053 document: {0}")]
+    Xml(String),
+    #[error("Invalid CSV: {0}")]
+    Csv(String),
+    #[error("CSV has no '{0}' column")]
+    MissingColumn(&'static str),
+    #[error("Entry {entry}: {reason}")]
+    InvalidEntry { entry: usize, reason: String },
+}
+
+/// Parse a statement in `format`
+pub fn parse_statement(format: StatementFormat, data: &str) -> Result<Vec<StatementEntry>, StatementError> {
+    match format {
+        StatementFormat::Camt053 => parse_camt053(data),
+        StatementFormat::Csv => parse_csv(data),
+    }
+}
+
+/// Booked credits of a CAMT.053 `BkToCstmrStmt`
+///
+/// A batched entry with several `TxDtls` yields one payment per
+/// transaction.
+pub fn parse_camt053(xml: &str) -> Result<Vec<StatementEntry>, StatementError> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| StatementError::Xml(e.to_string()))?;
+    let root = doc.root_element();
+    if root.descendants().all(|n| n.tag_name().name() != "BkToCstmrStmt") {
+        return Err(StatementError::Xml("no BkToCstmrStmt element".to_string()));
+    }
+
+    let mut entries = Vec::new();
+    let statements = root.descendants().filter(|n| n.tag_name().name() == "Stmt");
+    for (stmt_index, stmt) in statements.enumerate() {
+        let stmt_id = text(stmt, &["Id"]).unwrap_or_else(|| format!("stmt{}", stmt_index));
+        // Servicer references are only unique within an account
+        let account = text(stmt, &["Acct", "Id", "IBAN"]).or_else(|| text(stmt, &["Acct", "Id", "Othr", "Id"]));
+        let scoped = |reference: String| match &account {
+            Some(account) => format!("{}:{}", account, reference),
+            None => reference,
+        };
+
+        for (ntry_index, ntry) in children(stmt, "Ntry").enumerate() {
+            let entry = ntry_index + 1;
+            if text(ntry, &["CdtDbtInd"]).as_deref() != Some("CRDT") {
+                continue;
+            }
+            // `Sts` is a code in camt.053.001.02 and wraps a `Cd` from .001.08
+            let status = text(ntry, &["Sts", "Cd"]).or_else(|| text(ntry, &["Sts"]));
+            if status.as_deref().is_some_and(|s| s != "BOOK") {
+                continue;
+            }
+
+            let booking_date = text(ntry, &["BookgDt", "Dt"])
+                .or_else(|| text(ntry, &["BookgDt", "DtTm"]).map(|d| d.chars().take(10).collect()))
+                .and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok());
+            let (ntry_amount, ntry_currency) = amount(ntry, entry)?;
+            let ntry_ref = text(ntry, &["AcctSvcrRef"])
+                .or_else(|| text(ntry, &["NtryRef"]))
+                .unwrap_or_else(|| format!("{}:{}", stmt_id, entry));
+
+            let details: Vec<_> = children(ntry, "NtryDtls")
+                .flat_map(|d| children(d, "TxDtls"))
+                .collect();
+            if details.is_empty() {
+                entries.push(StatementEntry {
+                    bank_ref: scoped(ntry_ref),
+                    booking_date,
+                    amount: ntry_amount,
+                    currency: ntry_currency,
+                    debtor_name: None,
+                    debtor_iban: None,
+                    remittance: String::new(),
+                });
+                continue;
+            }
+
+            let batched = details.len() > 1;
+            for (tx_index, tx) in details.into_iter().enumerate() {
+                let (tx_amount, tx_currency) = if path(tx, &["Amt"]).is_some() {
+                    amount(tx, entry)?
+                } else if batched {
+                    return Err(StatementError::InvalidEntry {
+                        entry,
+                        reason: "batched transaction without an amount".to_string(),
+                    });
+                } else {
+                    (ntry_amount, ntry_currency.clone())
+                };
+                let bank_ref = text(tx, &["Refs", "AcctSvcrRef"]).unwrap_or_else(|| {
+                    if batched {
+                        format!("{}/{}", ntry_ref, tx_index + 1)
+                    } else {
+                        ntry_ref.clone()
+                    }
+                });
+                let debtor_name = text(tx, &["RltdPties", "Dbtr", "Nm"])
+                    .or_else(|| text(tx, &["RltdPties", "Dbtr", "Pty", "Nm"]));
+                let debtor_iban = text(tx, &["RltdPties", "DbtrAcct", "Id", "IBAN"]);
+                let remittance = path(tx, &["RmtInf"])
+                    .map(|rmt| {
+                        rmt.descendants()
+                            .filter(|n| matches!(n.tag_name().name(), "Ustrd" | "Ref"))
+                            .filter_map(|n| n.text())
+                            .map(str::trim)
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .unwrap_or_default();
+
+                entries.push(StatementEntry {
+                    bank_ref: scoped(bank_ref),
+                    booking_date,
+                    amount: tx_amount,
+                    currency: tx_currency,
+                    debtor_name,
+                    debtor_iban,
+                    remittance,
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn children<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(move |n| n.tag_name().name() == name)
+}
+
+/// Follow child elements by local name, ignoring the CAMT namespace version
+fn path<'a, 'input>(node: roxmltree::Node<'a, 'input>, names: &[&'static str]) -> Option<roxmltree::Node<'a, 'input>> {
+    names
+        .iter()
+        .try_fold(node, |node, name| children(node, name).next())
+}
+
+fn text(node: roxmltree::Node, names: &[&'static str]) -> Option<String> {
+    path(node, names)
+        .and_then(|n| n.text())
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+}
+
+fn amount(node: roxmltree::Node, entry: usize) -> Result<(TokenAmount, String), StatementError> {
+    let invalid = |reason: String| StatementError::InvalidEntry { entry, reason };
+    let amt = path(node, &["Amt"]).ok_or_else(|| invalid("no amount".to_string()))?;
+    let value = amt.text().map(str::trim).unwrap_or_default();
+    let amount = TokenAmount::parse_decimal(value).map_err(|e| invalid(e.to_string()))?;
+    let currency = amt.attribute("Ccy").unwrap_or_default().to_uppercase();
+    Ok((amount, currency))
+}
+
+/// Column names accepted in CSV exports, by field
+const CSV_COLUMNS: &[(&str, &[&str])] = &[
+    ("booking_date", &["booking_date", "date", "value_date"]),
+    ("amount", &["amount"]),
+    ("currency", &["currency"]),
+    ("debtor_name", &["debtor_name", "name", "counterparty"]),
+    ("debtor_iban", &["debtor_iban", "iban"]),
+    ("remittance", &["remittance", "purpose", "description", "reference"]),
+];
+
+/// Incoming payments of a CSV export with a header row
+///
+/// The delimiter is `,` or `;`. `amount` and `remittance` columns are
+/// required; negative amounts are debits and skipped. An `id` column is
+/// not unique across accounts or exports, so each row's id is derived from
+/// its whole contents, `id` included.
+pub fn parse_csv(data: &str) -> Result<Vec<StatementEntry>, StatementError> {
+    let header = data.lines().next().unwrap_or_default();
+    let delimiter = if header.matches(';').count() > header.matches(',').count() {
+        b';'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(data.as_bytes());
+
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| StatementError::Csv(e.to_string()))?
+        .iter()
+        .map(|h| h.trim_start_matches('\u{feff}').to_lowercase())
+        .collect();
+    let column = |field: &str| {
+        CSV_COLUMNS
+            .iter()
+            .find(|(f, _)| *f == field)
+            .and_then(|(_, names)| headers.iter().position(|h| names.contains(&h.as_str())))
+    };
+    let amount_col = column("amount").ok_or(StatementError::MissingColumn("amount"))?;
+    let remittance_col = column("remittance").ok_or(StatementError::MissingColumn("remittance"))?;
+    let (date_col, currency_col) = (column("booking_date"), column("currency"));
+    let (name_col, iban_col) = (column("debtor_name"), column("debtor_iban"));
+
+    let mut entries = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (index, record) in reader.records().enumerate() {
+        let entry = index + 1;
+        let record = record.map_err(|e| StatementError::Csv(e.to_string()))?;
+        let field = |col: Option<usize>| {
+            col.and_then(|c| record.get(c))
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+
+        let raw_amount = field(Some(amount_col)).ok_or_else(|| StatementError::InvalidEntry {
+            entry,
+            reason: "no amount".to_string(),
+        })?;
+        if raw_amount.starts_with('-') {
+            continue;
+        }
+        let amount = parse_csv_amount(&raw_amount).map_err(|reason| StatementError::InvalidEntry { entry, reason })?;
+        let booking_date = match field(date_col) {
+            Some(date) => Some(parse_csv_date(&date).ok_or_else(|| StatementError::InvalidEntry {
+                entry,
+                reason: format!("invalid date '{}'", date),
+            })?),
+            None => None,
+        };
+        let currency = field(currency_col).unwrap_or_else(|| "EUR".to_string()).to_uppercase();
+        let debtor_name = field(name_col);
+        let debtor_iban = field(iban_col).map(|iban| iban.replace(' ', "").to_uppercase());
+        let remittance = field(Some(remittance_col)).unwrap_or_default();
+
+        // Identical rows are distinct payments, told apart by their order in
+        // the file
+        let digest = hex::encode(Sha256::digest(record.iter().collect::<Vec<_>>().join("\u{1f}")));
+        let occurrence = seen.entry(digest.clone()).or_default();
+        *occurrence += 1;
+        let bank_ref = format!("csv:{}:{}", &digest[..32], occurrence);
+
+        entries.push(StatementEntry {
+            bank_ref,
+            booking_date,
+            amount,
+            currency,
+            debtor_name,
+            debtor_iban,
+            remittance,
+        });
+    }
+    Ok(entries)
+}
+
+/// `1234.56`, `1234,56` and `1.234,56` style amounts
+fn parse_csv_amount(raw: &str) -> Result<TokenAmount, String> {
+    let raw = raw.trim_start_matches('+').replace(' ', "");
+    let normalized = match (raw.rfind(','), raw.rfind('.')) {
+        (Some(comma), Some(dot)) if comma > dot => raw.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => raw.replace(',', ""),
+        (Some(_), None) => raw.replace(',', "."),
+        _ => raw,
+    };
+    TokenAmount::parse_decimal(&normalized).map_err(|e| e.to_string())
+}
+
+fn parse_csv_date(raw: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%d.%m.%Y", "%d/%m/%Y"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(raw, fmt).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Id>STMT-2026-10-18</Id>
+      <Acct><Id><IBAN>DE02120300000000202051</IBAN></Id></Acct>
+      <Ntry>
+        <Amt Ccy="EUR">250.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2026-10-18</Dt></BookgDt>
+        <AcctSvcrRef>BANK-0001</AcctSvcrRef>
+        <NtryDtls><TxDtls>
+          <RltdPties>
+            <Dbtr><Nm>Ana Farmer</Nm></Dbtr>
+            <DbtrAcct><Id><IBAN>DE89370400440532013000</IBAN></Id></DbtrAcct>
+          </RltdPties>
+          <RmtInf><Ustrd>MKOIN HZN-ABCD</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">80.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <AcctSvcrRef>BANK-0002</AcctSvcrRef>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">30.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>PDNG</Cd></Sts>
+        <AcctSvcrRef>BANK-0003</AcctSvcrRef>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">15.50</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><DtTm>2026-10-18T09:30:00</DtTm></BookgDt>
+        <AcctSvcrRef>BANK-0004</AcctSvcrRef>
+        <NtryDtls>
+          <TxDtls>
+            <Amt Ccy="EUR">10.00</Amt>
+            <RltdPties><Dbtr><Pty><Nm>Bo</Nm></Pty></Dbtr></RltdPties>
+            <RmtInf><Strd><CdtrRefInf><Ref>RF18HZN1</Ref></CdtrRefInf></Strd></RmtInf>
+          </TxDtls>
+          <TxDtls>
+            <Amt Ccy="EUR">5.50</Amt>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+    #[test]
+    fn test_camt053_keeps_booked_credits() {
+        let entries = parse_camt053(CAMT).unwrap();
+        let refs: Vec<&str> = entries.iter().map(|e| e.bank_ref.as_str()).collect();
+        assert_eq!(
+            refs,
+            vec![
+                "DE02120300000000202051:BANK-0001",
+                "DE02120300000000202051:BANK-0004/1",
+                "DE02120300000000202051:BANK-0004/2",
+            ]
+        );
+
+        assert_eq!(entries[0].amount, TokenAmount::parse_decimal("250").unwrap());
+        assert_eq!(entries[0].currency, "EUR");
+        assert_eq!(entries[0].booking_date, NaiveDate::from_ymd_opt(2026, 10, 18));
+        assert_eq!(entries[0].debtor_name.as_deref(), Some("Ana Farmer"));
+        assert_eq!(entries[0].debtor_iban.as_deref(), Some("DE89370400440532013000"));
+        assert_eq!(entries[0].remittance, "MKOIN HZN-ABCD");
+
+        assert_eq!(entries[1].amount, TokenAmount::parse_decimal("10").unwrap());
+        assert_eq!(entries[1].debtor_name.as_deref(), Some("Bo"));
+        assert_eq!(entries[1].remittance, "RF18HZN1");
+        assert_eq!(entries[2].amount, TokenAmount::parse_decimal("5.5").unwrap());
+        assert_eq!(entries[2].booking_date, NaiveDate::from_ymd_opt(2026, 10, 18));
+    }
+
+    #[test]
+    fn test_camt053_rejects_other_documents() {
+        assert!(matches!(parse_camt053("<Document/>"), Err(StatementError::Xml(_))));
+        assert!(matches!(parse_camt053("not xml"), Err(StatementError::Xml(_))));
+    }
+
+    #[test]
+    fn test_csv_with_semicolons_and_decimal_commas() {
+        let data = "Date;Amount;Currency;Name;Purpose\n\
+                    18.10.2026;1.250,00;EUR;Ana Farmer;HZN ABCD\n\
+                    18.10.2026;-20,00;EUR;Shop;Fee\n\
+                    19.10.2026;5,5;EUR;Bo;gift\n\
+                    19.10.2026;5,5;EUR;Bo;gift\n";
+        let entries = parse_csv(data).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].amount, TokenAmount::parse_decimal("1250").unwrap());
+        assert_eq!(entries[0].booking_date, NaiveDate::from_ymd_opt(2026, 10, 18));
+        assert_eq!(entries[0].remittance, "HZN ABCD");
+        // Identical rows stay distinct, and keep their ids on re-import
+        assert_ne!(entries[1].bank_ref, entries[2].bank_ref);
+        assert_eq!(parse_csv(data).unwrap()[2].bank_ref, entries[2].bank_ref);
+
+        // The same export id on different payments does not collide
+        let ids = parse_csv("id,amount,remittance\n1,5.00,a\n1,6.00,b\n").unwrap();
+        assert_ne!(ids[0].bank_ref, ids[1].bank_ref);
+    }
+
+    #[test]
+    fn test_csv_requires_amount_and_remittance() {
+        assert_eq!(parse_csv("id,amount\n1,5.00\n"), Err(StatementError::MissingColumn("remittance")));
+        let bad = parse_csv("id,amount,remittance\n1,abc,x\n");
+        assert!(matches!(bad, Err(StatementError::InvalidEntry { entry: 1, .. })));
+    }
+}
@@ -29,6 +29,19 @@ pub fn store_ton_address(builder: &mut CellBuilder, address_str: &str) -> Result
     Ok(())
 }
 
+/// Normalize any supported address format to raw `workchain:hex`
+///
+/// Use this before comparing or storing addresses that may arrive in
+/// different encodings (EQ.../UQ.../raw).
+pub fn to_raw_address(address_str: &str) -> Result<String> {
+    let (workchain, hash_bytes) = if address_str.contains(':') {
+        parse_raw_address(address_str)?
+    } else {
+        parse_friendly_address(address_str)?
+    };
+    Ok(format!("{}:{}", workchain, hex::encode(hash_bytes)))
+}
+
 fn parse_raw_address(address_str: &str) -> Result<(i8, Vec<u8>)> {
     let parts: Vec<&str> = address_str.split(':').collect();
     if parts.len() != 2 {
@@ -208,6 +221,13 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_raw_address() {
+        let raw = "0:0d204ad68e3e94e0536049f107c11eb1f9b9242425759f9c353886fe3dfe9e59";
+        assert_eq!(to_raw_address("EQANIErWjj6U4FNgSfEHwR6x-bkkJCV1n5w1OIb-Pf6eWQwD").unwrap(), raw);
+        assert_eq!(to_raw_address(&raw.to_uppercase()).unwrap(), raw);
+    }
+
     #[test]
     fn test_invalid_address_length() {
         let mut builder = CellBuilder::new();
@@ -1,7 +1,30 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use reqwest::Client as HttpClient;
 use serde_json::json;
 
+// Transactions per page when walking an account's history
+const HISTORY_PAGE_SIZE: u32 = 50;
+const DEFAULT_API_URL: &str = "https://testnet.toncenter.com/api/v2";
+
+/// Base URL of the toncenter v2 API, from `TON_API_URL` or testnet
+pub fn api_url() -> String {
+    std::env::var("TON_API_URL")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(|v| v.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| DEFAULT_API_URL.to_string())
+}
+
+/// Transactions of an account back to some point in time
+#[derive(Debug, Default)]
+pub struct History {
+    /// Newest first
+    pub txs: Vec<serde_json::Value>,
+    /// False if the walk stopped at its page limit before reaching the point
+    pub complete: bool,
+}
+
 #[derive(Clone)]
 pub struct Client {
     http: HttpClient,
@@ -39,6 +62,45 @@ impl Client {
         .await
     }
 
+    /// Full transactions of a shard block, messages included
+    ///
+    /// Up to `count` transactions after `after`, the lt and base64 account
+    /// id of the last transaction of the previous page; `incomplete` in the
+    /// result tells whether more follow.
+    pub async fn get_block_transactions_ext(
+        &self,
+        workchain: i32,
+        shard: i64,
+        seqno: u64,
+        count: u32,
+        after: Option<(i64, &str)>,
+    ) -> Result<serde_json::Value> {
+        let mut params = json!({
+            "workchain": workchain,
+            "shard": shard,
+            "seqno": seqno,
+            "count": count,
+        });
+        if let Some((lt, account)) = after {
+            params["after_lt"] = json!(lt);
+            params["after_hash"] = json!(account);
+        }
+        self.post("getBlockTransactionsExt", params).await
+    }
+
+    /// Header of a block, with the blocks it follows in `prev_blocks`
+    pub async fn get_block_header(&self, workchain: i32, shard: i64, seqno: u64) -> Result<serde_json::Value> {
+        self.post(
+            "getBlockHeader",
+            json!({
+                "workchain": workchain,
+                "shard": shard,
+                "seqno": seqno,
+            }),
+        )
+        .await
+    }
+
     pub async fn get_shards(&self, seqno: u64) -> Result<serde_json::Value> {
         self.post(
             "shards",
@@ -89,6 +151,72 @@ impl Client {
         .await
     }
 
+    /// Up to `limit` transactions of `address` from the one at `lt`/`hash`
+    /// backwards, that transaction included
+    pub async fn get_transactions_from(
+        &self,
+        address: &str,
+        limit: u32,
+        lt: i64,
+        hash: &str,
+    ) -> Result<serde_json::Value> {
+        self.post(
+            "getTransactions",
+            json!({
+                "address": address,
+                "limit": limit,
+                "lt": lt.to_string(),
+                "hash": hash,
+                "to_lt": 0,
+                "archival": true
+            }),
+        )
+        .await
+    }
+
+    /// Transactions of `address` made at or after `since`, walking back at
+    /// most `max_pages` pages
+    pub async fn get_transactions_since(
+        &self,
+        address: &str,
+        since: DateTime<Utc>,
+        max_pages: usize,
+    ) -> Result<History> {
+        let mut history = History::default();
+        let mut cursor: Option<(i64, String)> = None;
+        for _ in 0..max_pages {
+            let page = match &cursor {
+                None => self.get_transactions(address, HISTORY_PAGE_SIZE).await?,
+                Some((lt, hash)) => self.get_transactions_from(address, HISTORY_PAGE_SIZE, *lt, hash).await?,
+            };
+            let txs = page.as_array().cloned().unwrap_or_default();
+            let full = txs.len() >= HISTORY_PAGE_SIZE as usize;
+
+            let mut next = None;
+            for tx in txs {
+                let Some((lt, utime, hash)) = history_point(&tx) else {
+                    continue;
+                };
+                // Pages after the first start with the cursor transaction
+                if cursor.as_ref().is_some_and(|(cursor_lt, _)| lt >= *cursor_lt) {
+                    continue;
+                }
+                if utime < since.timestamp() {
+                    history.complete = true;
+                    return Ok(history);
+                }
+                next = Some((lt, hash.to_string()));
+                history.txs.push(tx);
+            }
+            if !full || next.is_none() {
+                history.complete = true;
+                return Ok(history);
+            }
+            cursor = next;
+        }
+        Ok(history)
+    }
+
     // Helper to get wallet seqno
     pub async fn get_wallet_seqno(&self, address: &str) -> Result<u64> {
         let result = self.run_get_method(address, "seqno", vec![]).await?;
@@ -225,3 +353,10 @@ impl Client {
         }
     }
 }
+
+fn history_point(tx: &serde_json::Value) -> Option<(i64, i64, &str)> {
+    let id = tx.get("transaction_id")?;
+    let lt = id.get("lt")?.as_str()?.parse().ok()?;
+    let hash = id.get("hash")?.as_str()?;
+    Some((lt, tx.get("utime")?.as_i64()?, hash))
+}
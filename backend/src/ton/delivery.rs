@@ -0,0 +1,203 @@
+//! Outcome of campaign jetton deliveries sent from the platform wallet
+//!
+//! A delivery is a TEP-74 `transfer` sent by the platform wallet to its own
+//! jetton wallet with the platform wallet as `response_destination`. Its
+//! outcome shows up in the platform wallet's history as a message from that
+//! jetton wallet carrying the transfer's `query_id`:
+//!
+//! - `excesses`: the jettons left the jetton wallet and the leftover TON
+//!   came back, so the transfer went through;
+//! - a bounced `transfer`: the jetton wallet rejected it (e.g. not enough
+//!   jettons, or the wallet is not deployed) and nothing moved.
+//!
+//! Burns from the platform wallet resolve the same way, except that their
+//! excesses come from the jetton master.
+
+use crate::ton::address_utils::to_raw_address;
+use crate::ton::jetton::{
+    self, BOUNCED_PREFIX, JETTON_BURN_OPCODE, JETTON_EXCESSES_OPCODE, JETTON_TRANSFER_OPCODE,
+};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// Transfer executed; `tx_hash` received the excesses
+    Delivered { tx_hash: String },
+    /// Transfer bounced back in `tx_hash`; safe to send again
+    Bounced { tx_hash: String },
+    /// No outcome seen yet
+    InFlight,
+}
+
+/// `query_id` of attempt `attempt` of a delivery
+///
+/// Each attempt gets its own id so a late bounce of an earlier attempt is
+/// never mistaken for the outcome of the current one.
+pub fn delivery_query_id(delivery_id: Uuid, attempt: i32) -> u64 {
+    delivery_id
+        .as_u64_pair()
+        .0
+        .wrapping_add(attempt.max(0) as u64)
+}
+
+/// Find the outcome of the transfer with `query_id` in the platform wallet's
+/// transactions
+pub fn find_delivery_outcome(
+    txs: &[serde_json::Value],
+    jetton_wallet: &str,
+    query_id: u64,
+) -> DeliveryOutcome {
+    find_outcome(txs, jetton_wallet, jetton_wallet, query_id, JETTON_TRANSFER_OPCODE)
+}
+
+/// Find the outcome of the `burn` with `query_id` sent to the platform
+/// wallet's `jetton_wallet`
+///
+/// A burn's excesses come back from the jetton master once the supply was
+/// reduced; a rejected burn bounces from the jetton wallet.
+pub fn find_burn_outcome(
+    txs: &[serde_json::Value],
+    jetton_master: &str,
+    jetton_wallet: &str,
+    query_id: u64,
+) -> DeliveryOutcome {
+    find_outcome(txs, jetton_master, jetton_wallet, query_id, JETTON_BURN_OPCODE)
+}
+
+fn find_outcome(
+    txs: &[serde_json::Value],
+    excesses_from: &str,
+    bounce_from: &str,
+    query_id: u64,
+    bounced_opcode: u32,
+) -> DeliveryOutcome {
+    let (Ok(excesses_from), Ok(bounce_from)) = (to_raw_address(excesses_from), to_raw_address(bounce_from)) else {
+        return DeliveryOutcome::InFlight;
+    };
+
+    for tx in txs {
+        let Some(in_msg) = tx.get("in_msg") else {
+            continue;
+        };
+        let Some(source) = in_msg
+            .get("source")
+            .and_then(|s| s.as_str())
+            .and_then(|s| to_raw_address(s).ok())
+        else {
+            continue;
+        };
+        let Some(tx_hash) = tx
+            .get("transaction_id")
+            .and_then(|id| id.get("hash"))
+            .and_then(|h| h.as_str())
+        else {
+            continue;
+        };
+
+        // Only the contracts involved speak for the outcome
+        match parse_outcome(in_msg, query_id, bounced_opcode) {
+            Some(true) if source == excesses_from => {
+                return DeliveryOutcome::Delivered { tx_hash: tx_hash.to_string() };
+            }
+            Some(false) if source == bounce_from => {
+                return DeliveryOutcome::Bounced { tx_hash: tx_hash.to_string() };
+            }
+            _ => {}
+        }
+    }
+
+    DeliveryOutcome::InFlight
+}
+
+/// Some(true) for matching excesses, Some(false) for a matching bounce
+fn parse_outcome(in_msg: &serde_json::Value, query_id: u64, bounced_opcode: u32) -> Option<bool> {
+    let body = jetton::message_body(in_msg)?;
+    let mut parser = body.parser();
+
+    match parser.load_u32(32).ok()? {
+        JETTON_EXCESSES_OPCODE => (parser.load_u64(64).ok()? == query_id).then_some(true),
+        BOUNCED_PREFIX => {
+            let bounced = parser.load_u32(32).ok()? == bounced_opcode
+                && parser.load_u64(64).ok()? == query_id;
+            bounced.then_some(false)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use tonlib_core::cell::{BagOfCells, CellBuilder};
+
+    const JETTON_WALLET: &str = "EQATDLvt8bY8BGb-DGBZxwe6EZla3Rcij41fqv_OFlLXvgpV";
+    const OTHER: &str = "0:0d204ad68e3e94e0536049f107c11eb1f9b9242425759f9c353886fe3dfe9e59";
+
+    fn tx(source: &str, hash: &str, words: &[(u32, usize)], query_id: u64) -> serde_json::Value {
+        let mut builder = CellBuilder::new();
+        for (value, bits) in words {
+            builder.store_u32(*bits, *value).unwrap();
+        }
+        builder.store_u64(64, query_id).unwrap();
+        let boc = BagOfCells::from_root(builder.build().unwrap()).serialize(true).unwrap();
+        serde_json::json!({
+            "transaction_id": { "lt": "1", "hash": hash },
+            "in_msg": {
+                "source": source,
+                "msg_data": { "body": base64::engine::general_purpose::STANDARD.encode(boc) },
+            },
+        })
+    }
+
+    #[test]
+    fn test_excesses_mean_delivered() {
+        let txs = vec![
+            tx(OTHER, "forged", &[(JETTON_EXCESSES_OPCODE, 32)], 5),
+            tx(JETTON_WALLET, "excess", &[(JETTON_EXCESSES_OPCODE, 32)], 5),
+        ];
+        assert_eq!(
+            find_delivery_outcome(&txs, JETTON_WALLET, 5),
+            DeliveryOutcome::Delivered { tx_hash: "excess".to_string() }
+        );
+        assert_eq!(find_delivery_outcome(&txs, JETTON_WALLET, 6), DeliveryOutcome::InFlight);
+    }
+
+    #[test]
+    fn test_bounced_transfer() {
+        let txs = vec![tx(
+            JETTON_WALLET,
+            "bounce",
+            &[(BOUNCED_PREFIX, 32), (JETTON_TRANSFER_OPCODE, 32)],
+            5,
+        )];
+        assert_eq!(
+            find_delivery_outcome(&txs, JETTON_WALLET, 5),
+            DeliveryOutcome::Bounced { tx_hash: "bounce".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_burn_excesses_come_from_master() {
+        let txs = vec![
+            tx(JETTON_WALLET, "wallet", &[(JETTON_EXCESSES_OPCODE, 32)], 5),
+            tx(OTHER, "master", &[(JETTON_EXCESSES_OPCODE, 32)], 5),
+        ];
+        assert_eq!(
+            find_burn_outcome(&txs, OTHER, JETTON_WALLET, 5),
+            DeliveryOutcome::Delivered { tx_hash: "master".to_string() }
+        );
+
+        let bounced = vec![tx(JETTON_WALLET, "bounce", &[(BOUNCED_PREFIX, 32), (JETTON_BURN_OPCODE, 32)], 5)];
+        assert_eq!(
+            find_burn_outcome(&bounced, OTHER, JETTON_WALLET, 5),
+            DeliveryOutcome::Bounced { tx_hash: "bounce".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_query_id_differs_per_attempt() {
+        let id = Uuid::new_v4();
+        assert_ne!(delivery_query_id(id, 1), delivery_query_id(id, 2));
+    }
+}
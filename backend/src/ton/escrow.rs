@@ -0,0 +1,59 @@
+//! Escrow of secondary market orders in the platform wallet
+//!
+//! An order is backed by a TEP-74 jetton `transfer` from its owner to the
+//! platform wallet: campaign jettons for a sell, MKOIN for a buy. The
+//! transfer carries the order's `query_id` and an `order:<id>` comment. The
+//! escrow counts as received once the platform wallet's jetton wallet
+//! notified it of the exact amount from the order's owner.
+
+use crate::db::market::MarketOrder;
+use crate::ton::address_utils::to_raw_address;
+use crate::ton::jetton::{self, JettonTransfer};
+use crate::ton::mkoin_service::TRANSFER_FORWARD_TON;
+use crate::ton::purchase_verifier::find_receipt;
+use anyhow::Result;
+use tonlib_core::cell::Cell;
+use uuid::Uuid;
+
+/// Prefix of the text comment that ties an escrow transfer to an order
+pub const ORDER_COMMENT_PREFIX: &str = "order:";
+
+/// Forward payload comment of the escrow transfer for `order_id`
+pub fn order_comment(order_id: Uuid) -> String {
+    format!("{}{}", ORDER_COMMENT_PREFIX, order_id)
+}
+
+/// `query_id` of the escrow transfer for `order_id`
+pub fn order_query_id(order_id: Uuid) -> u64 {
+    order_id.as_u64_pair().0
+}
+
+/// `query_id` of attempt `attempt` of a transfer out of escrow
+pub fn escrow_transfer_query_id(transfer_id: Uuid, attempt: i32) -> u64 {
+    transfer_id
+        .as_u64_pair()
+        .0
+        .wrapping_add(attempt.max(0) as u64)
+}
+
+/// Body of the jetton `transfer` an order's owner sends to their jetton
+/// wallet to escrow it; excess TON is returned to the owner
+pub fn escrow_transfer_body(order: &MarketOrder, platform_wallet: &str) -> Result<Cell> {
+    jetton::build_transfer_body(&JettonTransfer {
+        query_id: order_query_id(order.id),
+        amount: order.escrow_amount,
+        destination: platform_wallet,
+        response_destination: &order.user_address,
+        forward_ton_amount: TRANSFER_FORWARD_TON,
+        comment: Some(&order_comment(order.id)),
+    })
+}
+
+/// The platform wallet transaction that received `order`'s escrow
+///
+/// `txs` is the platform wallet's history and `jetton_wallet` its jetton
+/// wallet for the escrowed asset.
+pub fn find_escrow(txs: &[serde_json::Value], jetton_wallet: &str, order: &MarketOrder) -> Option<String> {
+    let owner = to_raw_address(&order.user_address).ok()?;
+    find_receipt(txs, jetton_wallet, order_query_id(order.id), &owner, order.escrow_amount)
+}
@@ -1,5 +1,6 @@
+use crate::amount::TokenAmount;
 use crate::ton::address_utils::store_ton_address;
-use crate::ton::client::Client;
+use crate::ton::client::{self, Client};
 use crate::ton::wallet::Wallet;
 use anyhow::Result;
 use num_bigint::BigUint;
@@ -7,6 +8,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tonlib_core::cell::{BagOfCells, CellBuilder};
 use tracing::{error, info};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateTokenResult {
@@ -17,6 +19,19 @@ pub struct CreateTokenResult {
 // Factory contract address on testnet
 const FACTORY_ADDRESS: &str = "EQBY-OWwam2n7DO25xV7juUWS9MV9xjJ1bwL1dISkYDNcGP2";
 
+// Public base URL under which the API serves TEP-64 metadata documents
+const METADATA_BASE_URL_DEFAULT: &str = "https://hazelnut.ag/api";
+
+/// Off-chain metadata URI for a campaign jetton
+///
+/// Keyed by campaign id so the URI stays valid if the campaign is renamed.
+/// Served by `GET /metadata/{campaign_id}`.
+pub fn jetton_metadata_url(campaign_id: Uuid) -> String {
+    let base = std::env::var("METADATA_BASE_URL")
+        .unwrap_or_else(|_| METADATA_BASE_URL_DEFAULT.to_string());
+    format!("{}/metadata/{}", base.trim_end_matches('/'), campaign_id)
+}
+
 // Tact message opcodes (calculated from message name CRC32)
 // For "CreateJetton" message: opcode = crc32("CreateJetton") & 0x7fffffff
 // Calculated: 0x1B8B6387
@@ -54,7 +69,7 @@ impl FactoryService {
         let api_key = std::env::var("TON_API_KEY").ok();
 
         Self {
-            client: Client::new("https://testnet.toncenter.com/api/v2/jsonRPC", api_key),
+            client: Client::new(&format!("{}/jsonRPC", client::api_url()), api_key),
             admin_wallet,
         }
     }
@@ -62,34 +77,36 @@ impl FactoryService {
     /// Create a new campaign jetton via Factory contract
     ///
     /// # Arguments
-    /// * `farmer_wallet` - Farmer's wallet address
+    /// * `supply_wallet` - Wallet the initial supply is minted to
     /// * `name` - Token name
     /// * `symbol` - Token symbol
-    /// * `initial_supply` - Initial supply in nanocoins
+    /// * `initial_supply` - Initial supply of the campaign jetton
+    /// * `metadata_url` - TEP-64 off-chain metadata URI (see `jetton_metadata_url`)
     ///
     /// # Returns
     /// CreateTokenResult with tx_hash and jetton_address
     pub async fn create_campaign_token(
         &self,
-        farmer_wallet: &str,
+        supply_wallet: &str,
         name: &str,
         symbol: &str,
-        initial_supply: u128,
+        initial_supply: TokenAmount,
+        metadata_url: &str,
     ) -> Result<CreateTokenResult> {
         info!(
-            "Creating campaign token: {} ({}) with supply {} for farmer {}",
-            name, symbol, initial_supply, farmer_wallet
+            "Creating campaign token: {} ({}) with supply {} minted to {}",
+            name, symbol, initial_supply, supply_wallet
         );
 
         // Validate inputs
-        if initial_supply == 0 {
+        if initial_supply.is_zero() {
             return Err(anyhow::anyhow!("Initial supply must be greater than 0"));
         }
 
         // Build CreateJetton message body
         // Message structure (Tact):
         // [32 bits] opcode
-        // [MsgAddress] farmer_wallet
+        // [MsgAddress] wallet receiving the initial supply
         // [Cell] content (metadata)
         // [coins] initial_supply (VarUInteger 16)
         let mut body_builder = CellBuilder::new();
@@ -97,16 +114,16 @@ impl FactoryService {
         // Store opcode
         body_builder.store_u32(32, CREATE_JETTON_OPCODE)?;
 
-        // Store farmer_wallet address
-        store_ton_address(&mut body_builder, farmer_wallet)
-            .map_err(|e| anyhow::anyhow!("Invalid farmer address: {}", e))?;
+        // Store the supply wallet address
+        store_ton_address(&mut body_builder, supply_wallet)
+            .map_err(|e| anyhow::anyhow!("Invalid supply wallet address: {}", e))?;
 
         // Build content cell (Jetton metadata)
-        let content_cell = self.build_jetton_metadata(name, symbol)?;
+        let content_cell = self.build_jetton_metadata(metadata_url)?;
         body_builder.store_reference(&content_cell)?;
 
         // Store initial_supply as coins (VarUInteger 16)
-        body_builder.store_coins(&BigUint::from(initial_supply))?;
+        body_builder.store_coins(&BigUint::from(initial_supply.nano()))?;
 
         let body = body_builder.build()?;
 
@@ -161,15 +178,8 @@ impl FactoryService {
 
     /// Build Jetton metadata cell
     ///
-    /// Creates a cell containing token metadata (name, symbol, etc.)
-    /// Build Jetton metadata cell
-    ///
-    /// Creates a cell containing token metadata (name, symbol, etc.)
-    fn build_jetton_metadata(
-        &self,
-        name: &str,
-        symbol: &str,
-    ) -> Result<Arc<tonlib_core::cell::Cell>> {
+    /// Creates a TEP-64 off-chain content cell pointing at `uri`
+    fn build_jetton_metadata(&self, uri: &str) -> Result<Arc<tonlib_core::cell::Cell>> {
         // Implement TEP-64 off-chain metadata format (0x01 prefix + URI)
         let mut metadata_builder = CellBuilder::new();
 
@@ -177,10 +187,6 @@ impl FactoryService {
         metadata_builder.store_u8(8, 0x01)?;
 
         // Store URI as string
-        // For now using a placeholder that depends on name/symbol conceptually,
-        // but in reality we should upload a JSON to IPFS/S3 and point there.
-        // Aligning with the TS script which hardcodes a placeholder.
-        let uri = format!("https://hazelnut.ag/api/metadata/{}/{}", symbol, name);
         metadata_builder.store_slice(uri.as_bytes())?;
 
         Ok(Arc::new(metadata_builder.build()?))
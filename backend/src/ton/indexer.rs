@@ -1,24 +1,110 @@
+//! Chain indexer
+//!
+//! Walks new masterchain blocks and every shard block they commit, back to
+//! the shard blocks committed by the previous masterchain block, and stores
+//! the transfers the backend cares about (see `db::transfers`):
+//! MKOIN and campaign token credits, decoded from the `internal_transfer`
+//! received by a jetton wallet, and plain TON transfers from or to a
+//! tracked address. A transfer is stored from the receiving side only;
+//! campaign token transfers also feed the balance ledger, crediting the
+//! recipient and debiting the sender at the lt each side changed.
+//!
+//! The last processed masterchain block is persisted, so a restart resumes
+//! after it. Jetton wallets are trusted only if their master derives the
+//! same address for their owner.
+
+use crate::amount::TokenAmount;
 use crate::db::Database;
-use crate::ton::client::Client;
+use crate::db::transfers::NewTransfer;
+use crate::ton::address_utils::to_raw_address;
+use crate::ton::client::{self, Client};
+use crate::ton::jetton::{self, BOUNCED_PREFIX, JETTON_INTERNAL_TRANSFER_OPCODE};
+use crate::ton::mkoin_service::get_mkoin_address;
 use anyhow::Result;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 use tokio::time::sleep;
-use tracing::{error, info}; // Removed warn
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+const TEXT_COMMENT_OPCODE: u32 = 0;
+// Resolved jetton wallets kept in memory before the cache starts over
+const MAX_CACHED_WALLETS: usize = 100_000;
+// Transactions asked for per getBlockTransactionsExt call
+const BLOCK_TRANSACTIONS_PAGE: u32 = 100;
+// Rows get their timestamp when their transaction starts but become visible
+// when it commits, so tracked addresses are re-read with this much overlap
+const TRACKED_OVERLAP_SECS: i64 = 60;
 
 pub struct Indexer {
     db: Database,
     client: Client,
     current_seqno: Option<u64>,
+    /// Masterchain block last processed and its shard tops
+    last_shards: Option<(u64, Vec<BlockId>)>,
+    tracked: Tracked,
+    /// Owner and master of each account seen receiving jettons; None for
+    /// accounts that are not jetton wallets
+    jetton_wallets: HashMap<String, Option<(String, String)>>,
+}
+
+/// What the indexer stores transfers of, refreshed every block
+#[derive(Debug, Default)]
+struct Tracked {
+    /// Raw master address -> (asset, master as stored, campaign)
+    masters: HashMap<String, (&'static str, String, Option<Uuid>)>,
+    /// Raw addresses whose TON transfers are stored
+    addresses: HashSet<String>,
+    /// When the addresses were last read
+    refreshed_at: Option<DateTime<Utc>>,
+}
+
+/// A shard block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct BlockId {
+    workchain: i32,
+    shard: i64,
+    seqno: u64,
+}
+
+/// Jettons credited to the wallet receiving a transaction's inbound message
+#[derive(Debug, Clone, PartialEq)]
+pub struct JettonCredit {
+    /// Raw address of the account that sent the credit: a jetton wallet,
+    /// or the master when minting
+    pub source: String,
+    /// Raw address of the owner of the sending wallet
+    pub from: String,
+    pub amount: TokenAmount,
+    /// The credit was refused and bounced back to the sender
+    pub bounced: bool,
+    /// lt of the `internal_transfer`, created by the sending wallet in the
+    /// transaction that debited it
+    pub sent_lt: Option<i64>,
+}
+
+/// A plain TON transfer: no body, or a text comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct TonTransfer {
+    pub source: String,
+    pub destination: String,
+    pub amount: TokenAmount,
 }
 
 impl Indexer {
     pub async fn new(db: Database) -> Result<Self> {
         let api_key = std::env::var("TON_API_KEY").ok();
-        let client = Client::new("https://testnet.toncenter.com/api/v2", api_key);
+        let client = Client::new(&client::api_url(), api_key);
+        let current_seqno = db.get_indexer_seqno().await?.map(|seqno| seqno as u64 + 1);
         Ok(Self {
             db,
             client,
-            current_seqno: None,
+            current_seqno,
+            last_shards: None,
+            tracked: Tracked::default(),
+            jetton_wallets: HashMap::new(),
         })
     }
 
@@ -47,58 +133,460 @@ impl Indexer {
         }
 
         info!("Processing block seqno: {}", start);
+        self.refresh_tracked().await?;
 
-        // 2. Get Shards info for this Masterchain block
-        // The masterchain block contains the latest state of all shards
-        let shards_resp = self.client.get_shards(start).await?;
-        let shards = shards_resp["shards"]
-            .as_array()
-            .map(|v| v.clone())
-            .unwrap_or_default();
-
-        info!(
-            "Processing MC block {}, found {} shards",
-            start,
-            shards.len()
-        );
+        let tops = self.shard_tops(start).await?;
+        let previous = match self.last_shards.take() {
+            Some((seqno, tops)) if seqno + 1 == start => tops,
+            _ if start > 0 => self.shard_tops(start - 1).await?,
+            _ => Vec::new(),
+        };
+        let blocks = self.new_shard_blocks(&tops, &previous).await?;
+        info!("Processing MC block {}, {} new shard blocks", start, blocks.len());
 
-        // 3. Process Shards (Basechain)
-        for shard in shards {
-            let workchain = shard["workchain"].as_i64().unwrap_or(0);
-            let shard_id = shard["shard"].as_i64().unwrap_or(-9223372036854775808);
-            let shard_seqno = shard["seqno"].as_u64().unwrap_or(0);
+        for block in blocks {
+            self.process_block(block).await?;
+        }
 
-            // Fetch txs for this specific shard block
-            let txs_val = self
+        self.db.set_indexer_seqno(start as i64).await?;
+        self.current_seqno = Some(start + 1);
+        self.last_shards = Some((start, tops));
+        Ok(())
+    }
+
+    /// Latest shard blocks committed by a masterchain block
+    async fn shard_tops(&self, seqno: u64) -> Result<Vec<BlockId>> {
+        let shards = self.client.get_shards(seqno).await?;
+        Ok(shards["shards"].as_array().into_iter().flatten().filter_map(block_id).collect())
+    }
+
+    /// Shard blocks committed by a masterchain block with shard tops `tops`,
+    /// oldest first: the tops and the blocks before them, back to the tops
+    /// of the previous masterchain block
+    async fn new_shard_blocks(&self, tops: &[BlockId], previous: &[BlockId]) -> Result<Vec<BlockId>> {
+        let mut blocks = Vec::new();
+        let mut queue: Vec<BlockId> = tops.iter().filter(|b| !processed(b, previous)).copied().collect();
+        while let Some(block) = queue.pop() {
+            if blocks.contains(&block) {
+                continue;
+            }
+            blocks.push(block);
+            // Without the previous tops there is nothing to stop at
+            if previous.is_empty() {
+                continue;
+            }
+            let header = self
                 .client
-                .get_block_transactions(workchain as i32, shard_id, shard_seqno)
+                .get_block_header(block.workchain, block.shard, block.seqno)
                 .await?;
+            queue.extend(
+                header["prev_blocks"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(block_id)
+                    .filter(|prev| !processed(prev, previous)),
+            );
+        }
+        blocks.sort_by_key(|b| (b.seqno, b.workchain, b.shard));
+        Ok(blocks)
+    }
 
-            if let Some(txs) = txs_val.as_array() {
-                if !txs.is_empty() {
-                    info!("  Shard {}: {} txs", shard_id, txs.len());
-                }
-                for tx in txs {
-                    self.process_transaction(tx).await?;
-                }
+    /// Every transaction of a shard block, page by page
+    async fn process_block(&mut self, block: BlockId) -> Result<()> {
+        let mut after: Option<(i64, String)> = None;
+        loop {
+            let page = self
+                .client
+                .get_block_transactions_ext(
+                    block.workchain,
+                    block.shard,
+                    block.seqno,
+                    BLOCK_TRANSACTIONS_PAGE,
+                    after.as_ref().map(|(lt, account)| (*lt, account.as_str())),
+                )
+                .await?;
+            let txs = page["transactions"].as_array().cloned().unwrap_or_default();
+            if !txs.is_empty() {
+                info!("  Shard {} block {}: {} txs", block.shard, block.seqno, txs.len());
+            }
+            for tx in &txs {
+                self.process_transaction(tx).await?;
+            }
+            match next_page(&page)? {
+                Some(next) => after = Some(next),
+                None => return Ok(()),
             }
         }
+    }
 
-        self.current_seqno = Some(start + 1);
+    /// Reload the tracked masters, and add the addresses that became
+    /// tracked since the last refresh
+    async fn refresh_tracked(&mut self) -> Result<()> {
+        let mut masters = HashMap::new();
+        let mkoin = get_mkoin_address();
+        if let Ok(raw) = to_raw_address(&mkoin) {
+            masters.insert(raw, ("MKOIN", mkoin, None));
+        }
+        for (campaign_id, token_address) in self.db.get_token_masters().await? {
+            if let Ok(raw) = to_raw_address(&token_address) {
+                masters.insert(raw, ("campaign_token", token_address, Some(campaign_id)));
+            }
+        }
+        self.tracked.masters = masters;
+
+        let now = Utc::now();
+        let since = self
+            .tracked
+            .refreshed_at
+            .map(|at| at - chrono::Duration::seconds(TRACKED_OVERLAP_SECS));
+        let added = self.db.tracked_addresses(since).await?;
+        self.tracked
+            .addresses
+            .extend(added.iter().filter_map(|a| to_raw_address(a).ok()));
+        self.tracked.refreshed_at = Some(now);
         Ok(())
     }
 
-    async fn process_transaction(&self, tx: &serde_json::Value) -> Result<()> {
-        // Look for incoming messages or out messages with body
-        let _tx_hash = tx["transaction_id"]["hash"].as_str().unwrap_or("?");
+    async fn process_transaction(&mut self, tx: &serde_json::Value) -> Result<()> {
+        let Some((tx_hash, lt, occurred_at)) = tx_point(tx) else {
+            return Ok(());
+        };
+
+        if let Some(credit) = decode_jetton_credit(tx) {
+            let Some(wallet) = tx["in_msg"]["destination"].as_str() else {
+                return Ok(());
+            };
+            let Some((owner, master)) = self.jetton_wallet(wallet).await else {
+                return Ok(());
+            };
+            let Some((asset, token_address, campaign_id)) = self.tracked.masters.get(&master) else {
+                return Ok(());
+            };
+
+            let transfer = NewTransfer {
+                tx_hash,
+                lt,
+                asset: asset.to_string(),
+                token_address: Some(token_address.clone()),
+                campaign_id: *campaign_id,
+                sender: credit.from,
+                recipient: owner,
+                amount: credit.amount,
+                is_mint: credit.source == master,
+                status: if credit.bounced { "failed" } else { "completed" }.to_string(),
+                occurred_at,
+                sent_lt: credit.sent_lt,
+            };
+            if self.db.record_transfer(&transfer).await? {
+                info!("    Indexed {} {} transfer in {}", transfer.amount, asset, transfer.tx_hash);
+            }
+            return Ok(());
+        }
 
-        // Simple log for now to prove we see data
-        // In real impl, we parse the "in_msg" body for OP_CODE 0x7362d096
-        if let Some(in_msg) = tx.get("in_msg") {
-            if let Some(source) = in_msg.get("source") {
-                info!("    Tx {} from {}", _tx_hash, source);
+        if let Some(ton) = decode_ton_transfer(tx)
+            && (self.tracked.addresses.contains(&ton.source) || self.tracked.addresses.contains(&ton.destination))
+        {
+            let transfer = NewTransfer {
+                tx_hash,
+                lt,
+                asset: "TON".to_string(),
+                token_address: None,
+                campaign_id: None,
+                sender: ton.source,
+                recipient: ton.destination,
+                amount: ton.amount,
+                is_mint: false,
+                status: "completed".to_string(),
+                occurred_at,
+                sent_lt: None,
+            };
+            if self.db.record_transfer(&transfer).await? {
+                info!("    Indexed {} TON transfer in {}", transfer.amount, transfer.tx_hash);
             }
         }
         Ok(())
     }
+
+    /// Owner and master of a jetton wallet, resolved once per wallet
+    ///
+    /// Anyone can deploy a contract answering `get_wallet_data` with a
+    /// tracked master, so the wallet counts only if the master derives its
+    /// address for the owner.
+    async fn jetton_wallet(&mut self, wallet: &str) -> Option<(String, String)> {
+        if let Some(known) = self.jetton_wallets.get(wallet) {
+            return known.clone();
+        }
+        let resolved = match self.verified_jetton_wallet(wallet).await {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                // Not cached: the next credit tries again
+                warn!("Failed to read jetton wallet {}: {}", wallet, e);
+                return None;
+            }
+        };
+        if self.jetton_wallets.len() >= MAX_CACHED_WALLETS {
+            self.jetton_wallets.clear();
+        }
+        self.jetton_wallets.insert(wallet.to_string(), resolved.clone());
+        resolved
+    }
+
+    async fn verified_jetton_wallet(&self, wallet: &str) -> Result<Option<(String, String)>> {
+        let Some((owner, master)) = jetton::get_jetton_wallet_data(&self.client, wallet).await? else {
+            return Ok(None);
+        };
+        let expected = jetton::get_jetton_wallet_address(&self.client, &master, &owner).await?;
+        if to_raw_address(&expected)? != to_raw_address(wallet)? {
+            warn!("{} claims to be the {} wallet of {}, which is {}", wallet, master, owner, expected);
+            return Ok(None);
+        }
+        Ok(Some((owner, master)))
+    }
+}
+
+/// A block id as given by toncenter, whose shard may be a number or a string
+fn block_id(value: &serde_json::Value) -> Option<BlockId> {
+    let shard = match &value["shard"] {
+        serde_json::Value::String(shard) => shard.parse().ok()?,
+        shard => shard.as_i64()?,
+    };
+    Some(BlockId {
+        workchain: i32::try_from(value["workchain"].as_i64()?).ok()?,
+        shard,
+        seqno: value["seqno"].as_u64()?,
+    })
+}
+
+/// Whether shard `a` covers shard `b`: b's prefix starts with a's
+fn shard_covers(a: i64, b: i64) -> bool {
+    let (a, b) = (a as u64, b as u64);
+    let (a_tag, b_tag) = (a & a.wrapping_neg(), b & b.wrapping_neg());
+    let prefix = !((a_tag << 1).wrapping_sub(1));
+    b_tag <= a_tag && a & prefix == b & prefix
+}
+
+/// Whether a block is at or before one of the previously processed shard
+/// tops: seqnos grow along a shard's history, across splits and merges
+fn processed(block: &BlockId, previous: &[BlockId]) -> bool {
+    previous.iter().any(|top| {
+        top.workchain == block.workchain
+            && (shard_covers(top.shard, block.shard) || shard_covers(block.shard, top.shard))
+            && block.seqno <= top.seqno
+    })
+}
+
+/// Where the next page of a getBlockTransactionsExt result starts: the lt
+/// and base64 account id of its last transaction; None on the last page
+fn next_page(page: &serde_json::Value) -> Result<Option<(i64, String)>> {
+    if !page["incomplete"].as_bool().unwrap_or(false) {
+        return Ok(None);
+    }
+    let cursor = || {
+        let last = page["transactions"].as_array()?.last()?;
+        let lt = last["transaction_id"]["lt"].as_str()?.parse().ok()?;
+        let raw = to_raw_address(last["address"]["account_address"].as_str()?).ok()?;
+        let account = hex::decode(raw.split_once(':')?.1).ok()?;
+        Some((lt, base64::engine::general_purpose::STANDARD.encode(account)))
+    };
+    cursor()
+        .map(Some)
+        .ok_or_else(|| anyhow::anyhow!("Incomplete block page without a last transaction to continue after"))
+}
+
+/// Hex hash, lt and time of a transaction
+fn tx_point(tx: &serde_json::Value) -> Option<(String, i64, DateTime<Utc>)> {
+    let id = tx.get("transaction_id")?;
+    let hash = id.get("hash")?.as_str()?;
+    let hash = match hash.len() {
+        64 => hash.to_lowercase(),
+        _ => hex::encode(base64::engine::general_purpose::STANDARD.decode(hash).ok()?),
+    };
+    let lt = id.get("lt")?.as_str()?.parse().ok()?;
+    let time = DateTime::from_timestamp(tx.get("utime")?.as_i64()?, 0)?;
+    Some((hash, lt, time))
+}
+
+/// The jetton credit a transaction received, if its inbound message is an
+/// `internal_transfer`
+pub fn decode_jetton_credit(tx: &serde_json::Value) -> Option<JettonCredit> {
+    let in_msg = tx.get("in_msg")?;
+    let body = jetton::message_body(in_msg)?;
+    let mut parser = body.parser();
+    if parser.load_u32(32).ok()? != JETTON_INTERNAL_TRANSFER_OPCODE {
+        return None;
+    }
+    parser.load_u64(64).ok()?;
+    let amount = u128::try_from(parser.load_coins().ok()?).ok()?;
+    let from = parser.load_address().ok()?.to_hex();
+
+    Some(JettonCredit {
+        source: to_raw_address(in_msg.get("source")?.as_str()?).ok()?,
+        from,
+        amount: TokenAmount::from_nano(amount),
+        bounced: jetton::sends_op(tx, BOUNCED_PREFIX),
+        sent_lt: in_msg.get("created_lt").and_then(|lt| lt.as_str()?.parse().ok()),
+    })
+}
+
+/// The TON a transaction received in a plain transfer
+pub fn decode_ton_transfer(tx: &serde_json::Value) -> Option<TonTransfer> {
+    let in_msg = tx.get("in_msg")?;
+    let source = in_msg.get("source")?.as_str().filter(|s| !s.is_empty())?;
+    let destination = in_msg.get("destination")?.as_str()?;
+    let amount: u128 = in_msg.get("value")?.as_str()?.parse().ok()?;
+    if amount == 0 {
+        return None;
+    }
+    // Anything but a text comment is a contract call, jetton messages
+    // (which carry TON for fees) included
+    if let Some(body) = jetton::message_body(in_msg)
+        && body.parser().load_u32(32).is_ok_and(|op| op != TEXT_COMMENT_OPCODE)
+    {
+        return None;
+    }
+
+    Some(TonTransfer {
+        source: to_raw_address(source).ok()?,
+        destination: to_raw_address(destination).ok()?,
+        amount: TokenAmount::from_nano(amount),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::address_utils::store_ton_address;
+    use num_bigint::BigUint;
+    use serde_json::json;
+    use tonlib_core::cell::{BagOfCells, Cell, CellBuilder};
+
+    const WALLET: &str = "0:1111111111111111111111111111111111111111111111111111111111111111";
+    const SENDER_WALLET: &str = "0:2222222222222222222222222222222222222222222222222222222222222222";
+    const SENDER: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+
+    fn boc_b64(cell: Cell) -> String {
+        let boc = BagOfCells::from_root(cell).serialize(true).unwrap();
+        base64::engine::general_purpose::STANDARD.encode(boc)
+    }
+
+    fn internal_transfer(amount: u64) -> String {
+        let mut builder = CellBuilder::new();
+        builder.store_u32(32, JETTON_INTERNAL_TRANSFER_OPCODE).unwrap();
+        builder.store_u64(64, 9).unwrap();
+        builder.store_coins(&BigUint::from(amount)).unwrap();
+        store_ton_address(&mut builder, SENDER).unwrap();
+        store_ton_address(&mut builder, SENDER).unwrap();
+        boc_b64(builder.build().unwrap())
+    }
+
+    fn op_body(op: u32) -> String {
+        let mut builder = CellBuilder::new();
+        builder.store_u32(32, op).unwrap();
+        boc_b64(builder.build().unwrap())
+    }
+
+    fn tx(value: &str, body: Option<String>, out_ops: &[u32]) -> serde_json::Value {
+        json!({
+            "utime": 1_700_000_000,
+            "transaction_id": { "lt": "4711", "hash": "qrvM3e7/ABEiM0RVZneImaq7zN3u/wARIjNEVWZ3iJk=" },
+            "in_msg": {
+                "source": SENDER_WALLET,
+                "destination": WALLET,
+                "value": value,
+                "created_lt": "4710",
+                "msg_data": body.map(|b| json!({ "body": b })).unwrap_or(json!({})),
+            },
+            "out_msgs": out_ops.iter().map(|op| json!({ "msg_data": { "body": op_body(*op) } })).collect::<Vec<_>>(),
+        })
+    }
+
+    #[test]
+    fn test_decode_jetton_credit() {
+        let credit = decode_jetton_credit(&tx("50000000", Some(internal_transfer(2_500)), &[])).unwrap();
+        assert_eq!(
+            credit,
+            JettonCredit {
+                source: SENDER_WALLET.to_string(),
+                from: SENDER.to_string(),
+                amount: TokenAmount::from_nano(2_500),
+                bounced: false,
+                sent_lt: Some(4710),
+            }
+        );
+        let refused = decode_jetton_credit(&tx("50000000", Some(internal_transfer(2_500)), &[BOUNCED_PREFIX])).unwrap();
+        assert!(refused.bounced);
+
+        assert!(decode_jetton_credit(&tx("50000000", Some(op_body(jetton::JETTON_TRANSFER_OPCODE)), &[])).is_none());
+        assert!(decode_jetton_credit(&tx("50000000", None, &[])).is_none());
+    }
+
+    #[test]
+    fn test_decode_ton_transfer() {
+        let transfer = decode_ton_transfer(&tx("1500000000", None, &[])).unwrap();
+        assert_eq!(transfer.source, SENDER_WALLET);
+        assert_eq!(transfer.destination, WALLET);
+        assert_eq!(transfer.amount, TokenAmount::from_nano(1_500_000_000));
+
+        let comment = boc_b64(jetton::text_comment_cell("Thanks").unwrap());
+        assert!(decode_ton_transfer(&tx("1", Some(comment), &[])).is_some());
+        // Fees attached to jetton messages are not transfers
+        assert!(decode_ton_transfer(&tx("50000000", Some(internal_transfer(1)), &[])).is_none());
+        assert!(decode_ton_transfer(&tx("0", None, &[])).is_none());
+    }
+
+    #[test]
+    fn test_block_id_shard_forms() {
+        let numeric = json!({ "workchain": 0, "shard": -9223372036854775808i64, "seqno": 7 });
+        let string = json!({ "workchain": 0, "shard": "-9223372036854775808", "seqno": 7 });
+        assert_eq!(block_id(&numeric), block_id(&string));
+        assert_eq!(block_id(&numeric).unwrap().shard, i64::MIN);
+        assert!(block_id(&json!({ "workchain": 0, "seqno": 7 })).is_none());
+    }
+
+    #[test]
+    fn test_processed_follows_splits_and_merges() {
+        let block = |shard: u64, seqno| BlockId { workchain: 0, shard: shard as i64, seqno };
+        let (root, left, right) = (0x8000_0000_0000_0000, 0x4000_0000_0000_0000, 0xc000_0000_0000_0000);
+        assert!(shard_covers(root as i64, left as i64));
+        assert!(!shard_covers(left as i64, right as i64));
+
+        // Before the split: the root shard's history up to its top
+        let previous = [block(root, 10)];
+        assert!(processed(&block(root, 10), &previous));
+        assert!(!processed(&block(root, 11), &previous));
+        assert!(!processed(&block(left, 11), &previous));
+
+        // After it: each half on its own, other workchains apart
+        let previous = [block(left, 12), block(right, 20)];
+        assert!(processed(&block(root, 10), &previous));
+        assert!(!processed(&block(left, 13), &previous));
+        assert!(processed(&block(right, 13), &previous));
+        assert!(!processed(&BlockId { workchain: -1, ..block(left, 5) }, &previous));
+    }
+
+    #[test]
+    fn test_next_page() {
+        let page = |incomplete| {
+            json!({
+                "incomplete": incomplete,
+                "transactions": [{
+                    "address": { "account_address": WALLET },
+                    "transaction_id": { "lt": "4711" },
+                }],
+            })
+        };
+        assert_eq!(next_page(&page(false)).unwrap(), None);
+        let (lt, account) = next_page(&page(true)).unwrap().unwrap();
+        assert_eq!(lt, 4711);
+        assert_eq!(base64::engine::general_purpose::STANDARD.decode(account).unwrap(), [0x11; 32]);
+        assert!(next_page(&json!({ "incomplete": true, "transactions": [] })).is_err());
+    }
+
+    #[test]
+    fn test_tx_point_hex_hash() {
+        let (hash, lt, time) = tx_point(&tx("1", None, &[])).unwrap();
+        assert_eq!(hash, "aabbccddeeff00112233445566778899aabbccddeeff00112233445566778899");
+        assert_eq!((lt, time.timestamp()), (4711, 1_700_000_000));
+    }
 }
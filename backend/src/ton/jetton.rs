@@ -0,0 +1,300 @@
+use crate::amount::TokenAmount;
+use crate::ton::address_utils::store_ton_address;
+use crate::ton::client::Client;
+use anyhow::Result;
+use base64::Engine;
+use num_bigint::BigUint;
+use std::sync::Arc;
+use tonlib_core::cell::{BagOfCells, Cell, CellBuilder};
+
+// TEP-74 jetton wallet opcodes
+pub const JETTON_TRANSFER_OPCODE: u32 = 0x0f8a7ea5;
+pub const JETTON_INTERNAL_TRANSFER_OPCODE: u32 = 0x178d4519;
+pub const JETTON_EXCESSES_OPCODE: u32 = 0xd53276db;
+pub const JETTON_BURN_OPCODE: u32 = 0x595f07bc;
+pub const JETTON_BURN_NOTIFICATION_OPCODE: u32 = 0x7bdd97de;
+// Bounced message bodies start with 0xffffffff followed by the original body
+pub const BOUNCED_PREFIX: u32 = 0xffffffff;
+// Simple text comment payload (op = 0)
+const TEXT_COMMENT_OPCODE: u32 = 0;
+// A root cell holds 1023 bits; 4 bytes go to the opcode
+const MAX_COMMENT_BYTES: usize = 123;
+
+/// Parameters of a TEP-74 `transfer` sent to the sender's own jetton wallet
+#[derive(Debug, Clone)]
+pub struct JettonTransfer<'a> {
+    pub query_id: u64,
+    pub amount: TokenAmount,
+    /// Owner wallet of the recipient (not their jetton wallet)
+    pub destination: &'a str,
+    /// Where excess TON is returned, usually the sender
+    pub response_destination: &'a str,
+    /// TON attached to the transfer_notification sent to `destination`
+    pub forward_ton_amount: u64,
+    /// Optional text comment carried in the forward payload
+    pub comment: Option<&'a str>,
+}
+
+/// Build the body of a jetton `transfer` message
+///
+/// ```raw
+/// transfer#0f8a7ea5 query_id:uint64 amount:(VarUInteger 16) destination:MsgAddress
+///                  response_destination:MsgAddress custom_payload:(Maybe ^Cell)
+///                  forward_ton_amount:(VarUInteger 16) forward_payload:(Either Cell ^Cell)
+/// ```
+pub fn build_transfer_body(transfer: &JettonTransfer) -> Result<Cell> {
+    let mut builder = CellBuilder::new();
+    builder.store_u32(32, JETTON_TRANSFER_OPCODE)?;
+    builder.store_u64(64, transfer.query_id)?;
+    builder.store_coins(&BigUint::from(transfer.amount.nano()))?;
+    store_ton_address(&mut builder, transfer.destination)?;
+    store_ton_address(&mut builder, transfer.response_destination)?;
+    builder.store_bit(false)?; // custom_payload: nothing
+    builder.store_coins(&BigUint::from(transfer.forward_ton_amount))?;
+
+    match transfer.comment {
+        Some(text) => {
+            builder.store_bit(true)?; // forward_payload in a reference
+            builder.store_reference(&Arc::new(text_comment_cell(text)?))?;
+        }
+        None => {
+            builder.store_bit(false)?; // empty inline forward_payload
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Build the body of a jetton `burn` message
+///
+/// ```raw
+/// burn#595f07bc query_id:uint64 amount:(VarUInteger 16)
+///               response_destination:MsgAddress custom_payload:(Maybe ^Cell)
+/// ```
+pub fn build_burn_body(query_id: u64, amount: TokenAmount, response_destination: &str) -> Result<Cell> {
+    let mut builder = CellBuilder::new();
+    builder.store_u32(32, JETTON_BURN_OPCODE)?;
+    builder.store_u64(64, query_id)?;
+    builder.store_coins(&BigUint::from(amount.nano()))?;
+    store_ton_address(&mut builder, response_destination)?;
+    builder.store_bit(false)?; // custom_payload: nothing
+    Ok(builder.build()?)
+}
+
+/// Build a text comment cell (op 0 + UTF-8), truncated to fit one cell
+pub fn text_comment_cell(text: &str) -> Result<Cell> {
+    let mut end = text.len().min(MAX_COMMENT_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let mut builder = CellBuilder::new();
+    builder.store_u32(32, TEXT_COMMENT_OPCODE)?;
+    builder.store_slice(&text.as_bytes()[..end])?;
+    Ok(builder.build()?)
+}
+
+/// Read a text comment payload (op 0 + UTF-8, snake-encoded)
+pub fn parse_text_comment(payload: &Cell) -> Option<String> {
+    let mut parser = payload.parser();
+    if parser.load_u32(32).ok()? != TEXT_COMMENT_OPCODE {
+        return None;
+    }
+    let bytes = parser.load_snake_format_aligned(false).ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Decode the body of a message as returned by toncenter (`msg_data.body`)
+pub fn message_body(msg: &serde_json::Value) -> Option<Cell> {
+    let body_b64 = msg.get("msg_data")?.get("body")?.as_str()?;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(body_b64).ok()?;
+    let boc = BagOfCells::parse(&bytes).ok()?;
+    boc.single_root().ok().map(|root| root.as_ref().clone())
+}
+
+/// Whether a transaction sent a message whose body starts with `op`
+pub fn sends_op(tx: &serde_json::Value, op: u32) -> bool {
+    tx.get("out_msgs")
+        .and_then(|msgs| msgs.as_array())
+        .is_some_and(|msgs| {
+            msgs.iter()
+                .filter_map(message_body)
+                .any(|body| body.parser().load_u32(32).is_ok_and(|o| o == op))
+        })
+}
+
+/// Encode an address as a `tvm.Slice` get-method argument
+pub fn address_stack_param(address: &str) -> Result<serde_json::Value> {
+    let mut builder = CellBuilder::new();
+    store_ton_address(&mut builder, address)?;
+    let boc = BagOfCells::from_root(builder.build()?).serialize(true)?;
+    Ok(serde_json::json!([
+        "tvm.Slice",
+        base64::engine::general_purpose::STANDARD.encode(boc)
+    ]))
+}
+
+/// Decode an address returned on the get-method stack as a cell or slice
+///
+/// Returns raw `workchain:hex` form.
+pub fn parse_stack_address(entry: &serde_json::Value) -> Result<String> {
+    let boc_b64 = entry
+        .get(1)
+        .and_then(|v| v.get("bytes").or(Some(v)))
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Unexpected stack entry: {}", entry))?;
+
+    let bytes = base64::engine::general_purpose::STANDARD.decode(boc_b64)?;
+    let boc = BagOfCells::parse(&bytes)?;
+    let root = boc.single_root()?;
+    let address = root.parser().load_address()?;
+    Ok(address.to_hex())
+}
+
+/// Decode a `num` stack entry (hex string, possibly negative)
+pub fn parse_stack_num(entry: &serde_json::Value) -> Option<u128> {
+    let arr = entry.as_array()?;
+    if arr.len() != 2 || arr[0] != "num" {
+        return None;
+    }
+    let hex_val = arr[1].as_str()?;
+    if hex_val.starts_with('-') {
+        return None;
+    }
+    u128::from_str_radix(hex_val.trim_start_matches("0x"), 16).ok()
+}
+
+/// Resolve the jetton wallet of `owner` for the jetton `master`
+pub async fn get_jetton_wallet_address(client: &Client, master: &str, owner: &str) -> Result<String> {
+    let result = client
+        .run_get_method(master, "get_wallet_address", vec![address_stack_param(owner)?])
+        .await?;
+
+    let entry = result
+        .get("stack")
+        .and_then(|s| s.as_array())
+        .and_then(|s| s.first())
+        .ok_or_else(|| anyhow::anyhow!("No wallet address returned by {}", master))?;
+
+    parse_stack_address(entry)
+}
+
+/// Read the balance of a jetton wallet via `get_wallet_data`
+///
+/// Wallets that were never deployed hold nothing, so a failing get-method
+/// (non-zero exit code) is reported as a zero balance.
+pub async fn get_jetton_wallet_balance(client: &Client, jetton_wallet: &str) -> Result<TokenAmount> {
+    Ok(get_jetton_wallet_state(client, jetton_wallet).await?.0)
+}
+
+/// Balance of a jetton wallet and the lt of its last transaction
+///
+/// The lt is None for wallets that were never deployed.
+pub async fn get_jetton_wallet_state(client: &Client, jetton_wallet: &str) -> Result<(TokenAmount, Option<i64>)> {
+    let result = client
+        .run_get_method(jetton_wallet, "get_wallet_data", vec![])
+        .await?;
+
+    if result.get("exit_code").and_then(|c| c.as_i64()).unwrap_or(0) != 0 {
+        return Ok((TokenAmount::ZERO, None));
+    }
+
+    // Stack: [balance, owner, master, wallet_code]
+    let balance = result
+        .get("stack")
+        .and_then(|s| s.as_array())
+        .and_then(|s| s.first())
+        .and_then(parse_stack_num)
+        .map(TokenAmount::from_nano)
+        .ok_or_else(|| anyhow::anyhow!("Unexpected get_wallet_data result for {}", jetton_wallet))?;
+    let last_lt = result["last_transaction_id"]["lt"]
+        .as_str()
+        .and_then(|lt| lt.parse().ok());
+    Ok((balance, last_lt))
+}
+
+/// Owner and master of a jetton wallet, in raw form
+///
+/// None if the account is not a deployed jetton wallet.
+pub async fn get_jetton_wallet_data(client: &Client, jetton_wallet: &str) -> Result<Option<(String, String)>> {
+    let result = client
+        .run_get_method(jetton_wallet, "get_wallet_data", vec![])
+        .await?;
+
+    if result.get("exit_code").and_then(|c| c.as_i64()).unwrap_or(0) != 0 {
+        return Ok(None);
+    }
+
+    // Stack: [balance, owner, master, wallet_code]
+    let stack = result.get("stack").and_then(|s| s.as_array());
+    match stack.map(|s| (s.get(1), s.get(2))) {
+        Some((Some(owner), Some(master))) => Ok(Some((parse_stack_address(owner)?, parse_stack_address(master)?))),
+        _ => Ok(None),
+    }
+}
+
+/// Balance of `owner` in the jetton `master`
+pub async fn get_jetton_balance(client: &Client, master: &str, owner: &str) -> Result<TokenAmount> {
+    let wallet = get_jetton_wallet_address(client, master, owner).await?;
+    get_jetton_wallet_balance(client, &wallet).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonlib_core::message::{JettonBurnMessage, JettonTransferMessage, TonMessage};
+
+    const OWNER: &str = "0:0d204ad68e3e94e0536049f107c11eb1f9b9242425759f9c353886fe3dfe9e59";
+
+    #[test]
+    fn test_transfer_body_matches_tep74() {
+        let body = build_transfer_body(&JettonTransfer {
+            query_id: 42,
+            amount: TokenAmount::from_nano(1_500_000_000),
+            destination: OWNER,
+            response_destination: OWNER,
+            forward_ton_amount: 1,
+            comment: Some("Refund"),
+        })
+        .unwrap();
+
+        let parsed = JettonTransferMessage::parse(&body).unwrap();
+        assert_eq!(parsed.query_id, 42);
+        assert_eq!(parsed.amount, BigUint::from(1_500_000_000u64));
+        assert_eq!(parsed.destination.to_hex(), OWNER);
+        assert_eq!(parsed.forward_ton_amount, BigUint::from(1u32));
+        assert_eq!(parse_text_comment(&parsed.forward_payload).as_deref(), Some("Refund"));
+    }
+
+    #[test]
+    fn test_burn_body_matches_tep74() {
+        let body = build_burn_body(7, TokenAmount::from_nano(2_000_000_000), OWNER).unwrap();
+
+        let parsed = JettonBurnMessage::parse(&body).unwrap();
+        assert_eq!(parsed.query_id, 7);
+        assert_eq!(parsed.amount, BigUint::from(2_000_000_000u64));
+        assert_eq!(parsed.response_destination.to_hex(), OWNER);
+        assert!(parsed.custom_payload.is_none());
+    }
+
+    #[test]
+    fn test_stack_address_roundtrip() {
+        let param = address_stack_param(OWNER).unwrap();
+        assert_eq!(param[0], "tvm.Slice");
+        let entry = serde_json::json!(["cell", { "bytes": param[1] }]);
+        assert_eq!(parse_stack_address(&entry).unwrap(), OWNER);
+    }
+
+    #[test]
+    fn test_parse_stack_num() {
+        assert_eq!(parse_stack_num(&serde_json::json!(["num", "0x3b9aca00"])), Some(1_000_000_000));
+        assert_eq!(parse_stack_num(&serde_json::json!(["num", "-0x1"])), None);
+        assert_eq!(parse_stack_num(&serde_json::json!(["cell", {}])), None);
+    }
+
+    #[test]
+    fn test_comment_truncated_on_char_boundary() {
+        let long = "é".repeat(100);
+        assert!(text_comment_cell(&long).is_ok());
+    }
+}
@@ -0,0 +1,143 @@
+//! Merkle tree of distribution payouts, hashed the way TVM hashes cells
+//!
+//! A claim contract only stores the root. Each holder proves their share
+//! with the sibling hashes on the path from their leaf to the root:
+//!
+//! ```raw
+//! leaf$_ address:MsgAddressInt amount:Coins = MerkleLeaf;
+//! node$_ lo:bits256 hi:bits256 = MerkleNode;
+//! ```
+//!
+//! Every hash is the representation hash of such a cell, so the contract
+//! recomputes it with `begin_cell()...end_cell().cell_hash()`. The two
+//! child hashes of a node are stored in ascending order, which makes
+//! proofs independent of leaf positions. Leaves are sorted by hash and an
+//! unpaired node is carried to the next level unchanged.
+
+use crate::amount::TokenAmount;
+use crate::ton::address_utils::store_ton_address;
+use anyhow::{Result, bail};
+use num_bigint::BigUint;
+use tonlib_core::TonHash;
+use tonlib_core::cell::CellBuilder;
+
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// Level 0 holds the sorted leaves, the last level the root
+    levels: Vec<Vec<TonHash>>,
+}
+
+/// Hash of the leaf paying `amount` MKOIN nanocoins to `address`
+pub fn leaf_hash(address: &str, amount: TokenAmount) -> Result<TonHash> {
+    let mut builder = CellBuilder::new();
+    store_ton_address(&mut builder, address)?;
+    builder.store_coins(&BigUint::from(amount.nano()))?;
+    Ok(builder.build()?.cell_hash())
+}
+
+fn node_hash(a: &TonHash, b: &TonHash) -> Result<TonHash> {
+    let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+    let mut builder = CellBuilder::new();
+    builder.store_tonhash(lo)?;
+    builder.store_tonhash(hi)?;
+    Ok(builder.build()?.cell_hash())
+}
+
+impl MerkleTree {
+    pub fn build(mut leaves: Vec<TonHash>) -> Result<Self> {
+        if leaves.is_empty() {
+            bail!("Merkle tree needs at least one leaf");
+        }
+        leaves.sort();
+
+        let mut levels = vec![leaves];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let level = levels.last().unwrap();
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => node_hash(a, b),
+                    [single] => Ok(single.clone()),
+                    _ => unreachable!(),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            levels.push(next);
+        }
+        Ok(Self { levels })
+    }
+
+    pub fn root(&self) -> &TonHash {
+        &self.levels[self.levels.len() - 1][0]
+    }
+
+    /// Sibling hashes from the leaf up to the root; None if the leaf is not
+    /// in the tree
+    pub fn proof(&self, leaf: &TonHash) -> Option<Vec<TonHash>> {
+        let mut index = self.levels[0].binary_search(leaf).ok()?;
+        let mut proof = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(index ^ 1) {
+                proof.push(sibling.clone());
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+}
+
+/// Check a proof the way the claim contract does
+pub fn verify_proof(root: &TonHash, leaf: &TonHash, proof: &[TonHash]) -> Result<bool> {
+    let mut hash = leaf.clone();
+    for sibling in proof {
+        hash = node_hash(&hash, sibling)?;
+    }
+    Ok(&hash == root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(i: u8) -> String {
+        format!("0:{}", hex::encode([i; 32]))
+    }
+
+    #[test]
+    fn test_every_leaf_proves_against_root() {
+        for size in 1..=9u8 {
+            let leaves: Vec<TonHash> = (0..size)
+                .map(|i| leaf_hash(&address(i), TokenAmount::from_nano(1_000 + i as u128)).unwrap())
+                .collect();
+            let tree = MerkleTree::build(leaves.clone()).unwrap();
+            for leaf in &leaves {
+                let proof = tree.proof(leaf).unwrap();
+                assert!(verify_proof(tree.root(), leaf, &proof).unwrap(), "size {}", size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_root_ignores_leaf_order_and_binds_amounts() {
+        let a = leaf_hash(&address(1), TokenAmount::from_nano(5)).unwrap();
+        let b = leaf_hash(&address(2), TokenAmount::from_nano(7)).unwrap();
+        let c = leaf_hash(&address(3), TokenAmount::from_nano(9)).unwrap();
+        let tree = MerkleTree::build(vec![a.clone(), b.clone(), c.clone()]).unwrap();
+        let shuffled = MerkleTree::build(vec![c, a.clone(), b]).unwrap();
+        assert_eq!(tree.root(), shuffled.root());
+
+        // A proof does not carry over to another amount
+        let proof = tree.proof(&a).unwrap();
+        let inflated = leaf_hash(&address(1), TokenAmount::from_nano(6)).unwrap();
+        assert!(!verify_proof(tree.root(), &inflated, &proof).unwrap());
+        assert!(tree.proof(&inflated).is_none());
+    }
+
+    #[test]
+    fn test_single_leaf_is_root() {
+        let a = leaf_hash(&address(1), TokenAmount::from_nano(5)).unwrap();
+        let tree = MerkleTree::build(vec![a.clone()]).unwrap();
+        assert_eq!(tree.root(), &a);
+        assert!(tree.proof(&a).unwrap().is_empty());
+        assert!(MerkleTree::build(Vec::new()).is_err());
+    }
+}
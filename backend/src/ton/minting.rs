@@ -1,8 +1,8 @@
+use crate::amount::TokenAmount;
 use crate::db::Campaign;
-use crate::ton::client::Client;
+use crate::ton::client::{self, Client};
 use crate::ton::wallet::Wallet;
 use anyhow::Result;
-use std::str::FromStr;
 use tracing::{error, info};
 
 pub struct MintingService {
@@ -15,7 +15,7 @@ impl MintingService {
         let mnemonic = std::env::var("ADMIN_MNEMONIC")
             .unwrap_or_else(|_| "admin_seed_placeholder".to_string());
 
-        let client = Client::new("https://testnet.toncenter.com/api/v2", None);
+        let client = Client::new(&client::api_url(), None);
 
         let wallet = Wallet::from_seed(&mnemonic).unwrap_or_else(|_| {
             error!("Invalid seed, using random wallet");
@@ -36,8 +36,7 @@ impl MintingService {
         const MKOIN_CONTRACT: &str = "0:00d2042b5a38fa538142608b0c87eaab75780684ca2313066dbc693c954253c9";
 
         // Parse token supply
-        let supply_float = f64::from_str(&campaign.token_supply).unwrap_or(0.0);
-        let supply_amount = (supply_float * 1_000_000_000.0) as u128; // Convert to nanocoins
+        let supply_amount = TokenAmount::parse_decimal(&campaign.token_supply)?;
 
         // Get farmer address from user table
         let farmer = db.get_user_by_id(campaign.farmer_id).await?
@@ -47,21 +46,21 @@ impl MintingService {
         let _mint_id = db.record_campaign_mint(
             campaign.id,
             &farmer.address,
-            &supply_amount.to_string(),
+            supply_amount,
             None, // tx_hash will be filled when we actually deploy contracts
         ).await?;
 
         // Update campaign with mint information
         db.update_campaign_mint_info(
             campaign.id,
-            &supply_amount.to_string(),
+            supply_amount,
             None, // tx_hash
         ).await?;
 
         info!(
             "Recorded mint for campaign {}: {} tokens allocated to {}",
             campaign.token_symbol,
-            supply_float,
+            supply_amount,
             farmer.address
         );
 
@@ -1,8 +1,12 @@
-use crate::ton::address_utils::store_ton_address;
-use crate::ton::client::Client;
-use crate::ton::wallet::Wallet;
+use crate::amount::TokenAmount;
+use crate::ton::address_utils::{store_ton_address, to_raw_address};
+use crate::ton::client::{self, Client, History};
+use crate::ton::jetton::{self, JettonTransfer};
+use crate::ton::wallet::{MESSAGE_TTL_SECS, Wallet};
 use anyhow::Result;
 use base64::Engine;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
 use num_bigint::BigUint;
 use std::sync::Arc;
 use tonlib_core::cell::{BagOfCells, CellBuilder};
@@ -13,7 +17,7 @@ use tracing::{error, info};
 // Default fallback if not in ENV
 const MKOIN_ADDRESS_DEFAULT: &str = "EQATDLvt8bY8BGb-DGBZxwe6EZla3Rcij41fqv_OFlLXvgpV";
 
-fn get_mkoin_address() -> String {
+pub(crate) fn get_mkoin_address() -> String {
     std::env::var("MKOIN_ADDRESS").unwrap_or_else(|_| MKOIN_ADDRESS_DEFAULT.to_string())
 }
 
@@ -22,13 +26,110 @@ fn get_mkoin_address() -> String {
 // Calculated: 0x642B7D07
 const MINT_OPCODE: u32 = 0x642B7D07;
 
+// TON attached to jetton transfers from the admin wallet; excess is returned
+pub(crate) const TRANSFER_ATTACHED_TON: u64 = 100_000_000; // 0.1 TON
+// TON forwarded with the transfer_notification so wallets show the comment
+pub(crate) const TRANSFER_FORWARD_TON: u64 = 1;
+
+// How far back from a message's send time its transaction is looked for
+pub const MESSAGE_LOOKBACK_SECS: i64 = 300;
+// Grace after a message's expiry before it is known not to have landed
+const MESSAGE_EXPIRY_GRACE_SECS: i64 = 120;
+// Pages of wallet history walked to find a message
+const MESSAGE_SCAN_PAGES: usize = 40;
+
+use std::collections::HashMap;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+/// `sendBoc` failed, but the message may still have reached the network
+///
+/// Treat the message as sent and settle it with
+/// [`message_status`](MkoinService::message_status): sending it again before
+/// it expired could execute it twice.
+#[derive(Debug, Error)]
+#[error("Message {message_hash} may have been broadcast: {reason}")]
+pub struct BroadcastUncertain {
+    pub message_hash: String,
+    pub reason: String,
+}
+
+/// Hash of the message a failed send may have broadcast
+pub fn uncertain_message(e: &anyhow::Error) -> Option<&str> {
+    e.downcast_ref::<BroadcastUncertain>().map(|u| u.message_hash.as_str())
+}
+
+/// What became of an external message sent from a platform wallet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageStatus {
+    /// Processed by the wallet in `tx_hash`
+    Landed { tx_hash: String },
+    /// Not seen yet and still valid
+    Pending,
+    /// Expired without landing; it can never execute, so sending again is safe
+    Expired,
+}
+
+/// A wallet the platform signs for
+struct Signer {
+    wallet: Mutex<Wallet>,
+    // Serialises outgoing messages so two sends never reuse a seqno
+    send_lock: tokio::sync::Mutex<()>,
+}
+
+impl Signer {
+    fn new(wallet: Wallet) -> Self {
+        Self {
+            wallet: Mutex::new(wallet),
+            send_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn address(&self) -> String {
+        self.wallet.lock().unwrap().address.clone()
+    }
+}
+
 pub struct MkoinService {
     client: Client,
-    admin_wallet: Mutex<Wallet>,
-    supply_cache: Mutex<Option<(u128, Instant)>>,
+    admin: Signer,
+    /// Campaign treasury wallets besides the admin wallet, by raw address
+    treasuries: HashMap<String, Signer>,
+    supply_cache: Mutex<Option<(TokenAmount, Instant)>>,
+}
+
+/// Treasury wallets from `TREASURY_WALLETS`: `;`-separated mnemonics, each
+/// optionally prefixed with `<address>=` like `ADMIN_ADDRESS` overrides the
+/// admin wallet's derived address
+fn load_treasuries() -> HashMap<String, Signer> {
+    let Ok(config) = std::env::var("TREASURY_WALLETS") else {
+        return HashMap::new();
+    };
+    let mut treasuries = HashMap::new();
+    for entry in config.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (address, mnemonic) = match entry.split_once('=') {
+            Some((address, mnemonic)) => (Some(address.trim()), mnemonic.trim()),
+            None => (None, entry),
+        };
+        let mut wallet = match Wallet::from_seed(mnemonic) {
+            Ok(wallet) => wallet,
+            Err(e) => {
+                error!("Failed to load treasury wallet: {}", e);
+                continue;
+            }
+        };
+        if let Some(address) = address {
+            wallet.address = address.to_string();
+        }
+        match to_raw_address(&wallet.address) {
+            Ok(raw) => {
+                info!("Loaded treasury wallet {}", wallet.address);
+                treasuries.insert(raw, Signer::new(wallet));
+            }
+            Err(e) => error!("Invalid treasury wallet address {}: {}", wallet.address, e),
+        }
+    }
+    treasuries
 }
 
 impl MkoinService {
@@ -73,252 +174,298 @@ impl MkoinService {
         }
 
         Self {
-            client: Client::new("https://testnet.toncenter.com/api/v2/jsonRPC", api_key),
-            admin_wallet: Mutex::new(admin_wallet),
+            client: Client::new(&format!("{}/jsonRPC", client::api_url()), api_key),
+            admin: Signer::new(admin_wallet),
+            treasuries: load_treasuries(),
             supply_cache: Mutex::new(None),
         }
     }
 
     /// Mint MKOIN to a recipient address
     ///
+    /// Sent from the admin wallet like any other message, with the same
+    /// error semantics as [`send_message`](Self::send_message): settle the
+    /// returned hash with [`admin_message_status`](Self::admin_message_status).
+    ///
     /// # Arguments
     /// * `recipient` - TON address of recipient (EQ...)
-    /// * `amount` - Amount in nanocoins (1 MKOIN = 1_000_000_000 nanocoins)
+    /// * `amount` - Amount of MKOIN to mint
     ///
     /// # Returns
-    /// Transaction hash on success
-    pub async fn mint_mkoin(&self, recipient: &str, amount: u128) -> Result<String> {
-        info!(
-            "Minting {} nanocoins ({} MKOIN) to {}",
-            amount,
-            amount as f64 / 1_000_000_000.0,
-            recipient
-        );
+    /// Hex hash of the external message
+    pub async fn mint_mkoin(&self, recipient: &str, amount: TokenAmount) -> Result<String> {
+        info!("Minting {} MKOIN to {}", amount, recipient);
 
         // Validate inputs
-        if amount == 0 {
+        if amount.is_zero() {
             return Err(anyhow::anyhow!("Amount must be greater than 0"));
         }
 
-        // 1. Fetch current seqno from network to ensure transaction is accepted
-        let wallet_address = {
-            let wallet = self.admin_wallet.lock().unwrap();
-            wallet.address.clone()
-        };
-
-        let seqno = self.client.get_wallet_seqno(&wallet_address).await?;
-        info!("Current admin wallet seqno: {}", seqno);
-
-        // 2. Build Mint message body
         let mut body_builder = CellBuilder::new();
         body_builder.store_u32(32, MINT_OPCODE)?;
-        body_builder.store_coins(&BigUint::from(amount))?;
+        body_builder.store_coins(&BigUint::from(amount.nano()))?;
         store_ton_address(&mut body_builder, recipient)?;
         let body = body_builder.build()?;
 
-        info!("Mint message body created");
+        self.send_message(
+            &self.get_admin_address(),
+            &get_mkoin_address(),
+            50_000_000, // 0.05 TON
+            body,
+        )
+        .await
+    }
 
-        // 3. Create, sign, and build external message
-        // We lock the wallet to update seqno and sign
-        // Note: In a high-concurrency env, we might need a better lock or queue,
-        // but for admin tool this is fine.
-        let (boc_base64, calculated_hash) = {
-            let mut wallet = self.admin_wallet.lock().unwrap();
-            wallet.seqno = seqno;
+    /// Get MKOIN balance for an address
+    ///
+    /// # Arguments
+    /// * `owner` - TON address to check balance for
+    ///
+    /// # Returns
+    /// Balance held in the owner's MKOIN jetton wallet
+    pub async fn get_balance(&self, owner: &str) -> Result<TokenAmount> {
+        info!("Fetching MKOIN balance for {}", owner);
 
-            let message = wallet.create_external_message(
-                &get_mkoin_address(),
-                50_000_000, // 0.05 TON
-                Arc::new(body),
-            )?;
+        // Step 1: Get wallet address for owner
+        let wallet_address = self.get_wallet_address(owner).await?;
 
-            // Calculate message hash
-            let msg_hash = message.cell_hash();
-            let calculated_hash = msg_hash.to_string();
+        info!("MKOIN wallet address: {}", wallet_address);
 
-            // Serialize
-            let boc = BagOfCells::from_root((*message).clone());
-            let serialized = boc.serialize(true)?;
-            let boc_base64 = base64::engine::general_purpose::STANDARD.encode(&serialized);
+        // Step 2: Call get_wallet_data on the wallet
+        let balance = jetton::get_jetton_wallet_balance(&self.client, &wallet_address).await?;
 
-            (boc_base64, calculated_hash)
-        };
+        info!("Balance: {} MKOIN", balance);
+        Ok(balance)
+    }
 
-        info!("Generated Message Hash: {}", calculated_hash);
-        info!("Sending mint transaction...");
+    /// Get jetton wallet address for an owner
+    ///
+    /// Calls get_wallet_address(owner) on MKOIN master contract
+    async fn get_wallet_address(&self, owner: &str) -> Result<String> {
+        jetton::get_jetton_wallet_address(&self.client, &get_mkoin_address(), owner).await
+    }
 
-        // 4. Send transaction
-        let _result = self.client.send_boc(&boc_base64).await?;
+    /// Transfer MKOIN from the admin wallet to `recipient`
+    ///
+    /// See [`transfer_jetton`](Self::transfer_jetton).
+    pub async fn transfer_mkoin(
+        &self,
+        recipient: &str,
+        amount: TokenAmount,
+        query_id: u64,
+        comment: Option<&str>,
+    ) -> Result<String> {
+        self.transfer_jetton(&get_mkoin_address(), recipient, amount, query_id, comment)
+            .await
+    }
 
-        info!("Local message hash: {}", calculated_hash);
-        info!("Waiting for transaction confirmation...");
+    /// Transfer jettons of `jetton_master` held by the admin wallet to `recipient`
+    ///
+    /// See [`transfer_jetton_from`](Self::transfer_jetton_from).
+    pub async fn transfer_jetton(
+        &self,
+        jetton_master: &str,
+        recipient: &str,
+        amount: TokenAmount,
+        query_id: u64,
+        comment: Option<&str>,
+    ) -> Result<String> {
+        self.transfer_jetton_from(&self.get_admin_address(), jetton_master, recipient, amount, query_id, comment)
+            .await
+    }
 
-        // 5. Verification Loop
-        // Poll for up to 60 seconds (30 attempts * 2s)
-        for i in 0..30 {
-            tokio::time::sleep(Duration::from_secs(2)).await;
+    /// Transfer jettons of `jetton_master` held by the platform wallet `from`
+    /// to `recipient`
+    ///
+    /// Sends a TEP-74 transfer to `from`'s jetton wallet, with excess TON
+    /// returned to `from`. Delivery is asynchronous; the excesses or bounce
+    /// carrying `query_id` in `from`'s history tell the outcome. See
+    /// [`send_message`](Self::send_message) for errors.
+    ///
+    /// # Returns
+    /// Hex hash of the external message
+    pub async fn transfer_jetton_from(
+        &self,
+        from: &str,
+        jetton_master: &str,
+        recipient: &str,
+        amount: TokenAmount,
+        query_id: u64,
+        comment: Option<&str>,
+    ) -> Result<String> {
+        if amount.is_zero() {
+            return Err(anyhow::anyhow!("Amount must be greater than 0"));
+        }
 
-            let txs_result = self.client.get_transactions(&wallet_address, 20).await;
-
-            match txs_result {
-                Ok(txs_value) => {
-                    if let Some(txs) = txs_value.as_array() {
-                        for tx in txs {
-                            // Check in_msg
-                            if let Some(in_msg) = tx.get("in_msg") {
-                                // The API might return body_hash field or we might need to look at msg body
-                                // Standard toncenter v2 likely returns body_hash or hash of the message itself
-
-                                // We are looking for the HASH of the inbound message, which matches our calculated_hash
-                                // Note: We need to match base64 encoded hash
-
-                                // Try "body_hash" first, then generic "hash"
-                                let _msg_recv_hash = in_msg
-                                    .get("body_hash")
-                                    .and_then(|h| h.as_str())
-                                    .or_else(|| in_msg.get("hash").and_then(|h| h.as_str())) // 'hash' is usually msg ID
-                                    .unwrap_or("");
-
-                                // Our calculated_hash is HEX. The API typically returns BASE64 for hashes.
-                                // We need to handle this comparison carefuly.
-
-                                // Let's simplify: Convert our HEX hash to Base64 to compare with API
-                                let calc_bytes = hex::decode(&calculated_hash).unwrap_or_default();
-                                let calc_b64 =
-                                    base64::engine::general_purpose::STANDARD.encode(&calc_bytes);
-
-                                // Check if this message corresponds to our send
-                                // 1. Compare hashes (either direct or body)
-                                // Note: For external messages, "hash" of in_msg is usually the message hash we calculated.
-                                let api_hash_b64 = in_msg
-                                    .get("body_hash")
-                                    .and_then(|h| h.as_str())
-                                    .unwrap_or("");
-
-                                // Also check `msg_id` or `hash` field if body_hash doesn't match
-                                // toncenter v2 structure: transaction -> in_msg -> body_hash
-
-                                if api_hash_b64 == calc_b64 {
-                                    // MATCH FOUND!
-                                    let real_tx_hash = tx
-                                        .get("transaction_id")
-                                        .and_then(|id| id.get("hash"))
-                                        .and_then(|h| h.as_str())
-                                        .unwrap_or(&calculated_hash)
-                                        .to_string();
-
-                                    info!(
-                                        "Transaction confirmed on-chain! Real TX Hash: {}",
-                                        real_tx_hash
-                                    );
-
-                                    // Optionally check compute phase success
-                                    // if let Some(compute) = tx.get("compute_phase") { ... }
-
-                                    return Ok(real_tx_hash);
-                                }
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to fetch transactions while polling: {}", e);
-                }
-            }
+        let jetton_wallet = self.jetton_wallet_of(from, jetton_master).await?;
 
-            if i % 5 == 0 {
-                info!("Still waiting for confirmation... ({}/30)", i);
-            }
-        }
+        info!(
+            "Transferring {} of jetton {} from {} to {} (query {})",
+            amount, jetton_master, from, recipient, query_id
+        );
 
-        // If we timeout, we return Error.
-        // The user can try again or check explorer manually.
-        Err(anyhow::anyhow!(
-            "Transaction sent but not confirmed within 60 seconds. It may still process. Local Msg Hash: {}",
-            calculated_hash
-        ))
+        let body = jetton::build_transfer_body(&JettonTransfer {
+            query_id,
+            amount,
+            destination: recipient,
+            response_destination: from,
+            forward_ton_amount: TRANSFER_FORWARD_TON,
+            comment,
+        })?;
+
+        self.send_message(from, &jetton_wallet, TRANSFER_ATTACHED_TON, body)
+            .await
     }
 
-    /// Get MKOIN balance for an address
+    /// Burn MKOIN held by the admin wallet
     ///
-    /// # Arguments
-    /// * `owner` - TON address to check balance for
+    /// Sends a TEP-74 burn to the admin's MKOIN wallet; the master returns
+    /// the excess TON to the admin wallet with `query_id`. See
+    /// [`send_message`](Self::send_message) for errors.
     ///
     /// # Returns
-    /// Balance in nanocoins
-    pub async fn get_balance(&self, owner: &str) -> Result<u128> {
-        info!("Fetching MKOIN balance for {}", owner);
+    /// Hex hash of the external message
+    pub async fn burn_mkoin(&self, amount: TokenAmount, query_id: u64) -> Result<String> {
+        if amount.is_zero() {
+            return Err(anyhow::anyhow!("Amount must be greater than 0"));
+        }
 
-        // Step 1: Get wallet address for owner
-        let wallet_address = self.get_wallet_address(owner).await?;
+        let jetton_wallet = self.admin_jetton_wallet(&get_mkoin_address()).await?;
+        info!("Burning {} MKOIN (query {})", amount, query_id);
 
-        info!("MKOIN wallet address: {}", wallet_address);
+        let admin_address = self.get_admin_address();
+        let body = jetton::build_burn_body(query_id, amount, &admin_address)?;
+        self.send_message(&admin_address, &jetton_wallet, TRANSFER_ATTACHED_TON, body)
+            .await
+    }
 
-        // Step 2: Call get_wallet_data on the wallet
-        let result = self
-            .client
-            .run_get_method(&wallet_address, "get_wallet_data", vec![])
-            .await?;
+    /// The admin wallet's jetton wallet for `jetton_master`
+    pub async fn admin_jetton_wallet(&self, jetton_master: &str) -> Result<String> {
+        self.jetton_wallet_of(&self.get_admin_address(), jetton_master).await
+    }
 
-        // Parse result: [balance, owner, master, code]
-        if let Some(stack) = result.get("stack").and_then(|s| s.as_array()) {
-            if let Some(balance_item) = stack.first() {
-                // Parse balance from stack item
-                if let Some(val_arr) = balance_item.as_array() {
-                    if val_arr.len() == 2 && val_arr[0] == "num" {
-                        let hex_val = val_arr[1].as_str().unwrap_or("0x0");
-                        let clean_hex = hex_val.trim_start_matches("0x");
-                        let balance = u128::from_str_radix(clean_hex, 16).unwrap_or(0);
-
-                        info!(
-                            "Balance: {} nanocoins ({} MKOIN)",
-                            balance,
-                            balance as f64 / 1_000_000_000.0
-                        );
-                        return Ok(balance);
-                    }
-                }
-            }
-        }
+    /// `owner`'s jetton wallet for `jetton_master`
+    pub async fn jetton_wallet_of(&self, owner: &str, jetton_master: &str) -> Result<String> {
+        jetton::get_jetton_wallet_address(&self.client, jetton_master, owner).await
+    }
 
-        Ok(0)
+    /// Recent transactions of the admin wallet, newest first
+    pub async fn get_admin_transactions(&self, limit: u32) -> Result<serde_json::Value> {
+        self.client
+            .get_transactions(&self.get_admin_address(), limit)
+            .await
     }
 
-    /// Get jetton wallet address for an owner
+    /// Transactions of a wallet made since `since`, newest first
+    pub async fn wallet_transactions_since(&self, address: &str, since: DateTime<Utc>) -> Result<History> {
+        self.client
+            .get_transactions_since(address, since, MESSAGE_SCAN_PAGES)
+            .await
+    }
+
+    /// Whether the platform signs for `address` (the admin wallet or a
+    /// configured treasury)
+    pub fn controls(&self, address: &str) -> bool {
+        self.signer(address).is_ok()
+    }
+
+    fn signer(&self, address: &str) -> Result<&Signer> {
+        let raw = to_raw_address(address)?;
+        if to_raw_address(&self.admin.address()).ok().as_deref() == Some(raw.as_str()) {
+            return Ok(&self.admin);
+        }
+        self.treasuries
+            .get(&raw)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a platform wallet", address))
+    }
+
+    /// Sign and send an external message from the platform wallet `from`
     ///
-    /// Calls get_wallet_address(owner) on MKOIN master contract
-    async fn get_wallet_address(&self, owner: &str) -> Result<String> {
-        // Build address cell for parameter
-        let addr_json = serde_json::json!({
-            "type": "tvm.Slice",
-            "bytes": base64::engine::general_purpose::STANDARD.encode(owner)
-        });
+    /// An error before the message was handed to the network means nothing
+    /// was broadcast. A failed `sendBoc` is reported as
+    /// [`BroadcastUncertain`]: the network may have accepted the message
+    /// anyway.
+    ///
+    /// # Returns
+    /// Hex hash of the external message
+    pub async fn send_message(
+        &self,
+        from: &str,
+        destination: &str,
+        value: u64,
+        body: tonlib_core::cell::Cell,
+    ) -> Result<String> {
+        let signer = self.signer(from)?;
+        let _guard = signer.send_lock.lock().await;
+
+        let wallet_address = signer.address();
+        let seqno = self.client.get_wallet_seqno(&wallet_address).await?;
 
-        // Call get_wallet_address(owner) on MKOIN master
-        let result = self
-            .client
-            .run_get_method(&get_mkoin_address(), "get_wallet_address", vec![addr_json])
-            .await?;
+        let (boc_base64, message_hash) = {
+            let mut wallet = signer.wallet.lock().unwrap();
+            wallet.seqno = seqno;
 
-        // Parse returned address from stack
-        if let Some(stack) = result.get("stack").and_then(|s| s.as_array()) {
-            if let Some(addr_item) = stack.first() {
-                // Parse address from stack item
-                if let Some(val_arr) = addr_item.as_array() {
-                    if val_arr.len() == 2 {
-                        let addr_bytes = val_arr[1].as_str().unwrap_or("");
-                        // TODO: Properly decode address bytes
-                        return Ok(addr_bytes.to_string());
-                    }
-                }
+            let message = wallet.create_external_message(destination, value, Arc::new(body))?;
+            let message_hash = message.cell_hash().to_string();
+            let serialized = BagOfCells::from_root((*message).clone()).serialize(true)?;
+
+            (
+                base64::engine::general_purpose::STANDARD.encode(&serialized),
+                message_hash,
+            )
+        };
+
+        if let Err(e) = self.client.send_boc(&boc_base64).await {
+            return Err(BroadcastUncertain {
+                message_hash,
+                reason: e.to_string(),
             }
+            .into());
         }
+        info!("Sent message {} from {} (seqno {})", message_hash, wallet_address, seqno);
 
-        Err(anyhow::anyhow!("No wallet address returned"))
+        // Hold the lock until the seqno moves so the next send signs a fresh one
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            match self.client.get_wallet_seqno(&wallet_address).await {
+                Ok(current) if current > seqno => return Ok(message_hash),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to fetch seqno while waiting: {}", e),
+            }
+        }
+
+        // Already broadcast, so report it as sent and let confirmation decide
+        tracing::warn!(
+            "Message {} sent but seqno did not advance within 60 seconds",
+            message_hash
+        );
+        Ok(message_hash)
+    }
+
+    /// Settle an external message `from` sent around `sent_at`
+    ///
+    /// The wallet's history is searched from shortly before `sent_at`, so
+    /// messages are found however many transactions followed them. A
+    /// message not found once it expired can never land.
+    pub async fn message_status(
+        &self,
+        from: &str,
+        message_hash: &str,
+        sent_at: DateTime<Utc>,
+    ) -> Result<MessageStatus> {
+        let since = sent_at - chrono::Duration::seconds(MESSAGE_LOOKBACK_SECS);
+        let history = self.wallet_transactions_since(from, since).await?;
+        Ok(message_outcome(&history, message_hash, sent_at, Utc::now()))
+    }
+
+    /// [`message_status`](Self::message_status) of an admin wallet message
+    pub async fn admin_message_status(&self, message_hash: &str, sent_at: DateTime<Utc>) -> Result<MessageStatus> {
+        self.message_status(&self.get_admin_address(), message_hash, sent_at)
+            .await
     }
 
     /// Get total supply of MKOIN
-    pub async fn get_total_supply(&self) -> Result<u128> {
+    pub async fn get_total_supply(&self) -> Result<TokenAmount> {
         // Check cache first (valid for 30 seconds)
         {
             let cache = self.supply_cache.lock().unwrap();
@@ -351,37 +498,54 @@ impl MkoinService {
         // Parse result: [total_supply, mintable, admin_address, content, jetton_wallet_code]
         if let Some(stack) = result.get("stack").and_then(|s| s.as_array()) {
             info!("get_jetton_data stack: {:?}", stack);
-            if let Some(supply_item) = stack.first() {
-                // Parse total_supply from stack item
-                if let Some(val_arr) = supply_item.as_array() {
-                    if val_arr.len() == 2 && val_arr[0] == "num" {
-                        let hex_val = val_arr[1].as_str().unwrap_or("0x0");
-                        let clean_hex = hex_val.trim_start_matches("0x");
-                        let total_supply = u128::from_str_radix(clean_hex, 16).unwrap_or(0);
-
-                        info!(
-                            "Total supply: {} nanocoins ({} MKOIN)",
-                            total_supply,
-                            total_supply as f64 / 1_000_000_000.0
-                        );
-
-                        // Update cache
-                        let mut cache = self.supply_cache.lock().unwrap();
-                        *cache = Some((total_supply, Instant::now()));
-
-                        return Ok(total_supply);
-                    }
-                }
+            if let Some(nano) = stack.first().and_then(jetton::parse_stack_num) {
+                let total_supply = TokenAmount::from_nano(nano);
+                info!("Total supply: {} MKOIN", total_supply);
+
+                // Update cache
+                let mut cache = self.supply_cache.lock().unwrap();
+                *cache = Some((total_supply, Instant::now()));
+
+                return Ok(total_supply);
             }
         }
 
-        Ok(0)
+        Ok(TokenAmount::ZERO)
     }
 
     /// Get admin wallet address (for verification)
     pub fn get_admin_address(&self) -> String {
-        let wallet = self.admin_wallet.lock().unwrap();
-        wallet.address.clone()
+        self.admin.address()
+    }
+}
+
+/// Status of the message with `message_hash` (hex), sent at `sent_at`, in a
+/// wallet's history since shortly before then
+pub fn message_outcome(history: &History, message_hash: &str, sent_at: DateTime<Utc>, now: DateTime<Utc>) -> MessageStatus {
+    let hash_b64 = hex::decode(message_hash)
+        .map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes))
+        .unwrap_or_default();
+    let landed = history.txs.iter().find(|tx| {
+        tx.get("in_msg")
+            .and_then(|m| m.get("hash"))
+            .and_then(|h| h.as_str())
+            == Some(hash_b64.as_str())
+    });
+    if let Some(tx_hash) = landed
+        .and_then(|tx| tx.get("transaction_id"))
+        .and_then(|id| id.get("hash"))
+        .and_then(|h| h.as_str())
+    {
+        return MessageStatus::Landed {
+            tx_hash: tx_hash.to_string(),
+        };
+    }
+
+    let expired_at = sent_at + chrono::Duration::seconds(MESSAGE_TTL_SECS as i64 + MESSAGE_EXPIRY_GRACE_SECS);
+    if history.complete && now > expired_at {
+        MessageStatus::Expired
+    } else {
+        MessageStatus::Pending
     }
 }
 
@@ -389,6 +553,49 @@ impl MkoinService {
 mod tests {
     use super::*;
 
+    fn landed(in_msg_hash: &str, tx_hash: &str) -> serde_json::Value {
+        serde_json::json!({
+            "transaction_id": { "lt": "1", "hash": tx_hash },
+            "in_msg": { "hash": in_msg_hash },
+        })
+    }
+
+    #[test]
+    fn test_message_outcome() {
+        let message = "ab".repeat(32);
+        let message_b64 = base64::engine::general_purpose::STANDARD.encode(hex::decode(&message).unwrap());
+        let sent_at = DateTime::parse_from_rfc3339("2026-10-19T10:00:00Z").unwrap().with_timezone(&Utc);
+        let soon = sent_at + chrono::Duration::seconds(60);
+        let later = sent_at + chrono::Duration::seconds(MESSAGE_TTL_SECS as i64 + MESSAGE_EXPIRY_GRACE_SECS + 1);
+
+        let history = History {
+            txs: vec![landed("other", "tx1"), landed(&message_b64, "tx2")],
+            complete: true,
+        };
+        assert_eq!(
+            message_outcome(&history, &message, sent_at, later),
+            MessageStatus::Landed { tx_hash: "tx2".to_string() }
+        );
+
+        // Not seen: pending until it expired, and only a full search proves it never landed
+        let empty = History { txs: vec![landed("other", "tx1")], complete: true };
+        assert_eq!(message_outcome(&empty, &message, sent_at, soon), MessageStatus::Pending);
+        assert_eq!(message_outcome(&empty, &message, sent_at, later), MessageStatus::Expired);
+        let truncated = History { txs: Vec::new(), complete: false };
+        assert_eq!(message_outcome(&truncated, &message, sent_at, later), MessageStatus::Pending);
+    }
+
+    #[test]
+    fn test_uncertain_message() {
+        let uncertain: anyhow::Error = BroadcastUncertain {
+            message_hash: "abc".to_string(),
+            reason: "timeout".to_string(),
+        }
+        .into();
+        assert_eq!(uncertain_message(&uncertain), Some("abc"));
+        assert_eq!(uncertain_message(&anyhow::anyhow!("Seqno lookup failed")), None);
+    }
+
     #[tokio::test]
     async fn test_get_total_supply() {
         // This test requires testnet access
@@ -8,3 +8,10 @@ pub mod cell_utils;
 pub mod mkoin_service;
 pub mod factory_service;
 pub mod address_utils;
+pub mod jetton;
+pub mod delivery;
+pub mod escrow;
+pub mod purchase_verifier;
+pub mod redemption;
+pub mod snapshot;
+pub mod merkle;
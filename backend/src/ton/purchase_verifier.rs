@@ -0,0 +1,520 @@
+//! On-chain verification of MKOIN payments for campaign purchases
+//!
+//! A buyer pays by sending a TEP-74 jetton `transfer` of MKOIN to the
+//! treasury, built from a purchase quote: the quote fixes the amount, the
+//! `query_id` and a `purchase:<campaign_id>:<quote_id>` text comment carried
+//! as forward payload. A purchase is verified in three steps:
+//!
+//! 1. the purchase must match the quote it was created from;
+//! 2. the buyer's wallet transaction referenced by `tx_hash` must send exactly
+//!    the quoted transfer to the buyer's own MKOIN jetton wallet, before the
+//!    quote expired;
+//! 3. the treasury must have received the matching `transfer_notification`
+//!    from its MKOIN jetton wallet, which proves the jettons actually arrived.
+
+use crate::amount::TokenAmount;
+use crate::db::Purchase;
+use crate::db::quotes::{PurchaseQuote, QUOTE_TTL_SECS};
+use crate::ton::address_utils::to_raw_address;
+use crate::ton::client::{self, Client};
+use crate::ton::jetton::{self, JettonTransfer};
+use crate::ton::mkoin_service::{TRANSFER_FORWARD_TON, get_mkoin_address};
+use anyhow::Result;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use tonlib_core::cell::Cell;
+use tonlib_core::message::{JettonTransferMessage, JettonTransferNotificationMessage, TonMessage};
+use tracing::info;
+use uuid::Uuid;
+
+/// Prefix of the text comment that ties a payment to a campaign and quote
+pub const PURCHASE_COMMENT_PREFIX: &str = "purchase:";
+
+// Pages of the buyer's and the treasury's history searched for a payment
+const BUYER_TX_PAGES: usize = 10;
+const TREASURY_TX_PAGES: usize = 40;
+
+/// Forward payload comment expected for a purchase quoted as `quote_id`
+pub fn purchase_comment(campaign_id: Uuid, quote_id: Uuid) -> String {
+    format!("{}{}:{}", PURCHASE_COMMENT_PREFIX, campaign_id, quote_id)
+}
+
+/// `query_id` of the jetton transfer paying for a quote
+pub fn quote_query_id(quote_id: Uuid) -> u64 {
+    quote_id.as_u64_pair().0
+}
+
+/// Body of the jetton `transfer` a buyer sends to their MKOIN wallet to pay
+/// for `quote`; excess TON is returned to the buyer
+pub fn quote_transfer_body(quote: &PurchaseQuote, treasury: &str) -> Result<Cell> {
+    jetton::build_transfer_body(&JettonTransfer {
+        query_id: quote_query_id(quote.id),
+        amount: quote.mkoin_amount,
+        destination: treasury,
+        response_destination: &quote.user_address,
+        forward_ton_amount: TRANSFER_FORWARD_TON,
+        comment: Some(&purchase_comment(quote.campaign_id, quote.id)),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// Payment arrived; `tx_hash` is the treasury transaction that received it
+    Confirmed { tx_hash: String },
+    /// Not on chain yet, or still in flight between jetton wallets
+    Pending,
+    /// On-chain data contradicts the purchase
+    Rejected(String),
+}
+
+/// What a purchase claims was paid
+#[derive(Debug, Clone)]
+pub struct ExpectedPayment<'a> {
+    /// Buyer wallet, raw form
+    pub buyer: &'a str,
+    /// Treasury wallet, raw form
+    pub treasury: &'a str,
+    pub amount: TokenAmount,
+    pub campaign_id: Uuid,
+    pub quote_id: Uuid,
+}
+
+pub struct PurchaseVerifier {
+    client: Client,
+    mkoin_master: String,
+}
+
+impl PurchaseVerifier {
+    pub fn new() -> Self {
+        let api_key = std::env::var("TON_API_KEY").ok();
+
+        Self {
+            client: Client::new(&format!("{}/jsonRPC", client::api_url()), api_key),
+            mkoin_master: get_mkoin_address(),
+        }
+    }
+
+    /// MKOIN jetton wallet of `owner`
+    pub async fn mkoin_wallet(&self, owner: &str) -> Result<String> {
+        self.jetton_wallet(&self.mkoin_master, owner).await
+    }
+
+    /// Jetton wallet of `owner` for the jetton `master`
+    pub async fn jetton_wallet(&self, master: &str, owner: &str) -> Result<String> {
+        jetton::get_jetton_wallet_address(&self.client, master, owner).await
+    }
+
+    /// Check the payment referenced by a purchase against its quote and chain data
+    pub async fn verify(
+        &self,
+        purchase: &Purchase,
+        quote: Option<&PurchaseQuote>,
+        treasury: &str,
+    ) -> Result<Verification> {
+        let quote = match check_quote(purchase, quote) {
+            Ok(quote) => quote,
+            Err(reason) => return Ok(Verification::Rejected(reason)),
+        };
+        let Some(tx_hash) = purchase.tx_hash.as_deref() else {
+            return Ok(Verification::Rejected("Purchase has no transaction hash".to_string()));
+        };
+        let Ok(buyer) = to_raw_address(&purchase.user_address) else {
+            return Ok(Verification::Rejected(format!(
+                "Invalid buyer address {}",
+                purchase.user_address
+            )));
+        };
+        let treasury = to_raw_address(treasury)?;
+        let expected = ExpectedPayment {
+            buyer: &buyer,
+            treasury: &treasury,
+            amount: purchase.mkoin_paid,
+            campaign_id: purchase.campaign_id,
+            quote_id: quote.id,
+        };
+
+        // 2. The buyer's transaction and the transfer it requested, sent
+        // while the quote was live
+        let quoted_at = quote.expires_at - chrono::Duration::seconds(QUOTE_TTL_SECS);
+        let buyer_txs = self
+            .client
+            .get_transactions_since(&buyer, quoted_at, BUYER_TX_PAGES)
+            .await?;
+        let Some(buyer_tx) = find_transaction(&buyer_txs.txs, tx_hash) else {
+            return Ok(Verification::Pending);
+        };
+        if let Err(reason) = check_sent_before(buyer_tx, quote.expires_at) {
+            return Ok(Verification::Rejected(reason));
+        }
+
+        let buyer_jetton_wallet = self.mkoin_wallet(&buyer).await?;
+        let transfer = match find_transfer(buyer_tx, &buyer_jetton_wallet) {
+            Some(transfer) => transfer,
+            None => {
+                return Ok(Verification::Rejected(
+                    "Transaction does not transfer MKOIN".to_string(),
+                ));
+            }
+        };
+        if let Err(reason) = check_transfer(&transfer, &expected) {
+            return Ok(Verification::Rejected(reason));
+        }
+
+        // 3. The notification proving the jettons reached the treasury, which
+        // can only follow the buyer's transaction
+        let sent_at = buyer_tx
+            .get("utime")
+            .and_then(|t| t.as_i64())
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+            .unwrap_or(quoted_at);
+        let treasury_jetton_wallet = self.mkoin_wallet(&treasury).await?;
+        let treasury_txs = self
+            .client
+            .get_transactions_since(&treasury, sent_at, TREASURY_TX_PAGES)
+            .await?;
+
+        match find_notification(
+            &treasury_txs.txs,
+            &treasury_jetton_wallet,
+            transfer.query_id,
+            &expected,
+        ) {
+            Some(tx_hash) => {
+                info!("Purchase {} paid in treasury tx {}", purchase.id, tx_hash);
+                Ok(Verification::Confirmed { tx_hash })
+            }
+            None => Ok(Verification::Pending),
+        }
+    }
+}
+
+impl Default for PurchaseVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decode a transaction or message hash given as hex or base64
+fn decode_hash(hash: &str) -> Option<Vec<u8>> {
+    let hash = hash.trim();
+    if hash.len() == 64
+        && let Ok(bytes) = hex::decode(hash)
+    {
+        return Some(bytes);
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(hash)
+        .or_else(|_| base64::engine::general_purpose::URL_SAFE.decode(hash))
+        .ok()
+        .filter(|bytes| bytes.len() == 32)
+}
+
+fn tx_hash(tx: &serde_json::Value) -> Option<&str> {
+    tx.get("transaction_id")?.get("hash")?.as_str()
+}
+
+/// Find the transaction with hash `wanted`, or the one that processed the
+/// external message with that hash (what TON Connect wallets report)
+pub fn find_transaction<'a>(
+    txs: &'a [serde_json::Value],
+    wanted: &str,
+) -> Option<&'a serde_json::Value> {
+    let wanted = decode_hash(wanted)?;
+    let matches = |hash: Option<&str>| hash.and_then(decode_hash).as_ref() == Some(&wanted);
+
+    txs.iter().find(|tx| {
+        let in_msg_hash = tx.get("in_msg").and_then(|m| m.get("hash")).and_then(|h| h.as_str());
+        matches(tx_hash(tx)) || matches(in_msg_hash)
+    })
+}
+
+fn same_address(a: &str, b: &str) -> bool {
+    match (to_raw_address(a), to_raw_address(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The jetton transfer a buyer transaction sent to the buyer's jetton wallet
+pub fn find_transfer(tx: &serde_json::Value, jetton_wallet: &str) -> Option<JettonTransferMessage> {
+    tx.get("out_msgs")?
+        .as_array()?
+        .iter()
+        .filter(|msg| {
+            msg.get("destination")
+                .and_then(|d| d.as_str())
+                .is_some_and(|d| same_address(d, jetton_wallet))
+        })
+        .find_map(|msg| JettonTransferMessage::parse(&jetton::message_body(msg)?).ok())
+}
+
+/// The quote a purchase was created from, if its terms match the purchase
+pub fn check_quote<'a>(
+    purchase: &Purchase,
+    quote: Option<&'a PurchaseQuote>,
+) -> Result<&'a PurchaseQuote, String> {
+    let quote = quote
+        .filter(|q| purchase.quote_id == Some(q.id))
+        .ok_or_else(|| "Purchase was not made against a quote".to_string())?;
+
+    if quote.campaign_id != purchase.campaign_id
+        || quote.user_address != purchase.user_address
+        || quote.mkoin_amount != purchase.mkoin_paid
+        || quote.tokens != purchase.tokens_received
+    {
+        return Err(format!("Purchase does not match quote {}", quote.id));
+    }
+    Ok(quote)
+}
+
+/// The buyer must have sent the payment while the quote was live
+pub fn check_sent_before(tx: &serde_json::Value, expires_at: DateTime<Utc>) -> Result<(), String> {
+    let utime = tx
+        .get("utime")
+        .and_then(|t| t.as_i64())
+        .ok_or_else(|| "Transaction has no timestamp".to_string())?;
+    if utime > expires_at.timestamp() {
+        return Err(format!("Payment was sent after the quote expired at {}", expires_at));
+    }
+    Ok(())
+}
+
+/// Check a requested transfer against what the purchase claims
+pub fn check_transfer(
+    transfer: &JettonTransferMessage,
+    expected: &ExpectedPayment,
+) -> Result<(), String> {
+    if transfer.destination.to_hex() != expected.treasury {
+        return Err(format!(
+            "MKOIN was sent to {} instead of the treasury",
+            transfer.destination.to_hex()
+        ));
+    }
+
+    let amount = TokenAmount::parse_nano(&transfer.amount.to_string()).map_err(|e| e.to_string())?;
+    if amount != expected.amount {
+        return Err(format!(
+            "Transferred {} MKOIN but the purchase claims {}",
+            amount, expected.amount
+        ));
+    }
+
+    if transfer.query_id != quote_query_id(expected.quote_id) {
+        return Err(format!("Transfer query_id {} does not match the quote", transfer.query_id));
+    }
+
+    let comment = jetton::parse_text_comment(&transfer.forward_payload);
+    if comment.as_deref() != Some(purchase_comment(expected.campaign_id, expected.quote_id).as_str()) {
+        return Err("Forward payload does not reference the campaign and quote".to_string());
+    }
+
+    Ok(())
+}
+
+/// The treasury transaction that received the matching transfer notification
+pub fn find_notification(
+    txs: &[serde_json::Value],
+    treasury_jetton_wallet: &str,
+    query_id: u64,
+    expected: &ExpectedPayment,
+) -> Option<String> {
+    find_receipt(txs, treasury_jetton_wallet, query_id, expected.buyer, expected.amount)
+}
+
+/// The transaction in which `jetton_wallet` notified its owner of `amount`
+/// jettons sent by `sender` (raw form) with `query_id`
+pub fn find_receipt(
+    txs: &[serde_json::Value],
+    jetton_wallet: &str,
+    query_id: u64,
+    sender: &str,
+    amount: TokenAmount,
+) -> Option<String> {
+    txs.iter()
+        .find(|tx| {
+            let Some(in_msg) = tx.get("in_msg") else {
+                return false;
+            };
+            // Only the owner's own jetton wallet can vouch for a receipt
+            let from_wallet = in_msg
+                .get("source")
+                .and_then(|s| s.as_str())
+                .is_some_and(|s| same_address(s, jetton_wallet));
+            if !from_wallet {
+                return false;
+            }
+
+            jetton::message_body(in_msg)
+                .and_then(|body| JettonTransferNotificationMessage::parse(&body).ok())
+                .is_some_and(|n| {
+                    n.query_id == query_id
+                        && n.sender.to_hex() == sender
+                        && n.amount.to_string() == amount.nano().to_string()
+                })
+        })
+        .and_then(tx_hash)
+        .map(|h| h.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use tonlib_core::TonAddress;
+    use tonlib_core::cell::BagOfCells;
+
+    const BUYER: &str = "0:0d204ad68e3e94e0536049f107c11eb1f9b9242425759f9c353886fe3dfe9e59";
+    const TREASURY: &str = "0:58f8e5b06a6da7ec33b6e7157b8ee5164bd315f718c9d5bc0bd5d2129180cd70";
+    const TREASURY_WALLET: &str = "EQATDLvt8bY8BGb-DGBZxwe6EZla3Rcij41fqv_OFlLXvgpV";
+
+    fn boc_b64(cell: tonlib_core::cell::Cell) -> String {
+        let boc = BagOfCells::from_root(cell).serialize(true).unwrap();
+        base64::engine::general_purpose::STANDARD.encode(boc)
+    }
+
+    fn expected(campaign_id: Uuid, quote_id: Uuid) -> ExpectedPayment<'static> {
+        ExpectedPayment {
+            buyer: BUYER,
+            treasury: TREASURY,
+            amount: TokenAmount::parse_decimal("25").unwrap(),
+            campaign_id,
+            quote_id,
+        }
+    }
+
+    fn transfer(
+        campaign_id: Uuid,
+        quote_id: Uuid,
+        query_id: u64,
+        destination: &str,
+        amount: &str,
+    ) -> JettonTransferMessage {
+        let body = jetton::build_transfer_body(&JettonTransfer {
+            query_id,
+            amount: TokenAmount::parse_decimal(amount).unwrap(),
+            destination,
+            response_destination: BUYER,
+            forward_ton_amount: 1,
+            comment: Some(&purchase_comment(campaign_id, quote_id)),
+        })
+        .unwrap();
+        JettonTransferMessage::parse(&body).unwrap()
+    }
+
+    #[test]
+    fn test_check_transfer() {
+        let (campaign_id, quote_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let expected = expected(campaign_id, quote_id);
+        let query_id = quote_query_id(quote_id);
+
+        assert_eq!(
+            check_transfer(&transfer(campaign_id, quote_id, query_id, TREASURY, "25"), &expected),
+            Ok(())
+        );
+        assert!(check_transfer(&transfer(campaign_id, quote_id, query_id, BUYER, "25"), &expected).is_err());
+        assert!(
+            check_transfer(&transfer(campaign_id, quote_id, query_id, TREASURY, "24.999999999"), &expected)
+                .is_err()
+        );
+        assert!(check_transfer(&transfer(Uuid::new_v4(), quote_id, query_id, TREASURY, "25"), &expected).is_err());
+        // Paying for another quote does not count
+        assert!(check_transfer(&transfer(campaign_id, Uuid::new_v4(), query_id, TREASURY, "25"), &expected).is_err());
+        assert!(check_transfer(&transfer(campaign_id, quote_id, 7, TREASURY, "25"), &expected).is_err());
+    }
+
+    #[test]
+    fn test_check_quote() {
+        let quote = PurchaseQuote {
+            id: Uuid::new_v4(),
+            campaign_id: Uuid::new_v4(),
+            user_address: BUYER.to_string(),
+            mkoin_amount: TokenAmount::parse_decimal("25").unwrap(),
+            tokens: TokenAmount::parse_decimal("5").unwrap(),
+            price: TokenAmount::parse_decimal("5").unwrap(),
+            treasury_address: None,
+            expires_at: Utc::now(),
+            used_at: Some(Utc::now()),
+            created_at: None,
+        };
+        let mut purchase = Purchase {
+            id: Uuid::new_v4(),
+            user_address: BUYER.to_string(),
+            campaign_id: quote.campaign_id,
+            mkoin_paid: quote.mkoin_amount,
+            tokens_received: quote.tokens,
+            tx_hash: None,
+            status: "pending".to_string(),
+            purchased_at: Utc::now(),
+            confirmed_at: None,
+            verified_tx_hash: None,
+            failure_reason: None,
+            quote_id: Some(quote.id),
+        };
+
+        assert!(check_quote(&purchase, Some(&quote)).is_ok());
+        assert!(check_quote(&purchase, None).is_err());
+        purchase.tokens_received = TokenAmount::parse_decimal("6").unwrap();
+        assert!(check_quote(&purchase, Some(&quote)).is_err());
+        purchase.tokens_received = quote.tokens;
+        purchase.quote_id = None;
+        assert!(check_quote(&purchase, Some(&quote)).is_err());
+    }
+
+    #[test]
+    fn test_check_sent_before() {
+        let expires_at = DateTime::from_timestamp(1_000, 0).unwrap();
+        assert!(check_sent_before(&serde_json::json!({ "utime": 1_000 }), expires_at).is_ok());
+        assert!(check_sent_before(&serde_json::json!({ "utime": 1_001 }), expires_at).is_err());
+        assert!(check_sent_before(&serde_json::json!({}), expires_at).is_err());
+    }
+
+    #[test]
+    fn test_find_transaction_by_hex_or_base64() {
+        let tx_hash = [1u8; 32];
+        let msg_hash = [2u8; 32];
+        let b64 = |h: &[u8]| base64::engine::general_purpose::STANDARD.encode(h);
+        let txs = vec![serde_json::json!({
+            "transaction_id": { "lt": "1", "hash": b64(&tx_hash) },
+            "in_msg": { "hash": b64(&msg_hash) },
+        })];
+
+        assert!(find_transaction(&txs, &hex::encode(tx_hash)).is_some());
+        assert!(find_transaction(&txs, &b64(&tx_hash)).is_some());
+        assert!(find_transaction(&txs, &hex::encode(msg_hash)).is_some());
+        assert!(find_transaction(&txs, &hex::encode([3u8; 32])).is_none());
+        assert!(find_transaction(&txs, "not-a-hash").is_none());
+    }
+
+    #[test]
+    fn test_find_notification() {
+        let (campaign_id, quote_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let expected = expected(campaign_id, quote_id);
+
+        let mut notification = JettonTransferNotificationMessage::new(
+            &TonAddress::from_str(BUYER).unwrap(),
+            &BigUint::from(expected.amount.nano()),
+        );
+        notification.query_id = 7;
+        notification.with_forward_payload(Arc::new(
+            jetton::text_comment_cell(&purchase_comment(campaign_id, quote_id)).unwrap(),
+        ));
+        let body = boc_b64(notification.build().unwrap());
+
+        let tx = |source: &str, hash: &str| {
+            serde_json::json!({
+                "transaction_id": { "lt": "1", "hash": hash },
+                "in_msg": { "source": source, "msg_data": { "@type": "msg.dataRaw", "body": body } },
+            })
+        };
+        // A notification from any other contract is not proof of payment
+        let txs = vec![tx(BUYER, "forged"), tx(TREASURY_WALLET, "genuine")];
+
+        assert_eq!(
+            find_notification(&txs, TREASURY_WALLET, 7, &expected).as_deref(),
+            Some("genuine")
+        );
+        assert_eq!(find_notification(&txs, TREASURY_WALLET, 8, &expected), None);
+    }
+}
@@ -0,0 +1,72 @@
+//! MKOIN transfers backing redemption requests
+//!
+//! A redemption is backed by a TEP-74 jetton `transfer` of its MKOIN from
+//! the user to the treasury (the platform wallet), carrying the
+//! redemption's `query_id` and a `redeem:<id>` comment. It counts as
+//! received once the treasury's MKOIN wallet notified it of the exact
+//! amount from the user. After payout the MKOIN is burned from the
+//! treasury; a rejected request has it sent back instead.
+
+use crate::db::redemptions::Redemption;
+use crate::ton::address_utils::to_raw_address;
+use crate::ton::jetton::{self, JettonTransfer};
+use crate::ton::mkoin_service::TRANSFER_FORWARD_TON;
+use crate::ton::purchase_verifier::find_receipt;
+use anyhow::Result;
+use tonlib_core::cell::Cell;
+use uuid::Uuid;
+
+/// Prefix of the text comment that ties an MKOIN transfer to a redemption
+pub const REDEMPTION_COMMENT_PREFIX: &str = "redeem:";
+
+/// Forward payload comment of the transfer for `redemption_id`
+pub fn redemption_comment(redemption_id: Uuid) -> String {
+    format!("{}{}", REDEMPTION_COMMENT_PREFIX, redemption_id)
+}
+
+/// `query_id` of the user's transfer for `redemption_id`
+pub fn redemption_query_id(redemption_id: Uuid) -> u64 {
+    redemption_id.as_u64_pair().0
+}
+
+/// `query_id` of attempt `attempt` at burning or returning the MKOIN
+///
+/// Taken from the other half of the id so it never collides with the
+/// user's own transfer.
+pub fn settlement_query_id(redemption_id: Uuid, attempt: i32) -> u64 {
+    redemption_id
+        .as_u64_pair()
+        .1
+        .wrapping_add(attempt.max(0) as u64)
+}
+
+/// Body of the jetton `transfer` the user sends to their MKOIN wallet;
+/// excess TON is returned to the user
+pub fn redemption_transfer_body(redemption: &Redemption, treasury: &str) -> Result<Cell> {
+    jetton::build_transfer_body(&JettonTransfer {
+        query_id: redemption_query_id(redemption.id),
+        amount: redemption.amount,
+        destination: treasury,
+        response_destination: &redemption.user_address,
+        forward_ton_amount: TRANSFER_FORWARD_TON,
+        comment: Some(&redemption_comment(redemption.id)),
+    })
+}
+
+/// The treasury transaction that received `redemption`'s MKOIN
+///
+/// `txs` is the treasury's history and `jetton_wallet` its MKOIN wallet.
+pub fn find_redemption_transfer(
+    txs: &[serde_json::Value],
+    jetton_wallet: &str,
+    redemption: &Redemption,
+) -> Option<String> {
+    let owner = to_raw_address(&redemption.user_address).ok()?;
+    find_receipt(
+        txs,
+        jetton_wallet,
+        redemption_query_id(redemption.id),
+        &owner,
+        redemption.amount,
+    )
+}
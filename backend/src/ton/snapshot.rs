@@ -0,0 +1,352 @@
+//! Holder snapshots and balances read from chain
+//!
+//! A jetton master cannot list its wallets, so the candidates come from the
+//! balance ledger: every address that ever held the token. Each candidate's
+//! jetton wallet is then asked for its balance with `get_wallet_data`, which
+//! makes the result independent of what the ledger says the balances are.
+//!
+//! Balances at an earlier point are replayed: starting from the current
+//! balance, the jetton wallet's transactions since that point are undone
+//! newest first, using its archival transaction list.
+
+use crate::amount::TokenAmount;
+use crate::db::snapshots::{BalanceAt, SnapshotEntry, holder_address};
+use crate::ton::client::{self, Client};
+use crate::ton::jetton::{
+    self, BOUNCED_PREFIX, JETTON_BURN_NOTIFICATION_OPCODE, JETTON_BURN_OPCODE, JETTON_INTERNAL_TRANSFER_OPCODE,
+    JETTON_TRANSFER_OPCODE,
+};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Jetton wallets read at once when snapshotting the holders of one token
+const HOLDER_BALANCE_CONCURRENCY: usize = 8;
+// Jetton wallets read at once when looking up the balances of one owner
+const OWNER_BALANCE_CONCURRENCY: usize = 4;
+// Transactions fetched per getTransactions call while replaying
+const REPLAY_PAGE_SIZE: u32 = 50;
+// Replays give up rather than walk wallets with a longer history
+const REPLAY_MAX_PAGES: usize = 20;
+// Jetton wallet addresses remembered before the cache starts over
+const MAX_CACHED_WALLETS: usize = 100_000;
+
+type WalletCache = Arc<Mutex<HashMap<(String, String), String>>>;
+type BalanceRead = Shared<BoxFuture<'static, Result<TokenAmount, String>>>;
+
+pub struct ChainSnapshotter {
+    client: Client,
+    /// Jetton wallet of each (master, owner); wallet addresses never change
+    wallets: WalletCache,
+    /// Balance reads in flight, shared by concurrent lookups of one wallet
+    in_flight: Mutex<HashMap<(String, String), BalanceRead>>,
+}
+
+impl ChainSnapshotter {
+    pub fn new() -> Self {
+        let api_key = std::env::var("TON_API_KEY").ok();
+
+        Self {
+            client: Client::new(&format!("{}/jsonRPC", client::api_url()), api_key),
+            wallets: Arc::default(),
+            in_flight: Mutex::default(),
+        }
+    }
+
+    /// Current balances of `owners` in the jetton `master`
+    ///
+    /// Owners holding nothing are left out. Each entry carries the lt of
+    /// the last transaction of the owner's jetton wallet. Up to
+    /// `HOLDER_BALANCE_CONCURRENCY` wallets are read at once.
+    pub async fn holder_balances(&self, master: &str, owners: &[String]) -> Result<Vec<SnapshotEntry>> {
+        let reads: Vec<_> = owners.iter().map(|owner| self.holder_balance(master, owner)).collect();
+        let entries: Vec<Option<SnapshotEntry>> = futures::stream::iter(reads)
+            .buffered(HOLDER_BALANCE_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_>>()?;
+        Ok(entries.into_iter().flatten().collect())
+    }
+
+    async fn holder_balance(&self, master: &str, owner: &str) -> Result<Option<SnapshotEntry>> {
+        let wallet = resolve_wallet(&self.client, &self.wallets, master, owner).await?;
+        let (balance, last_lt) = jetton::get_jetton_wallet_state(&self.client, &wallet)
+            .await
+            .with_context(|| format!("Failed to read the jetton wallet {}", wallet))?;
+
+        Ok((!balance.is_zero()).then(|| SnapshotEntry {
+            user_address: owner.to_string(),
+            balance,
+            lt: last_lt.unwrap_or(0),
+        }))
+    }
+
+    /// Balances of `owner` in each of the jetton `masters`, in order
+    ///
+    /// Up to `OWNER_BALANCE_CONCURRENCY` wallets are read at once. A failed
+    /// read only fails its own entry.
+    pub async fn owner_balances(&self, owner: &str, masters: &[String]) -> Vec<Result<TokenAmount>> {
+        // Collected first: a stream over a borrowing closure is not Send in handlers
+        let reads: Vec<_> = masters.iter().map(|master| self.owner_balance(master, owner)).collect();
+        futures::stream::iter(reads)
+            .buffered(OWNER_BALANCE_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Current balance of `owner` in the jetton `master`
+    ///
+    /// Lookups of a wallet that is already being read, in whatever form
+    /// its addresses were given, wait for that read instead of starting
+    /// another one.
+    pub async fn owner_balance(&self, master: &str, owner: &str) -> Result<TokenAmount> {
+        let key = (holder_address(master), holder_address(owner));
+        let read = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            in_flight
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    let client = self.client.clone();
+                    let wallets = self.wallets.clone();
+                    let (master, owner) = key.clone();
+                    async move {
+                        read_balance(&client, &wallets, &master, &owner)
+                            .await
+                            .map_err(|e| format!("{:#}", e))
+                    }
+                    .boxed()
+                    .shared()
+                })
+                .clone()
+        };
+
+        let result = read.clone().await;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight.get(&key).is_some_and(|current| current.ptr_eq(&read)) {
+            in_flight.remove(&key);
+        }
+        result.map_err(anyhow::Error::msg)
+    }
+
+    /// Balances of the `(master, owner)` pairs at `at`, in order
+    ///
+    /// Replays run `OWNER_BALANCE_CONCURRENCY` at a time; a failed replay
+    /// only fails its own entry.
+    pub async fn balances_at(&self, wallets: &[(String, String)], at: BalanceAt) -> Vec<Result<TokenAmount>> {
+        let replays: Vec<_> = wallets
+            .iter()
+            .map(|(master, owner)| self.balance_at(master, owner, at))
+            .collect();
+        futures::stream::iter(replays)
+            .buffered(OWNER_BALANCE_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Balance of `owner` in the jetton `master` at `at`, replayed from chain
+    pub async fn balance_at(&self, master: &str, owner: &str, at: BalanceAt) -> Result<TokenAmount> {
+        let wallet = resolve_wallet(&self.client, &self.wallets, master, owner).await?;
+        let (current, last_lt) = jetton::get_jetton_wallet_state(&self.client, &wallet)
+            .await
+            .with_context(|| format!("Failed to read the jetton wallet {}", wallet))?;
+        let Some(last_lt) = last_lt else {
+            // Never deployed, so it never held anything
+            return Ok(TokenAmount::ZERO);
+        };
+        if at.lt().is_some_and(|at| last_lt <= at) {
+            return Ok(current);
+        }
+
+        let mut balance = i128::try_from(current.nano())?;
+        let mut cursor: Option<(i64, String)> = None;
+        for _ in 0..REPLAY_MAX_PAGES {
+            let page = match &cursor {
+                None => self.client.get_transactions(&wallet, REPLAY_PAGE_SIZE).await?,
+                Some((lt, hash)) => {
+                    self.client
+                        .get_transactions_from(&wallet, REPLAY_PAGE_SIZE, *lt, hash)
+                        .await?
+                }
+            };
+            let txs = page.as_array().map(Vec::as_slice).unwrap_or_default();
+
+            let mut next = None;
+            for tx in txs {
+                let (lt, time, hash) =
+                    tx_point(tx).with_context(|| format!("Malformed transaction of {}", wallet))?;
+                // Pages after the first start with the cursor transaction
+                if cursor.as_ref().is_some_and(|(cursor_lt, _)| lt >= *cursor_lt) {
+                    continue;
+                }
+                if at.includes(lt, time) {
+                    return replayed_balance(balance, &wallet);
+                }
+                balance -= balance_change(tx);
+                next = Some((lt, hash.to_string()));
+            }
+
+            // The wallet's first transaction came after `at`
+            if txs.len() < REPLAY_PAGE_SIZE as usize || next.is_none() {
+                return replayed_balance(balance, &wallet);
+            }
+            cursor = next;
+        }
+        bail!(
+            "History of {} goes back further than {} transactions",
+            wallet,
+            REPLAY_MAX_PAGES * REPLAY_PAGE_SIZE as usize
+        )
+    }
+}
+
+async fn resolve_wallet(client: &Client, wallets: &WalletCache, master: &str, owner: &str) -> Result<String> {
+    let key = (holder_address(master), holder_address(owner));
+    if let Some(wallet) = wallets.lock().unwrap().get(&key) {
+        return Ok(wallet.clone());
+    }
+    let wallet = jetton::get_jetton_wallet_address(client, master, owner)
+        .await
+        .with_context(|| format!("Failed to resolve the {} wallet of {}", master, owner))?;
+
+    let mut wallets = wallets.lock().unwrap();
+    if wallets.len() >= MAX_CACHED_WALLETS {
+        wallets.clear();
+    }
+    wallets.insert(key, wallet.clone());
+    Ok(wallet)
+}
+
+async fn read_balance(client: &Client, wallets: &WalletCache, master: &str, owner: &str) -> Result<TokenAmount> {
+    let wallet = resolve_wallet(client, wallets, master, owner).await?;
+    let (balance, _) = jetton::get_jetton_wallet_state(client, &wallet)
+        .await
+        .with_context(|| format!("Failed to read the jetton wallet {}", wallet))?;
+    Ok(balance)
+}
+
+fn replayed_balance(balance: i128, wallet: &str) -> Result<TokenAmount> {
+    match u128::try_from(balance) {
+        Ok(nano) => Ok(TokenAmount::from_nano(nano)),
+        Err(_) => bail!("Replaying {} gives a negative balance", wallet),
+    }
+}
+
+/// Lt, time and hash of a transaction as returned by `getTransactions`
+fn tx_point(tx: &serde_json::Value) -> Option<(i64, DateTime<Utc>, &str)> {
+    let id = tx.get("transaction_id")?;
+    let lt = id.get("lt")?.as_str()?.parse().ok()?;
+    let hash = id.get("hash")?.as_str()?;
+    let time = DateTime::from_timestamp(tx.get("utime")?.as_i64()?, 0)?;
+    Some((lt, time, hash))
+}
+
+/// Nanotokens a jetton wallet transaction added to the wallet's balance
+///
+/// Incoming transfers credit the wallet and outgoing transfers and burns
+/// debit it; a bounced transfer or burn notification is credited back.
+/// A transfer or burn only took effect if it sent the message passing the
+/// amount on, and an incoming transfer only if it was not bounced back.
+/// Anything else leaves the balance alone.
+pub fn balance_change(tx: &serde_json::Value) -> i128 {
+    let Some(body) = tx.get("in_msg").and_then(jetton::message_body) else {
+        return 0;
+    };
+    let mut parser = body.parser();
+    let Ok(mut op) = parser.load_u32(32) else {
+        return 0;
+    };
+    let bounced = op == BOUNCED_PREFIX;
+    if bounced {
+        let Ok(original) = parser.load_u32(32) else {
+            return 0;
+        };
+        op = original;
+    }
+    if parser.load_u64(64).is_err() {
+        return 0;
+    }
+    // Bounced bodies are truncated to 256 bits, which still holds the amount
+    let Some(amount) = parser.load_coins().ok().and_then(|a| i128::try_from(a).ok()) else {
+        return 0;
+    };
+
+    match (bounced, op) {
+        (false, JETTON_INTERNAL_TRANSFER_OPCODE) if !jetton::sends_op(tx, BOUNCED_PREFIX) => amount,
+        (false, JETTON_TRANSFER_OPCODE) if jetton::sends_op(tx, JETTON_INTERNAL_TRANSFER_OPCODE) => -amount,
+        (false, JETTON_BURN_OPCODE) if jetton::sends_op(tx, JETTON_BURN_NOTIFICATION_OPCODE) => -amount,
+        (true, JETTON_INTERNAL_TRANSFER_OPCODE | JETTON_BURN_NOTIFICATION_OPCODE) => amount,
+        _ => 0,
+    }
+}
+
+impl Default for ChainSnapshotter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use num_bigint::BigUint;
+    use serde_json::json;
+    use tonlib_core::cell::{BagOfCells, CellBuilder};
+
+    fn message(ops: &[u32], amount: u64) -> serde_json::Value {
+        let mut builder = CellBuilder::new();
+        for op in ops {
+            builder.store_u32(32, *op).unwrap();
+        }
+        builder.store_u64(64, 7).unwrap();
+        builder.store_coins(&BigUint::from(amount)).unwrap();
+        let boc = BagOfCells::from_root(builder.build().unwrap()).serialize(true).unwrap();
+        json!({ "msg_data": { "body": base64::engine::general_purpose::STANDARD.encode(boc) } })
+    }
+
+    fn tx(in_ops: &[u32], out_ops: &[u32]) -> serde_json::Value {
+        json!({
+            "utime": 1_700_000_000,
+            "transaction_id": { "lt": "4711", "hash": "aGFzaA==" },
+            "in_msg": message(in_ops, 500),
+            "out_msgs": out_ops.iter().map(|op| message(&[*op], 500)).collect::<Vec<_>>(),
+        })
+    }
+
+    #[test]
+    fn test_balance_change() {
+        let transfer = JETTON_TRANSFER_OPCODE;
+        let internal = JETTON_INTERNAL_TRANSFER_OPCODE;
+
+        assert_eq!(balance_change(&tx(&[internal], &[jetton::JETTON_EXCESSES_OPCODE])), 500);
+        assert_eq!(balance_change(&tx(&[transfer], &[internal])), -500);
+        assert_eq!(balance_change(&tx(&[JETTON_BURN_OPCODE], &[JETTON_BURN_NOTIFICATION_OPCODE])), -500);
+        // Sent but bounced: credited back
+        assert_eq!(balance_change(&tx(&[BOUNCED_PREFIX, internal], &[])), 500);
+        assert_eq!(balance_change(&tx(&[BOUNCED_PREFIX, JETTON_BURN_NOTIFICATION_OPCODE], &[])), 500);
+    }
+
+    #[test]
+    fn test_balance_change_ignores_aborted_and_unrelated() {
+        // A transfer the wallet refused is bounced to the sender
+        assert_eq!(balance_change(&tx(&[JETTON_TRANSFER_OPCODE], &[BOUNCED_PREFIX])), 0);
+        assert_eq!(balance_change(&tx(&[JETTON_INTERNAL_TRANSFER_OPCODE], &[BOUNCED_PREFIX])), 0);
+        assert_eq!(balance_change(&tx(&[JETTON_BURN_OPCODE], &[])), 0);
+        assert_eq!(balance_change(&tx(&[jetton::JETTON_EXCESSES_OPCODE], &[])), 0);
+        assert_eq!(balance_change(&json!({ "in_msg": {} })), 0);
+    }
+
+    #[test]
+    fn test_tx_point() {
+        let tx = tx(&[], &[]);
+        let (lt, time, hash) = tx_point(&tx).unwrap();
+        assert_eq!((lt, time.timestamp(), hash), (4711, 1_700_000_000, "aGFzaA=="));
+        assert!(BalanceAt::Lt(4711).includes(lt, time));
+        assert!(!BalanceAt::Lt(4710).includes(lt, time));
+        assert!(tx_point(&json!({ "utime": 1 })).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_balances_fall_back_to_purchases_before_mint() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "balances").await;
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Balances Orchard".to_string();
+        c.token_name = "Balances".to_string();
+        c.token_symbol = "BAL".to_string();
+        c.suggested_price = TokenAmount::parse_decimal("1.15").unwrap();
+        c.status = "running".to_string();
+    })
+    .await;
+
+    let holder = format!("EQ_BALANCES_HOLDER_{}", suffix);
+
+    // One confirmed purchase; the pending one is not held yet
+    for (tokens, status) in [("100", "confirmed"), ("50", "pending")] {
+        sqlx::query(
+            "INSERT INTO purchases (user_address, campaign_id, mkoin_paid, tokens_received, status) VALUES ($1, $2, $3, $3, $4)",
+        )
+        .bind(&holder)
+        .bind(campaign_id)
+        .bind(TokenAmount::parse_decimal(tokens).unwrap())
+        .bind(status)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    }
+
+    let req = Request::builder()
+        .uri(format!("/balances/{}", holder))
+        .body(Body::empty())
+        .unwrap();
+    let res = app.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+
+    // Not minted: nothing to read on chain, the purchases stand in
+    let tokens = body["campaign_tokens"].as_array().unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["symbol"], "BAL");
+    assert_eq!(tokens[0]["balance"], "100.000000000");
+    assert_eq!(tokens[0]["purchased_balance"], "100.000000000");
+    assert_eq!(tokens[0]["balance_source"], "purchases");
+    assert_eq!(tokens[0]["balance_mismatch"], false);
+    assert!(tokens[0]["token_address"].is_null());
+}
+
+#[tokio::test]
+async fn test_batch_balances_report_errors_per_item() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let admin = common::create_admin(&db, "batch").await;
+    let farmer = common::create_farmer(&db, "batch").await;
+
+    let post = |token: &str, body: Value| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri("/balances/batch")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // Unresolvable wallets fail their own entries, in request order
+    let masters = ["EQ_BATCH_MASTER_A", "EQ_BATCH_MASTER_B"];
+    let (status, body) = post(
+        &admin.token,
+        serde_json::json!({ "addresses": ["EQ_BATCH_ONE", "EQ_BATCH_TWO"], "masters": masters }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let balances = body["balances"].as_array().unwrap();
+    let pairs: Vec<(&str, &str)> = balances
+        .iter()
+        .map(|b| (b["address"].as_str().unwrap(), b["master"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            ("EQ_BATCH_ONE", masters[0]),
+            ("EQ_BATCH_ONE", masters[1]),
+            ("EQ_BATCH_TWO", masters[0]),
+            ("EQ_BATCH_TWO", masters[1]),
+        ]
+    );
+    assert_eq!(body["failed"], 4);
+    assert!(balances.iter().all(|b| b["balance"].is_null() && b["error"].is_string()));
+
+    let (status, _) = post(&farmer.token, serde_json::json!({ "addresses": ["EQ_BATCH_ONE"] })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = post(&admin.token, serde_json::json!({ "addresses": [] })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let too_many: Vec<String> = (0..1_001).map(|i| format!("EQ_BATCH_{}", i)).collect();
+    let (status, _) = post(&admin.token, serde_json::json!({ "addresses": too_many })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
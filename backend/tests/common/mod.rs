@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use anyhow::Result;
 use redis::{Client, AsyncCommands};
 use std::sync::Once;
@@ -5,6 +7,9 @@ use sqlx::postgres::PgPoolOptions;
 use web_app::config::Config;
 use web_app::db::Database;
 use web_app::cache::CacheService;
+use web_app::amount::TokenAmount;
+use web_app::db::Campaign;
+use uuid::Uuid;
 
 static INIT: Once = Once::new();
 
@@ -30,3 +35,70 @@ pub async fn setup() -> (Database, CacheService) {
 
     (db, cache)
 }
+
+/// A user created for one test, with a JWT for it
+pub struct TestUser {
+    pub id: Uuid,
+    pub name: String,
+    pub token: String,
+}
+
+/// Create a user with a unique name, which doubles as their address
+pub async fn create_user(db: &Database, role: &str, label: &str) -> TestUser {
+    let name = format!("test_{}_{}_{}", role, label, Uuid::new_v4());
+    let id = db.create_user_full(&name, "x", role, &name, None).await.unwrap();
+    let token = web_app::auth::create_jwt(id, &name, role).unwrap();
+    TestUser { id, name, token }
+}
+
+pub async fn create_farmer(db: &Database, label: &str) -> TestUser {
+    create_user(db, "farmer", label).await
+}
+
+pub async fn create_admin(db: &Database, label: &str) -> TestUser {
+    create_user(db, "admin", label).await
+}
+
+/// A campaign of `farmer_id` with test defaults: approved, 100 tokens
+/// selling at 1 MKOIN, on sale from now for 30 days
+pub fn campaign(farmer_id: Uuid) -> Campaign {
+    Campaign {
+        id: Uuid::new_v4(),
+        farmer_id,
+        name: "Test Orchard".to_string(),
+        description: None,
+        token_name: "Test".to_string(),
+        token_symbol: "TST".to_string(),
+        token_supply: "100".to_string(),
+        logo_url: None,
+        image_url: None,
+        start_time: chrono::Utc::now(),
+        end_time: chrono::Utc::now() + chrono::Duration::days(30),
+        suggested_price: TokenAmount::parse_decimal("1").unwrap(),
+        status: "approved".to_string(),
+        token_address: None,
+        created_at: None,
+        minted_at: None,
+        mint_amount: None,
+        mint_tx_hash: None,
+        soft_cap: None,
+        hard_cap: None,
+        min_ticket: None,
+        max_ticket: None,
+        max_per_investor: None,
+        presale_start_time: None,
+        presale_price: None,
+    }
+}
+
+/// Insert `campaign(farmer_id)` as adjusted by `overrides`; a token address
+/// set there is stored as well
+pub async fn insert_campaign(db: &Database, farmer_id: Uuid, overrides: impl FnOnce(&mut Campaign)) -> Uuid {
+    let mut campaign = campaign(farmer_id);
+    overrides(&mut campaign);
+    let id = db.create_campaign(&campaign).await.unwrap();
+    if let Some(token_address) = &campaign.token_address {
+        db.update_campaign_token_address(id, token_address).await.unwrap();
+    }
+    id
+}
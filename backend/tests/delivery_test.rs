@@ -0,0 +1,122 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+const TOKEN: &str = "0:58f8e5b06a6da7ec33b6e7157b8ee5164bd315f718c9d5bc0bd5d2129180cd70";
+
+#[tokio::test]
+async fn test_confirmed_purchase_is_queued_and_retried() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "delivery").await;
+
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Delivered Orchard".to_string();
+        c.token_name = "Delivered".to_string();
+        c.token_symbol = "DLV".to_string();
+        c.suggested_price = TokenAmount::parse_decimal("2").unwrap();
+    })
+    .await;
+    db.update_campaign_token_address(campaign_id, TOKEN).await.unwrap();
+
+    let quote = db
+        .create_quote("EQ_DELIVERY_BUYER", campaign_id, TokenAmount::from_nano(6_000_000_000), "EQ_TREASURY")
+        .await
+        .unwrap();
+    let (purchase_id, _) = db
+        .create_purchase("EQ_DELIVERY_BUYER", quote.id, &uuid::Uuid::new_v4().to_string())
+        .await
+        .unwrap();
+
+    let delivery = || {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .uri(format!("/purchases/{}/delivery", purchase_id))
+                .body(Body::empty())
+                .unwrap();
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // 1. Nothing is delivered before the payment is confirmed
+    db.queue_token_deliveries().await.unwrap();
+    assert_eq!(delivery().await.0, StatusCode::NOT_FOUND);
+
+    // 2. Confirmation queues the purchased amount for the buyer
+    assert!(db.confirm_purchase(purchase_id, &uuid::Uuid::new_v4().to_string()).await.unwrap());
+    db.queue_token_deliveries().await.unwrap();
+    let (status, body) = delivery().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "pending");
+    assert_eq!(body["amount"], "3000000000");
+    assert_eq!(body["token_address"], TOKEN);
+
+    // 3. Bounced attempts are retried until the limit
+    let id = db.get_purchase_delivery(purchase_id).await.unwrap().unwrap().id;
+    for attempt in 1..=3 {
+        assert_eq!(db.start_delivery_attempt(id).await.unwrap(), Some(attempt));
+        db.mark_delivery_sent(id, "msg").await.unwrap();
+        // Only pending deliveries can be attempted
+        assert_eq!(db.start_delivery_attempt(id).await.unwrap(), None);
+        let status = db.retry_delivery(id, "Transfer bounced", 3).await.unwrap();
+        assert_eq!(status, if attempt < 3 { "pending" } else { "failed" });
+    }
+    let (_, body) = delivery().await;
+    assert_eq!(body["status"], "failed");
+    assert_eq!(body["error"], "Transfer bounced");
+}
+
+#[tokio::test]
+async fn test_refunded_purchase_is_not_delivered() {
+    let (db, _cache) = common::setup().await;
+
+    let farmer = common::create_farmer(&db, "undelivered").await;
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.token_address = Some(TOKEN.to_string());
+    })
+    .await;
+
+    let mut purchases = Vec::new();
+    for buyer in ["EQ_UNDELIVERED_A", "EQ_UNDELIVERED_B"] {
+        let quote = db
+            .create_quote(buyer, campaign_id, TokenAmount::from_nano(1_000_000_000), "EQ_TREASURY")
+            .await
+            .unwrap();
+        let (purchase_id, _) = db
+            .create_purchase(buyer, quote.id, &uuid::Uuid::new_v4().to_string())
+            .await
+            .unwrap();
+        assert!(db.confirm_purchase(purchase_id, &uuid::Uuid::new_v4().to_string()).await.unwrap());
+        purchases.push(purchase_id);
+    }
+    let refund = |purchase_id: uuid::Uuid| {
+        sqlx::query("UPDATE purchases SET status = 'refunded' WHERE id = $1")
+            .bind(purchase_id)
+            .execute(&db.pool)
+    };
+
+    // 1. A purchase refunded before queueing gets no delivery
+    refund(purchases[0]).await.unwrap();
+    db.queue_token_deliveries().await.unwrap();
+    assert!(db.get_purchase_delivery(purchases[0]).await.unwrap().is_none());
+
+    // 2. One refunded while its delivery waits is never attempted
+    let delivery = db.get_purchase_delivery(purchases[1]).await.unwrap().unwrap();
+    assert_eq!(delivery.status, "pending");
+    refund(purchases[1]).await.unwrap();
+    assert_eq!(db.start_delivery_attempt(delivery.id).await.unwrap(), None);
+    assert_eq!(db.get_purchase_delivery(purchases[1]).await.unwrap().unwrap().attempts, 0);
+}
@@ -0,0 +1,151 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+const BOUNDARY: &str = "statement-boundary";
+
+fn statement_upload(csv: &str) -> Body {
+    Body::from(format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"statement.csv\"\r\n\
+         Content-Type: text/csv\r\n\r\n{csv}\r\n--{b}--\r\n",
+        b = BOUNDARY,
+        csv = csv
+    ))
+}
+
+#[tokio::test]
+async fn test_statement_import_matches_deposits_and_queues_the_rest() {
+    // SAFETY: no other test in this binary reads the environment concurrently
+    unsafe { std::env::set_var("DEPOSIT_IBAN", "DE89370400440532013000") };
+
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let admin_username = format!("test_admin_deposit_{}", uuid::Uuid::new_v4());
+    let admin_id = db.create_user_full(&admin_username, "x", "admin", &admin_username, None).await.unwrap();
+    let admin_token = web_app::auth::create_jwt(admin_id, &admin_username, "admin").unwrap();
+
+    let send = |req: Request<Body>| {
+        let app = app.clone();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+    let call = |method: &str, uri: String, user: &str, body: Value| {
+        Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("X-User-Address", user)
+            .header("Authorization", format!("Bearer {}", admin_token))
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let import = |csv: &str| {
+        Request::builder()
+            .uri("/admin/bank-statements")
+            .method("POST")
+            .header("content-type", format!("multipart/form-data; boundary={}", BOUNDARY))
+            .header("Authorization", format!("Bearer {}", admin_token))
+            .body(statement_upload(csv))
+            .unwrap()
+    };
+    let eur = |a: &str| TokenAmount::parse_decimal(a).unwrap();
+    let user = format!("EQ_DEPOSITOR_{}", uuid::Uuid::new_v4());
+
+    // 1. Users announce deposits and get a reference to pay with
+    let (status, created) = send(call("POST", "/deposits".into(), &user, serde_json::json!({ "amount": eur("100") }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["bank_transfer"]["iban"], "DE89370400440532013000");
+    assert_eq!(created["bank_transfer"]["amount"], "100.000000000");
+    let exact_ref = created["deposit"]["reference"].as_str().unwrap().to_string();
+    let exact_id: uuid::Uuid = created["deposit"]["id"].as_str().unwrap().parse().unwrap();
+    let (status, _) = send(call("POST", "/deposits".into(), &user, serde_json::json!({ "amount": "0" }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let partial = db.create_deposit_intent(&user, eur("50")).await.unwrap();
+
+    // 2. The importer matches exact payments and queues the rest for review
+    let spaced = format!("{} {}", &partial.reference[..6], &partial.reference[6..]);
+    let csv = format!(
+        "id;date;amount;currency;name;purpose\n\
+         {r}-1;18.10.2026;100,00;EUR;Ana;MKOIN {exact}\n\
+         {r}-2;18.10.2026;30,00;EUR;Ana;{partial}\n\
+         {r}-3;18.10.2026;12,00;EUR;Bo;no reference\n\
+         {r}-4;18.10.2026;-5,00;EUR;Bank;fee\n",
+        r = uuid::Uuid::new_v4(),
+        exact = exact_ref.to_lowercase(),
+        partial = spaced
+    );
+    let (status, imported) = send(import(&csv)).await;
+    assert_eq!(status, StatusCode::OK, "{}", imported);
+    let counts = &imported["statement"];
+    assert_eq!((counts["entry_count"].as_i64(), counts["matched_count"].as_i64(), counts["review_count"].as_i64()), (Some(3), Some(1), Some(2)));
+    assert_eq!(db.get_deposit_intent(exact_id).await.unwrap().unwrap().status, "paid");
+
+    let payments = imported["payments"].as_array().unwrap();
+    let reason_of = |amount: &str| {
+        payments
+            .iter()
+            .find(|p| p["amount"] == eur(amount).nano().to_string())
+            .map(|p| (p["id"].as_str().unwrap().to_string(), p["review_reason"].clone()))
+            .unwrap()
+    };
+    let (partial_payment, reason) = reason_of("30");
+    assert_eq!(reason, "partial");
+    let (unmatched_payment, reason) = reason_of("12");
+    assert_eq!(reason, "unmatched");
+
+    // 3. Importing the same statement again credits nothing twice
+    let (_, again) = send(import(&csv)).await;
+    assert_eq!((again["statement"]["matched_count"].as_i64(), again["statement"]["duplicate_count"].as_i64()), (Some(0), Some(3)));
+
+    // 4. Admins resolve the review queue
+    let (_, queue) = send(call("GET", "/admin/bank-payments".into(), &user, Value::Null)).await;
+    assert!(queue.as_array().unwrap().iter().any(|p| p["id"] == unmatched_payment.as_str()));
+    let assign = serde_json::json!({ "deposit_id": partial.id, "note": "short payment accepted" });
+    let (status, assigned) = send(call("PUT", format!("/admin/bank-payments/{}/assign", partial_payment), &user, assign.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(assigned["status"], "assigned");
+    let partial = db.get_deposit_intent(partial.id).await.unwrap().unwrap();
+    assert_eq!((partial.status.as_str(), partial.paid_amount), ("pending", Some(eur("30"))));
+    let (status, _) = send(call("PUT", format!("/admin/bank-payments/{}/assign", partial_payment), &user, assign)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(call("DELETE", format!("/deposits/{}", partial.id), &user, Value::Null)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // The rest of a partially paid deposit completes it
+    let rest = format!(
+        "date;amount;currency;name;purpose\n19.10.2026;20,00;EUR;Ana;{} {}\n",
+        partial.reference,
+        uuid::Uuid::new_v4()
+    );
+    let (_, imported) = send(import(&rest)).await;
+    assert_eq!(imported["statement"]["matched_count"].as_i64(), Some(1));
+    let partial = db.get_deposit_intent(partial.id).await.unwrap().unwrap();
+    assert_eq!((partial.status.as_str(), partial.paid_amount), ("paid", Some(eur("50"))));
+    let (status, dismissed) = send(call("PUT", format!("/admin/bank-payments/{}/dismiss", unmatched_payment), &user, serde_json::json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(dismissed["status"], "dismissed");
+
+    // 5. A paid deposit is minted once
+    assert_eq!(db.start_deposit_mint(exact_id).await.unwrap(), Some(1));
+    assert_eq!(db.start_deposit_mint(exact_id).await.unwrap(), None);
+    db.mark_deposit_mint_sent(exact_id, "mint-msg").await.unwrap();
+    assert!(db.confirm_deposit_mint(exact_id, "mint-tx").await.unwrap());
+    let (_, mine) = send(call("GET", "/deposits/my".into(), &user, Value::Null)).await;
+    let minted = mine.as_array().unwrap().iter().find(|d| d["id"] == exact_id.to_string()).unwrap();
+    assert_eq!((minted["status"].as_str(), minted["mint_tx_hash"].as_str()), (Some("minted"), Some("mint-tx")));
+    let (status, _) = send(call("DELETE", format!("/deposits/{}", exact_id), &user, Value::Null)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
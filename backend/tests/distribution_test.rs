@@ -0,0 +1,134 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use web_app::db::distributions::PayoutMode;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_distribution_preview_and_persisted_payouts() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "distribution").await;
+    let admin = common::create_admin(&db, "distribution").await;
+
+    let token = format!("EQ_HARVEST_TOKEN_{}", suffix);
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Harvest Orchard".to_string();
+        c.token_name = "Harvest".to_string();
+        c.token_symbol = "HRV".to_string();
+        c.status = "finished".to_string();
+        c.token_address = Some(token.clone());
+    })
+    .await;
+
+    // Three holders with equal balances, so 100 nanocoins leave 1 of dust
+    for holder in ["EQ_HOLDER_A", "EQ_HOLDER_B", "EQ_HOLDER_C"] {
+        db.upsert_portfolio(holder, &token, TokenAmount::from_nano(5), 1).await.unwrap();
+    }
+    db.upsert_portfolio("EQ_HOLDER_GONE", &token, TokenAmount::from_nano(0), 1).await.unwrap();
+
+    let distribute = |body: Value| {
+        Request::builder()
+            .uri("/admin/distribution")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("Authorization", format!("Bearer {}", admin.token))
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let send = |req: Request<Body>| {
+        let app = app.clone();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // 1. A dry run previews the shares without storing anything
+    let (status, preview) = send(distribute(serde_json::json!({
+        "target_token": token,
+        "amount_mkoin": "0.0000001",
+        "dry_run": true
+    })))
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", preview);
+    assert_eq!(preview["campaign_id"], campaign_id.to_string());
+    assert_eq!(preview["snapshot_supply"], "15");
+    assert_eq!(preview["distributed_amount"], "99");
+    assert_eq!(preview["remainder"], "1");
+    let payouts = preview["payouts"].as_array().unwrap();
+    assert_eq!(payouts.len(), 3);
+    assert!(payouts.iter().all(|p| p["amount"] == "33"));
+
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM distributions WHERE campaign_id = $1")
+        .bind(campaign_id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 0);
+
+    // 2. Unknown tokens and zero amounts are rejected
+    let (status, _) = send(distribute(serde_json::json!({
+        "target_token": "EQ_NOT_A_CAMPAIGN_TOKEN",
+        "amount_mkoin": "1",
+        "dry_run": true
+    })))
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(distribute(serde_json::json!({
+        "target_token": token,
+        "amount_mkoin": "0",
+        "dry_run": true
+    })))
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // 3. A persisted distribution has one pending line per holder
+    let holders: Vec<_> = db.ledger_holders(&token, None, None).await.unwrap().into_iter().map(Into::into).collect();
+    let plan = web_app::db::distributions::pro_rata(TokenAmount::from_nano(100), &holders).unwrap();
+    let id = db.create_distribution(campaign_id, &token, &plan, PayoutMode::Transfer, None, Some(admin.id)).await.unwrap();
+
+    let (status, loaded) = send(
+        Request::builder()
+            .uri(format!("/admin/distributions/{}", id))
+            .header("Authorization", format!("Bearer {}", admin.token))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(loaded["distribution"]["status"], "created");
+    assert_eq!(loaded["distribution"]["holder_count"], 3);
+    let lines = loaded["payouts"].as_array().unwrap();
+    assert!(lines.iter().all(|p| p["status"] == "pending"));
+
+    // 4. Payout lines track their own progress and decide the final status
+    let payouts = db.get_distribution_payouts(id).await.unwrap();
+    assert!(db.claim_distribution(id).await.unwrap());
+    assert!(!db.claim_distribution(id).await.unwrap());
+    db.mark_payout_sent(payouts[0].id, "msg-a").await.unwrap();
+    db.confirm_payout(payouts[0].id, "tx-a").await.unwrap();
+    db.mark_payout_sent(payouts[1].id, "msg-b").await.unwrap();
+    db.mark_payout_failed(payouts[2].id, "wallet busy").await.unwrap();
+    assert_eq!(db.finish_distribution(id).await.unwrap(), "partial");
+
+    // 5. Executing it again queues the failed payout once more
+    assert!(db.claim_distribution(id).await.unwrap());
+    let retried = db.get_distribution_payouts(id).await.unwrap();
+    let statuses: Vec<&str> = retried.iter().map(|p| p.status.as_str()).collect();
+    assert_eq!(statuses, vec!["confirmed", "sent", "pending"]);
+    assert_eq!(db.start_payout_attempt(payouts[2].id).await.unwrap(), Some(1));
+    assert_eq!(db.start_payout_attempt(payouts[1].id).await.unwrap(), None);
+    assert!(db.get_distribution_ids_by_status("processing", 1000).await.unwrap().contains(&id));
+}
@@ -0,0 +1,159 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_verified_harvest_shows_yield_and_seeds_distribution() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "harvest").await;
+    let admin = common::create_admin(&db, "harvest").await;
+    let other = common::create_farmer(&db, "other_harvest").await;
+
+    let token = format!("EQ_HARVEST_TOKEN_{}", suffix);
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Harvest Orchard".to_string();
+        c.token_name = "Harvest".to_string();
+        c.token_symbol = "HRV".to_string();
+        c.status = "finished".to_string();
+        c.token_address = Some(token.clone());
+    })
+    .await;
+
+    let holder_a = format!("EQ_HARVEST_A_{}", suffix);
+    let holder_b = format!("EQ_HARVEST_B_{}", suffix);
+    db.upsert_portfolio(&holder_a, &token, TokenAmount::from_nano(3), 1).await.unwrap();
+    db.upsert_portfolio(&holder_b, &token, TokenAmount::from_nano(1), 1).await.unwrap();
+
+    // 10 000 MKOIN raised
+    sqlx::query(
+        "INSERT INTO purchases (user_address, campaign_id, mkoin_paid, tokens_received, status) VALUES ($1, $2, $3, $4, 'confirmed')",
+    )
+    .bind(&holder_a)
+    .bind(campaign_id)
+    .bind(TokenAmount::parse_decimal("10000").unwrap())
+    .bind(TokenAmount::from_nano(4))
+    .execute(&db.pool)
+    .await
+    .unwrap();
+
+    let evidence_key = format!("harvest/{}.jpg", suffix);
+    db.record_media_file(&evidence_key, "image/jpeg", 1024, 800, 600, "original", None, Some(farmer.id))
+        .await
+        .unwrap();
+    let foreign_key = format!("harvest/{}-other.jpg", suffix);
+    db.record_media_file(&foreign_key, "image/jpeg", 1024, 800, 600, "original", None, Some(other.id))
+        .await
+        .unwrap();
+
+    let send = |method: &str, uri: String, token: &str, body: Value| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+    let report = |season: &str, evidence: &str| {
+        serde_json::json!({
+            "season": season,
+            "quantity": "1250.5",
+            "quantity_unit": "kg",
+            "revenue": "8000",
+            "costs": "2500",
+            "evidence_keys": [evidence],
+        })
+    };
+
+    // 1. The campaign aims for 8%
+    let (status, _) = send("PUT", format!("/campaigns/{}/target-yield", campaign_id), &farmer.token, serde_json::json!({ "target_yield_bps": 800 })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send("PUT", format!("/campaigns/{}/target-yield", campaign_id), &admin.token, serde_json::json!({ "target_yield_bps": 800 })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // 2. Only the owning farmer reports, with their own uploaded evidence, once per season
+    let reports_uri = format!("/campaigns/{}/harvest-reports", campaign_id);
+    let (status, _) = send("POST", reports_uri.clone(), &other.token, report("2026", &evidence_key)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send("POST", reports_uri.clone(), &farmer.token, report("2026", "harvest/missing.jpg")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send("POST", reports_uri.clone(), &farmer.token, report("2026", &foreign_key)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, submitted) = send("POST", reports_uri.clone(), &farmer.token, report("2026", &evidence_key)).await;
+    assert_eq!(status, StatusCode::OK, "{}", submitted);
+    assert_eq!(submitted["status"], "submitted");
+    let report_id = submitted["id"].as_str().unwrap().to_string();
+    let (status, _) = send("POST", reports_uri.clone(), &farmer.token, report("2026", &evidence_key)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // 3. Unverified reports neither show a yield nor seed distributions
+    let (status, _) = send("POST", format!("/admin/harvest-reports/{}/distribution", report_id), &admin.token, serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, view) = send("GET", format!("/campaigns/{}", campaign_id), &farmer.token, Value::Null).await;
+    assert_eq!(view["target_yield_bps"], 800);
+    assert!(view["yields"].as_array().unwrap().is_empty());
+
+    // 4. Verified: 5 500 EUR profit on 10 000 MKOIN raised
+    let (status, _) = send("PUT", format!("/admin/harvest-reports/{}/verify", report_id), &farmer.token, serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, verified) = send("PUT", format!("/admin/harvest-reports/{}/verify", report_id), &admin.token, serde_json::json!({ "note": "Invoices match" })).await;
+    assert_eq!(status, StatusCode::OK, "{}", verified);
+    assert_eq!(verified["status"], "verified");
+    let (status, _) = send("PUT", format!("/admin/harvest-reports/{}/reject", report_id), &admin.token, serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (_, view) = send("GET", format!("/campaigns/{}", campaign_id), &farmer.token, Value::Null).await;
+    let season = &view["yields"][0];
+    assert_eq!(season["season"], "2026");
+    assert_eq!((season["target_yield_bps"].as_i64(), season["actual_yield_bps"].as_i64()), (Some(800), Some(5500)));
+    let (_, listed) = send("GET", reports_uri.clone(), &farmer.token, Value::Null).await;
+    assert_eq!(listed[0]["review_note"], "Invoices match");
+
+    // 5. The profit seeds a draft claim distribution, paid once executed
+    let (status, draft) = send("POST", format!("/admin/harvest-reports/{}/distribution", report_id), &admin.token, serde_json::json!({ "mode": "claim" })).await;
+    assert_eq!(status, StatusCode::OK, "{}", draft);
+    assert_eq!(draft["distribution"]["status"], "draft");
+    assert_eq!(draft["payouts"].as_array().unwrap().len(), 2);
+    let distribution_id = draft["distribution"]["id"].as_str().unwrap().to_string();
+    let (status, _) = send("POST", format!("/admin/harvest-reports/{}/distribution", report_id), &admin.token, serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, executed) = send("POST", format!("/admin/distributions/{}/execute", distribution_id), &admin.token, Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", executed);
+    let rewards = db.get_distribution_rewards(distribution_id.parse().unwrap()).await.unwrap();
+    let mut amounts: Vec<_> = rewards.iter().map(|r| (r.user_address.clone(), r.amount)).collect();
+    amounts.sort();
+    assert_eq!(
+        amounts,
+        vec![
+            (holder_a.clone(), TokenAmount::parse_decimal("4125").unwrap()),
+            (holder_b.clone(), TokenAmount::parse_decimal("1375").unwrap()),
+        ]
+    );
+
+    // 6. A rejected season can be reported again
+    let (_, next) = send("POST", reports_uri.clone(), &farmer.token, report("2027", &evidence_key)).await;
+    let next_id = next["id"].as_str().unwrap().to_string();
+    let (status, _) = send("PUT", format!("/admin/harvest-reports/{}/reject", next_id), &admin.token, serde_json::json!({ "note": "Blurry photos" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send("POST", reports_uri, &farmer.token, report("2027", &evidence_key)).await;
+    assert_eq!(status, StatusCode::OK);
+}
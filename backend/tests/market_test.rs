@@ -0,0 +1,156 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use web_app::db::market::{NewOrder, OrderSide, OrderType};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+const TOKEN: &str = "0:58f8e5b06a6da7ec33b6e7157b8ee5164bd315f718c9d5bc0bd5d2129180cd70";
+
+#[tokio::test]
+async fn test_orders_match_and_settle_from_escrow() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "market").await;
+
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Traded Orchard".to_string();
+        c.token_name = "Traded".to_string();
+        c.token_symbol = "TRD".to_string();
+    })
+    .await;
+
+    let call = |method: &str, uri: String, user: &str, body: Option<Value>| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("X-User-Address", user)
+            .body(body.map(|b| Body::from(b.to_string())).unwrap_or_else(Body::empty))
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+    let whole = |n: u128| TokenAmount::from_whole(n).unwrap();
+    let seller = format!("EQ_SELLER_{}", uuid::Uuid::new_v4());
+    let buyer = format!("EQ_BUYER_{}", uuid::Uuid::new_v4());
+    let orders_uri = format!("/market/{}/orders", campaign_id);
+
+    // 1. Nothing trades before the campaign jetton exists, and bad orders are refused
+    let sell = NewOrder {
+        side: OrderSide::Sell,
+        order_type: OrderType::Limit,
+        price: Some(whole(2)),
+        amount: whole(10),
+        max_mkoin: None,
+    };
+    let (status, _) = call("POST", orders_uri.clone(), &seller, Some(serde_json::to_value(sell).unwrap())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    db.update_campaign_token_address(campaign_id, TOKEN).await.unwrap();
+    let priceless = serde_json::json!({ "side": "buy", "order_type": "limit", "amount": whole(1) });
+    assert_eq!(call("POST", orders_uri.clone(), &buyer, Some(priceless)).await.0, StatusCode::BAD_REQUEST);
+    let unbounded = serde_json::json!({ "side": "buy", "order_type": "market", "amount": whole(1) });
+    assert_eq!(call("POST", orders_uri.clone(), &buyer, Some(unbounded)).await.0, StatusCode::BAD_REQUEST);
+
+    // 2. An escrowed ask rests in the book
+    let ask = db.create_order(&seller, campaign_id, &sell).await.unwrap();
+    assert_eq!(ask.status, "awaiting_escrow");
+    assert_eq!(ask.escrow_amount, whole(10));
+    let trades = db.activate_order(ask.id, &format!("escrow-{}", ask.id)).await.unwrap().unwrap();
+    assert!(trades.is_empty());
+    assert!(db.activate_order(ask.id, "again").await.unwrap().is_none());
+
+    // 3. A crossing bid trades at the ask's price and gets back what it saved
+    let bid = NewOrder {
+        side: OrderSide::Buy,
+        order_type: OrderType::Limit,
+        price: Some(whole(3)),
+        amount: whole(4),
+        max_mkoin: None,
+    };
+    let bid = db.create_order(&buyer, campaign_id, &bid).await.unwrap();
+    assert_eq!(bid.escrow_amount, whole(12));
+    let trades = db.activate_order(bid.id, &format!("escrow-{}", bid.id)).await.unwrap().unwrap();
+    assert_eq!(trades.len(), 1);
+    assert_eq!((trades[0].price, trades[0].amount, trades[0].mkoin_amount), (whole(2), whole(4), whole(8)));
+
+    let (status, details) = call("GET", format!("/market/orders/{}", bid.id), &buyer, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(details["order"]["status"], "filled");
+    let mut legs: Vec<(String, String)> = details["transfers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| (t["asset"].as_str().unwrap().to_string(), t["amount"].as_str().unwrap().to_string()))
+        .collect();
+    legs.sort();
+    assert_eq!(
+        legs,
+        vec![
+            ("mkoin".to_string(), whole(4).nano().to_string()),
+            ("tokens".to_string(), whole(4).nano().to_string()),
+        ]
+    );
+    let seller_legs = db.get_order_transfers(ask.id).await.unwrap();
+    assert_eq!(seller_legs.len(), 1);
+    assert_eq!((seller_legs[0].asset.as_str(), seller_legs[0].amount), ("mkoin", whole(8)));
+
+    // 4. Book, trade history and portfolio valuation reflect the trade
+    let (_, book) = call("GET", format!("/market/{}/orderbook", campaign_id), &buyer, None).await;
+    assert_eq!(book["last_price"], whole(2).nano().to_string());
+    assert_eq!(book["asks"][0]["amount"], whole(6).nano().to_string());
+    assert_eq!(book["bids"].as_array().unwrap().len(), 0);
+    let (_, trades) = call("GET", format!("/market/{}/trades", campaign_id), &buyer, None).await;
+    assert_eq!(trades.as_array().unwrap().len(), 1);
+    assert_eq!(db.get_last_trade_prices(&[campaign_id]).await.unwrap()[&campaign_id], whole(2));
+
+    // 5. Only the owner cancels, and the rest of the escrow goes back
+    let cancel_uri = format!("/market/orders/{}", ask.id);
+    assert_eq!(call("DELETE", cancel_uri.clone(), &buyer, None).await.0, StatusCode::NOT_FOUND);
+    let (status, cancelled) = call("DELETE", cancel_uri.clone(), &seller, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cancelled["status"], "cancelled");
+    assert_eq!(call("DELETE", cancel_uri, &seller, None).await.0, StatusCode::CONFLICT);
+    let refund = db
+        .get_order_transfers(ask.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|t| t.trade_id.is_none())
+        .unwrap();
+    assert_eq!((refund.asset.as_str(), refund.amount), ("tokens", whole(6)));
+
+    // 6. Escrow landing after its order was cancelled is refunded once
+    let late = db.create_order(&buyer, campaign_id, &NewOrder {
+        side: OrderSide::Buy,
+        order_type: OrderType::Limit,
+        price: Some(whole(1)),
+        amount: whole(3),
+        max_mkoin: None,
+    }).await.unwrap();
+    let (status, cancelled) = call("DELETE", format!("/market/orders/{}", late.id), &buyer, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cancelled["status"], "cancelled");
+    assert!(db.get_order_transfers(late.id).await.unwrap().is_empty());
+    assert!(db.get_unescrowed_closed_orders(1000).await.unwrap().iter().any(|o| o.id == late.id));
+
+    assert!(db.refund_late_escrow(late.id, &format!("escrow-{}", late.id)).await.unwrap());
+    assert!(!db.refund_late_escrow(late.id, &format!("escrow-{}", late.id)).await.unwrap());
+    let refunds = db.get_order_transfers(late.id).await.unwrap();
+    assert_eq!(refunds.len(), 1);
+    assert_eq!((refunds[0].asset.as_str(), refunds[0].amount), ("mkoin", whole(3)));
+    assert!(!db.get_unescrowed_closed_orders(1000).await.unwrap().iter().any(|o| o.id == late.id));
+}
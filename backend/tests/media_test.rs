@@ -0,0 +1,156 @@
+use web_app::api;
+use web_app::media::s3::S3Storage;
+use web_app::media::storage::Storage;
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, Request, StatusCode},
+    routing::put,
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+// Minimal MinIO-style stand-in: path-style PUT/GET/HEAD that insists on SigV4 headers
+async fn put_object(
+    State(objects): State<Objects>,
+    Path((bucket, key)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).unwrap_or("");
+    let declared = headers.get("x-amz-content-sha256").and_then(|v| v.to_str().ok()).unwrap_or("");
+    if !auth.starts_with("AWS4-HMAC-SHA256 Credential=test-key/") || declared != hex::encode(Sha256::digest(&body)) {
+        return StatusCode::FORBIDDEN;
+    }
+    objects.lock().unwrap().insert(format!("{}/{}", bucket, key), body.to_vec());
+    StatusCode::OK
+}
+
+async fn get_object(
+    State(objects): State<Objects>,
+    Path((bucket, key)): Path<(String, String)>,
+) -> Result<Vec<u8>, StatusCode> {
+    objects
+        .lock()
+        .unwrap()
+        .get(&format!("{}/{}", bucket, key))
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[tokio::test]
+async fn test_s3_storage_roundtrip() {
+    let objects: Objects = Arc::default();
+    let app = Router::new()
+        .route("/{bucket}/{*key}", put(put_object).get(get_object))
+        .with_state(objects.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let storage = S3Storage::new(&endpoint, "media", "us-east-1", "test-key", "test-secret");
+
+    assert!(!storage.exists("abc.png").await.unwrap());
+    storage.put("abc.png", b"hello".to_vec(), "image/png").await.unwrap();
+    assert!(storage.exists("abc.png").await.unwrap());
+    assert_eq!(storage.get("abc.png").await.unwrap(), Some(b"hello".to_vec()));
+    assert_eq!(storage.get("missing.png").await.unwrap(), None);
+    assert!(objects.lock().unwrap().contains_key("media/abc.png"));
+}
+
+fn multipart_body(boundary: &str, content_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"logo\"\r\nContent-Type: {content_type}\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body
+}
+
+#[tokio::test]
+async fn test_upload_media() {
+    let media_dir = std::env::temp_dir().join(format!("hazelnut-media-{}", uuid::Uuid::new_v4()));
+    // SAFETY: only test in this binary that reads the media env vars
+    unsafe {
+        std::env::set_var("MEDIA_STORAGE", "local");
+        std::env::set_var("MEDIA_DIR", &media_dir);
+        std::env::set_var("MEDIA_BASE_URL", "http://localhost/media");
+    }
+
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let username = format!("test_media_{}", uuid::Uuid::new_v4());
+    let user_id = db.create_user_full(&username, "x", "farmer", &username, None).await.unwrap();
+    let token = web_app::auth::create_jwt(user_id, &username, "farmer").unwrap();
+
+    let mut png = Cursor::new(Vec::new());
+    image::DynamicImage::new_rgb8(600, 300)
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+
+    // 1. Upload
+    let boundary = "XBOUNDARYX";
+    let req = Request::builder()
+        .uri("/media")
+        .method("POST")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::from(multipart_body(boundary, "image/png", png.get_ref())))
+        .unwrap();
+
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["width"], 600);
+    assert_eq!(json["content_type"], "image/png");
+    let key = json["key"].as_str().unwrap().to_string();
+    assert_eq!(json["url"], format!("http://localhost/media/{}", key));
+    assert!(json["thumbnails"]["128"].as_str().unwrap().starts_with("http://localhost/media/"));
+
+    // 2. Serve it back with immutable caching
+    let req_get = Request::builder()
+        .uri(format!("/media/{}", key))
+        .body(Body::empty())
+        .unwrap();
+    let response_get = app.clone().oneshot(req_get).await.unwrap();
+    assert_eq!(response_get.status(), StatusCode::OK);
+    assert!(response_get.headers()["cache-control"].to_str().unwrap().contains("immutable"));
+
+    // 3. Declared type must match the content
+    let req_bad = Request::builder()
+        .uri("/media")
+        .method("POST")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .header("Authorization", format!("Bearer {}", token))
+        .body(Body::from(multipart_body(boundary, "image/gif", png.get_ref())))
+        .unwrap();
+    let response_bad = app.clone().oneshot(req_bad).await.unwrap();
+    assert_eq!(response_bad.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    // 4. Only farmers and admins upload
+    let viewer_token = web_app::auth::create_jwt(user_id, &username, "viewer").unwrap();
+    let req_viewer = Request::builder()
+        .uri("/media")
+        .method("POST")
+        .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+        .header("Authorization", format!("Bearer {}", viewer_token))
+        .body(Body::from(multipart_body(boundary, "image/png", png.get_ref())))
+        .unwrap();
+    let response_viewer = app.oneshot(req_viewer).await.unwrap();
+    assert_eq!(response_viewer.status(), StatusCode::FORBIDDEN);
+
+    let _ = std::fs::remove_dir_all(media_dir);
+}
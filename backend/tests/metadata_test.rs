@@ -0,0 +1,75 @@
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_jetton_metadata() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    // 1. Create a farmer and a campaign directly in the DB
+    let farmer = common::create_farmer(&db, "meta").await;
+
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Hazelnut Orchard".to_string();
+        c.description = Some("Orchard in Bar".to_string());
+        c.token_name = "Orchard Token".to_string();
+        c.token_symbol = "ORCH".to_string();
+        c.token_supply = "1000".to_string();
+        c.logo_url = Some("https://example.com/logo.png".to_string());
+        c.status = "pending".to_string();
+    })
+    .await;
+
+    // 2. A campaign under review has no jetton yet
+    let req = Request::builder()
+        .uri(format!("/metadata/{}", campaign_id))
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // 3. Once approved and deployed, fetch TEP-64 metadata
+    db.update_campaign_status(campaign_id, "approved").await.unwrap();
+    db.update_campaign_token_address(campaign_id, &format!("EQ_META_TOKEN_{}", campaign_id))
+        .await
+        .unwrap();
+    let req = Request::builder()
+        .uri(format!("/metadata/{}", campaign_id))
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["name"], "Orchard Token");
+    assert_eq!(json["symbol"], "ORCH");
+    assert_eq!(json["description"], "Orchard in Bar");
+    assert_eq!(json["image"], "https://example.com/logo.png");
+    assert_eq!(json["decimals"], "9");
+
+    // 4. Unknown campaign
+    let req_missing = Request::builder()
+        .uri(format!("/metadata/{}", uuid::Uuid::new_v4()))
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let response_missing = app.oneshot(req_missing).await.unwrap();
+    assert_eq!(response_missing.status(), StatusCode::NOT_FOUND);
+
+    // URI baked into the jetton content is keyed by campaign id
+    let url = web_app::ton::factory_service::jetton_metadata_url(campaign_id);
+    assert!(url.ends_with(&format!("/metadata/{}", campaign_id)));
+}
@@ -0,0 +1,87 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_portfolio_values_holdings() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "portfolio").await;
+    let token = format!("EQ_PORTFOLIO_TOKEN_{}", suffix);
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Portfolio Orchard".to_string();
+        c.token_name = "Portfolio".to_string();
+        c.token_symbol = "PFL".to_string();
+        c.suggested_price = TokenAmount::parse_decimal("1.15").unwrap();
+        c.status = "running".to_string();
+        c.token_address = Some(token.clone());
+    })
+    .await;
+
+    // Balances are kept under the raw address, purchases under the form given
+    let raw_holder = format!("0:{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let holder = tonlib_core::TonAddress::from_hex_str(&raw_holder).unwrap().to_base64_url();
+    let nobody = format!("EQ_PORTFOLIO_NOBODY_{}", suffix);
+
+    // Bought 100 tokens for 100 MKOIN, now priced at 1.15
+    sqlx::query(
+        "INSERT INTO purchases (user_address, campaign_id, mkoin_paid, tokens_received, status) VALUES ($1, $2, $3, $4, 'confirmed')",
+    )
+    .bind(&holder)
+    .bind(campaign_id)
+    .bind(TokenAmount::parse_decimal("100").unwrap())
+    .bind(TokenAmount::parse_decimal("100").unwrap())
+    .execute(&db.pool)
+    .await
+    .unwrap();
+    db.upsert_portfolio(&holder, &token, TokenAmount::parse_decimal("100").unwrap(), 1).await.unwrap();
+
+    let get = |address: String| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri(format!("/portfolio/{}", address))
+            .body(Body::empty())
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    let (status, body) = get(holder.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    // Either form of the address is one position
+    let (_, raw_body) = get(raw_holder.clone()).await;
+    assert_eq!(raw_body, body);
+    let portfolio = &body["portfolio"];
+    assert_eq!(portfolio["totalInvested"], 100.0);
+    assert_eq!(portfolio["totalValue"], 115.0);
+    assert_eq!(portfolio["totalProfit"].as_f64().map(|p| p.round()), Some(15.0));
+    assert_eq!(portfolio["profitPercentage"].as_f64().map(|p| p.round()), Some(15.0));
+
+    let holding = &portfolio["holdings"][0];
+    assert_eq!(holding["amount"], 100.0);
+    assert_eq!(holding["currentValue"], 115.0);
+    assert_eq!(holding["token"]["id"], campaign_id.to_string());
+    assert_eq!(holding["token"]["symbol"], "PFL");
+    assert_eq!(holding["token"]["price"], 1.15);
+    assert_eq!(holding["token"]["status"], "active");
+
+    // No holdings, no totals
+    let (status, body) = get(nobody).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["portfolio"]["holdings"].as_array().unwrap().is_empty());
+    assert_eq!(body["portfolio"]["totalValue"], 0.0);
+}
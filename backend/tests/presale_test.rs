@@ -0,0 +1,198 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+const LISTED: &str = "EQANIErWjj6U4FNgSfEHwR6x-bkkJCV1n5w1OIb-Pf6eWQwD";
+const NOT_LISTED: &str = "EQBY-OWwam2n7DO25xV7juUWS9MV9xjJ1bwL1dISkYDNcGP2";
+
+#[tokio::test]
+async fn test_presale_allowlist_and_price() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "presale").await;
+    let admin = common::create_admin(&db, "presale").await;
+
+    // Public sale tomorrow, presale open since an hour ago at 2 MKOIN per token
+    let now = chrono::Utc::now();
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Presale Orchard".to_string();
+        c.token_name = "Presale".to_string();
+        c.token_symbol = "PRE".to_string();
+        c.start_time = now + chrono::Duration::days(1);
+        c.end_time = now + chrono::Duration::days(30);
+        c.suggested_price = TokenAmount::parse_decimal("3").unwrap();
+        c.presale_start_time = Some(now - chrono::Duration::hours(1));
+        c.presale_price = Some(TokenAmount::parse_decimal("2").unwrap());
+    })
+    .await;
+
+    // 1. Import the allowlist as CSV
+    let csv = format!("address\n{}\nnot-an-address\n", LISTED);
+    let req = Request::builder()
+        .uri(format!("/campaigns/{}/allowlist", campaign_id))
+        .method("POST")
+        .header("content-type", "text/csv")
+        .header("Authorization", format!("Bearer {}", admin.token))
+        .body(Body::from(csv))
+        .unwrap();
+    let res = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    let import: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(import["added"], 1);
+    assert_eq!(import["invalid"][0], "not-an-address");
+
+    let post = |uri: &'static str, buyer: &'static str, body: Value| {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .uri(uri)
+                .method("POST")
+                .header("content-type", "application/json")
+                .header("X-User-Address", buyer)
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // 2. Only allowlisted buyers get a quote, at the presale price
+    let quote_body = serde_json::json!({ "campaign_id": campaign_id, "mkoin_amount": "2000000000" });
+    assert_eq!(post("/purchases/quote", NOT_LISTED, quote_body).await.0, StatusCode::FORBIDDEN);
+
+    let mkoin = TokenAmount::from_nano(2_000_000_000);
+    let quote = db.create_quote(LISTED, campaign_id, mkoin, "EQ_TREASURY").await.unwrap();
+    assert_eq!(quote.tokens, TokenAmount::from_nano(1_000_000_000));
+    let (status, body) = post(
+        "/purchases",
+        LISTED,
+        serde_json::json!({
+            "campaign_id": campaign_id,
+            "mkoin_paid": mkoin,
+            "quote_id": quote.id,
+            "signature": web_app::auth::sign_message(&quote.signing_message()),
+            "tx_hash": uuid::Uuid::new_v4().to_string(),
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["tokens_received"], "1000000000");
+
+    // 3. The catalog tells the caller whether they are eligible
+    let eligibility = |address: &'static str| {
+        let app = app.clone();
+        let token = admin.token.clone();
+        async move {
+            let req = Request::builder()
+                .uri(format!("/campaigns/{}", campaign_id))
+                .header("Authorization", format!("Bearer {}", token))
+                .header("X-User-Address", address)
+                .body(Body::empty())
+                .unwrap();
+            let res = app.oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            let campaign: Value = serde_json::from_slice(&body).unwrap();
+            campaign["presale_eligible"].clone()
+        }
+    };
+    assert_eq!(eligibility(LISTED).await, Value::Bool(true));
+    assert_eq!(eligibility(NOT_LISTED).await, Value::Bool(false));
+
+    // 4. A new presale price joins the sale price series, an unchanged one does not
+    let sale_prices = || async {
+        db.get_price_history(campaign_id, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|p| p.source == "sale")
+            .map(|p| p.price)
+            .collect::<Vec<_>>()
+    };
+    let before = sale_prices().await.len();
+    let presale_price = TokenAmount::parse_decimal("2.5").unwrap();
+    let presale_start = Some(now - chrono::Duration::hours(1));
+    db.update_campaign_presale(campaign_id, presale_start, Some(presale_price)).await.unwrap();
+    db.update_campaign_presale(campaign_id, presale_start, Some(presale_price)).await.unwrap();
+    let prices = sale_prices().await;
+    assert_eq!(prices.len(), before + 1);
+    assert_eq!(prices[0], presale_price);
+}
+
+#[tokio::test]
+async fn test_sale_window_boundaries() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "window").await;
+    let now = chrono::Utc::now();
+    let upcoming = common::insert_campaign(&db, farmer.id, |c| {
+        c.start_time = now + chrono::Duration::days(1);
+        c.end_time = now + chrono::Duration::days(30);
+    })
+    .await;
+    let ended = common::insert_campaign(&db, farmer.id, |c| {
+        c.start_time = now - chrono::Duration::days(30);
+        c.end_time = now - chrono::Duration::hours(1);
+    })
+    .await;
+    let presale_ended = common::insert_campaign(&db, farmer.id, |c| {
+        c.start_time = now - chrono::Duration::days(30);
+        c.end_time = now - chrono::Duration::hours(1);
+        c.presale_start_time = Some(now - chrono::Duration::days(31));
+    })
+    .await;
+
+    let quote = |campaign_id: uuid::Uuid| {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .uri("/purchases/quote")
+                .method("POST")
+                .header("content-type", "application/json")
+                .header("X-User-Address", NOT_LISTED)
+                .body(Body::from(
+                    serde_json::json!({ "campaign_id": campaign_id, "mkoin_amount": "1000000000" }).to_string(),
+                ))
+                .unwrap();
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, String::from_utf8_lossy(&body).into_owned())
+        }
+    };
+
+    // Without a presale nothing sells before the start time
+    let (status, body) = quote(upcoming).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(body.starts_with("Sale opens at"), "{}", body);
+
+    // Once the campaign has ended, neither the public sale nor a presale sells
+    for campaign_id in [ended, presale_ended] {
+        let (status, body) = quote(campaign_id).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.starts_with("Sale closed at"), "{}", body);
+    }
+
+    // The db layer reports which end of the window was hit
+    let err = db
+        .create_quote(NOT_LISTED, ended, TokenAmount::from_nano(1_000_000_000), "EQ_TREASURY")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<web_app::db::presale::PresaleError>(),
+        Some(web_app::db::presale::PresaleError::Closed(_))
+    ));
+}
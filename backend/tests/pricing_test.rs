@@ -0,0 +1,114 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use web_app::db::prices::PriceSource;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_nav_override_revalues_portfolio_and_is_audited() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "pricing").await;
+    let admin = common::create_admin(&db, "pricing").await;
+
+    let token = format!("EQ_PRICING_TOKEN_{}", suffix);
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Pricing Orchard".to_string();
+        c.token_name = "Pricing".to_string();
+        c.token_symbol = "PRC".to_string();
+        c.status = "running".to_string();
+        c.token_address = Some(token.clone());
+    })
+    .await;
+
+    let holder = format!("EQ_PRICING_HOLDER_{}", suffix);
+    db.upsert_portfolio(&holder, &token, TokenAmount::parse_decimal("10").unwrap(), 1).await.unwrap();
+
+    let send = |method: &str, uri: String, token: &str, body: Value| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+    let prices_uri = format!("/admin/campaigns/{}/prices", campaign_id);
+    let price_uri = format!("/admin/campaigns/{}/price", campaign_id);
+    let source_uri = format!("/admin/campaigns/{}/price-source", campaign_id);
+    let portfolio_uri = format!("/portfolio/{}", holder);
+
+    // 1. Nothing traded: valued at the sale price
+    let (status, body) = send("GET", prices_uri.clone(), &admin.token, Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["price_source"], "trade");
+    assert_eq!(body["current"]["source"], "sale");
+    assert_eq!(body["history"].as_array().unwrap().len(), 1);
+    let (_, body) = send("GET", portfolio_uri.clone(), &admin.token, Value::Null).await;
+    assert_eq!(body["portfolio"]["totalValue"], 10.0);
+
+    // 2. Only admins set prices, and there is no NAV to switch to yet
+    let nav = serde_json::json!({ "price": "1.5", "note": "Q3 valuation" });
+    let (status, _) = send("PUT", price_uri.clone(), &farmer.token, nav.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send("PUT", price_uri.clone(), &admin.token, serde_json::json!({ "price": "0" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send("PUT", source_uri.clone(), &admin.token, serde_json::json!({ "source": "nav" })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send("PUT", source_uri.clone(), &admin.token, serde_json::json!({ "source": "spot" })).await;
+    assert!(status.is_client_error());
+
+    // 3. A NAV switches the campaign to it
+    let (status, body) = send("PUT", price_uri.clone(), &admin.token, nav).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["price_source"], "nav");
+    assert_eq!(body["current"]["source"], "nav");
+    assert_eq!(body["current"]["price"], TokenAmount::parse_decimal("1.5").unwrap().nano().to_string());
+    let (_, body) = send("GET", portfolio_uri.clone(), &admin.token, Value::Null).await;
+    assert_eq!(body["portfolio"]["totalValue"], 15.0);
+    assert_eq!(body["portfolio"]["holdings"][0]["token"]["price"], 1.5);
+    let while_nav = chrono::Utc::now();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+    // 4. Back to the sale price
+    let (status, body) = send("PUT", source_uri.clone(), &admin.token, serde_json::json!({ "source": "sale" })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["current"]["source"], "sale");
+    let (_, body) = send("GET", portfolio_uri, &admin.token, Value::Null).await;
+    assert_eq!(body["portfolio"]["totalValue"], 10.0);
+
+    // 5. Every change is audited, newest first
+    let (_, body) = send("GET", prices_uri, &admin.token, Value::Null).await;
+    let audit = body["audit"].as_array().unwrap();
+    let actions: Vec<_> = audit.iter().map(|e| (e["action"].as_str().unwrap(), e["new_value"].as_str().unwrap())).collect();
+    assert_eq!(actions.len(), 3);
+    assert!(actions.contains(&("set_source", "sale")));
+    assert!(actions.contains(&("set_source", "nav")));
+    assert!(audit.iter().any(|e| e["action"] == "set_nav" && e["note"] == "Q3 valuation" && e["actor_id"] == admin.id.to_string()));
+    assert_eq!(body["history"].as_array().unwrap().len(), 2);
+
+    // 6. Past values use the source the campaign had then
+    let then = db.token_prices_at(&[campaign_id], Some(while_nav)).await.unwrap()[&campaign_id];
+    assert_eq!((then.price, then.source), (TokenAmount::parse_decimal("1.5").unwrap(), PriceSource::Nav));
+    let now = db.token_prices_at(&[campaign_id], None).await.unwrap()[&campaign_id];
+    assert_eq!(now.source, PriceSource::Sale);
+
+    let (status, _) = send("GET", format!("/admin/campaigns/{}/prices", uuid::Uuid::new_v4()), &admin.token, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
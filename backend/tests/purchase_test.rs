@@ -0,0 +1,351 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_purchase_limits_prevent_overselling() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "limits").await;
+
+    // 10 tokens for sale, tickets between 1 and 5 MKOIN
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Limited Orchard".to_string();
+        c.token_name = "Limited".to_string();
+        c.token_symbol = "LIM".to_string();
+        c.token_supply = "10".to_string();
+        c.soft_cap = Some(TokenAmount::from_nano(8_000_000_000));
+        c.min_ticket = Some(TokenAmount::from_nano(1_000_000_000));
+        c.max_ticket = Some(TokenAmount::from_nano(5_000_000_000));
+    })
+    .await;
+
+    let post = |uri: &'static str, buyer: String, body: Value| {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .uri(uri)
+                .method("POST")
+                .header("content-type", "application/json")
+                .header("X-User-Address", buyer)
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        }
+    };
+    let quote_request = |amount: u64| {
+        serde_json::json!({ "campaign_id": campaign_id, "mkoin_amount": amount.to_string() })
+    };
+
+    // 1. Ticket size is enforced when quoting
+    let small = post("/purchases/quote", "EQ_BUYER_SMALL".into(), quote_request(500_000_000));
+    assert_eq!(small.await, StatusCode::BAD_REQUEST);
+    let big = post("/purchases/quote", "EQ_BUYER_BIG".into(), quote_request(6_000_000_000));
+    assert_eq!(big.await, StatusCode::BAD_REQUEST);
+
+    // 2. Five concurrent 3-token purchases against a 10-token supply: only three fit
+    let mut requests = Vec::new();
+    for i in 0..5 {
+        let buyer = format!("EQ_BUYER_{}", i);
+        let quote = db
+            .create_quote(&buyer, campaign_id, TokenAmount::from_nano(3_000_000_000), "EQ_TREASURY")
+            .await
+            .unwrap();
+        let body = serde_json::json!({
+            "campaign_id": campaign_id,
+            "mkoin_paid": quote.mkoin_amount,
+            "quote_id": quote.id,
+            "signature": web_app::auth::sign_message(&quote.signing_message()),
+            "tx_hash": uuid::Uuid::new_v4().to_string(),
+        });
+        requests.push(post("/purchases", buyer, body));
+    }
+    let handles: Vec<_> = requests.into_iter().map(tokio::spawn).collect();
+    let mut ok = 0;
+    let mut conflict = 0;
+    for h in handles {
+        match h.await.unwrap() {
+            StatusCode::OK => ok += 1,
+            StatusCode::CONFLICT => conflict += 1,
+            other => panic!("unexpected status {}", other),
+        }
+    }
+    assert_eq!((ok, conflict), (3, 2));
+
+    // 3. Remaining allocation shows up in stats
+    let req = Request::builder()
+        .uri(format!("/campaigns/{}/stats", campaign_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let stats: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats["remaining_tokens"], "1000000000");
+    // Nothing is confirmed yet
+    assert_eq!(stats["soft_cap_reached"], false);
+}
+
+#[tokio::test]
+async fn test_purchase_requires_live_signed_quote() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "quotes").await;
+
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Quoted Orchard".to_string();
+        c.token_name = "Quoted".to_string();
+        c.token_symbol = "QTE".to_string();
+    })
+    .await;
+
+    const BUYER: &str = "EQ_QUOTE_BUYER";
+    let amount = TokenAmount::from_nano(2_000_000_000);
+    let submit = |quote: &web_app::db::quotes::PurchaseQuote, mkoin_paid: TokenAmount, signature: String| {
+        let app = app.clone();
+        let body = serde_json::json!({
+            "campaign_id": campaign_id,
+            "mkoin_paid": mkoin_paid,
+            "quote_id": quote.id,
+            "signature": signature,
+            "tx_hash": uuid::Uuid::new_v4().to_string(),
+        });
+        async move {
+            let req = Request::builder()
+                .uri("/purchases")
+                .method("POST")
+                .header("content-type", "application/json")
+                .header("X-User-Address", BUYER)
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            app.oneshot(req).await.unwrap().status()
+        }
+    };
+    let sign = |quote: &web_app::db::quotes::PurchaseQuote| web_app::auth::sign_message(&quote.signing_message());
+
+    let quote = db.create_quote(BUYER, campaign_id, amount, "EQ_TREASURY").await.unwrap();
+
+    // 1. Forged signatures and altered terms are rejected
+    assert_eq!(submit(&quote, amount, "00".repeat(32)).await, StatusCode::BAD_REQUEST);
+    let more = TokenAmount::from_nano(3_000_000_000);
+    assert_eq!(submit(&quote, more, sign(&quote)).await, StatusCode::BAD_REQUEST);
+
+    // 2. A quote backs exactly one purchase
+    assert_eq!(submit(&quote, amount, sign(&quote)).await, StatusCode::OK);
+    assert_eq!(submit(&quote, amount, sign(&quote)).await, StatusCode::CONFLICT);
+
+    // 3. Expired quotes are not accepted
+    let expired = db.create_quote(BUYER, campaign_id, amount, "EQ_TREASURY").await.unwrap();
+    sqlx::query("UPDATE purchase_quotes SET expires_at = NOW() - INTERVAL '1 second' WHERE id = $1")
+        .bind(expired.id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let expired = db.get_quote(expired.id).await.unwrap().unwrap();
+    assert_eq!(submit(&expired, amount, sign(&expired)).await, StatusCode::CONFLICT);
+
+    // 4. The quote fixes the wallet paid, and clients cannot hammer the chain
+    // checks of a purchase
+    assert_eq!(quote.treasury_address.as_deref(), Some("EQ_TREASURY"));
+    let purchase_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM purchases WHERE quote_id = $1")
+        .bind(quote.id)
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert!(db.start_purchase_check(purchase_id, 60).await.unwrap());
+    assert!(!db.start_purchase_check(purchase_id, 60).await.unwrap());
+    let req = Request::builder()
+        .uri(format!("/purchases/{}/verify", purchase_id))
+        .method("POST")
+        .body(Body::empty())
+        .unwrap();
+    let status = app.clone().oneshot(req).await.unwrap().status();
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_purchase_idempotency_key_replays_response() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "idempotent").await;
+
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Retried Orchard".to_string();
+        c.token_name = "Retried".to_string();
+        c.token_symbol = "RTY".to_string();
+    })
+    .await;
+
+    let buyer = format!("EQ_IDEMPOTENT_{}", uuid::Uuid::new_v4());
+    let amount = TokenAmount::from_nano(2_000_000_000);
+    let quote = db.create_quote(&buyer, campaign_id, amount, "EQ_TREASURY").await.unwrap();
+    let body = serde_json::json!({
+        "campaign_id": campaign_id,
+        "mkoin_paid": amount,
+        "quote_id": quote.id,
+        "signature": web_app::auth::sign_message(&quote.signing_message()),
+        "tx_hash": uuid::Uuid::new_v4().to_string(),
+    });
+    let key = uuid::Uuid::new_v4().to_string();
+
+    let submit = |body: Value| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri("/purchases")
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("X-User-Address", &buyer)
+            .header("Idempotency-Key", &key)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        async move {
+            let response = app.oneshot(req).await.unwrap();
+            let status = response.status();
+            let bytes = response.into_body().collect().await.unwrap().to_bytes();
+            (status, bytes)
+        }
+    };
+
+    // 1. A retry returns the original purchase instead of a conflict
+    let (status, first) = submit(body.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, replayed) = submit(body.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first, replayed);
+
+    let purchases = db.get_user_purchases(&buyer).await.unwrap();
+    assert_eq!(purchases.len(), 1);
+
+    // 2. The same key cannot be reused for a different request
+    let mut altered = body.clone();
+    altered["tx_hash"] = Value::from(uuid::Uuid::new_v4().to_string());
+    let (status, _) = submit(altered).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // 3. A key left behind by a request that never finished is held for its
+    // lease, then can be claimed again
+    let scope = format!("test_{}", uuid::Uuid::new_v4());
+    assert!(db.begin_idempotent_request(&scope, &buyer, &key, "f").await.unwrap().is_none());
+    let held = db.begin_idempotent_request(&scope, &buyer, &key, "f").await.unwrap().unwrap();
+    assert_eq!(held.response_status, None);
+    sqlx::query("UPDATE idempotency_keys SET created_at = NOW() - INTERVAL '1 hour' WHERE scope = $1")
+        .bind(&scope)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    assert!(db.begin_idempotent_request(&scope, &buyer, &key, "f").await.unwrap().is_none());
+
+    // 4. Failures on our side are replayed rather than run again
+    db.complete_idempotent_request(&scope, &buyer, &key, 502, "Mint may have been broadcast")
+        .await
+        .unwrap();
+    let stored = db.begin_idempotent_request(&scope, &buyer, &key, "f").await.unwrap().unwrap();
+    assert_eq!(stored.response_status, Some(502));
+}
+
+#[tokio::test]
+async fn test_admin_confirm_override_is_audited() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "confirm").await;
+    let admin = common::create_admin(&db, "confirm").await;
+
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Confirmed Orchard".to_string();
+        c.token_name = "Confirmed".to_string();
+        c.token_symbol = "CNF".to_string();
+    })
+    .await;
+
+    let quote = db
+        .create_quote("EQ_CONFIRM_BUYER", campaign_id, TokenAmount::from_nano(4_000_000_000), "EQ_TREASURY")
+        .await
+        .unwrap();
+    let (purchase_id, _) = db
+        .create_purchase("EQ_CONFIRM_BUYER", quote.id, &uuid::Uuid::new_v4().to_string())
+        .await
+        .unwrap();
+    db.expire_purchase(purchase_id, "No payment found").await.unwrap();
+
+    let confirm = |token: String| {
+        let app = app.clone();
+        async move {
+            let req = Request::builder()
+                .uri(format!("/purchases/{}/confirm", purchase_id))
+                .method("PUT")
+                .header("content-type", "application/json")
+                .header("Authorization", format!("Bearer {}", token))
+                .body(Body::from(r#"{"note": "Paid, verified in explorer"}"#))
+                .unwrap();
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // 1. Only admins can override, and only once
+    assert_eq!(confirm(farmer.token).await.0, StatusCode::FORBIDDEN);
+    let (status, purchase) = confirm(admin.token.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(purchase["status"], "confirmed");
+    assert_eq!(purchase["failure_reason"], Value::Null);
+    assert_eq!(confirm(admin.token.clone()).await.0, StatusCode::CONFLICT);
+
+    // 2. Confirmed purchases count towards the campaign
+    let stats = db.get_campaign_stats(campaign_id).await.unwrap();
+    assert_eq!(stats.total_purchases, 1);
+
+    // 3. The override is attributed to the admin
+    let req = Request::builder()
+        .uri(format!("/admin/purchases/{}/audit", purchase_id))
+        .header("Authorization", format!("Bearer {}", admin.token))
+        .body(Body::empty())
+        .unwrap();
+    let res = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    let audit: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(audit.as_array().unwrap().len(), 1);
+    assert_eq!(audit[0]["previous_status"], "expired");
+    assert_eq!(audit[0]["actor_id"], admin.id.to_string());
+
+    // 4. An expired purchase whose tokens were sold since cannot be revived
+    let quote = db
+        .create_quote("EQ_CONFIRM_LATE", campaign_id, TokenAmount::from_nano(4_000_000_000), "EQ_TREASURY")
+        .await
+        .unwrap();
+    let (late_id, _) = db
+        .create_purchase("EQ_CONFIRM_LATE", quote.id, &uuid::Uuid::new_v4().to_string())
+        .await
+        .unwrap();
+    db.expire_purchase(late_id, "No payment found").await.unwrap();
+    let quote = db
+        .create_quote("EQ_CONFIRM_REST", campaign_id, TokenAmount::from_nano(96_000_000_000), "EQ_TREASURY")
+        .await
+        .unwrap();
+    db.create_purchase("EQ_CONFIRM_REST", quote.id, &uuid::Uuid::new_v4().to_string())
+        .await
+        .unwrap();
+    let err = db
+        .admin_confirm_purchase(late_id, Some(admin.id), None, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<web_app::db::limits::PurchaseLimitError>(),
+        Some(web_app::db::limits::PurchaseLimitError::SoldOut { .. })
+    ));
+    assert_eq!(db.get_purchase(late_id).await.unwrap().unwrap().status, "expired");
+}
@@ -0,0 +1,124 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_redemption_is_approved_paid_and_audited() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let admin_username = format!("test_admin_redeem_{}", uuid::Uuid::new_v4());
+    let admin_id = db.create_user_full(&admin_username, "x", "admin", &admin_username, None).await.unwrap();
+    let admin_token = web_app::auth::create_jwt(admin_id, &admin_username, "admin").unwrap();
+
+    let call = |method: &str, uri: String, user: &str, body: Option<Value>| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("X-User-Address", user)
+            .header("Authorization", format!("Bearer {}", admin_token))
+            .body(body.map(|b| Body::from(b.to_string())).unwrap_or_else(Body::empty))
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+    let user = format!("EQ_REDEEMER_{}", uuid::Uuid::new_v4());
+    let other = format!("EQ_OTHER_{}", uuid::Uuid::new_v4());
+
+    // 1. Bank accounts are validated and belong to their user
+    let bad = serde_json::json!({ "iban": "DE88 3704 0044 0532 0130 00", "holder_name": "Ana Farmer" });
+    assert_eq!(call("POST", "/bank-accounts".into(), &user, Some(bad)).await.0, StatusCode::BAD_REQUEST);
+    let good = serde_json::json!({ "iban": "de89 3704 0044 0532 0130 00", "holder_name": "Ana Farmer" });
+    let (status, account) = call("POST", "/bank-accounts".into(), &user, Some(good)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(account["iban"], "DE89370400440532013000");
+    let account_id: uuid::Uuid = account["id"].as_str().unwrap().parse().unwrap();
+    let (_, accounts) = call("GET", "/bank-accounts".into(), &user, None).await;
+    assert_eq!(accounts.as_array().unwrap().len(), 1);
+
+    let amount = TokenAmount::from_whole(25).unwrap();
+    assert!(db.create_redemption(&other, account_id, amount).await.is_err());
+
+    // 2. A request is held until its MKOIN is received
+    let redemption = db.create_redemption(&user, account_id, amount).await.unwrap();
+    assert_eq!(redemption.status, "awaiting_transfer");
+    let approve_uri = format!("/admin/redemptions/{}/approve", redemption.id);
+    assert_eq!(call("PUT", approve_uri.clone(), &user, Some(serde_json::json!({}))).await.0, StatusCode::CONFLICT);
+
+    db.mark_redemption_received(redemption.id, &format!("tx-{}", redemption.id)).await.unwrap();
+    assert_eq!(
+        call("DELETE", format!("/redemptions/{}", redemption.id), &user, None).await.0,
+        StatusCode::CONFLICT
+    );
+    let (_, queue) = call("GET", "/admin/redemptions".into(), &user, None).await;
+    assert!(queue.as_array().unwrap().iter().any(|r| r["id"] == redemption.id.to_string()));
+
+    // 3. Admin approves, then records the payout
+    let paid_uri = format!("/admin/redemptions/{}/paid", redemption.id);
+    let paid = serde_json::json!({ "bank_reference": "SEPA-0001" });
+    assert_eq!(call("PUT", paid_uri.clone(), &user, Some(paid.clone())).await.0, StatusCode::CONFLICT);
+    let (status, approved) = call("PUT", approve_uri, &user, Some(serde_json::json!({ "note": "KYC checked" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(approved["status"], "approved");
+    let (status, marked) = call("PUT", paid_uri, &user, Some(paid)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((marked["status"].as_str(), marked["bank_reference"].as_str()), (Some("paid"), Some("SEPA-0001")));
+    assert_eq!(
+        call("PUT", format!("/admin/redemptions/{}/reject", redemption.id), &user, Some(serde_json::json!({}))).await.0,
+        StatusCode::CONFLICT
+    );
+
+    // 4. The burn completes the request, and every step is on record
+    assert_eq!(db.start_redemption_settlement(redemption.id, 3).await.unwrap(), Some(1));
+    let paid = db.get_redemption(redemption.id).await.unwrap().unwrap();
+    let burning = db.mark_redemption_settlement_sent(&paid, "burn-msg").await.unwrap();
+    assert_eq!(burning.status, "burning");
+    let burned = db.complete_redemption_settlement(&burning, "burn-tx").await.unwrap();
+    assert_eq!(burned.status, "burned");
+
+    assert_eq!(
+        call("GET", format!("/redemptions/{}", redemption.id), &other, None).await.0,
+        StatusCode::NOT_FOUND
+    );
+    let (status, details) = call("GET", format!("/redemptions/{}", redemption.id), &user, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(details["bank_account"]["holder_name"], "Ana Farmer");
+    let history: Vec<&str> = details["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["new_status"].as_str().unwrap())
+        .collect();
+    assert_eq!(history, vec!["awaiting_transfer", "received", "approved", "paid", "burning", "burned"]);
+    assert_eq!(details["events"][2]["actor_id"], admin_id.to_string());
+
+    assert!(burned.settle_sent_at.is_some());
+
+    // 5. MKOIN arriving after a request was cancelled is returned
+    let late = db.create_redemption(&user, account_id, amount).await.unwrap();
+    let (status, cancelled) = call("DELETE", format!("/redemptions/{}", late.id), &user, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cancelled["status"], "cancelled");
+    let closed = db.get_unreceived_closed_redemptions(1000).await.unwrap();
+    assert!(closed.iter().any(|r| r.id == late.id));
+
+    let rejected = db.reject_late_redemption(late.id, &format!("tx-{}", late.id)).await.unwrap();
+    assert_eq!(rejected.status, "rejected");
+    assert!(db.reject_late_redemption(late.id, "again").await.is_err());
+    assert!(!db.get_unreceived_closed_redemptions(1000).await.unwrap().iter().any(|r| r.id == late.id));
+    assert_eq!(db.start_redemption_settlement(late.id, 3).await.unwrap(), Some(1));
+}
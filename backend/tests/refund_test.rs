@@ -0,0 +1,155 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use web_app::db::refunds::RefundReason;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_refund_batch_for_cancelled_campaign() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let farmer = common::create_farmer(&db, "refund").await;
+    let admin = common::create_admin(&db, "refund").await;
+
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Refund Orchard".to_string();
+        c.token_name = "Refund".to_string();
+        c.token_symbol = "RFD".to_string();
+        c.status = "running".to_string();
+        c.token_address = Some(format!("EQ_REFUND_TOKEN_{}", uuid::Uuid::new_v4()));
+    })
+    .await;
+
+    // Three confirmed purchases and one that never confirmed
+    let mut confirmed = Vec::new();
+    for (buyer, amount) in [
+        ("EQ_REFUND_A", 2_000_000_000),
+        ("EQ_REFUND_B", 3_000_000_000),
+        ("EQ_REFUND_D", 4_000_000_000),
+    ] {
+        let amount = TokenAmount::from_nano(amount);
+        let quote = db.create_quote(buyer, campaign_id, amount, "EQ_TREASURY").await.unwrap();
+        let (id, _) = db
+            .create_purchase(buyer, quote.id, &uuid::Uuid::new_v4().to_string())
+            .await
+            .unwrap();
+        sqlx::query("UPDATE purchases SET status = 'confirmed' WHERE id = $1")
+            .bind(id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        confirmed.push(id);
+    }
+    let amount = TokenAmount::from_nano(1_000_000_000);
+    let quote = db.create_quote("EQ_REFUND_C", campaign_id, amount, "EQ_TREASURY").await.unwrap();
+    db.create_purchase("EQ_REFUND_C", quote.id, &uuid::Uuid::new_v4().to_string())
+        .await
+        .unwrap();
+
+    // The tokens of the last confirmed purchase are already on their way
+    db.queue_token_deliveries().await.unwrap();
+    let sent = db.get_purchase_delivery(confirmed[2]).await.unwrap().unwrap();
+    assert_eq!(db.start_delivery_attempt(sent.id).await.unwrap(), Some(1));
+    db.mark_delivery_sent(sent.id, "msg").await.unwrap();
+
+    let dry_run = || {
+        Request::builder()
+            .uri(format!("/admin/campaigns/{}/refunds", campaign_id))
+            .method("POST")
+            .header("content-type", "application/json")
+            .header("Authorization", format!("Bearer {}", admin.token))
+            .body(Body::from(r#"{"dry_run": true}"#))
+            .unwrap()
+    };
+
+    // Treasuries must be wallets the platform signs for
+    let set_treasury = |address: Value| {
+        Request::builder()
+            .uri(format!("/campaigns/{}/treasury", campaign_id))
+            .method("PUT")
+            .header("content-type", "application/json")
+            .header("Authorization", format!("Bearer {}", admin.token))
+            .body(Body::from(serde_json::json!({ "address": address }).to_string()))
+            .unwrap()
+    };
+    let res = app
+        .clone()
+        .oneshot(set_treasury(Value::from("EQATDLvt8bY8BGb-DGBZxwe6EZla3Rcij41fqv_OFlLXvgpV")))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = app.clone().oneshot(set_treasury(Value::Null)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(db.get_campaign_treasury(campaign_id).await.unwrap(), None);
+
+    // 1. A running campaign is not refundable
+    let res = app.clone().oneshot(dry_run()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    // 2. Once cancelled, the dry run lists only confirmed purchases, and
+    // flags the one whose tokens were sent instead of refunding it
+    db.update_campaign_status(campaign_id, "cancelled").await.unwrap();
+    let res = app.clone().oneshot(dry_run()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    let preview: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(preview["reason"], "cancelled");
+    assert_eq!(preview["total_amount"], "5000000000");
+    assert_eq!(preview["refunds"].as_array().unwrap().len(), 2);
+    let delivered = preview["tokens_delivered"].as_array().unwrap();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0]["purchase_id"], confirmed[2].to_string());
+
+    // Dry run stores nothing
+    assert_eq!(db.plan_refunds(campaign_id).await.unwrap().refunds.len(), 2);
+
+    // 3. Purchases are refunded at most once
+    let batch_id = db
+        .create_refund_batch(campaign_id, RefundReason::Cancelled, Some("EQ_REFUND_TREASURY"), Some(admin.id))
+        .await
+        .unwrap()
+        .expect("batch with refunds");
+    assert!(db
+        .create_refund_batch(campaign_id, RefundReason::Cancelled, Some("EQ_REFUND_TREASURY"), Some(admin.id))
+        .await
+        .unwrap()
+        .is_none());
+
+    let batch = db.get_refund_batch(batch_id).await.unwrap().unwrap();
+    assert_eq!(batch.total_amount.nano(), 5_000_000_000);
+    assert_eq!(batch.treasury_address.as_deref(), Some("EQ_REFUND_TREASURY"));
+
+    // Refunded purchases no longer get their tokens; sent ones are left alone
+    let delivery_status = |purchase_id| {
+        let db = db.clone();
+        async move { db.get_purchase_delivery(purchase_id).await.unwrap().unwrap().status }
+    };
+    assert_eq!(delivery_status(confirmed[0]).await, "cancelled");
+    assert_eq!(delivery_status(confirmed[1]).await, "cancelled");
+    assert_eq!(delivery_status(confirmed[2]).await, "sent");
+
+    // Each send attempt is counted, and only unsent or failed refunds are sent
+    let refunds = db.get_batch_refunds(batch_id).await.unwrap();
+    assert_eq!(db.start_refund_attempt(refunds[1].id).await.unwrap(), Some(1));
+    db.mark_refund_sent(refunds[1].id, "msg").await.unwrap();
+    assert_eq!(db.start_refund_attempt(refunds[1].id).await.unwrap(), None);
+    db.mark_refund_failed(refunds[1].id, "Transfer expired without landing").await.unwrap();
+    assert_eq!(db.start_refund_attempt(refunds[1].id).await.unwrap(), Some(2));
+
+    // 4. Confirmation marks the purchase as refunded
+    let refunds = db.get_batch_refunds(batch_id).await.unwrap();
+    assert_eq!(refunds.len(), 2);
+    db.confirm_refund(refunds[0].id, "tx_refund").await.unwrap();
+
+    let purchases = db.get_campaign_purchases(campaign_id).await.unwrap();
+    let refunded = purchases.iter().find(|p| p.id == refunds[0].purchase_id).unwrap();
+    assert_eq!(refunded.status, "refunded");
+}
@@ -0,0 +1,232 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use web_app::db::rewards::CLAIM_ABANDON_SECS;
+use web_app::ton::merkle;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_claim_distribution_credits_claimable_rewards() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "reward").await;
+    let admin = common::create_admin(&db, "reward").await;
+
+    let token = format!("EQ_REWARD_TOKEN_{}", suffix);
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Reward Orchard".to_string();
+        c.token_name = "Reward".to_string();
+        c.token_symbol = "RWD".to_string();
+        c.status = "finished".to_string();
+        c.token_address = Some(token.clone());
+    })
+    .await;
+
+    let holder_a = format!("EQ_REWARD_A_{}", suffix);
+    let holder_b = format!("EQ_REWARD_B_{}", suffix);
+    db.upsert_portfolio(&holder_a, &token, TokenAmount::from_nano(3), 1).await.unwrap();
+    db.upsert_portfolio(&holder_b, &token, TokenAmount::from_nano(1), 1).await.unwrap();
+
+    let send = |method: &str, uri: String, user: &str, body: Value| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("X-User-Address", user)
+            .header("Authorization", format!("Bearer {}", admin.token))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // 1. Rewards of this campaign expire after 30 days
+    let (status, _) = send("PUT", format!("/campaigns/{}/reward-rules", campaign_id), "", serde_json::json!({ "claim_days": 0 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send("PUT", format!("/campaigns/{}/reward-rules", campaign_id), "", serde_json::json!({ "claim_days": 30 })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // 2. A claim distribution credits rewards instead of sending transfers
+    let (status, created) = send(
+        "POST",
+        "/admin/distribution".into(),
+        "",
+        serde_json::json!({ "target_token": token, "amount_mkoin": "0.000000008", "mode": "claim" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    assert_eq!(created["distribution"]["payout_mode"], "claim");
+    assert_eq!(created["distribution"]["status"], "completed");
+    assert!(created["payouts"].as_array().unwrap().is_empty());
+    assert_eq!(created["rewards"].as_array().unwrap().len(), 2);
+    let distribution_id = created["distribution"]["id"].as_str().unwrap().to_string();
+    let (status, _) = send("POST", format!("/admin/distributions/{}/execute", distribution_id), "", Value::Null).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, mine) = send("GET", "/rewards/my".into(), &holder_a, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(mine["claimable"], "6");
+    let reward = &mine["rewards"][0];
+    assert_eq!((reward["status"].as_str(), reward["amount"].as_str()), (Some("unclaimed"), Some("6")));
+    let expires_at: chrono::DateTime<chrono::Utc> = reward["expires_at"].as_str().unwrap().parse().unwrap();
+    assert!(expires_at > chrono::Utc::now() + chrono::Duration::days(29));
+
+    // 3. Claiming bundles the unclaimed rewards; a failed transfer frees them again
+    let claim = db.start_reward_claim(&holder_a).await.unwrap();
+    assert_eq!(claim.amount, TokenAmount::from_nano(6));
+    assert!(db.start_reward_claim(&holder_a).await.is_err());
+    assert!(db.fail_reward_claim(claim.id, "wallet busy").await.unwrap());
+    let (_, mine) = send("GET", "/rewards/my".into(), &holder_a, Value::Null).await;
+    assert_eq!(mine["rewards"][0]["status"], "unclaimed");
+
+    // A claim left pending by a request that died is settled once abandoned
+    let claim = db.start_reward_claim(&holder_a).await.unwrap();
+    let abandoned = || async {
+        db.get_abandoned_reward_claims(1_000)
+            .await
+            .unwrap()
+            .iter()
+            .any(|c| c.id == claim.id)
+    };
+    assert!(!abandoned().await);
+    sqlx::query("UPDATE reward_claims SET created_at = NOW() - make_interval(secs => $2) WHERE id = $1")
+        .bind(claim.id)
+        .bind((CLAIM_ABANDON_SECS + 1) as f64)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    assert!(abandoned().await);
+    assert!(db.fail_reward_claim(claim.id, "Transfer was never sent").await.unwrap());
+
+    let claim = db.start_reward_claim(&holder_a).await.unwrap();
+    db.mark_reward_claim_sent(claim.id, "claim-msg").await.unwrap();
+    assert!(db.confirm_reward_claim(claim.id, "claim-tx").await.unwrap());
+    let (status, loaded) = send("GET", format!("/rewards/claims/{}", claim.id), &holder_a, Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(loaded["claim"]["status"], "confirmed");
+    let paid = &loaded["rewards"][0];
+    assert_eq!((paid["status"].as_str(), paid["tx_hash"].as_str()), (Some("claimed"), Some("claim-tx")));
+    assert!(paid["claimed_at"].is_string());
+    let (status, _) = send("GET", format!("/rewards/claims/{}", claim.id), &holder_b, Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // 4. Nothing left to claim
+    let (status, _) = send("POST", "/rewards/claim".into(), &holder_a, Value::Null).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // 5. Unclaimed rewards expire with the claim period
+    sqlx::query("UPDATE rewards SET expires_at = NOW() - INTERVAL '1 day' WHERE user_address = $1")
+        .bind(&holder_b)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    let (_, mine) = send("GET", "/rewards/my".into(), &holder_b, Value::Null).await;
+    assert_eq!(mine["claimable"], "0");
+    assert!(db.expire_rewards().await.unwrap() >= 1);
+    let (_, mine) = send("GET", "/rewards/my".into(), &holder_b, Value::Null).await;
+    assert_eq!(mine["rewards"][0]["status"], "expired");
+    let (status, _) = send("POST", "/rewards/claim".into(), &holder_b, Value::Null).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_merkle_distribution_serves_proofs() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "merkle").await;
+    let admin = common::create_admin(&db, "merkle").await;
+
+    let token = format!("EQ_MERKLE_TOKEN_{}", suffix);
+    common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Merkle Orchard".to_string();
+        c.token_name = "Merkle".to_string();
+        c.token_symbol = "MRK".to_string();
+        c.status = "finished".to_string();
+        c.token_address = Some(token.clone());
+    })
+    .await;
+
+    // Leaves hash the holder address, so these have to be real addresses
+    let holder_a = format!("0:{}", hex::encode(suffix.as_bytes()).repeat(2));
+    let holder_b = format!("0:{}{}", "ab".repeat(16), hex::encode(suffix.as_bytes()));
+    db.upsert_portfolio(&holder_a, &token, TokenAmount::from_nano(3), 1).await.unwrap();
+    db.upsert_portfolio(&holder_b, &token, TokenAmount::from_nano(1), 1).await.unwrap();
+
+    let send = |method: &str, uri: String, body: Value| {
+        let app = app.clone();
+        let req = Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .header("Authorization", format!("Bearer {}", admin.token))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // 1. Merkle mode is off unless the operator runs the claim contract
+    let body = serde_json::json!({ "target_token": token, "amount_mkoin": "0.000000008", "mode": "merkle" });
+    let (status, _) = send("POST", "/admin/distribution".into(), body.clone()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    unsafe { std::env::set_var("MERKLE_DISTRIBUTIONS", "true") };
+
+    // 2. A merkle distribution computes the root of its payout lines, and
+    // says it pays nobody
+    let (status, draft) = send("POST", "/admin/distribution".into(), body).await;
+    assert_eq!(status, StatusCode::OK, "{}", draft);
+    assert_eq!(draft["distribution"]["status"], "root_ready");
+    assert!(draft["notice"].as_str().unwrap().contains("not executed"));
+    let distribution_id = draft["distribution"]["id"].as_str().unwrap().to_string();
+    let root = draft["distribution"]["merkle_root"].as_str().unwrap().to_string();
+    assert_eq!(root.len(), 64);
+    assert_eq!(draft["payouts"].as_array().unwrap().len(), 2);
+    assert!(draft["rewards"].as_array().unwrap().is_empty());
+
+    // 3. Nothing is sent by the admin wallet
+    let (status, _) = send("POST", format!("/admin/distributions/{}/execute", distribution_id), Value::Null).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // 4. Each holder gets a proof of their share against the root
+    let (status, proof) = send("GET", format!("/rewards/{}/proof/{}", distribution_id, holder_a), Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", proof);
+    assert_eq!(proof["merkle_root"], root.as_str());
+    assert_eq!(proof["amount"], "6");
+    let hash = |v: &Value| tonlib_core::TonHash::from_hex(v.as_str().unwrap()).unwrap();
+    let siblings: Vec<_> = proof["proof"].as_array().unwrap().iter().map(hash).collect();
+    let leaf = merkle::leaf_hash(&holder_a, TokenAmount::from_nano(6)).unwrap();
+    assert_eq!(leaf, hash(&proof["leaf"]));
+    assert!(merkle::verify_proof(&hash(&proof["merkle_root"]), &leaf, &siblings).unwrap());
+
+    // The user-friendly form of the address finds the same line
+    let friendly = tonlib_core::TonAddress::from_hex_str(&holder_b).unwrap().to_base64_url();
+    let (status, proof) = send("GET", format!("/rewards/{}/proof/{}", distribution_id, friendly), Value::Null).await;
+    assert_eq!(status, StatusCode::OK, "{}", proof);
+    assert_eq!((proof["address"].as_str(), proof["amount"].as_str()), (Some(holder_b.as_str()), Some("2")));
+
+    let stranger = format!("0:{}", "cd".repeat(32));
+    let (status, _) = send("GET", format!("/rewards/{}/proof/{}", distribution_id, stranger), Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send("GET", format!("/rewards/{}/proof/{}", uuid::Uuid::new_v4(), holder_a), Value::Null).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
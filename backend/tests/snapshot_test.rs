@@ -0,0 +1,175 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use web_app::db::transfers::NewTransfer;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+#[tokio::test]
+async fn test_ledger_snapshots_at_lt_and_comparison() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "snapshot").await;
+    let admin = common::create_admin(&db, "snapshot").await;
+
+    let token = format!("EQ_SNAPSHOT_TOKEN_{}", suffix);
+    common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Snapshot Orchard".to_string();
+        c.token_name = "Snapshot".to_string();
+        c.token_symbol = "SNP".to_string();
+        c.status = "finished".to_string();
+        c.token_address = Some(token.clone());
+    })
+    .await;
+
+    // A holds 10 from lt 100; B gets 5 at lt 200; A sells out at lt 300
+    let nano = TokenAmount::from_nano;
+    db.upsert_portfolio("EQ_SNAP_A", &token, nano(10), 100).await.unwrap();
+    db.upsert_portfolio("EQ_SNAP_B", &token, nano(5), 200).await.unwrap();
+    db.upsert_portfolio("EQ_SNAP_A", &token, nano(0), 300).await.unwrap();
+    // A stale update is neither applied nor recorded
+    db.upsert_portfolio("EQ_SNAP_A", &token, nano(99), 250).await.unwrap();
+
+    let balances = |entries: Vec<web_app::db::snapshots::SnapshotEntry>| {
+        entries.into_iter().map(|e| (e.user_address, e.balance.nano())).collect::<Vec<_>>()
+    };
+    assert_eq!(balances(db.ledger_holders(&token, Some(150), None).await.unwrap()), vec![("EQ_SNAP_A".to_string(), 10)]);
+    assert_eq!(
+        balances(db.ledger_holders(&token, Some(250), None).await.unwrap()),
+        vec![("EQ_SNAP_A".to_string(), 10), ("EQ_SNAP_B".to_string(), 5)]
+    );
+    assert_eq!(balances(db.ledger_holders(&token, None, None).await.unwrap()), vec![("EQ_SNAP_B".to_string(), 5)]);
+    let before = chrono::Utc::now() - chrono::Duration::hours(1);
+    assert!(db.ledger_holders(&token, None, Some(before)).await.unwrap().is_empty());
+
+    let send = |uri: String, body: Option<Value>| {
+        let app = app.clone();
+        let admin_token = admin.token.clone();
+        async move {
+            let builder = Request::builder()
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("Authorization", format!("Bearer {}", admin_token));
+            let req = match body {
+                Some(body) => builder.method("POST").body(Body::from(body.to_string())).unwrap(),
+                None => builder.body(Body::empty()).unwrap(),
+            };
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    // 1. Snapshots persist the materialized holder sets
+    let (status, early) = send("/admin/snapshots".into(), Some(serde_json::json!({ "token_address": token, "at_lt": 250 }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", early);
+    assert_eq!(early["snapshot"]["source"], "ledger");
+    assert_eq!(early["snapshot"]["holder_count"], 2);
+    assert_eq!(early["snapshot"]["total_supply"], "15");
+    let (_, latest) = send("/admin/snapshots".into(), Some(serde_json::json!({ "token_address": token }))).await;
+    assert_eq!(latest["entries"].as_array().unwrap().len(), 1);
+    let early_id = early["snapshot"]["id"].as_str().unwrap().to_string();
+    let latest_id = latest["snapshot"]["id"].as_str().unwrap().to_string();
+
+    let (status, listed) = send(format!("/admin/snapshots?token_address={}", token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed.as_array().unwrap().len(), 2);
+
+    // 2. Comparing two snapshots lists the holders whose balances moved
+    let (status, diff) = send(format!("/admin/snapshots/{}/compare/{}", early_id, latest_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let mismatches = diff["mismatches"].as_array().unwrap();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!((mismatches[0]["user_address"].as_str(), mismatches[0]["left"].as_str(), mismatches[0]["right"].as_str()), (Some("EQ_SNAP_A"), Some("10"), Some("0")));
+
+    // 3. Chain snapshots read the current state only; unknown inputs are rejected
+    let (status, _) = send("/admin/snapshots".into(), Some(serde_json::json!({ "token_address": token, "source": "chain", "at_lt": 1 }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send("/admin/snapshots".into(), Some(serde_json::json!({ "token_address": token, "source": "guess" }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send("/admin/snapshots".into(), Some(serde_json::json!({ "token_address": "EQ_NO_SUCH_TOKEN" }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // 4. A distribution can be shared by an earlier snapshot
+    let (status, preview) = send(
+        "/admin/distribution".into(),
+        Some(serde_json::json!({ "target_token": token, "amount_mkoin": "0.00000003", "snapshot_id": early_id, "dry_run": true })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", preview);
+    assert_eq!(preview["snapshot_id"], early_id.as_str());
+    let shares: Vec<(&str, &str)> = preview["payouts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| (p["user_address"].as_str().unwrap(), p["amount"].as_str().unwrap()))
+        .collect();
+    assert_eq!(shares, vec![("EQ_SNAP_A", "20"), ("EQ_SNAP_B", "10")]);
+}
+
+#[tokio::test]
+async fn test_indexed_transfers_feed_the_ledger() {
+    let (db, _cache) = common::setup().await;
+
+    let suffix = uuid::Uuid::new_v4();
+    let token = format!("EQ_LEDGER_TOKEN_{}", suffix);
+    let (a, b) = (format!("0:LEDGER_A_{}", suffix), format!("0:LEDGER_B_{}", suffix));
+    let nano = TokenAmount::from_nano;
+    let transfer = |sender: &str, recipient: &str, amount: u128, lt: i64, sent_lt: i64| NewTransfer {
+        tx_hash: format!("{}{:x}", suffix.simple(), lt),
+        lt,
+        asset: "campaign_token".to_string(),
+        token_address: Some(token.clone()),
+        campaign_id: None,
+        sender: sender.to_string(),
+        recipient: recipient.to_string(),
+        amount: nano(amount),
+        is_mint: sender == "master",
+        status: "completed".to_string(),
+        occurred_at: chrono::Utc::now(),
+        sent_lt: Some(sent_lt),
+    };
+    let balances = |entries: Vec<web_app::db::snapshots::SnapshotEntry>| {
+        entries.into_iter().map(|e| (e.user_address, e.balance.nano())).collect::<Vec<_>>()
+    };
+
+    // A is minted 10 at lt 100 and sends 4 to B at lt 290, credited at 300
+    assert!(db.record_transfer(&transfer("master", &a, 10, 100, 99)).await.unwrap());
+    assert!(db.record_transfer(&transfer(&a, &b, 4, 300, 290)).await.unwrap());
+    // A transaction is applied once
+    assert!(!db.record_transfer(&transfer(&a, &b, 4, 300, 290)).await.unwrap());
+    assert_eq!(
+        balances(db.ledger_holders(&token, None, None).await.unwrap()),
+        vec![(a.clone(), 6), (b.clone(), 4)]
+    );
+
+    // A mint at lt 200 indexed late shifts A's later balances
+    assert!(db.record_transfer(&transfer("master", &a, 5, 200, 199)).await.unwrap());
+    assert_eq!(balances(db.ledger_holders(&token, Some(250), None).await.unwrap()), vec![(a.clone(), 15)]);
+    assert_eq!(
+        balances(db.ledger_holders(&token, None, None).await.unwrap()),
+        vec![(a.clone(), 11), (b.clone(), 4)]
+    );
+
+    // Portfolios follow the newest entry; bounced transfers move nothing
+    let mut bounced = transfer(&b, &a, 4, 400, 390);
+    bounced.status = "failed".to_string();
+    assert!(db.record_transfer(&bounced).await.unwrap());
+    let portfolio: TokenAmount =
+        sqlx::query_scalar("SELECT balance FROM portfolios WHERE user_address = $1 AND token_address = $2")
+            .bind(&a)
+            .bind(&token)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+    assert_eq!(portfolio, nano(11));
+}
@@ -0,0 +1,109 @@
+use web_app::amount::TokenAmount;
+use web_app::api;
+use web_app::db::transfers::NewTransfer;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use http_body_util::BodyExt;
+use serde_json::Value;
+
+mod common;
+
+fn random_address() -> String {
+    format!("0:{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+#[tokio::test]
+async fn test_transaction_history_from_indexed_transfers() {
+    let (db, cache) = common::setup().await;
+    let app = api::router(db.clone(), cache.clone());
+
+    let suffix = uuid::Uuid::new_v4();
+    let farmer = common::create_farmer(&db, "transactions").await;
+    let token_address = random_address();
+    let campaign_id = common::insert_campaign(&db, farmer.id, |c| {
+        c.name = "Transactions Orchard".to_string();
+        c.token_name = "Transactions".to_string();
+        c.token_symbol = "TXN".to_string();
+        c.suggested_price = TokenAmount::parse_decimal("1.15").unwrap();
+        c.status = "running".to_string();
+        c.token_address = Some(token_address.clone());
+    })
+    .await;
+
+    let owner = random_address();
+    let friend = random_address();
+    let friend_name = format!("test_friend_transactions_{}", suffix);
+    db.create_user_full(&friend_name, "x", "farmer", &friend, None).await.unwrap();
+    let stranger = random_address();
+
+    let at = |minutes: i64| chrono::Utc::now() - chrono::Duration::minutes(minutes);
+    let transfers = [
+        // Oldest first: minted MKOIN, tokens sent to a friend, TON from a stranger
+        ("MKOIN", None, None, "mint_source", owner.as_str(), "25", true, 30),
+        ("campaign_token", Some(token_address.clone()), Some(campaign_id), owner.as_str(), friend.as_str(), "10", false, 20),
+        ("TON", None, None, stranger.as_str(), owner.as_str(), "1.5", false, 10),
+    ];
+    for (asset, token, campaign, sender, recipient, amount, is_mint, minutes) in transfers {
+        db.record_transfer(&NewTransfer {
+            tx_hash: format!("{}{:x}", uuid::Uuid::new_v4().simple(), minutes),
+            lt: 1_000 - minutes,
+            asset: asset.to_string(),
+            token_address: token,
+            campaign_id: campaign,
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount: TokenAmount::parse_decimal(amount).unwrap(),
+            is_mint,
+            status: "completed".to_string(),
+            occurred_at: at(minutes),
+            sent_lt: Some(999 - minutes),
+        })
+        .await
+        .unwrap();
+    }
+
+    let get = |uri: String| {
+        let app = app.clone();
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<Value>(&body).unwrap_or_default())
+        }
+    };
+
+    let (status, page) = get(format!("/transactions/{}?limit=2", owner)).await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = page["transactions"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0]["type"], "received");
+    assert_eq!(entries[0]["currency"], "TON");
+    assert_eq!(entries[0]["counterparty"], stranger.as_str());
+    assert_eq!(entries[0]["counterpartyLabel"], "external");
+    assert_eq!(entries[0]["amount"], 1.5);
+    assert!(entries[0]["eurValue"].is_null());
+    assert!(entries[0]["explorerUrl"].as_str().unwrap().ends_with(entries[0]["id"].as_str().unwrap()));
+
+    assert_eq!(entries[1]["type"], "sent");
+    assert_eq!(entries[1]["currency"], "TXN");
+    assert_eq!(entries[1]["counterpartyLabel"], "user");
+    // No trades yet: valued at the sale price
+    assert_eq!(entries[1]["eurValue"], 11.5);
+
+    let cursor = page["nextCursor"].as_i64().unwrap();
+    let (_, page) = get(format!("/transactions/{}?limit=2&cursor={}", owner, cursor)).await;
+    let entries = page["transactions"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["currency"], "MKOIN");
+    assert_eq!(entries[0]["counterpartyLabel"], "mint");
+    assert_eq!(entries[0]["eurValue"], 25.0);
+    assert!(page["nextCursor"].is_null());
+
+    let (status, _) = get("/transactions/not-an-address".to_string()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}